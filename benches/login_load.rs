@@ -0,0 +1,264 @@
+//! `/health` latency while the server is busy logging users in.
+//!
+//! Clients log in through the real `/api/v1/auth/login` handler while a
+//! probe measures the real `/api/v1/health` handler, first with passwords
+//! checked inline on the actix workers (how logins used to hash) and then
+//! on the hashing pool configured from `[passwords]`. A burst of logins
+//! should no longer show up in the latency of unrelated requests.
+//!
+//! Needs the database from the configuration with the migrations applied,
+//! like the server. A `bench-login` user is created for each run and
+//! deleted afterwards, taking its sessions and refresh tokens with it. The
+//! hashing pool is installed once per process, so each mode runs in a
+//! child process of its own.
+//!
+//! ```bash
+//! cargo run -p migration
+//! cargo bench --bench login_load
+//! ```
+//!
+//! Sample run on a single-core machine with a local PostgreSQL:
+//!
+//! ```text
+//! MODE                     PROBES   P50 (ms)   P99 (ms)   MAX (ms)   LOGINS/S
+//! inline (before)              46      87.86     286.89     286.89       52.2
+//! hashing pool (after)        761       0.12       1.39      13.14       59.6
+//! ```
+
+use actix_web::{App, HttpServer, web};
+use apistos::app::OpenApiWrapper;
+use apistos::spec::Spec;
+use entity::{AuthUserEntityExt, AuthUsers, CreateUserData};
+use sea_orm::{DatabaseConnection, EntityTrait};
+use security::password::{HashingPool, configure_pool};
+use service::config::Settings;
+use service::{db, routes, state};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+const WORKERS: usize = 2;
+const LOGIN_CLIENTS: usize = 32;
+const DURATION: Duration = Duration::from_secs(5);
+const PROBE_INTERVAL: Duration = Duration::from_millis(5);
+const USERNAME: &str = "bench-login";
+const PASSWORD: &str = "correct horse battery staple";
+/// Set for the child process running one mode
+const MODE_VAR: &str = "LOGIN_LOAD_MODE";
+
+#[derive(Clone, Copy)]
+enum Mode {
+    Inline,
+    Pool,
+}
+
+impl Mode {
+    const ALL: [Mode; 2] = [Mode::Inline, Mode::Pool];
+
+    fn name(self) -> &'static str {
+        match self {
+            Mode::Inline => "inline",
+            Mode::Pool => "pool",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Mode::Inline => "inline (before)",
+            Mode::Pool => "hashing pool (after)",
+        }
+    }
+
+    fn from_env() -> Option<Self> {
+        let name = std::env::var(MODE_VAR).ok()?;
+        Self::ALL.into_iter().find(|mode| mode.name() == name)
+    }
+}
+
+fn start_server(app_state: state::AppState) -> (SocketAddr, actix_web::dev::ServerHandle) {
+    let server = HttpServer::new(move || {
+        App::new()
+            .document(Spec::default())
+            .app_data(web::Data::new(app_state.clone()))
+            .configure(routes::configure)
+            .build("/openapi.json")
+    })
+    .workers(WORKERS)
+    .bind("127.0.0.1:0")
+    .expect("Failed to bind benchmark server");
+
+    let addr = server.addrs()[0];
+    let server = server.run();
+    let handle = server.handle();
+    tokio::spawn(server);
+    (addr, handle)
+}
+
+/// Send a request and return the response status
+async fn request(addr: SocketAddr, method: &str, path: &str, body: &str) -> std::io::Result<u16> {
+    let mut stream = TcpStream::connect(addr).await?;
+    let request = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        method,
+        path,
+        addr,
+        body.len(),
+        body
+    );
+    stream.write_all(request.as_bytes()).await?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await?;
+
+    // `HTTP/1.1 200 OK`
+    let status = String::from_utf8_lossy(&response)
+        .split(' ')
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .unwrap_or(0);
+    Ok(status)
+}
+
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    let index = ((sorted.len() as f64 - 1.0) * p).round() as usize;
+    sorted[index]
+}
+
+/// The user the clients log in as, with a fresh hash of `PASSWORD`
+async fn create_bench_user(db: &DatabaseConnection) -> entity::auth_users::Model {
+    if let Some(user) = AuthUsers::find_by_username(db, USERNAME)
+        .await
+        .expect("Failed to look up the benchmark user")
+    {
+        AuthUsers::delete_by_id(user.id)
+            .exec(db)
+            .await
+            .expect("Failed to delete the previous benchmark user");
+    }
+
+    AuthUsers::create_user(
+        db,
+        CreateUserData {
+            email: format!("{}@example.com", USERNAME),
+            username: USERNAME.to_string(),
+            password: PASSWORD.to_string(),
+            is_active: true,
+            is_verified: true,
+            ..Default::default()
+        },
+    )
+    .await
+    .expect("Failed to create the benchmark user")
+}
+
+async fn run(addr: SocketAddr, mode: Mode) {
+    let stop = Arc::new(AtomicBool::new(false));
+    let logins = Arc::new(AtomicU64::new(0));
+    let body = serde_json::json!({ "username": USERNAME, "password": PASSWORD }).to_string();
+
+    let mut clients = Vec::new();
+    for _ in 0..LOGIN_CLIENTS {
+        let stop = stop.clone();
+        let logins = logins.clone();
+        let body = body.clone();
+        clients.push(tokio::spawn(async move {
+            while !stop.load(Ordering::Relaxed) {
+                let status = request(addr, "POST", "/api/v1/auth/login", &body).await;
+                match status {
+                    Ok(200) => {
+                        logins.fetch_add(1, Ordering::Relaxed);
+                    }
+                    // Busy pool or lockout: wait a moment like a real client
+                    Ok(503 | 429) => tokio::time::sleep(Duration::from_millis(10)).await,
+                    Ok(status) => panic!("Login failed with status {}", status),
+                    Err(_) => {}
+                }
+            }
+        }));
+    }
+
+    let mut latencies = Vec::new();
+    let started = Instant::now();
+    while started.elapsed() < DURATION {
+        let sent = Instant::now();
+        let status = request(addr, "GET", "/api/v1/health", "")
+            .await
+            .expect("Health probe failed");
+        assert_eq!(status, 200, "The database is not reachable");
+        latencies.push(sent.elapsed());
+        tokio::time::sleep(PROBE_INTERVAL).await;
+    }
+
+    stop.store(true, Ordering::Relaxed);
+    for client in clients {
+        client.await.expect("Login client failed");
+    }
+
+    latencies.sort();
+    println!(
+        "{:<22} {:>8} {:>10.2} {:>10.2} {:>10.2} {:>10.1}",
+        mode.label(),
+        latencies.len(),
+        percentile(&latencies, 0.50).as_secs_f64() * 1000.0,
+        percentile(&latencies, 0.99).as_secs_f64() * 1000.0,
+        latencies.last().unwrap().as_secs_f64() * 1000.0,
+        logins.load(Ordering::Relaxed) as f64 / DURATION.as_secs_f64(),
+    );
+}
+
+/// Serve logins and probe `/health` with passwords checked as `mode` says
+async fn bench(mode: Mode) {
+    let settings = Settings::new().expect("Failed to read configuration");
+    if let Mode::Inline = mode {
+        // Installed first, so the configured pool is not
+        let _ = configure_pool(HashingPool::inline());
+    }
+    settings
+        .passwords
+        .configure()
+        .expect("Invalid password configuration");
+    settings.lockout.configure();
+
+    let db = db::init_db(&settings)
+        .await
+        .expect("Failed to connect to database");
+    let user = create_bench_user(&db).await;
+
+    let app_state = state::AppState::new(db, settings);
+    let (addr, handle) = start_server(app_state.clone());
+    run(addr, mode).await;
+
+    // Before stopping: pooled connections opened by the workers close with them
+    AuthUsers::delete_by_id(user.id)
+        .exec(app_state.db.as_ref())
+        .await
+        .expect("Failed to delete the benchmark user");
+    handle.stop(true).await;
+}
+
+#[tokio::main]
+async fn main() {
+    if let Some(mode) = Mode::from_env() {
+        bench(mode).await;
+        return;
+    }
+
+    println!(
+        "/health latency with {} actix workers while {} clients log in as {}",
+        WORKERS, LOGIN_CLIENTS, USERNAME
+    );
+    println!(
+        "{:<22} {:>8} {:>10} {:>10} {:>10} {:>10}",
+        "MODE", "PROBES", "P50 (ms)", "P99 (ms)", "MAX (ms)", "LOGINS/S"
+    );
+    let exe = std::env::current_exe().expect("Failed to locate the benchmark binary");
+    for mode in Mode::ALL {
+        let status = std::process::Command::new(&exe)
+            .env(MODE_VAR, mode.name())
+            .status()
+            .expect("Failed to start the benchmark");
+        assert!(status.success(), "The {} run failed", mode.name());
+    }
+}
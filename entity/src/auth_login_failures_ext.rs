@@ -0,0 +1,594 @@
+use crate::auth_login_failures::{self, ActiveModel, Entity as AuthLoginFailures, Model};
+use crate::auth_users::Model as User;
+use crate::auth_users_ext::AuthError;
+use sea_orm::prelude::DateTime;
+use sea_orm::sea_query::{Expr, OnConflict};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, Set,
+};
+use std::fmt;
+use std::future::Future;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// What failed login attempts are counted against
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockoutScope {
+    /// An existing account, keyed by user id
+    User,
+    /// A username or email that matches no account, so probing unknown
+    /// names is throttled the same way as existing ones
+    Login,
+    /// A client IP address
+    Ip,
+}
+
+impl LockoutScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LockoutScope::User => "user",
+            LockoutScope::Login => "login",
+            LockoutScope::Ip => "ip",
+        }
+    }
+}
+
+impl fmt::Display for LockoutScope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for LockoutScope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "user" => Ok(LockoutScope::User),
+            "login" => Ok(LockoutScope::Login),
+            "ip" => Ok(LockoutScope::Ip),
+            other => Err(format!("Unknown lockout scope: {}", other)),
+        }
+    }
+}
+
+/// Failures allowed for one scope before attempts are slowed down and
+/// finally locked out
+#[derive(Debug, Clone, Copy)]
+pub struct LockoutRule {
+    /// Failures without any delay
+    pub free_attempts: u32,
+    /// Failures that lock the key out for `lockout_duration`
+    pub lockout_threshold: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct LockoutPolicy {
+    pub enabled: bool,
+    /// Applies to the `User` and `Login` scopes
+    pub account: LockoutRule,
+    pub ip: LockoutRule,
+    /// Delay after the first failure past `free_attempts`, doubled with each
+    /// further failure
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub lockout_duration: Duration,
+    /// Failures older than this are forgotten
+    pub failure_window: Duration,
+}
+
+impl Default for LockoutPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            account: LockoutRule {
+                free_attempts: 3,
+                lockout_threshold: 10,
+            },
+            ip: LockoutRule {
+                free_attempts: 10,
+                lockout_threshold: 50,
+            },
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+            lockout_duration: Duration::from_secs(900),
+            failure_window: Duration::from_secs(3600),
+        }
+    }
+}
+
+impl LockoutPolicy {
+    fn rule(&self, scope: LockoutScope) -> LockoutRule {
+        match scope {
+            LockoutScope::User | LockoutScope::Login => self.account,
+            LockoutScope::Ip => self.ip,
+        }
+    }
+
+    /// How long a key must wait after its `failures`-th consecutive failure
+    pub fn delay_after(&self, scope: LockoutScope, failures: u32) -> Option<Duration> {
+        let rule = self.rule(scope);
+        if failures >= rule.lockout_threshold {
+            return Some(self.lockout_duration);
+        }
+        if failures <= rule.free_attempts {
+            return None;
+        }
+
+        let doublings = (failures - rule.free_attempts - 1).min(31);
+        let delay = self.base_delay.saturating_mul(1 << doublings);
+        Some(delay.min(self.max_delay))
+    }
+}
+
+static POLICY: OnceLock<LockoutPolicy> = OnceLock::new();
+
+/// Install the process-wide lockout policy; returns it back if already set
+pub fn configure_lockout(policy: LockoutPolicy) -> Result<(), LockoutPolicy> {
+    POLICY.set(policy)
+}
+
+/// The process-wide lockout policy, the defaults unless configured
+pub fn lockout_policy() -> &'static LockoutPolicy {
+    POLICY.get_or_init(LockoutPolicy::default)
+}
+
+fn to_chrono(duration: Duration) -> chrono::Duration {
+    chrono::Duration::from_std(duration).unwrap_or(chrono::Duration::MAX)
+}
+
+// Trait for Entity-level operations (static methods)
+#[async_trait::async_trait]
+pub trait LoginFailureEntityExt {
+    /// Refuse the attempt while `key` is delayed or locked out
+    async fn ensure_not_locked(
+        db: &DatabaseConnection,
+        scope: LockoutScope,
+        key: &str,
+    ) -> Result<(), AuthError>;
+
+    /// Count a failed attempt and start a delay or lockout if the policy says so
+    async fn record_failure(
+        db: &DatabaseConnection,
+        scope: LockoutScope,
+        key: &str,
+    ) -> Result<Model, AuthError>;
+
+    /// Forget the failures of a key, returning whether there were any
+    async fn clear(
+        db: &DatabaseConnection,
+        scope: LockoutScope,
+        key: &str,
+    ) -> Result<bool, AuthError>;
+
+    /// Forget the failures of one entry, returning whether it existed
+    async fn clear_by_id(db: &DatabaseConnection, id: i32) -> Result<bool, AuthError>;
+
+    /// Forget every failure, or those of one scope
+    async fn clear_all(
+        db: &DatabaseConnection,
+        scope: Option<LockoutScope>,
+    ) -> Result<u64, AuthError>;
+
+    /// Forget keys whose last failure is past the failure window and that
+    /// are not locked out, which count from zero again anyway
+    async fn clear_expired(db: &DatabaseConnection) -> Result<u64, AuthError>;
+
+    /// Recorded failures, most recent first
+    async fn list(
+        db: &DatabaseConnection,
+        scope: Option<LockoutScope>,
+        locked_only: bool,
+    ) -> Result<Vec<Model>, AuthError>;
+}
+
+// Trait for Model-level operations (instance methods)
+pub trait LoginFailureModelExt {
+    /// Whether attempts are refused right now
+    fn is_locked(&self) -> bool;
+}
+
+#[async_trait::async_trait]
+impl LoginFailureEntityExt for AuthLoginFailures {
+    async fn ensure_not_locked(
+        db: &DatabaseConnection,
+        scope: LockoutScope,
+        key: &str,
+    ) -> Result<(), AuthError> {
+        if !lockout_policy().enabled {
+            return Ok(());
+        }
+
+        let now = chrono::Utc::now().naive_utc();
+        let locked_until = AuthLoginFailures::find()
+            .filter(auth_login_failures::Column::Scope.eq(scope.as_str()))
+            .filter(auth_login_failures::Column::Key.eq(key))
+            .one(db)
+            .await?
+            .and_then(|failure| failure.locked_until)
+            .filter(|locked_until| *locked_until > now);
+
+        match locked_until {
+            Some(locked_until) => Err(AuthError::TooManyAttempts {
+                retry_after: (locked_until - now).to_std().unwrap_or_default(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    async fn record_failure(
+        db: &DatabaseConnection,
+        scope: LockoutScope,
+        key: &str,
+    ) -> Result<Model, AuthError> {
+        let policy = lockout_policy();
+        let now = chrono::Utc::now().naive_utc();
+        let window_start: DateTime = now - to_chrono(policy.failure_window);
+
+        let failure = ActiveModel {
+            scope: Set(scope.as_str().to_string()),
+            key: Set(key.to_string()),
+            failures: Set(1),
+            last_failure_at: Set(now),
+            locked_until: Set(None),
+            ..Default::default()
+        };
+
+        // Counted in the database so concurrent attempts cannot slip through
+        let failure = AuthLoginFailures::insert(failure)
+            .on_conflict(
+                OnConflict::columns([
+                    auth_login_failures::Column::Scope,
+                    auth_login_failures::Column::Key,
+                ])
+                .value(
+                    auth_login_failures::Column::Failures,
+                    Expr::cust_with_values(
+                        "CASE WHEN auth_login_failures.last_failure_at < ? THEN 1 \
+                         ELSE auth_login_failures.failures + 1 END",
+                        [window_start],
+                    ),
+                )
+                .update_column(auth_login_failures::Column::LastFailureAt)
+                .to_owned(),
+            )
+            .exec_with_returning(db)
+            .await?;
+
+        let delay = policy.delay_after(scope, failure.failures.max(0) as u32);
+        let mut active_model: ActiveModel = failure.into();
+        active_model.locked_until = Set(delay.map(|delay| now + to_chrono(delay)));
+        Ok(active_model.update(db).await?)
+    }
+
+    async fn clear(
+        db: &DatabaseConnection,
+        scope: LockoutScope,
+        key: &str,
+    ) -> Result<bool, AuthError> {
+        let result = AuthLoginFailures::delete_many()
+            .filter(auth_login_failures::Column::Scope.eq(scope.as_str()))
+            .filter(auth_login_failures::Column::Key.eq(key))
+            .exec(db)
+            .await?;
+
+        Ok(result.rows_affected > 0)
+    }
+
+    async fn clear_by_id(db: &DatabaseConnection, id: i32) -> Result<bool, AuthError> {
+        let result = AuthLoginFailures::delete_by_id(id).exec(db).await?;
+        Ok(result.rows_affected > 0)
+    }
+
+    async fn clear_all(
+        db: &DatabaseConnection,
+        scope: Option<LockoutScope>,
+    ) -> Result<u64, AuthError> {
+        let mut query = AuthLoginFailures::delete_many();
+        if let Some(scope) = scope {
+            query = query.filter(auth_login_failures::Column::Scope.eq(scope.as_str()));
+        }
+
+        Ok(query.exec(db).await?.rows_affected)
+    }
+
+    async fn clear_expired(db: &DatabaseConnection) -> Result<u64, AuthError> {
+        let now = chrono::Utc::now().naive_utc();
+        let window_start: DateTime = now - to_chrono(lockout_policy().failure_window);
+        let result = AuthLoginFailures::delete_many()
+            .filter(auth_login_failures::Column::LastFailureAt.lt(window_start))
+            .filter(
+                auth_login_failures::Column::LockedUntil
+                    .is_null()
+                    .or(auth_login_failures::Column::LockedUntil.lte(now)),
+            )
+            .exec(db)
+            .await?;
+
+        Ok(result.rows_affected)
+    }
+
+    async fn list(
+        db: &DatabaseConnection,
+        scope: Option<LockoutScope>,
+        locked_only: bool,
+    ) -> Result<Vec<Model>, AuthError> {
+        let mut query =
+            AuthLoginFailures::find().order_by_desc(auth_login_failures::Column::LastFailureAt);
+        if let Some(scope) = scope {
+            query = query.filter(auth_login_failures::Column::Scope.eq(scope.as_str()));
+        }
+        if locked_only {
+            query = query.filter(
+                auth_login_failures::Column::LockedUntil.gt(chrono::Utc::now().naive_utc()),
+            );
+        }
+
+        Ok(query.all(db).await?)
+    }
+}
+
+impl LoginFailureModelExt for Model {
+    fn is_locked(&self) -> bool {
+        self.locked_until
+            .is_some_and(|locked_until| locked_until > chrono::Utc::now().naive_utc())
+    }
+}
+
+/// The keys one password check is counted against: the account, or the
+/// login name when it matches none, and the client IP
+#[derive(Debug, Clone)]
+pub struct LoginAttempt {
+    account: (LockoutScope, String),
+    ip: Option<String>,
+    action: Option<&'static str>,
+}
+
+impl LoginAttempt {
+    /// An attempt on `user`, or on the unknown `login` name, so probing
+    /// unknown names is throttled alike
+    pub fn new(user: Option<&User>, login: &str) -> Self {
+        let account = match user {
+            Some(user) => (LockoutScope::User, user.id.to_string()),
+            None => (LockoutScope::Login, login.to_lowercase()),
+        };
+        Self {
+            account,
+            ip: None,
+            action: None,
+        }
+    }
+
+    /// An attempt on the account `user_id`, such as re-entering a password
+    pub fn for_user(user_id: i32) -> Self {
+        Self {
+            account: (LockoutScope::User, user_id.to_string()),
+            ip: None,
+            action: None,
+        }
+    }
+
+    /// Count the attempt against `ip` as well
+    pub fn from_ip(mut self, ip: Option<&str>) -> Self {
+        self.ip = ip.map(str::to_string);
+        self
+    }
+
+    /// Count the attempt as `action`, under keys of the form
+    /// `<action>:<key>`, so it is limited by the same policy without
+    /// locking out password checks
+    pub fn counted_as(mut self, action: &'static str) -> Self {
+        self.action = Some(action);
+        self
+    }
+
+    fn key(&self, key: &str) -> String {
+        match self.action {
+            Some(action) => format!("{}:{}", action, key),
+            None => key.to_string(),
+        }
+    }
+
+    fn account_key(&self) -> (LockoutScope, String) {
+        (self.account.0, self.key(&self.account.1))
+    }
+
+    fn keys(&self) -> Vec<(LockoutScope, String)> {
+        let ip = self
+            .ip
+            .as_deref()
+            .map(|ip| (LockoutScope::Ip, self.key(ip)));
+        ip.into_iter().chain([self.account_key()]).collect()
+    }
+
+    /// Run a password `check` under the lockout policy. It is refused while
+    /// a key is locked out; `None` counts as a failure of every key and a
+    /// match forgets the failures of the account.
+    pub async fn check<T, E>(
+        &self,
+        db: &DatabaseConnection,
+        check: impl Future<Output = Result<Option<T>, E>>,
+    ) -> Result<Option<T>, E>
+    where
+        E: From<AuthError>,
+    {
+        if !lockout_policy().enabled {
+            return check.await;
+        }
+
+        for (scope, key) in self.keys() {
+            AuthLoginFailures::ensure_not_locked(db, scope, &key).await?;
+        }
+
+        let checked = check.await?;
+        if checked.is_some() {
+            let (scope, key) = self.account_key();
+            AuthLoginFailures::clear(db, scope, &key).await?;
+        } else {
+            for (scope, key) in self.keys() {
+                AuthLoginFailures::record_failure(db, scope, &key).await?;
+            }
+        }
+        Ok(checked)
+    }
+
+    /// Count an attempt that has nothing to check, such as asking for a
+    /// password reset email. It is refused while a key is locked out and
+    /// counted against every key otherwise.
+    pub async fn record<E>(&self, db: &DatabaseConnection) -> Result<(), E>
+    where
+        E: From<AuthError>,
+    {
+        if !lockout_policy().enabled {
+            return Ok(());
+        }
+
+        for (scope, key) in self.keys() {
+            AuthLoginFailures::ensure_not_locked(db, scope, &key).await?;
+        }
+        for (scope, key) in self.keys() {
+            AuthLoginFailures::record_failure(db, scope, &key).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sea_orm::{DatabaseBackend, MockDatabase, MockExecResult};
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    fn failure(scope: LockoutScope, locked_until: Option<DateTime>) -> Model {
+        Model {
+            id: 1,
+            scope: scope.as_str().to_string(),
+            key: "key".to_string(),
+            failures: 1,
+            last_failure_at: chrono::Utc::now().naive_utc(),
+            locked_until,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_login_attempt_refused_while_locked() {
+        let locked_until = chrono::Utc::now().naive_utc() + chrono::Duration::minutes(5);
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![failure(LockoutScope::Ip, Some(locked_until))]])
+            .into_connection();
+        let checked = AtomicBool::new(false);
+
+        let result = LoginAttempt::for_user(7)
+            .from_ip(Some("203.0.113.9"))
+            .check(&db, async {
+                checked.store(true, Ordering::Relaxed);
+                Ok::<_, AuthError>(Some(()))
+            })
+            .await;
+
+        assert!(matches!(result, Err(AuthError::TooManyAttempts { .. })));
+        assert!(!checked.load(Ordering::Relaxed), "No password is checked");
+    }
+
+    #[tokio::test]
+    async fn test_login_attempt_counts_failures_and_clears_on_success() {
+        let none: Vec<Model> = vec![];
+        let counted = failure(LockoutScope::User, None);
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([none.clone(), none.clone()])
+            // Insert and update of the account, then of the IP
+            .append_query_results(vec![vec![counted.clone()]; 4])
+            .into_connection();
+        let attempt = LoginAttempt::for_user(7).from_ip(Some("203.0.113.9"));
+
+        let result = attempt.check(&db, async { Ok::<Option<()>, AuthError>(None) });
+        assert_eq!(result.await.unwrap(), None);
+        let log = db.into_transaction_log();
+        assert_eq!(log.len(), 6);
+        assert!(format!("{:?}", log[2]).contains("INSERT"));
+        assert!(format!("{:?}", log[4]).contains("INSERT"));
+
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([none.clone(), none])
+            .append_exec_results([MockExecResult {
+                last_insert_id: 0,
+                rows_affected: 1,
+            }])
+            .into_connection();
+        let result = attempt.check(&db, async { Ok::<_, AuthError>(Some(())) });
+        assert_eq!(result.await.unwrap(), Some(()));
+        let log = db.into_transaction_log();
+        assert_eq!(log.len(), 3);
+        assert!(format!("{:?}", log[2]).contains("DELETE"));
+        assert!(format!("{:?}", log[2]).contains("\"user\""));
+    }
+
+    #[tokio::test]
+    async fn test_recorded_action_has_keys_of_its_own() {
+        let none: Vec<Model> = vec![];
+        let counted = failure(LockoutScope::User, None);
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([none.clone(), none])
+            .append_query_results(vec![vec![counted]; 4])
+            .into_connection();
+
+        LoginAttempt::for_user(7)
+            .from_ip(Some("203.0.113.9"))
+            .counted_as("password_reset")
+            .record::<AuthError>(&db)
+            .await
+            .unwrap();
+        let log = db.into_transaction_log();
+        assert_eq!(log.len(), 6);
+        assert!(format!("{:?}", log[2]).contains("\"password_reset:203.0.113.9\""));
+        assert!(format!("{:?}", log[4]).contains("\"password_reset:7\""));
+
+        let locked_until = chrono::Utc::now().naive_utc() + chrono::Duration::minutes(5);
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![failure(LockoutScope::Ip, Some(locked_until))]])
+            .into_connection();
+        let result = LoginAttempt::for_user(7)
+            .from_ip(Some("203.0.113.9"))
+            .counted_as("password_reset")
+            .record::<AuthError>(&db)
+            .await;
+        assert!(matches!(result, Err(AuthError::TooManyAttempts { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_clear_expired_keeps_lockouts() {
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_exec_results([MockExecResult {
+                last_insert_id: 0,
+                rows_affected: 2,
+            }])
+            .into_connection();
+
+        assert_eq!(AuthLoginFailures::clear_expired(&db).await.unwrap(), 2);
+        let log = format!("{:?}", db.into_transaction_log());
+        assert!(log.contains(r#"\"last_failure_at\" <"#), "{}", log);
+        assert!(log.contains(r#"\"locked_until\" IS NULL"#), "{}", log);
+    }
+
+    #[test]
+    fn test_progressive_delay_then_lockout() {
+        let policy = LockoutPolicy::default();
+        let delay = |failures| policy.delay_after(LockoutScope::User, failures);
+
+        assert_eq!(delay(3), None);
+        assert_eq!(delay(4), Some(Duration::from_secs(1)));
+        assert_eq!(delay(5), Some(Duration::from_secs(2)));
+        assert_eq!(delay(9), Some(Duration::from_secs(32)));
+        assert_eq!(delay(10), Some(Duration::from_secs(900)));
+        assert_eq!(
+            policy.delay_after(LockoutScope::Ip, 9),
+            None,
+            "IPs get more free attempts"
+        );
+        assert_eq!(
+            policy.delay_after(LockoutScope::Ip, 40),
+            Some(Duration::from_secs(60))
+        );
+    }
+}
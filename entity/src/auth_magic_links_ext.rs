@@ -0,0 +1,98 @@
+use crate::auth_magic_links::{self, ActiveModel, Entity as AuthMagicLinks, Model};
+use crate::auth_users_ext::AuthError;
+use sea_orm::prelude::DateTime;
+use sea_orm::sea_query::Expr;
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set};
+
+pub struct NewMagicLink {
+    pub user_id: i32,
+    pub token_hash: String,
+    /// Hash of the secret kept in the requesting browser's session, when
+    /// the link only works there
+    pub browser_hash: Option<String>,
+    pub expires_at: DateTime,
+}
+
+// Trait for magic link operations (static methods)
+#[async_trait::async_trait]
+pub trait MagicLinkEntityExt {
+    /// Store the hash of a new login link
+    async fn issue(db: &DatabaseConnection, link: NewMagicLink) -> Result<Model, AuthError>;
+
+    /// Find an unused, unexpired link by its hash
+    async fn find_active_by_hash(
+        db: &DatabaseConnection,
+        token_hash: &str,
+    ) -> Result<Option<Model>, AuthError>;
+
+    /// Mark a link as used, returning false if it already was so that a
+    /// link logs in at most once
+    async fn consume(db: &DatabaseConnection, id: i32) -> Result<bool, AuthError>;
+
+    /// Delete the user's links that were not used, returning how many
+    async fn revoke_unused(db: &DatabaseConnection, user_id: i32) -> Result<u64, AuthError>;
+}
+
+// Trait for magic link operations (instance methods)
+pub trait MagicLinkModelExt {
+    fn is_expired(&self) -> bool;
+}
+
+#[async_trait::async_trait]
+impl MagicLinkEntityExt for AuthMagicLinks {
+    async fn issue(db: &DatabaseConnection, link: NewMagicLink) -> Result<Model, AuthError> {
+        let new_link = ActiveModel {
+            user_id: Set(link.user_id),
+            token_hash: Set(link.token_hash),
+            browser_hash: Set(link.browser_hash),
+            expires_at: Set(link.expires_at),
+            used_at: Set(None),
+            created_at: Set(chrono::Utc::now().naive_utc()),
+            ..Default::default()
+        };
+
+        Ok(new_link.insert(db).await?)
+    }
+
+    async fn find_active_by_hash(
+        db: &DatabaseConnection,
+        token_hash: &str,
+    ) -> Result<Option<Model>, AuthError> {
+        Ok(AuthMagicLinks::find()
+            .filter(auth_magic_links::Column::TokenHash.eq(token_hash))
+            .filter(auth_magic_links::Column::UsedAt.is_null())
+            .one(db)
+            .await?
+            .filter(|link| !link.is_expired()))
+    }
+
+    async fn consume(db: &DatabaseConnection, id: i32) -> Result<bool, AuthError> {
+        let result = AuthMagicLinks::update_many()
+            .col_expr(
+                auth_magic_links::Column::UsedAt,
+                Expr::value(chrono::Utc::now().naive_utc()),
+            )
+            .filter(auth_magic_links::Column::Id.eq(id))
+            .filter(auth_magic_links::Column::UsedAt.is_null())
+            .exec(db)
+            .await?;
+
+        Ok(result.rows_affected == 1)
+    }
+
+    async fn revoke_unused(db: &DatabaseConnection, user_id: i32) -> Result<u64, AuthError> {
+        let result = AuthMagicLinks::delete_many()
+            .filter(auth_magic_links::Column::UserId.eq(user_id))
+            .filter(auth_magic_links::Column::UsedAt.is_null())
+            .exec(db)
+            .await?;
+
+        Ok(result.rows_affected)
+    }
+}
+
+impl MagicLinkModelExt for Model {
+    fn is_expired(&self) -> bool {
+        self.expires_at <= chrono::Utc::now().naive_utc()
+    }
+}
@@ -0,0 +1,229 @@
+use crate::auth_user_recovery_codes::{
+    self, ActiveModel as RecoveryCodeActiveModel, Entity as AuthUserRecoveryCodes,
+};
+use crate::auth_user_totp::{self, ActiveModel, Entity as AuthUserTotp, Model};
+use crate::auth_users_ext::AuthError;
+use sea_orm::sea_query::{Expr, OnConflict};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, Condition, DatabaseConnection, EntityTrait, PaginatorTrait,
+    QueryFilter, Set, TransactionTrait,
+};
+
+// Trait for Entity-level operations (static methods)
+#[async_trait::async_trait]
+pub trait TotpDeviceEntityExt {
+    /// Find the TOTP device of a user, confirmed or not
+    async fn find_for_user(
+        db: &DatabaseConnection,
+        user_id: i32,
+    ) -> Result<Option<Model>, AuthError>;
+
+    /// Store a new, unconfirmed secret for a user, replacing an earlier
+    /// unconfirmed one. Fails if the user already has a confirmed device.
+    async fn begin_enrollment(
+        db: &DatabaseConnection,
+        user_id: i32,
+        secret_encrypted: String,
+    ) -> Result<Model, AuthError>;
+
+    /// Remove the device and every recovery code of a user
+    async fn disable_for_user(db: &DatabaseConnection, user_id: i32) -> Result<(), AuthError>;
+}
+
+// Trait for Model-level operations (instance methods)
+#[async_trait::async_trait]
+pub trait TotpDeviceModelExt {
+    fn is_confirmed(&self) -> bool;
+
+    /// Mark enrollment as finished after the user proved they have the secret
+    async fn confirm(&self, db: &DatabaseConnection) -> Result<Model, AuthError>;
+
+    /// Record that the code of `step` was used, returning false if that step
+    /// or a later one was already used so a code cannot be replayed
+    async fn record_use(&self, db: &DatabaseConnection, step: i64) -> Result<bool, AuthError>;
+}
+
+// Trait for recovery code operations (static methods)
+#[async_trait::async_trait]
+pub trait RecoveryCodeEntityExt {
+    /// Replace every recovery code of a user with new hashes
+    async fn replace_for_user(
+        db: &DatabaseConnection,
+        user_id: i32,
+        code_hashes: Vec<String>,
+    ) -> Result<(), AuthError>;
+
+    /// Use up an unused recovery code, returning false if there is none
+    async fn consume(
+        db: &DatabaseConnection,
+        user_id: i32,
+        code_hash: &str,
+    ) -> Result<bool, AuthError>;
+
+    /// Number of unused recovery codes of a user
+    async fn count_remaining(db: &DatabaseConnection, user_id: i32) -> Result<u64, AuthError>;
+}
+
+#[async_trait::async_trait]
+impl TotpDeviceEntityExt for AuthUserTotp {
+    async fn find_for_user(
+        db: &DatabaseConnection,
+        user_id: i32,
+    ) -> Result<Option<Model>, AuthError> {
+        Ok(AuthUserTotp::find()
+            .filter(auth_user_totp::Column::UserId.eq(user_id))
+            .one(db)
+            .await?)
+    }
+
+    async fn begin_enrollment(
+        db: &DatabaseConnection,
+        user_id: i32,
+        secret_encrypted: String,
+    ) -> Result<Model, AuthError> {
+        if Self::find_for_user(db, user_id)
+            .await?
+            .is_some_and(|device| device.is_confirmed())
+        {
+            return Err(AuthError::MfaAlreadyEnabled);
+        }
+
+        let device = ActiveModel {
+            user_id: Set(user_id),
+            secret_encrypted: Set(secret_encrypted),
+            confirmed_at: Set(None),
+            last_used_step: Set(None),
+            created_at: Set(chrono::Utc::now().naive_utc()),
+            ..Default::default()
+        };
+
+        // Only an unconfirmed device is replaced, even if one was confirmed
+        // since the check above
+        AuthUserTotp::insert(device)
+            .on_conflict(
+                OnConflict::column(auth_user_totp::Column::UserId)
+                    .update_columns([
+                        auth_user_totp::Column::SecretEncrypted,
+                        auth_user_totp::Column::LastUsedStep,
+                        auth_user_totp::Column::CreatedAt,
+                    ])
+                    .action_and_where(auth_user_totp::Column::ConfirmedAt.is_null())
+                    .to_owned(),
+            )
+            .exec_without_returning(db)
+            .await?;
+
+        let device = Self::find_for_user(db, user_id)
+            .await?
+            .ok_or_else(|| AuthError::DatabaseError("TOTP device vanished".to_string()))?;
+        if device.is_confirmed() {
+            return Err(AuthError::MfaAlreadyEnabled);
+        }
+        Ok(device)
+    }
+
+    async fn disable_for_user(db: &DatabaseConnection, user_id: i32) -> Result<(), AuthError> {
+        let txn = db.begin().await?;
+        AuthUserTotp::delete_many()
+            .filter(auth_user_totp::Column::UserId.eq(user_id))
+            .exec(&txn)
+            .await?;
+        AuthUserRecoveryCodes::delete_many()
+            .filter(auth_user_recovery_codes::Column::UserId.eq(user_id))
+            .exec(&txn)
+            .await?;
+        txn.commit().await?;
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl TotpDeviceModelExt for Model {
+    fn is_confirmed(&self) -> bool {
+        self.confirmed_at.is_some()
+    }
+
+    async fn confirm(&self, db: &DatabaseConnection) -> Result<Model, AuthError> {
+        let mut active_model: ActiveModel = self.clone().into();
+        active_model.confirmed_at = Set(Some(chrono::Utc::now().naive_utc()));
+        Ok(active_model.update(db).await?)
+    }
+
+    async fn record_use(&self, db: &DatabaseConnection, step: i64) -> Result<bool, AuthError> {
+        // Conditional update so two requests with the same code cannot both pass
+        let result = AuthUserTotp::update_many()
+            .col_expr(auth_user_totp::Column::LastUsedStep, Expr::value(step))
+            .filter(auth_user_totp::Column::Id.eq(self.id))
+            .filter(
+                Condition::any()
+                    .add(auth_user_totp::Column::LastUsedStep.is_null())
+                    .add(auth_user_totp::Column::LastUsedStep.lt(step)),
+            )
+            .exec(db)
+            .await?;
+
+        Ok(result.rows_affected == 1)
+    }
+}
+
+#[async_trait::async_trait]
+impl RecoveryCodeEntityExt for AuthUserRecoveryCodes {
+    async fn replace_for_user(
+        db: &DatabaseConnection,
+        user_id: i32,
+        code_hashes: Vec<String>,
+    ) -> Result<(), AuthError> {
+        let now = chrono::Utc::now().naive_utc();
+        let txn = db.begin().await?;
+
+        AuthUserRecoveryCodes::delete_many()
+            .filter(auth_user_recovery_codes::Column::UserId.eq(user_id))
+            .exec(&txn)
+            .await?;
+
+        if !code_hashes.is_empty() {
+            let codes = code_hashes
+                .into_iter()
+                .map(|code_hash| RecoveryCodeActiveModel {
+                    user_id: Set(user_id),
+                    code_hash: Set(code_hash),
+                    used_at: Set(None),
+                    created_at: Set(now),
+                    ..Default::default()
+                });
+            AuthUserRecoveryCodes::insert_many(codes)
+                .exec_without_returning(&txn)
+                .await?;
+        }
+
+        txn.commit().await?;
+        Ok(())
+    }
+
+    async fn consume(
+        db: &DatabaseConnection,
+        user_id: i32,
+        code_hash: &str,
+    ) -> Result<bool, AuthError> {
+        let result = AuthUserRecoveryCodes::update_many()
+            .col_expr(
+                auth_user_recovery_codes::Column::UsedAt,
+                Expr::value(chrono::Utc::now().naive_utc()),
+            )
+            .filter(auth_user_recovery_codes::Column::UserId.eq(user_id))
+            .filter(auth_user_recovery_codes::Column::CodeHash.eq(code_hash))
+            .filter(auth_user_recovery_codes::Column::UsedAt.is_null())
+            .exec(db)
+            .await?;
+
+        Ok(result.rows_affected == 1)
+    }
+
+    async fn count_remaining(db: &DatabaseConnection, user_id: i32) -> Result<u64, AuthError> {
+        Ok(AuthUserRecoveryCodes::find()
+            .filter(auth_user_recovery_codes::Column::UserId.eq(user_id))
+            .filter(auth_user_recovery_codes::Column::UsedAt.is_null())
+            .count(db)
+            .await?)
+    }
+}
@@ -0,0 +1,160 @@
+use crate::auth_group::{self, Entity as AuthGroup};
+use crate::auth_group_permissions;
+use crate::auth_permission::{self, Entity as AuthPermission};
+use crate::auth_users_ext::AuthError;
+use sea_orm::sea_query::OnConflict;
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set};
+
+// Trait for Entity-level operations (static methods)
+#[async_trait::async_trait]
+pub trait PermissionEntityExt {
+    /// Find a permission by codename, e.g. `auth.view_user`
+    async fn find_by_codename(
+        db: &DatabaseConnection,
+        codename: &str,
+    ) -> Result<Option<auth_permission::Model>, AuthError>;
+
+    /// Fetch a permission by codename, creating it when missing
+    async fn get_or_create(
+        db: &DatabaseConnection,
+        codename: &str,
+        name: &str,
+    ) -> Result<auth_permission::Model, AuthError>;
+}
+
+// Trait for Entity-level operations (static methods)
+#[async_trait::async_trait]
+pub trait GroupEntityExt {
+    /// Find a group by name
+    async fn find_by_name(
+        db: &DatabaseConnection,
+        name: &str,
+    ) -> Result<Option<auth_group::Model>, AuthError>;
+
+    /// Fetch a group by name, creating it when missing
+    async fn get_or_create(
+        db: &DatabaseConnection,
+        name: &str,
+    ) -> Result<auth_group::Model, AuthError>;
+}
+
+// Trait for Model-level operations (instance methods)
+#[async_trait::async_trait]
+pub trait GroupModelExt {
+    /// Grant a permission to every member of the group
+    async fn add_permission(
+        &self,
+        db: &DatabaseConnection,
+        permission_id: i32,
+    ) -> Result<(), AuthError>;
+
+    /// Remove a permission from the group
+    async fn remove_permission(
+        &self,
+        db: &DatabaseConnection,
+        permission_id: i32,
+    ) -> Result<(), AuthError>;
+}
+
+#[async_trait::async_trait]
+impl PermissionEntityExt for AuthPermission {
+    async fn find_by_codename(
+        db: &DatabaseConnection,
+        codename: &str,
+    ) -> Result<Option<auth_permission::Model>, AuthError> {
+        Ok(AuthPermission::find()
+            .filter(auth_permission::Column::Codename.eq(codename))
+            .one(db)
+            .await?)
+    }
+
+    async fn get_or_create(
+        db: &DatabaseConnection,
+        codename: &str,
+        name: &str,
+    ) -> Result<auth_permission::Model, AuthError> {
+        if let Some(permission) = Self::find_by_codename(db, codename).await? {
+            return Ok(permission);
+        }
+
+        let new_permission = auth_permission::ActiveModel {
+            codename: Set(codename.to_string()),
+            name: Set(name.to_string()),
+            ..Default::default()
+        };
+
+        Ok(new_permission.insert(db).await?)
+    }
+}
+
+#[async_trait::async_trait]
+impl GroupEntityExt for AuthGroup {
+    async fn find_by_name(
+        db: &DatabaseConnection,
+        name: &str,
+    ) -> Result<Option<auth_group::Model>, AuthError> {
+        Ok(AuthGroup::find()
+            .filter(auth_group::Column::Name.eq(name))
+            .one(db)
+            .await?)
+    }
+
+    async fn get_or_create(
+        db: &DatabaseConnection,
+        name: &str,
+    ) -> Result<auth_group::Model, AuthError> {
+        if let Some(group) = Self::find_by_name(db, name).await? {
+            return Ok(group);
+        }
+
+        let new_group = auth_group::ActiveModel {
+            name: Set(name.to_string()),
+            ..Default::default()
+        };
+
+        Ok(new_group.insert(db).await?)
+    }
+}
+
+#[async_trait::async_trait]
+impl GroupModelExt for auth_group::Model {
+    async fn add_permission(
+        &self,
+        db: &DatabaseConnection,
+        permission_id: i32,
+    ) -> Result<(), AuthError> {
+        let link = auth_group_permissions::ActiveModel {
+            group_id: Set(self.id),
+            permission_id: Set(permission_id),
+            ..Default::default()
+        };
+
+        auth_group_permissions::Entity::insert(link)
+            .on_conflict(
+                OnConflict::columns([
+                    auth_group_permissions::Column::GroupId,
+                    auth_group_permissions::Column::PermissionId,
+                ])
+                .do_nothing()
+                .to_owned(),
+            )
+            .exec_without_returning(db)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn remove_permission(
+        &self,
+        db: &DatabaseConnection,
+        permission_id: i32,
+    ) -> Result<(), AuthError> {
+        auth_group_permissions::Entity::delete_many()
+            .filter(auth_group_permissions::Column::GroupId.eq(self.id))
+            .filter(auth_group_permissions::Column::PermissionId.eq(permission_id))
+            .exec(db)
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,156 @@
+use crate::auth_personal_access_tokens::{
+    self, ActiveModel, Entity as AuthPersonalAccessTokens, Model,
+};
+use crate::auth_users_ext::AuthError;
+use sea_orm::prelude::DateTime;
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, Condition, DatabaseConnection, EntityTrait, QueryFilter,
+    QueryOrder, Set,
+};
+
+/// `last_used_at` is only written when older than this, so busy tokens do
+/// not cost an update per request
+const LAST_USED_RESOLUTION_SECS: i64 = 60;
+
+pub struct NewPersonalAccessToken {
+    pub user_id: i32,
+    pub name: String,
+    /// Start of the token, kept in clear text so users can tell tokens apart
+    pub token_prefix: String,
+    pub token_hash: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<DateTime>,
+}
+
+// Trait for Entity-level operations (static methods)
+#[async_trait::async_trait]
+pub trait PersonalAccessTokenEntityExt {
+    /// Store a new token hash for a user
+    async fn issue(
+        db: &DatabaseConnection,
+        token: NewPersonalAccessToken,
+    ) -> Result<Model, AuthError>;
+
+    /// Find an unexpired token by its hash
+    async fn find_active_by_hash(
+        db: &DatabaseConnection,
+        token_hash: &str,
+    ) -> Result<Option<Model>, AuthError>;
+
+    /// Tokens of a user, newest first
+    async fn list_for_user(db: &DatabaseConnection, user_id: i32) -> Result<Vec<Model>, AuthError>;
+
+    /// Delete a token of a user, returning false if the user has no such token
+    async fn revoke(db: &DatabaseConnection, user_id: i32, id: i32) -> Result<bool, AuthError>;
+}
+
+// Trait for Model-level operations (instance methods)
+#[async_trait::async_trait]
+pub trait PersonalAccessTokenModelExt {
+    fn is_expired(&self) -> bool;
+
+    /// Scopes granted to the token
+    fn scope_list(&self) -> Vec<String>;
+
+    fn has_scope(&self, scope: &str) -> bool;
+
+    /// Record that the token was just used
+    async fn touch(&self, db: &DatabaseConnection) -> Result<(), AuthError>;
+}
+
+#[async_trait::async_trait]
+impl PersonalAccessTokenEntityExt for AuthPersonalAccessTokens {
+    async fn issue(
+        db: &DatabaseConnection,
+        token: NewPersonalAccessToken,
+    ) -> Result<Model, AuthError> {
+        let new_token = ActiveModel {
+            user_id: Set(token.user_id),
+            name: Set(token.name),
+            token_prefix: Set(token.token_prefix),
+            token_hash: Set(token.token_hash),
+            scopes: Set(token.scopes.join(" ")),
+            expires_at: Set(token.expires_at),
+            last_used_at: Set(None),
+            created_at: Set(chrono::Utc::now().naive_utc()),
+            ..Default::default()
+        };
+
+        Ok(new_token.insert(db).await?)
+    }
+
+    async fn find_active_by_hash(
+        db: &DatabaseConnection,
+        token_hash: &str,
+    ) -> Result<Option<Model>, AuthError> {
+        Ok(AuthPersonalAccessTokens::find()
+            .filter(auth_personal_access_tokens::Column::TokenHash.eq(token_hash))
+            .one(db)
+            .await?
+            .filter(|token| !token.is_expired()))
+    }
+
+    async fn list_for_user(db: &DatabaseConnection, user_id: i32) -> Result<Vec<Model>, AuthError> {
+        Ok(AuthPersonalAccessTokens::find()
+            .filter(auth_personal_access_tokens::Column::UserId.eq(user_id))
+            .order_by_desc(auth_personal_access_tokens::Column::CreatedAt)
+            .all(db)
+            .await?)
+    }
+
+    async fn revoke(db: &DatabaseConnection, user_id: i32, id: i32) -> Result<bool, AuthError> {
+        let result = AuthPersonalAccessTokens::delete_many()
+            .filter(auth_personal_access_tokens::Column::Id.eq(id))
+            .filter(auth_personal_access_tokens::Column::UserId.eq(user_id))
+            .exec(db)
+            .await?;
+
+        Ok(result.rows_affected == 1)
+    }
+}
+
+#[async_trait::async_trait]
+impl PersonalAccessTokenModelExt for Model {
+    fn is_expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at <= chrono::Utc::now().naive_utc())
+    }
+
+    fn scope_list(&self) -> Vec<String> {
+        self.scopes.split_whitespace().map(str::to_string).collect()
+    }
+
+    fn has_scope(&self, scope: &str) -> bool {
+        self.scopes
+            .split_whitespace()
+            .any(|granted| granted == scope)
+    }
+
+    async fn touch(&self, db: &DatabaseConnection) -> Result<(), AuthError> {
+        let now = chrono::Utc::now().naive_utc();
+        let stale_before = now - chrono::Duration::seconds(LAST_USED_RESOLUTION_SECS);
+        if self
+            .last_used_at
+            .is_some_and(|last_used_at| last_used_at >= stale_before)
+        {
+            return Ok(());
+        }
+
+        AuthPersonalAccessTokens::update_many()
+            .col_expr(
+                auth_personal_access_tokens::Column::LastUsedAt,
+                Expr::value(now),
+            )
+            .filter(auth_personal_access_tokens::Column::Id.eq(self.id))
+            .filter(
+                Condition::any()
+                    .add(auth_personal_access_tokens::Column::LastUsedAt.is_null())
+                    .add(auth_personal_access_tokens::Column::LastUsedAt.lt(stale_before)),
+            )
+            .exec(db)
+            .await?;
+
+        Ok(())
+    }
+}
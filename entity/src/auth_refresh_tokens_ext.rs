@@ -0,0 +1,121 @@
+use crate::auth_refresh_tokens::{self, ActiveModel, Entity as AuthRefreshTokens, Model};
+use crate::auth_users_ext::AuthError;
+use sea_orm::prelude::DateTime;
+use sea_orm::sea_query::Expr;
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set};
+
+// Trait for Entity-level operations (static methods)
+#[async_trait::async_trait]
+pub trait RefreshTokenEntityExt {
+    /// Store a new refresh token hash for a user's session
+    async fn issue(
+        db: &DatabaseConnection,
+        user_id: i32,
+        session_id: i32,
+        token_hash: String,
+        expires_at: DateTime,
+    ) -> Result<Model, AuthError>;
+
+    /// Find a refresh token by its hash
+    async fn find_by_hash(
+        db: &DatabaseConnection,
+        token_hash: &str,
+    ) -> Result<Option<Model>, AuthError>;
+
+    /// Revoke every active refresh token of a user
+    async fn revoke_all_for_user(db: &DatabaseConnection, user_id: i32) -> Result<u64, AuthError>;
+}
+
+// Trait for Model-level operations (instance methods)
+#[async_trait::async_trait]
+pub trait RefreshTokenModelExt {
+    /// Check whether the token is neither revoked nor expired
+    fn is_active(&self) -> bool;
+
+    /// Revoke the token, returning false if it was already revoked
+    async fn revoke(&self, db: &DatabaseConnection) -> Result<bool, AuthError>;
+
+    /// Revoke the token because a new one replaces it, returning false if
+    /// it was already revoked
+    async fn rotate(&self, db: &DatabaseConnection) -> Result<bool, AuthError>;
+}
+
+#[async_trait::async_trait]
+impl RefreshTokenEntityExt for AuthRefreshTokens {
+    async fn issue(
+        db: &DatabaseConnection,
+        user_id: i32,
+        session_id: i32,
+        token_hash: String,
+        expires_at: DateTime,
+    ) -> Result<Model, AuthError> {
+        let new_token = ActiveModel {
+            user_id: Set(user_id),
+            session_id: Set(Some(session_id)),
+            token_hash: Set(token_hash),
+            expires_at: Set(expires_at),
+            revoked_at: Set(None),
+            rotated: Set(false),
+            created_at: Set(chrono::Utc::now().naive_utc()),
+            ..Default::default()
+        };
+
+        Ok(new_token.insert(db).await?)
+    }
+
+    async fn find_by_hash(
+        db: &DatabaseConnection,
+        token_hash: &str,
+    ) -> Result<Option<Model>, AuthError> {
+        Ok(AuthRefreshTokens::find()
+            .filter(auth_refresh_tokens::Column::TokenHash.eq(token_hash))
+            .one(db)
+            .await?)
+    }
+
+    async fn revoke_all_for_user(db: &DatabaseConnection, user_id: i32) -> Result<u64, AuthError> {
+        let result = AuthRefreshTokens::update_many()
+            .col_expr(
+                auth_refresh_tokens::Column::RevokedAt,
+                Expr::value(chrono::Utc::now().naive_utc()),
+            )
+            .filter(auth_refresh_tokens::Column::UserId.eq(user_id))
+            .filter(auth_refresh_tokens::Column::RevokedAt.is_null())
+            .exec(db)
+            .await?;
+
+        Ok(result.rows_affected)
+    }
+}
+
+#[async_trait::async_trait]
+impl RefreshTokenModelExt for Model {
+    fn is_active(&self) -> bool {
+        self.revoked_at.is_none() && self.expires_at > chrono::Utc::now().naive_utc()
+    }
+
+    async fn revoke(&self, db: &DatabaseConnection) -> Result<bool, AuthError> {
+        revoke(db, self.id, false).await
+    }
+
+    async fn rotate(&self, db: &DatabaseConnection) -> Result<bool, AuthError> {
+        revoke(db, self.id, true).await
+    }
+}
+
+async fn revoke(db: &DatabaseConnection, id: i32, rotated: bool) -> Result<bool, AuthError> {
+    // Conditional update so that two concurrent rotations of the same
+    // token cannot both succeed
+    let result = AuthRefreshTokens::update_many()
+        .col_expr(
+            auth_refresh_tokens::Column::RevokedAt,
+            Expr::value(chrono::Utc::now().naive_utc()),
+        )
+        .col_expr(auth_refresh_tokens::Column::Rotated, Expr::value(rotated))
+        .filter(auth_refresh_tokens::Column::Id.eq(id))
+        .filter(auth_refresh_tokens::Column::RevokedAt.is_null())
+        .exec(db)
+        .await?;
+
+    Ok(result.rows_affected == 1)
+}
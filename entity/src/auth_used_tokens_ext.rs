@@ -0,0 +1,62 @@
+use crate::auth_used_tokens::{self, ActiveModel, Entity as AuthUsedTokens};
+use crate::auth_users_ext::AuthError;
+use sea_orm::prelude::DateTime;
+use sea_orm::sea_query::OnConflict;
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set};
+
+// Trait for used token operations (static methods)
+#[async_trait::async_trait]
+pub trait UsedTokenEntityExt {
+    /// Record the token `jti` as used until `expires_at`, returning false
+    /// if it already was so that a token is accepted at most once
+    async fn consume(
+        db: &DatabaseConnection,
+        jti: &str,
+        expires_at: DateTime,
+    ) -> Result<bool, AuthError>;
+
+    /// Whether the token `jti` was used already
+    async fn is_used(db: &DatabaseConnection, jti: &str) -> Result<bool, AuthError>;
+
+    /// Forget tokens past their expiry, which are refused anyway
+    async fn clear_expired(db: &DatabaseConnection) -> Result<u64, AuthError>;
+}
+
+#[async_trait::async_trait]
+impl UsedTokenEntityExt for AuthUsedTokens {
+    async fn consume(
+        db: &DatabaseConnection,
+        jti: &str,
+        expires_at: DateTime,
+    ) -> Result<bool, AuthError> {
+        let used = ActiveModel {
+            jti: Set(jti.to_string()),
+            expires_at: Set(expires_at),
+        };
+
+        // The primary key decides between concurrent attempts
+        let inserted = AuthUsedTokens::insert(used)
+            .on_conflict(
+                OnConflict::column(auth_used_tokens::Column::Jti)
+                    .do_nothing()
+                    .to_owned(),
+            )
+            .exec_without_returning(db)
+            .await?;
+
+        Ok(inserted == 1)
+    }
+
+    async fn is_used(db: &DatabaseConnection, jti: &str) -> Result<bool, AuthError> {
+        Ok(AuthUsedTokens::find_by_id(jti).one(db).await?.is_some())
+    }
+
+    async fn clear_expired(db: &DatabaseConnection) -> Result<u64, AuthError> {
+        let result = AuthUsedTokens::delete_many()
+            .filter(auth_used_tokens::Column::ExpiresAt.lt(chrono::Utc::now().naive_utc()))
+            .exec(db)
+            .await?;
+
+        Ok(result.rows_affected)
+    }
+}
@@ -0,0 +1,237 @@
+use crate::auth_user_sessions::{self, ActiveModel, Entity as AuthUserSessions, Model};
+use crate::auth_users_ext::AuthError;
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder,
+    QuerySelect, Set,
+};
+use std::fmt;
+use std::str::FromStr;
+
+/// `last_seen_at` is only written when older than this, so active devices
+/// do not cost an update per request
+const LAST_SEEN_RESOLUTION_SECS: i64 = 60;
+
+/// How a logged-in device authenticates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionKind {
+    /// Access and refresh tokens; the session outlives refresh rotations
+    Token,
+    /// A cookie session in `django_session`
+    Cookie,
+}
+
+impl SessionKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SessionKind::Token => "token",
+            SessionKind::Cookie => "cookie",
+        }
+    }
+}
+
+impl fmt::Display for SessionKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for SessionKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "token" => Ok(SessionKind::Token),
+            "cookie" => Ok(SessionKind::Cookie),
+            other => Err(format!("Unknown session kind: {}", other)),
+        }
+    }
+}
+
+pub struct NewUserSession {
+    pub user_id: i32,
+    pub kind: SessionKind,
+    /// Readable device name, e.g. `Firefox on Linux`
+    pub label: String,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+}
+
+// Trait for Entity-level operations (static methods)
+#[async_trait::async_trait]
+pub trait UserSessionEntityExt {
+    /// Record a login. When the user then has more than `max_per_user`
+    /// sessions, the oldest ones are ended.
+    async fn start(
+        db: &DatabaseConnection,
+        session: NewUserSession,
+        max_per_user: Option<usize>,
+    ) -> Result<Model, AuthError>;
+
+    /// Find a session of a user
+    async fn find_for_user(
+        db: &DatabaseConnection,
+        user_id: i32,
+        id: i32,
+    ) -> Result<Option<Model>, AuthError>;
+
+    /// Sessions of a user, most recently seen first
+    async fn list_for_user(db: &DatabaseConnection, user_id: i32) -> Result<Vec<Model>, AuthError>;
+
+    /// End a session of a user and delete its refresh tokens, returning
+    /// false if the user has no such session
+    async fn end(db: &DatabaseConnection, user_id: i32, id: i32) -> Result<bool, AuthError>;
+
+    /// End every session of a user, except `keep` when given
+    async fn end_all(
+        db: &DatabaseConnection,
+        user_id: i32,
+        keep: Option<i32>,
+    ) -> Result<u64, AuthError>;
+}
+
+// Trait for Model-level operations (instance methods)
+#[async_trait::async_trait]
+pub trait UserSessionModelExt {
+    fn session_kind(&self) -> Option<SessionKind>;
+
+    /// Record that the device was just seen, from `ip_address` when known
+    async fn touch(
+        &self,
+        db: &DatabaseConnection,
+        ip_address: Option<&str>,
+    ) -> Result<(), AuthError>;
+}
+
+#[async_trait::async_trait]
+impl UserSessionEntityExt for AuthUserSessions {
+    async fn start(
+        db: &DatabaseConnection,
+        session: NewUserSession,
+        max_per_user: Option<usize>,
+    ) -> Result<Model, AuthError> {
+        let now = chrono::Utc::now().naive_utc();
+        let new_session = ActiveModel {
+            user_id: Set(session.user_id),
+            kind: Set(session.kind.as_str().to_string()),
+            label: Set(session.label),
+            user_agent: Set(session.user_agent),
+            ip_address: Set(session.ip_address),
+            created_at: Set(now),
+            last_seen_at: Set(now),
+            ..Default::default()
+        };
+        let created = new_session.insert(db).await?;
+
+        if let Some(max_per_user) = max_per_user {
+            let evicted: Vec<i32> = AuthUserSessions::find()
+                .select_only()
+                .column(auth_user_sessions::Column::Id)
+                .filter(auth_user_sessions::Column::UserId.eq(session.user_id))
+                .order_by_desc(auth_user_sessions::Column::CreatedAt)
+                .order_by_desc(auth_user_sessions::Column::Id)
+                .offset(max_per_user.max(1) as u64)
+                .into_tuple()
+                .all(db)
+                .await?;
+
+            if !evicted.is_empty() {
+                AuthUserSessions::delete_many()
+                    .filter(auth_user_sessions::Column::Id.is_in(evicted))
+                    .exec(db)
+                    .await?;
+            }
+        }
+
+        Ok(created)
+    }
+
+    async fn find_for_user(
+        db: &DatabaseConnection,
+        user_id: i32,
+        id: i32,
+    ) -> Result<Option<Model>, AuthError> {
+        Ok(AuthUserSessions::find_by_id(id)
+            .filter(auth_user_sessions::Column::UserId.eq(user_id))
+            .one(db)
+            .await?)
+    }
+
+    async fn list_for_user(db: &DatabaseConnection, user_id: i32) -> Result<Vec<Model>, AuthError> {
+        Ok(AuthUserSessions::find()
+            .filter(auth_user_sessions::Column::UserId.eq(user_id))
+            .order_by_desc(auth_user_sessions::Column::LastSeenAt)
+            .all(db)
+            .await?)
+    }
+
+    async fn end(db: &DatabaseConnection, user_id: i32, id: i32) -> Result<bool, AuthError> {
+        let result = AuthUserSessions::delete_many()
+            .filter(auth_user_sessions::Column::Id.eq(id))
+            .filter(auth_user_sessions::Column::UserId.eq(user_id))
+            .exec(db)
+            .await?;
+
+        Ok(result.rows_affected == 1)
+    }
+
+    async fn end_all(
+        db: &DatabaseConnection,
+        user_id: i32,
+        keep: Option<i32>,
+    ) -> Result<u64, AuthError> {
+        let mut query =
+            AuthUserSessions::delete_many().filter(auth_user_sessions::Column::UserId.eq(user_id));
+        if let Some(keep) = keep {
+            query = query.filter(auth_user_sessions::Column::Id.ne(keep));
+        }
+
+        Ok(query.exec(db).await?.rows_affected)
+    }
+}
+
+#[async_trait::async_trait]
+impl UserSessionModelExt for Model {
+    fn session_kind(&self) -> Option<SessionKind> {
+        self.kind.parse().ok()
+    }
+
+    async fn touch(
+        &self,
+        db: &DatabaseConnection,
+        ip_address: Option<&str>,
+    ) -> Result<(), AuthError> {
+        let now = chrono::Utc::now().naive_utc();
+        let stale_before = now - chrono::Duration::seconds(LAST_SEEN_RESOLUTION_SECS);
+        let moved = ip_address.is_some_and(|ip| self.ip_address.as_deref() != Some(ip));
+        if self.last_seen_at >= stale_before && !moved {
+            return Ok(());
+        }
+
+        let mut update = AuthUserSessions::update_many()
+            .col_expr(auth_user_sessions::Column::LastSeenAt, Expr::value(now))
+            .filter(auth_user_sessions::Column::Id.eq(self.id));
+        if let Some(ip_address) = ip_address {
+            update = update.col_expr(
+                auth_user_sessions::Column::IpAddress,
+                Expr::value(ip_address),
+            );
+        }
+        update.exec(db).await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_kind_round_trip() {
+        for kind in [SessionKind::Token, SessionKind::Cookie] {
+            assert_eq!(kind.as_str().parse::<SessionKind>(), Ok(kind));
+        }
+        assert!("bearer".parse::<SessionKind>().is_err());
+    }
+}
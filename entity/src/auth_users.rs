@@ -24,7 +24,134 @@ pub struct Model {
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
-pub enum Relation {}
+pub enum Relation {
+    #[sea_orm(has_many = "super::auth_magic_links::Entity")]
+    AuthMagicLinks,
+    #[sea_orm(has_many = "super::auth_personal_access_tokens::Entity")]
+    AuthPersonalAccessTokens,
+    #[sea_orm(has_many = "super::auth_refresh_tokens::Entity")]
+    AuthRefreshTokens,
+    #[sea_orm(has_many = "super::auth_user_groups::Entity")]
+    AuthUserGroups,
+    #[sea_orm(has_many = "super::auth_user_user_permissions::Entity")]
+    AuthUserUserPermissions,
+    #[sea_orm(has_one = "super::auth_user_totp::Entity")]
+    AuthUserTotp,
+    #[sea_orm(has_many = "super::auth_user_recovery_codes::Entity")]
+    AuthUserRecoveryCodes,
+    #[sea_orm(has_many = "super::auth_user_sessions::Entity")]
+    AuthUserSessions,
+    #[sea_orm(has_many = "super::oauth_authorization_codes::Entity")]
+    OauthAuthorizationCodes,
+    #[sea_orm(has_many = "super::oauth_consents::Entity")]
+    OauthConsents,
+    #[sea_orm(has_many = "super::oauth_tokens::Entity")]
+    OauthTokens,
+    #[sea_orm(has_many = "super::user_identities::Entity")]
+    UserIdentities,
+    #[sea_orm(has_many = "super::webauthn_credentials::Entity")]
+    WebauthnCredentials,
+}
+
+impl Related<super::auth_magic_links::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::AuthMagicLinks.def()
+    }
+}
+
+impl Related<super::auth_personal_access_tokens::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::AuthPersonalAccessTokens.def()
+    }
+}
+
+impl Related<super::auth_refresh_tokens::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::AuthRefreshTokens.def()
+    }
+}
+
+impl Related<super::auth_user_groups::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::AuthUserGroups.def()
+    }
+}
+
+impl Related<super::auth_user_user_permissions::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::AuthUserUserPermissions.def()
+    }
+}
+
+impl Related<super::auth_user_totp::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::AuthUserTotp.def()
+    }
+}
+
+impl Related<super::auth_user_recovery_codes::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::AuthUserRecoveryCodes.def()
+    }
+}
+
+impl Related<super::auth_user_sessions::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::AuthUserSessions.def()
+    }
+}
+
+impl Related<super::oauth_authorization_codes::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::OauthAuthorizationCodes.def()
+    }
+}
+
+impl Related<super::oauth_consents::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::OauthConsents.def()
+    }
+}
+
+impl Related<super::oauth_tokens::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::OauthTokens.def()
+    }
+}
+
+impl Related<super::user_identities::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::UserIdentities.def()
+    }
+}
+
+impl Related<super::webauthn_credentials::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::WebauthnCredentials.def()
+    }
+}
+
+impl Related<super::auth_group::Entity> for Entity {
+    fn to() -> RelationDef {
+        super::auth_user_groups::Relation::AuthGroup.def()
+    }
+    fn via() -> Option<RelationDef> {
+        Some(super::auth_user_groups::Relation::AuthUsers.def().rev())
+    }
+}
+
+impl Related<super::auth_permission::Entity> for Entity {
+    fn to() -> RelationDef {
+        super::auth_user_user_permissions::Relation::AuthPermission.def()
+    }
+    fn via() -> Option<RelationDef> {
+        Some(
+            super::auth_user_user_permissions::Relation::AuthUsers
+                .def()
+                .rev(),
+        )
+    }
+}
 
 impl ActiveModelBehavior for ActiveModel {}
 
@@ -37,4 +164,26 @@ impl Model {
             (None, None) => self.username.clone(),
         }
     }
+
+    /// An active, verified user without a usable password, for tests to
+    /// adjust with struct update syntax
+    #[cfg(any(test, feature = "test-fixtures"))]
+    pub fn fixture() -> Self {
+        let now = chrono::Utc::now().naive_utc();
+        Self {
+            id: 42,
+            email: "ada@example.com".to_string(),
+            username: "ada".to_string(),
+            password: String::new(),
+            first_name: None,
+            last_name: None,
+            is_active: true,
+            is_verified: true,
+            is_superuser: false,
+            is_staff: false,
+            last_login: now,
+            created_at: now,
+            updated_at: now,
+        }
+    }
 }
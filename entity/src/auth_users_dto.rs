@@ -0,0 +1,241 @@
+//! Response shapes of `auth_users` rows.
+//!
+//! `auth_users::Model` holds the password hash and deliberately derives no
+//! `Serialize`; handlers return one of these instead. Each conversion
+//! destructures the whole model, so a column added later does not reach a
+//! response until someone decides which of them may show it.
+
+use crate::auth_users::Model;
+use apistos::ApiComponent;
+use schemars::JsonSchema;
+use sea_orm::prelude::DateTime;
+use serde::{Deserialize, Serialize};
+
+/// What anyone may see of another user
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema, ApiComponent)]
+pub struct UserPublic {
+    pub id: i32,
+    pub username: String,
+    /// First and last name, or the username when neither is set
+    pub full_name: String,
+}
+
+/// The logged in user's own account
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema, ApiComponent)]
+pub struct UserSelf {
+    pub id: i32,
+    pub email: String,
+    pub username: String,
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    /// First and last name, or the username when neither is set
+    pub full_name: String,
+    pub is_verified: bool,
+    pub is_staff: bool,
+    pub is_superuser: bool,
+    pub last_login: DateTime,
+    pub created_at: DateTime,
+}
+
+/// A user as staff manage it
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema, ApiComponent)]
+pub struct UserAdmin {
+    pub id: i32,
+    pub email: String,
+    pub username: String,
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    /// First and last name, or the username when neither is set
+    pub full_name: String,
+    pub is_active: bool,
+    pub is_verified: bool,
+    pub is_staff: bool,
+    pub is_superuser: bool,
+    /// Whether the user has a usable password, rather than logging in only
+    /// by other means such as single sign-on
+    pub has_usable_password: bool,
+    pub last_login: DateTime,
+    pub created_at: DateTime,
+    pub updated_at: DateTime,
+}
+
+impl From<&Model> for UserPublic {
+    fn from(user: &Model) -> Self {
+        let Model {
+            id,
+            email: _,
+            username,
+            password: _,
+            first_name: _,
+            last_name: _,
+            is_active: _,
+            is_verified: _,
+            is_superuser: _,
+            is_staff: _,
+            last_login: _,
+            created_at: _,
+            updated_at: _,
+        } = user;
+        Self {
+            id: *id,
+            username: username.clone(),
+            full_name: user.full_name(),
+        }
+    }
+}
+
+impl From<&Model> for UserSelf {
+    fn from(user: &Model) -> Self {
+        let Model {
+            id,
+            email,
+            username,
+            password: _,
+            first_name,
+            last_name,
+            is_active: _,
+            is_verified,
+            is_superuser,
+            is_staff,
+            last_login,
+            created_at,
+            updated_at: _,
+        } = user;
+        Self {
+            id: *id,
+            email: email.clone(),
+            username: username.clone(),
+            first_name: first_name.clone(),
+            last_name: last_name.clone(),
+            full_name: user.full_name(),
+            is_verified: *is_verified,
+            is_staff: *is_staff,
+            is_superuser: *is_superuser,
+            last_login: *last_login,
+            created_at: *created_at,
+        }
+    }
+}
+
+impl From<&Model> for UserAdmin {
+    fn from(user: &Model) -> Self {
+        let Model {
+            id,
+            email,
+            username,
+            password,
+            first_name,
+            last_name,
+            is_active,
+            is_verified,
+            is_superuser,
+            is_staff,
+            last_login,
+            created_at,
+            updated_at,
+        } = user;
+        Self {
+            id: *id,
+            email: email.clone(),
+            username: username.clone(),
+            first_name: first_name.clone(),
+            last_name: last_name.clone(),
+            full_name: user.full_name(),
+            is_active: *is_active,
+            is_verified: *is_verified,
+            is_staff: *is_staff,
+            is_superuser: *is_superuser,
+            has_usable_password: security::password::is_password_usable(password),
+            last_login: *last_login,
+            created_at: *created_at,
+            updated_at: *updated_at,
+        }
+    }
+}
+
+macro_rules! from_owned {
+    ($($dto:ty),*) => {
+        $(impl From<Model> for $dto {
+            fn from(user: Model) -> Self {
+                Self::from(&user)
+            }
+        })*
+    };
+}
+
+from_owned!(UserPublic, UserSelf, UserAdmin);
+
+/// Fails to compile once `Model` implements `Serialize`: the blanket impls
+/// below then both apply and the call becomes ambiguous.
+const _: fn() = || {
+    trait AmbiguousIfSerialize<A> {
+        fn check() {}
+    }
+    impl<T: ?Sized> AmbiguousIfSerialize<()> for T {}
+    struct Serializable;
+    impl<T: ?Sized + Serialize> AmbiguousIfSerialize<Serializable> for T {}
+    <Model as AmbiguousIfSerialize<_>>::check();
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use schemars::schema_for;
+
+    fn user() -> Model {
+        Model {
+            id: 7,
+            password: "pbkdf2_sha256$600000$salt$hash".to_string(),
+            first_name: Some("Ada".to_string()),
+            last_name: Some("Lovelace".to_string()),
+            is_staff: true,
+            ..Model::fixture()
+        }
+    }
+
+    #[test]
+    fn test_dtos_never_contain_the_password() {
+        let user = user();
+        for json in [
+            serde_json::to_value(UserPublic::from(&user)).unwrap(),
+            serde_json::to_value(UserSelf::from(&user)).unwrap(),
+            serde_json::to_value(UserAdmin::from(&user)).unwrap(),
+        ] {
+            assert!(json.get("password").is_none());
+            assert!(!json.to_string().contains("pbkdf2"));
+        }
+        for schema in [
+            schema_for!(UserPublic),
+            schema_for!(UserSelf),
+            schema_for!(UserAdmin),
+        ] {
+            let properties = &schema.schema.object.as_ref().unwrap().properties;
+            assert!(!properties.contains_key("password"));
+        }
+    }
+
+    #[test]
+    fn test_conversions() {
+        let user = user();
+        assert_eq!(
+            UserPublic::from(&user),
+            UserPublic {
+                id: 7,
+                username: "ada".to_string(),
+                full_name: "Ada Lovelace".to_string(),
+            }
+        );
+        let admin = UserAdmin::from(user.clone());
+        assert!(admin.has_usable_password);
+        assert!(admin.is_staff);
+
+        let user = Model {
+            first_name: None,
+            last_name: None,
+            password: security::password::make_unusable_password(),
+            ..user
+        };
+        assert_eq!(UserSelf::from(&user).full_name, "ada");
+        assert!(!UserAdmin::from(&user).has_usable_password);
+    }
+}
@@ -1,6 +1,20 @@
+use crate::auth_login_failures_ext::LoginAttempt;
+use crate::auth_permission::{self, Entity as AuthPermission};
 use crate::auth_users::{self, ActiveModel, Entity as AuthUsers, Model};
-use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set};
-use security::password::{hash_password, verify_password};
+use crate::{auth_group_permissions, auth_user_groups, auth_user_user_permissions};
+use sea_orm::prelude::DateTime;
+use sea_orm::sea_query::{Expr, Func, LikeExpr, OnConflict};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, Condition, DatabaseConnection, EntityTrait, JoinType,
+    ModelTrait, Order, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, RelationTrait, Set,
+};
+use security::password::{
+    PoolError, UserAttributes, ValidationError, hash_password_async, is_password_usable,
+    make_unusable_password, needs_rehash, validate_password, verify_password,
+    verify_password_async,
+};
+use std::collections::HashSet;
+use std::time::Duration;
 
 #[derive(Debug)]
 pub enum AuthError {
@@ -10,6 +24,16 @@ pub enum AuthError {
     InactiveAccount,
     DatabaseError(String),
     HashingError(String),
+    /// Too many passwords are being hashed right now
+    HashingUnavailable,
+    /// The password breaks one or more of the configured validators
+    PasswordValidation(Vec<ValidationError>),
+    /// The user already finished two-factor enrollment
+    MfaAlreadyEnabled,
+    /// Login attempts are delayed or locked out after repeated failures
+    TooManyAttempts {
+        retry_after: Duration,
+    },
 }
 
 impl From<sea_orm::DbErr> for AuthError {
@@ -18,6 +42,15 @@ impl From<sea_orm::DbErr> for AuthError {
     }
 }
 
+impl From<PoolError> for AuthError {
+    fn from(err: PoolError) -> Self {
+        match err {
+            PoolError::QueueTimeout => AuthError::HashingUnavailable,
+            PoolError::Cancelled => AuthError::HashingError(err.to_string()),
+        }
+    }
+}
+
 pub struct CreateUserData {
     pub email: String,
     pub username: String,
@@ -30,6 +63,17 @@ pub struct CreateUserData {
     pub is_staff: bool,
 }
 
+impl CreateUserData {
+    fn attributes(&self) -> UserAttributes<'_> {
+        UserAttributes {
+            username: &self.username,
+            email: &self.email,
+            first_name: self.first_name.as_deref(),
+            last_name: self.last_name.as_deref(),
+        }
+    }
+}
+
 impl Default for CreateUserData {
     fn default() -> Self {
         Self {
@@ -46,6 +90,107 @@ impl Default for CreateUserData {
     }
 }
 
+/// Changes to a user's details; `None` leaves a field as it is
+#[derive(Debug, Default)]
+pub struct UpdateUserData {
+    pub email: Option<String>,
+    pub username: Option<String>,
+    /// `Some(None)` clears the name
+    pub first_name: Option<Option<String>>,
+    pub last_name: Option<Option<String>>,
+    pub is_staff: Option<bool>,
+    pub is_superuser: Option<bool>,
+}
+
+/// Which users a listing returns; unset fields match everyone
+#[derive(Debug, Default, Clone)]
+pub struct UserFilter {
+    pub is_active: Option<bool>,
+    pub is_staff: Option<bool>,
+    pub is_verified: Option<bool>,
+    pub created_after: Option<DateTime>,
+    pub created_before: Option<DateTime>,
+    pub last_login_after: Option<DateTime>,
+    pub last_login_before: Option<DateTime>,
+    /// Case-insensitive part of the email address or username
+    pub search: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum UserSortField {
+    #[default]
+    Id,
+    Email,
+    Username,
+    CreatedAt,
+    LastLogin,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct UserSort {
+    pub field: UserSortField,
+    pub descending: bool,
+}
+
+impl UserSortField {
+    fn column(self) -> auth_users::Column {
+        match self {
+            UserSortField::Id => auth_users::Column::Id,
+            UserSortField::Email => auth_users::Column::Email,
+            UserSortField::Username => auth_users::Column::Username,
+            UserSortField::CreatedAt => auth_users::Column::CreatedAt,
+            UserSortField::LastLogin => auth_users::Column::LastLogin,
+        }
+    }
+}
+
+/// `%term%` for a LIKE on lowercased columns, with wildcards in the term
+/// matched literally
+fn contains_pattern(term: &str) -> LikeExpr {
+    let escaped = term
+        .to_lowercase()
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    LikeExpr::new(format!("%{}%", escaped)).escape('\\')
+}
+
+impl UserFilter {
+    fn condition(&self) -> Condition {
+        let mut condition = Condition::all();
+        if let Some(is_active) = self.is_active {
+            condition = condition.add(auth_users::Column::IsActive.eq(is_active));
+        }
+        if let Some(is_staff) = self.is_staff {
+            condition = condition.add(auth_users::Column::IsStaff.eq(is_staff));
+        }
+        if let Some(is_verified) = self.is_verified {
+            condition = condition.add(auth_users::Column::IsVerified.eq(is_verified));
+        }
+        if let Some(after) = self.created_after {
+            condition = condition.add(auth_users::Column::CreatedAt.gte(after));
+        }
+        if let Some(before) = self.created_before {
+            condition = condition.add(auth_users::Column::CreatedAt.lt(before));
+        }
+        if let Some(after) = self.last_login_after {
+            condition = condition.add(auth_users::Column::LastLogin.gte(after));
+        }
+        if let Some(before) = self.last_login_before {
+            condition = condition.add(auth_users::Column::LastLogin.lt(before));
+        }
+        if let Some(term) = self.search.as_deref().filter(|term| !term.is_empty()) {
+            let lower = |column: auth_users::Column| Expr::expr(Func::lower(Expr::col(column)));
+            condition = condition.add(
+                Condition::any()
+                    .add(lower(auth_users::Column::Email).like(contains_pattern(term)))
+                    .add(lower(auth_users::Column::Username).like(contains_pattern(term))),
+            );
+        }
+        condition
+    }
+}
+
 // Trait for Entity-level operations (static methods)
 #[async_trait::async_trait]
 pub trait AuthUserEntityExt {
@@ -53,6 +198,14 @@ pub trait AuthUserEntityExt {
     async fn create_user(db: &DatabaseConnection, data: CreateUserData)
     -> Result<Model, AuthError>;
 
+    /// Create a user with an unusable password, like Django's
+    /// `create_user(password=None)`. `data.password` is ignored; the user
+    /// logs in by other means until a password is set.
+    async fn create_user_without_password(
+        db: &DatabaseConnection,
+        data: CreateUserData,
+    ) -> Result<Model, AuthError>;
+
     /// Create a new superuser
     async fn create_superuser(
         db: &DatabaseConnection,
@@ -61,13 +214,32 @@ pub trait AuthUserEntityExt {
         password: String,
     ) -> Result<Model, AuthError>;
 
-    /// Authenticate a user by username/email and password
+    /// Authenticate a user by username/email and password.
+    /// Outdated password hashes are transparently upgraded on success.
+    /// Failures are counted per account and per `client_ip`, see
+    /// [`LoginAttempt`](crate::LoginAttempt).
     async fn authenticate(
         db: &DatabaseConnection,
         username_or_email: &str,
         password: &str,
+        client_ip: Option<&str>,
     ) -> Result<Model, AuthError>;
 
+    /// Check a password without counting failures, like Django's
+    /// `ModelBackend`: `None` for unknown users and wrong passwords.
+    /// Outdated password hashes are upgraded on success.
+    async fn verify_credentials(
+        db: &DatabaseConnection,
+        username_or_email: &str,
+        password: &str,
+    ) -> Result<Option<Model>, AuthError>;
+
+    /// Find user by username or email, as typed into a login form
+    async fn find_by_login(
+        db: &DatabaseConnection,
+        username_or_email: &str,
+    ) -> Result<Option<Model>, AuthError>;
+
     /// Find user by email
     async fn find_by_email(
         db: &DatabaseConnection,
@@ -85,6 +257,16 @@ pub trait AuthUserEntityExt {
 
     /// Check if username exists
     async fn username_exists(db: &DatabaseConnection, username: &str) -> Result<bool, AuthError>;
+
+    /// One page of the users matching `filter`, pages counting from 1, and
+    /// the number of matching users
+    async fn list_users(
+        db: &DatabaseConnection,
+        filter: &UserFilter,
+        sort: UserSort,
+        page: u64,
+        per_page: u64,
+    ) -> Result<(Vec<Model>, u64), AuthError>;
 }
 
 // Trait for Model-level operations (instance methods)
@@ -93,7 +275,7 @@ pub trait AuthUserModelExt {
     /// Update last login time
     async fn update_last_login(&self, db: &DatabaseConnection) -> Result<Model, AuthError>;
 
-    /// Set password for a user
+    /// Set password for a user, after checking it against the password validators
     async fn set_password(
         &self,
         db: &DatabaseConnection,
@@ -103,6 +285,9 @@ pub trait AuthUserModelExt {
     /// Verify user's password
     fn verify_password(&self, password: &str) -> bool;
 
+    /// Verify user's password on the hashing pool, without blocking the executor
+    async fn check_password(&self, password: &str) -> Result<bool, AuthError>;
+
     /// Activate user account
     async fn activate(&self, db: &DatabaseConnection) -> Result<Model, AuthError>;
 
@@ -111,6 +296,54 @@ pub trait AuthUserModelExt {
 
     /// Verify user email
     async fn verify_email(&self, db: &DatabaseConnection) -> Result<Model, AuthError>;
+
+    /// Apply `changes`, refusing an email address or username another user has
+    async fn update_details(
+        &self,
+        db: &DatabaseConnection,
+        changes: UpdateUserData,
+    ) -> Result<Model, AuthError>;
+
+    /// Add the user to a group
+    async fn add_to_group(&self, db: &DatabaseConnection, group_id: i32) -> Result<(), AuthError>;
+
+    /// Remove the user from a group
+    async fn remove_from_group(
+        &self,
+        db: &DatabaseConnection,
+        group_id: i32,
+    ) -> Result<(), AuthError>;
+
+    /// Grant a permission directly to the user
+    async fn add_permission(
+        &self,
+        db: &DatabaseConnection,
+        permission_id: i32,
+    ) -> Result<(), AuthError>;
+
+    /// Remove a permission granted directly to the user
+    async fn remove_permission(
+        &self,
+        db: &DatabaseConnection,
+        permission_id: i32,
+    ) -> Result<(), AuthError>;
+
+    /// Permission codenames granted directly or through groups.
+    /// Superusers get every known permission, inactive users none.
+    async fn get_all_permissions(
+        &self,
+        db: &DatabaseConnection,
+    ) -> Result<HashSet<String>, AuthError>;
+
+    /// Check a single permission codename, e.g. `auth.view_user`
+    async fn has_perm(&self, db: &DatabaseConnection, codename: &str) -> Result<bool, AuthError>;
+
+    /// Check that the user has every permission in `codenames`
+    async fn has_perms(
+        &self,
+        db: &DatabaseConnection,
+        codenames: &[&str],
+    ) -> Result<bool, AuthError>;
 }
 
 #[async_trait::async_trait]
@@ -119,37 +352,22 @@ impl AuthUserEntityExt for AuthUsers {
         db: &DatabaseConnection,
         data: CreateUserData,
     ) -> Result<Model, AuthError> {
-        // Check if email exists
-        if Self::email_exists(db, &data.email).await? {
-            return Err(AuthError::EmailExists);
-        }
-
-        // Check if username exists
-        if Self::username_exists(db, &data.username).await? {
-            return Err(AuthError::UsernameExists);
-        }
+        validate_password(&data.password, Some(&data.attributes()))
+            .map_err(AuthError::PasswordValidation)?;
+        check_available(db, &data).await?;
 
         // Hash the password
-        let password_hash = hash_password(&data.password);
-
-        // Create new user
-        let new_user = ActiveModel {
-            email: Set(data.email),
-            username: Set(data.username),
-            password: Set(password_hash),
-            first_name: Set(data.first_name),
-            last_name: Set(data.last_name),
-            is_active: Set(data.is_active),
-            is_verified: Set(data.is_verified),
-            is_superuser: Set(data.is_superuser),
-            is_staff: Set(data.is_staff),
-            last_login: Set(chrono::Utc::now().naive_utc()),
-            created_at: Set(chrono::Utc::now().naive_utc()),
-            updated_at: Set(chrono::Utc::now().naive_utc()),
-            ..Default::default()
-        };
+        let password_hash = hash_password_async(&data.password).await?;
+
+        insert_user(db, data, password_hash).await
+    }
 
-        Ok(new_user.insert(db).await?)
+    async fn create_user_without_password(
+        db: &DatabaseConnection,
+        data: CreateUserData,
+    ) -> Result<Model, AuthError> {
+        check_available(db, &data).await?;
+        insert_user(db, data, make_unusable_password()).await
     }
 
     async fn create_superuser(
@@ -179,30 +397,42 @@ impl AuthUserEntityExt for AuthUsers {
         db: &DatabaseConnection,
         username_or_email: &str,
         password: &str,
+        client_ip: Option<&str>,
     ) -> Result<Model, AuthError> {
-        // Find user by username or email
-        let user = AuthUsers::find()
+        let user = Self::find_by_login(db, username_or_email).await?;
+        let user = LoginAttempt::new(user.as_ref(), username_or_email)
+            .from_ip(client_ip)
+            .check(db, check_user_password(db, user.clone(), password))
+            .await?
+            .ok_or(AuthError::InvalidCredentials)?;
+
+        if !user.is_active {
+            return Err(AuthError::InactiveAccount);
+        }
+        Ok(user)
+    }
+
+    async fn verify_credentials(
+        db: &DatabaseConnection,
+        username_or_email: &str,
+        password: &str,
+    ) -> Result<Option<Model>, AuthError> {
+        let user = Self::find_by_login(db, username_or_email).await?;
+        check_user_password(db, user, password).await
+    }
+
+    async fn find_by_login(
+        db: &DatabaseConnection,
+        username_or_email: &str,
+    ) -> Result<Option<Model>, AuthError> {
+        Ok(AuthUsers::find()
             .filter(
                 auth_users::Column::Username
                     .eq(username_or_email)
                     .or(auth_users::Column::Email.eq(username_or_email)),
             )
             .one(db)
-            .await?;
-
-        let user = user.ok_or(AuthError::InvalidCredentials)?;
-
-        // Verify password
-        if !user.verify_password(password) {
-            return Err(AuthError::InvalidCredentials);
-        }
-
-        // Check if account is active
-        if !user.is_active {
-            return Err(AuthError::InactiveAccount);
-        }
-
-        Ok(user)
+            .await?)
     }
 
     async fn find_by_email(
@@ -232,6 +462,109 @@ impl AuthUserEntityExt for AuthUsers {
     async fn username_exists(db: &DatabaseConnection, username: &str) -> Result<bool, AuthError> {
         Ok(Self::find_by_username(db, username).await?.is_some())
     }
+
+    async fn list_users(
+        db: &DatabaseConnection,
+        filter: &UserFilter,
+        sort: UserSort,
+        page: u64,
+        per_page: u64,
+    ) -> Result<(Vec<Model>, u64), AuthError> {
+        let order = if sort.descending {
+            Order::Desc
+        } else {
+            Order::Asc
+        };
+        // Ties are broken by id so pages do not overlap
+        let paginator = AuthUsers::find()
+            .filter(filter.condition())
+            .order_by(sort.field.column(), order.clone())
+            .order_by(auth_users::Column::Id, order)
+            .paginate(db, per_page.max(1));
+
+        let total = paginator.num_items().await?;
+        let users = paginator.fetch_page(page.saturating_sub(1)).await?;
+        Ok((users, total))
+    }
+}
+
+/// Hash and save a password without validating it
+async fn store_password(
+    user: &Model,
+    db: &DatabaseConnection,
+    password: &str,
+) -> Result<Model, AuthError> {
+    let password_hash = hash_password_async(password).await?;
+    let mut active_model: ActiveModel = user.clone().into();
+    active_model.password = Set(password_hash);
+    active_model.updated_at = Set(chrono::Utc::now().naive_utc());
+    Ok(active_model.update(db).await?)
+}
+
+/// `user` when `password` is theirs. For unknown users and users without a
+/// usable password a password is hashed anyway, so the response time does
+/// not tell which accounts exist or can log in with a password.
+async fn check_user_password(
+    db: &DatabaseConnection,
+    user: Option<Model>,
+    password: &str,
+) -> Result<Option<Model>, AuthError> {
+    let user = match user {
+        Some(user) if is_password_usable(&user.password) => {
+            if !user.check_password(password).await? {
+                return Ok(None);
+            }
+            user
+        }
+        Some(_) | None => {
+            hash_password_async(password).await?;
+            return Ok(None);
+        }
+    };
+
+    // Upgrade hashes made with an older algorithm or cost, like Django does
+    // The password is not validated again, a stricter policy must not lock
+    // out existing users
+    if needs_rehash(&user.password) {
+        return Ok(Some(store_password(&user, db, password).await?));
+    }
+    Ok(Some(user))
+}
+
+/// Refuse an email address or username another user has
+async fn check_available(db: &DatabaseConnection, data: &CreateUserData) -> Result<(), AuthError> {
+    if AuthUsers::email_exists(db, &data.email).await? {
+        return Err(AuthError::EmailExists);
+    }
+    if AuthUsers::username_exists(db, &data.username).await? {
+        return Err(AuthError::UsernameExists);
+    }
+    Ok(())
+}
+
+async fn insert_user(
+    db: &DatabaseConnection,
+    data: CreateUserData,
+    password_hash: String,
+) -> Result<Model, AuthError> {
+    let now = chrono::Utc::now().naive_utc();
+    let new_user = ActiveModel {
+        email: Set(data.email),
+        username: Set(data.username),
+        password: Set(password_hash),
+        first_name: Set(data.first_name),
+        last_name: Set(data.last_name),
+        is_active: Set(data.is_active),
+        is_verified: Set(data.is_verified),
+        is_superuser: Set(data.is_superuser),
+        is_staff: Set(data.is_staff),
+        last_login: Set(now),
+        created_at: Set(now),
+        updated_at: Set(now),
+        ..Default::default()
+    };
+
+    Ok(new_user.insert(db).await?)
 }
 
 #[async_trait::async_trait]
@@ -248,17 +581,25 @@ impl AuthUserModelExt for Model {
         db: &DatabaseConnection,
         password: &str,
     ) -> Result<Model, AuthError> {
-        let password_hash = hash_password(password);
-        let mut active_model: ActiveModel = self.clone().into();
-        active_model.password = Set(password_hash);
-        active_model.updated_at = Set(chrono::Utc::now().naive_utc());
-        Ok(active_model.update(db).await?)
+        let attributes = UserAttributes {
+            username: &self.username,
+            email: &self.email,
+            first_name: self.first_name.as_deref(),
+            last_name: self.last_name.as_deref(),
+        };
+        validate_password(password, Some(&attributes)).map_err(AuthError::PasswordValidation)?;
+
+        store_password(self, db, password).await
     }
 
     fn verify_password(&self, password: &str) -> bool {
         verify_password(password, &self.password)
     }
 
+    async fn check_password(&self, password: &str) -> Result<bool, AuthError> {
+        Ok(verify_password_async(password, &self.password).await?)
+    }
+
     async fn activate(&self, db: &DatabaseConnection) -> Result<Model, AuthError> {
         let mut active_model: ActiveModel = self.clone().into();
         active_model.is_active = Set(true);
@@ -279,4 +620,458 @@ impl AuthUserModelExt for Model {
         active_model.updated_at = Set(chrono::Utc::now().naive_utc());
         Ok(active_model.update(db).await?)
     }
+
+    async fn update_details(
+        &self,
+        db: &DatabaseConnection,
+        changes: UpdateUserData,
+    ) -> Result<Model, AuthError> {
+        let mut active_model: ActiveModel = self.clone().into();
+        if let Some(email) = changes.email.filter(|email| *email != self.email) {
+            if AuthUsers::email_exists(db, &email).await? {
+                return Err(AuthError::EmailExists);
+            }
+            active_model.email = Set(email);
+        }
+        if let Some(username) = changes
+            .username
+            .filter(|username| *username != self.username)
+        {
+            if AuthUsers::username_exists(db, &username).await? {
+                return Err(AuthError::UsernameExists);
+            }
+            active_model.username = Set(username);
+        }
+        if let Some(first_name) = changes.first_name {
+            active_model.first_name = Set(first_name);
+        }
+        if let Some(last_name) = changes.last_name {
+            active_model.last_name = Set(last_name);
+        }
+        if let Some(is_staff) = changes.is_staff {
+            active_model.is_staff = Set(is_staff);
+        }
+        if let Some(is_superuser) = changes.is_superuser {
+            active_model.is_superuser = Set(is_superuser);
+        }
+        active_model.updated_at = Set(chrono::Utc::now().naive_utc());
+        Ok(active_model.update(db).await?)
+    }
+
+    async fn add_to_group(&self, db: &DatabaseConnection, group_id: i32) -> Result<(), AuthError> {
+        let link = auth_user_groups::ActiveModel {
+            user_id: Set(self.id),
+            group_id: Set(group_id),
+            ..Default::default()
+        };
+
+        auth_user_groups::Entity::insert(link)
+            .on_conflict(
+                OnConflict::columns([
+                    auth_user_groups::Column::UserId,
+                    auth_user_groups::Column::GroupId,
+                ])
+                .do_nothing()
+                .to_owned(),
+            )
+            .exec_without_returning(db)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn remove_from_group(
+        &self,
+        db: &DatabaseConnection,
+        group_id: i32,
+    ) -> Result<(), AuthError> {
+        auth_user_groups::Entity::delete_many()
+            .filter(auth_user_groups::Column::UserId.eq(self.id))
+            .filter(auth_user_groups::Column::GroupId.eq(group_id))
+            .exec(db)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn add_permission(
+        &self,
+        db: &DatabaseConnection,
+        permission_id: i32,
+    ) -> Result<(), AuthError> {
+        let link = auth_user_user_permissions::ActiveModel {
+            user_id: Set(self.id),
+            permission_id: Set(permission_id),
+            ..Default::default()
+        };
+
+        auth_user_user_permissions::Entity::insert(link)
+            .on_conflict(
+                OnConflict::columns([
+                    auth_user_user_permissions::Column::UserId,
+                    auth_user_user_permissions::Column::PermissionId,
+                ])
+                .do_nothing()
+                .to_owned(),
+            )
+            .exec_without_returning(db)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn remove_permission(
+        &self,
+        db: &DatabaseConnection,
+        permission_id: i32,
+    ) -> Result<(), AuthError> {
+        auth_user_user_permissions::Entity::delete_many()
+            .filter(auth_user_user_permissions::Column::UserId.eq(self.id))
+            .filter(auth_user_user_permissions::Column::PermissionId.eq(permission_id))
+            .exec(db)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_all_permissions(
+        &self,
+        db: &DatabaseConnection,
+    ) -> Result<HashSet<String>, AuthError> {
+        if !self.is_active {
+            return Ok(HashSet::new());
+        }
+
+        if self.is_superuser {
+            let all = AuthPermission::find().all(db).await?;
+            return Ok(all.into_iter().map(|p| p.codename).collect());
+        }
+
+        // Permissions granted directly to the user
+        let mut permissions: HashSet<String> = self
+            .find_related(AuthPermission)
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|p| p.codename)
+            .collect();
+
+        // Permissions inherited from the user's groups
+        let group_permissions: Vec<String> = AuthPermission::find()
+            .select_only()
+            .column(auth_permission::Column::Codename)
+            .join(
+                JoinType::InnerJoin,
+                auth_permission::Relation::AuthGroupPermissions.def(),
+            )
+            .join(
+                JoinType::InnerJoin,
+                auth_group_permissions::Relation::AuthGroup.def(),
+            )
+            .join(
+                JoinType::InnerJoin,
+                crate::auth_group::Relation::AuthUserGroups.def(),
+            )
+            .filter(auth_user_groups::Column::UserId.eq(self.id))
+            .distinct()
+            .into_tuple()
+            .all(db)
+            .await?;
+        permissions.extend(group_permissions);
+
+        Ok(permissions)
+    }
+
+    async fn has_perm(&self, db: &DatabaseConnection, codename: &str) -> Result<bool, AuthError> {
+        // Active superusers implicitly have every permission, even unknown ones
+        if self.is_active && self.is_superuser {
+            return Ok(true);
+        }
+
+        Ok(self.get_all_permissions(db).await?.contains(codename))
+    }
+
+    async fn has_perms(
+        &self,
+        db: &DatabaseConnection,
+        codenames: &[&str],
+    ) -> Result<bool, AuthError> {
+        if self.is_active && self.is_superuser {
+            return Ok(true);
+        }
+
+        let permissions = self.get_all_permissions(db).await?;
+        Ok(codenames
+            .iter()
+            .all(|codename| permissions.contains(*codename)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sea_orm::{DbBackend, MockDatabase, QueryTrait, Value};
+    use security::password::{PasswordHasher, Pbkdf2Sha1Hasher, Pbkdf2Sha256Hasher};
+    use std::collections::BTreeMap;
+
+    fn permission(id: i32, codename: &str) -> auth_permission::Model {
+        auth_permission::Model {
+            id,
+            name: codename.to_string(),
+            codename: codename.to_string(),
+        }
+    }
+
+    /// A row of the codename-only query of group permissions
+    fn codename_row(codename: &str) -> BTreeMap<&'static str, Value> {
+        BTreeMap::from([("codename", Value::from(codename))])
+    }
+
+    #[tokio::test]
+    async fn test_superuser_has_every_permission_without_queries() {
+        let db = MockDatabase::new(DbBackend::Postgres).into_connection();
+        let user = Model {
+            is_superuser: true,
+            ..Model::fixture()
+        };
+
+        assert!(user.has_perm(&db, "auth.view_user").await.unwrap());
+        assert!(user.has_perm(&db, "reports.unknown").await.unwrap());
+        assert!(
+            user.has_perms(&db, &["auth.add_user", "auth.delete_user"])
+                .await
+                .unwrap()
+        );
+        assert!(db.into_transaction_log().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_inactive_user_has_no_permissions() {
+        let db = MockDatabase::new(DbBackend::Postgres).into_connection();
+        for user in [
+            Model {
+                is_active: false,
+                ..Model::fixture()
+            },
+            Model {
+                is_active: false,
+                is_superuser: true,
+                ..Model::fixture()
+            },
+        ] {
+            assert!(user.get_all_permissions(&db).await.unwrap().is_empty());
+            assert!(!user.has_perm(&db, "auth.view_user").await.unwrap());
+            assert!(!user.has_perms(&db, &["auth.view_user"]).await.unwrap());
+        }
+        assert!(db.into_transaction_log().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_permissions_from_groups() {
+        let db = MockDatabase::new(DbBackend::Postgres)
+            .append_query_results([Vec::<auth_permission::Model>::new()])
+            .append_query_results([vec![
+                codename_row("auth.view_user"),
+                codename_row("auth.change_user"),
+            ]])
+            .into_connection();
+
+        let permissions = Model::fixture().get_all_permissions(&db).await.unwrap();
+        assert_eq!(
+            permissions,
+            HashSet::from(["auth.view_user".to_string(), "auth.change_user".to_string()])
+        );
+
+        let log = format!("{:?}", db.into_transaction_log());
+        assert!(
+            log.contains(r#"\"auth_user_groups\".\"user_id\" = $1"#),
+            "{}",
+            log
+        );
+    }
+
+    #[tokio::test]
+    async fn test_direct_permissions() {
+        let db = MockDatabase::new(DbBackend::Postgres)
+            .append_query_results([vec![permission(1, "auth.view_user")]])
+            .append_query_results([Vec::<BTreeMap<&str, Value>>::new()])
+            .append_query_results([vec![permission(1, "auth.view_user")]])
+            .append_query_results([Vec::<BTreeMap<&str, Value>>::new()])
+            .into_connection();
+        let user = Model::fixture();
+
+        assert!(user.has_perm(&db, "auth.view_user").await.unwrap());
+        assert!(
+            !user
+                .has_perms(&db, &["auth.view_user", "auth.delete_user"])
+                .await
+                .unwrap()
+        );
+    }
+
+    /// The password stored by the UPDATE that `check_user_password` ran
+    fn stored_password(db: DatabaseConnection) -> String {
+        let log = db.into_transaction_log();
+        assert_eq!(log.len(), 1, "{:?}", log);
+        let statement = &log[0].statements()[0];
+        assert!(statement.sql.starts_with("UPDATE"), "{}", statement.sql);
+        statement
+            .values
+            .iter()
+            .flat_map(|values| values.0.iter())
+            .find_map(|value| match value {
+                Value::String(Some(s)) if s.contains('$') => Some(s.to_string()),
+                _ => None,
+            })
+            .expect("the new password hash")
+    }
+
+    #[tokio::test]
+    async fn test_check_password_upgrades_outdated_iterations() {
+        let user = Model {
+            password: Pbkdf2Sha256Hasher { iterations: 1000 }.encode("correct horse", "seasalt"),
+            ..Model::fixture()
+        };
+        let db = MockDatabase::new(DbBackend::Postgres)
+            .append_query_results([vec![user.clone()]])
+            .into_connection();
+
+        let checked = check_user_password(&db, Some(user), "correct horse")
+            .await
+            .unwrap();
+        assert!(checked.is_some());
+
+        let stored = stored_password(db);
+        assert!(stored.starts_with("pbkdf2_sha256$150000$"), "{}", stored);
+        assert!(!needs_rehash(&stored));
+        assert!(verify_password("correct horse", &stored));
+    }
+
+    #[tokio::test]
+    async fn test_check_password_accepts_and_rehashes_other_listed_algorithm() {
+        // pbkdf2_sha1 is one of the default `passwords.hashers`, after pbkdf2_sha256
+        let user = Model {
+            password: Pbkdf2Sha1Hasher { iterations: 1000 }.encode("correct horse", "seasalt"),
+            ..Model::fixture()
+        };
+        let db = MockDatabase::new(DbBackend::Postgres)
+            .append_query_results([vec![user.clone()]])
+            .into_connection();
+
+        let checked = check_user_password(&db, Some(user), "correct horse")
+            .await
+            .unwrap();
+        assert!(checked.is_some());
+
+        let stored = stored_password(db);
+        assert!(stored.starts_with("pbkdf2_sha256$"), "{}", stored);
+        assert!(verify_password("correct horse", &stored));
+    }
+
+    #[tokio::test]
+    async fn test_check_password_leaves_hash_alone() {
+        let db = MockDatabase::new(DbBackend::Postgres).into_connection();
+        let outdated = Model {
+            password: Pbkdf2Sha1Hasher { iterations: 1000 }.encode("correct horse", "seasalt"),
+            ..Model::fixture()
+        };
+        let current = Model {
+            password: hash_password_async("correct horse").await.unwrap(),
+            ..Model::fixture()
+        };
+
+        // A wrong password never rewrites the hash
+        assert!(
+            check_user_password(&db, Some(outdated), "wrong horse")
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            check_user_password(&db, Some(current), "correct horse")
+                .await
+                .unwrap()
+                .is_some()
+        );
+        assert!(db.into_transaction_log().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_counts_failures_and_refuses_inactive_users() {
+        let none: Vec<crate::auth_login_failures::Model> = vec![];
+        let failure = crate::auth_login_failures::Model {
+            id: 1,
+            scope: "login".to_string(),
+            key: "nobody".to_string(),
+            failures: 1,
+            last_failure_at: chrono::Utc::now().naive_utc(),
+            locked_until: None,
+        };
+        let db = MockDatabase::new(DbBackend::Postgres)
+            .append_query_results([Vec::<Model>::new()])
+            .append_query_results([none.clone()])
+            .append_query_results([vec![failure.clone()], vec![failure]])
+            .into_connection();
+        let result = AuthUsers::authenticate(&db, "nobody", "correct horse", None).await;
+        assert!(matches!(result, Err(AuthError::InvalidCredentials)));
+        let log = format!("{:?}", db.into_transaction_log());
+        assert!(log.contains("INSERT INTO"), "{}", log);
+
+        let inactive = Model {
+            is_active: false,
+            password: hash_password_async("correct horse").await.unwrap(),
+            ..Model::fixture()
+        };
+        let db = MockDatabase::new(DbBackend::Postgres)
+            .append_query_results([vec![inactive]])
+            .append_query_results([none])
+            .append_exec_results([sea_orm::MockExecResult {
+                last_insert_id: 0,
+                rows_affected: 0,
+            }])
+            .into_connection();
+        let result = AuthUsers::authenticate(&db, "ada", "correct horse", None).await;
+        assert!(matches!(result, Err(AuthError::InactiveAccount)));
+    }
+
+    #[test]
+    fn test_user_filter_sql() {
+        let filter = UserFilter {
+            is_active: Some(true),
+            is_staff: Some(false),
+            search: Some("Ada_%".to_string()),
+            ..Default::default()
+        };
+        let sql = AuthUsers::find()
+            .filter(filter.condition())
+            .build(DbBackend::Postgres)
+            .to_string();
+
+        assert!(
+            sql.contains(r#""auth_users"."is_active" = TRUE"#),
+            "{}",
+            sql
+        );
+        assert!(
+            sql.contains(r#""auth_users"."is_staff" = FALSE"#),
+            "{}",
+            sql
+        );
+        assert!(
+            sql.contains(r#"LOWER("email") LIKE E'%ada\\_\\%%' ESCAPE E'\\'"#),
+            "{}",
+            sql
+        );
+        assert!(!sql.contains(r#""is_verified" ="#), "{}", sql);
+
+        let everyone = AuthUsers::find()
+            .filter(UserFilter::default().condition())
+            .build(DbBackend::Postgres)
+            .to_string();
+        assert!(
+            everyone.ends_with(r#"FROM "auth_users" WHERE TRUE"#),
+            "{}",
+            everyone
+        );
+    }
 }
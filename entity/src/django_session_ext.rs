@@ -0,0 +1,111 @@
+use crate::auth_users_ext::AuthError;
+use crate::django_session::{self, ActiveModel, Entity as DjangoSession, Model};
+use sea_orm::prelude::DateTime;
+use sea_orm::sea_query::OnConflict;
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set};
+
+// Trait for Entity-level operations (static methods)
+#[async_trait::async_trait]
+pub trait SessionEntityExt {
+    /// Find an unexpired session by its key
+    async fn find_active(
+        db: &DatabaseConnection,
+        session_key: &str,
+    ) -> Result<Option<Model>, AuthError>;
+
+    /// Store a session under a new key, returning false if the key is taken
+    async fn create(
+        db: &DatabaseConnection,
+        session_key: &str,
+        session_data: String,
+        expire_date: DateTime,
+    ) -> Result<bool, AuthError>;
+
+    /// Store a session, replacing what was saved under its key
+    async fn save(
+        db: &DatabaseConnection,
+        session_key: &str,
+        session_data: String,
+        expire_date: DateTime,
+    ) -> Result<(), AuthError>;
+
+    /// Delete a session, returning whether it existed
+    async fn delete_key(db: &DatabaseConnection, session_key: &str) -> Result<bool, AuthError>;
+
+    /// Delete every expired session, like Django's `clearsessions`
+    async fn clear_expired(db: &DatabaseConnection) -> Result<u64, AuthError>;
+}
+
+fn new_session(session_key: &str, session_data: String, expire_date: DateTime) -> ActiveModel {
+    ActiveModel {
+        session_key: Set(session_key.to_string()),
+        session_data: Set(session_data),
+        expire_date: Set(expire_date),
+    }
+}
+
+#[async_trait::async_trait]
+impl SessionEntityExt for DjangoSession {
+    async fn find_active(
+        db: &DatabaseConnection,
+        session_key: &str,
+    ) -> Result<Option<Model>, AuthError> {
+        Ok(DjangoSession::find_by_id(session_key)
+            .filter(django_session::Column::ExpireDate.gt(chrono::Utc::now().naive_utc()))
+            .one(db)
+            .await?)
+    }
+
+    async fn create(
+        db: &DatabaseConnection,
+        session_key: &str,
+        session_data: String,
+        expire_date: DateTime,
+    ) -> Result<bool, AuthError> {
+        let inserted = DjangoSession::insert(new_session(session_key, session_data, expire_date))
+            .on_conflict(
+                OnConflict::column(django_session::Column::SessionKey)
+                    .do_nothing()
+                    .to_owned(),
+            )
+            .exec_without_returning(db)
+            .await?;
+
+        Ok(inserted == 1)
+    }
+
+    async fn save(
+        db: &DatabaseConnection,
+        session_key: &str,
+        session_data: String,
+        expire_date: DateTime,
+    ) -> Result<(), AuthError> {
+        DjangoSession::insert(new_session(session_key, session_data, expire_date))
+            .on_conflict(
+                OnConflict::column(django_session::Column::SessionKey)
+                    .update_columns([
+                        django_session::Column::SessionData,
+                        django_session::Column::ExpireDate,
+                    ])
+                    .to_owned(),
+            )
+            .exec_without_returning(db)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn delete_key(db: &DatabaseConnection, session_key: &str) -> Result<bool, AuthError> {
+        let result = DjangoSession::delete_by_id(session_key).exec(db).await?;
+        Ok(result.rows_affected > 0)
+    }
+
+    async fn clear_expired(db: &DatabaseConnection) -> Result<u64, AuthError> {
+        let result = DjangoSession::delete_many()
+            .filter(django_session::Column::ExpireDate.lt(chrono::Utc::now().naive_utc()))
+            .exec(db)
+            .await?;
+
+        Ok(result.rows_affected)
+    }
+}
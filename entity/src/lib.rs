@@ -1,6 +1,89 @@
 pub mod prelude;
 
+pub mod auth_group;
+pub mod auth_group_permissions;
+pub mod auth_login_failures;
+pub mod auth_login_failures_ext;
+pub mod auth_magic_links;
+pub mod auth_magic_links_ext;
+pub mod auth_mfa_ext;
+pub mod auth_permission;
+pub mod auth_permissions_ext;
+pub mod auth_personal_access_tokens;
+pub mod auth_personal_access_tokens_ext;
+pub mod auth_refresh_tokens;
+pub mod auth_refresh_tokens_ext;
+pub mod auth_used_tokens;
+pub mod auth_used_tokens_ext;
+pub mod auth_user_groups;
+pub mod auth_user_recovery_codes;
+pub mod auth_user_sessions;
+pub mod auth_user_sessions_ext;
+pub mod auth_user_totp;
+pub mod auth_user_user_permissions;
 pub mod auth_users;
+pub mod auth_users_dto;
 pub mod auth_users_ext;
+pub mod django_session;
+pub mod django_session_ext;
+pub mod oauth_authorization_codes;
+pub mod oauth_clients;
+pub mod oauth_consents;
+pub mod oauth_ext;
+pub mod oauth_tokens;
+pub mod oidc_ext;
+pub mod oidc_signing_keys;
+pub mod user_identities;
+pub mod user_identities_ext;
+pub mod webauthn_credentials;
+pub mod webauthn_credentials_ext;
+pub use auth_group::Entity as AuthGroup;
+pub use auth_login_failures::Entity as AuthLoginFailures;
+pub use auth_login_failures_ext::{
+    LockoutPolicy, LockoutRule, LockoutScope, LoginAttempt, LoginFailureEntityExt,
+    LoginFailureModelExt, configure_lockout, lockout_policy,
+};
+pub use auth_magic_links::Entity as AuthMagicLinks;
+pub use auth_magic_links_ext::{MagicLinkEntityExt, MagicLinkModelExt, NewMagicLink};
+pub use auth_mfa_ext::{RecoveryCodeEntityExt, TotpDeviceEntityExt, TotpDeviceModelExt};
+pub use auth_permission::Entity as AuthPermission;
+pub use auth_permissions_ext::{GroupEntityExt, GroupModelExt, PermissionEntityExt};
+pub use auth_personal_access_tokens::Entity as AuthPersonalAccessTokens;
+pub use auth_personal_access_tokens_ext::{
+    NewPersonalAccessToken, PersonalAccessTokenEntityExt, PersonalAccessTokenModelExt,
+};
+pub use auth_refresh_tokens::Entity as AuthRefreshTokens;
+pub use auth_refresh_tokens_ext::{RefreshTokenEntityExt, RefreshTokenModelExt};
+pub use auth_used_tokens::Entity as AuthUsedTokens;
+pub use auth_used_tokens_ext::UsedTokenEntityExt;
+pub use auth_user_recovery_codes::Entity as AuthUserRecoveryCodes;
+pub use auth_user_sessions::Entity as AuthUserSessions;
+pub use auth_user_sessions_ext::{
+    NewUserSession, SessionKind, UserSessionEntityExt, UserSessionModelExt,
+};
+pub use auth_user_totp::Entity as AuthUserTotp;
 pub use auth_users::Entity as AuthUsers;
-pub use auth_users_ext::{AuthError, AuthUserEntityExt, AuthUserModelExt, CreateUserData};
+pub use auth_users_dto::{UserAdmin, UserPublic, UserSelf};
+pub use auth_users_ext::{
+    AuthError, AuthUserEntityExt, AuthUserModelExt, CreateUserData, UpdateUserData, UserFilter,
+    UserSort, UserSortField,
+};
+pub use django_session::Entity as DjangoSession;
+pub use django_session_ext::SessionEntityExt;
+pub use oauth_authorization_codes::Entity as OauthAuthorizationCodes;
+pub use oauth_clients::Entity as OauthClients;
+pub use oauth_consents::Entity as OauthConsents;
+pub use oauth_ext::{
+    AuthorizationCodeEntityExt, ConsentEntityExt, GrantType, NewAuthorizationCode, NewOAuthClient,
+    NewOAuthToken, OAuthClientEntityExt, OAuthClientModelExt, OAuthTokenEntityExt,
+    OAuthTokenModelExt, has_scopes, split_scopes,
+};
+pub use oauth_tokens::Entity as OauthTokens;
+pub use oidc_ext::{NewSigningKey, SigningKeyEntityExt};
+pub use oidc_signing_keys::Entity as OidcSigningKeys;
+pub use user_identities::Entity as UserIdentities;
+pub use user_identities_ext::{NewUserIdentity, UserIdentityEntityExt, UserIdentityModelExt};
+pub use webauthn_credentials::Entity as WebauthnCredentials;
+pub use webauthn_credentials_ext::{
+    NewWebauthnCredential, WebauthnCredentialEntityExt, WebauthnCredentialModelExt,
+};
@@ -2,4 +2,25 @@
 
 pub mod prelude;
 
+pub mod auth_group;
+pub mod auth_group_permissions;
+pub mod auth_login_failures;
+pub mod auth_magic_links;
+pub mod auth_permission;
+pub mod auth_personal_access_tokens;
+pub mod auth_refresh_tokens;
+pub mod auth_used_tokens;
+pub mod auth_user_groups;
+pub mod auth_user_recovery_codes;
+pub mod auth_user_sessions;
+pub mod auth_user_totp;
+pub mod auth_user_user_permissions;
 pub mod auth_users;
+pub mod django_session;
+pub mod oauth_authorization_codes;
+pub mod oauth_clients;
+pub mod oauth_consents;
+pub mod oauth_tokens;
+pub mod oidc_signing_keys;
+pub mod user_identities;
+pub mod webauthn_credentials;
@@ -0,0 +1,525 @@
+use crate::auth_users_ext::AuthError;
+use crate::oauth_authorization_codes::{
+    self, ActiveModel as AuthorizationCodeActiveModel, Entity as OauthAuthorizationCodes,
+    Model as AuthorizationCode,
+};
+use crate::oauth_clients::{self, ActiveModel, Entity as OauthClients, Model};
+use crate::oauth_consents::{
+    self, ActiveModel as ConsentActiveModel, Entity as OauthConsents, Model as Consent,
+};
+use crate::oauth_tokens::{
+    self, ActiveModel as TokenActiveModel, Entity as OauthTokens, Model as OAuthToken,
+};
+use sea_orm::prelude::DateTime;
+use sea_orm::sea_query::{Expr, OnConflict};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, Set,
+};
+use std::fmt;
+use std::str::FromStr;
+
+/// An OAuth2 grant a client may be registered for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GrantType {
+    AuthorizationCode,
+    RefreshToken,
+    ClientCredentials,
+}
+
+impl GrantType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GrantType::AuthorizationCode => "authorization_code",
+            GrantType::RefreshToken => "refresh_token",
+            GrantType::ClientCredentials => "client_credentials",
+        }
+    }
+}
+
+impl fmt::Display for GrantType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for GrantType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "authorization_code" => Ok(GrantType::AuthorizationCode),
+            "refresh_token" => Ok(GrantType::RefreshToken),
+            "client_credentials" => Ok(GrantType::ClientCredentials),
+            other => Err(format!("Unknown grant type: {}", other)),
+        }
+    }
+}
+
+/// Scopes of a space-separated scope string, as OAuth2 writes them
+pub fn split_scopes(scopes: &str) -> Vec<String> {
+    scopes.split_whitespace().map(str::to_string).collect()
+}
+
+/// Whether the space-separated `granted` scopes include every one of `requested`
+pub fn has_scopes(granted: &str, requested: &[String]) -> bool {
+    requested
+        .iter()
+        .all(|scope| granted.split_whitespace().any(|granted| granted == scope))
+}
+
+pub struct NewOAuthClient {
+    pub client_id: String,
+    /// `None` for public clients, such as single-page apps, that cannot
+    /// keep a secret
+    pub client_secret_hash: Option<String>,
+    pub name: String,
+    pub redirect_uris: Vec<String>,
+    pub grant_types: Vec<GrantType>,
+    pub scopes: Vec<String>,
+    /// First-party apps whose users are not asked for consent
+    pub skip_consent: bool,
+}
+
+pub struct NewAuthorizationCode {
+    pub client_id: i32,
+    pub user_id: i32,
+    pub code_hash: String,
+    pub redirect_uri: String,
+    pub scopes: Vec<String>,
+    /// PKCE `S256` challenge the token request must answer
+    pub code_challenge: Option<String>,
+    /// OpenID Connect `nonce` to repeat in the ID token
+    pub nonce: Option<String>,
+    pub expires_at: DateTime,
+}
+
+pub struct NewOAuthToken {
+    pub client_id: i32,
+    /// `None` for client credentials tokens, which act for the client itself
+    pub user_id: Option<i32>,
+    pub access_token_hash: String,
+    pub refresh_token_hash: Option<String>,
+    pub scopes: Vec<String>,
+    pub access_expires_at: DateTime,
+    pub refresh_expires_at: Option<DateTime>,
+}
+
+// Trait for Entity-level operations (static methods)
+#[async_trait::async_trait]
+pub trait OAuthClientEntityExt {
+    /// Register a client
+    async fn create(db: &DatabaseConnection, client: NewOAuthClient) -> Result<Model, AuthError>;
+
+    /// Find a client by its public `client_id`
+    async fn find_by_client_id(
+        db: &DatabaseConnection,
+        client_id: &str,
+    ) -> Result<Option<Model>, AuthError>;
+
+    /// Every client, ordered by `client_id`
+    async fn list(db: &DatabaseConnection) -> Result<Vec<Model>, AuthError>;
+
+    /// Delete a client with its codes, tokens and consents, returning false
+    /// if there is no such client
+    async fn delete_by_client_id(
+        db: &DatabaseConnection,
+        client_id: &str,
+    ) -> Result<bool, AuthError>;
+}
+
+// Trait for Model-level operations (instance methods)
+pub trait OAuthClientModelExt {
+    /// Confidential clients authenticate with a secret
+    fn is_confidential(&self) -> bool;
+
+    fn redirect_uri_list(&self) -> Vec<String>;
+
+    /// Redirect URIs must match a registered one exactly
+    fn allows_redirect_uri(&self, redirect_uri: &str) -> bool;
+
+    fn allows_grant(&self, grant: GrantType) -> bool;
+
+    /// Scopes the client may ask for
+    fn scope_list(&self) -> Vec<String>;
+}
+
+// Trait for authorization code operations (static methods)
+#[async_trait::async_trait]
+pub trait AuthorizationCodeEntityExt {
+    /// Store the hash of a new authorization code
+    async fn issue(
+        db: &DatabaseConnection,
+        code: NewAuthorizationCode,
+    ) -> Result<AuthorizationCode, AuthError>;
+
+    /// Find a code by its hash, used or not
+    async fn find_by_hash(
+        db: &DatabaseConnection,
+        code_hash: &str,
+    ) -> Result<Option<AuthorizationCode>, AuthError>;
+
+    /// Mark a code as used, returning false if it already was so that a
+    /// code is exchanged at most once
+    async fn consume(db: &DatabaseConnection, id: i32) -> Result<bool, AuthError>;
+}
+
+// Trait for OAuth token operations (static methods)
+#[async_trait::async_trait]
+pub trait OAuthTokenEntityExt {
+    /// Store the hashes of a new access token and its refresh token
+    async fn issue(db: &DatabaseConnection, token: NewOAuthToken) -> Result<OAuthToken, AuthError>;
+
+    async fn find_by_access_hash(
+        db: &DatabaseConnection,
+        access_token_hash: &str,
+    ) -> Result<Option<OAuthToken>, AuthError>;
+
+    async fn find_by_refresh_hash(
+        db: &DatabaseConnection,
+        refresh_token_hash: &str,
+    ) -> Result<Option<OAuthToken>, AuthError>;
+
+    /// Revoke every active token a user granted to a client
+    async fn revoke_all_for_grant(
+        db: &DatabaseConnection,
+        user_id: i32,
+        client_id: i32,
+    ) -> Result<u64, AuthError>;
+}
+
+// Trait for OAuth token operations (instance methods)
+#[async_trait::async_trait]
+pub trait OAuthTokenModelExt {
+    fn is_access_active(&self) -> bool;
+
+    fn is_refresh_active(&self) -> bool;
+
+    fn scope_list(&self) -> Vec<String>;
+
+    /// Revoke the access token and its refresh token, returning false if
+    /// they already were
+    async fn revoke(&self, db: &DatabaseConnection) -> Result<bool, AuthError>;
+}
+
+// Trait for consent operations (static methods)
+#[async_trait::async_trait]
+pub trait ConsentEntityExt {
+    /// What a user has consented to for a client
+    async fn find_for(
+        db: &DatabaseConnection,
+        user_id: i32,
+        client_id: i32,
+    ) -> Result<Option<Consent>, AuthError>;
+
+    /// Record that a user consented to `scopes` for a client, on top of
+    /// what they consented to before
+    async fn grant(
+        db: &DatabaseConnection,
+        user_id: i32,
+        client_id: i32,
+        scopes: &[String],
+    ) -> Result<(), AuthError>;
+}
+
+#[async_trait::async_trait]
+impl OAuthClientEntityExt for OauthClients {
+    async fn create(db: &DatabaseConnection, client: NewOAuthClient) -> Result<Model, AuthError> {
+        let grant_types: Vec<&str> = client.grant_types.iter().map(GrantType::as_str).collect();
+        let new_client = ActiveModel {
+            client_id: Set(client.client_id),
+            client_secret_hash: Set(client.client_secret_hash),
+            name: Set(client.name),
+            redirect_uris: Set(client.redirect_uris.join(" ")),
+            grant_types: Set(grant_types.join(" ")),
+            scopes: Set(client.scopes.join(" ")),
+            skip_consent: Set(client.skip_consent),
+            created_at: Set(chrono::Utc::now().naive_utc()),
+            ..Default::default()
+        };
+
+        Ok(new_client.insert(db).await?)
+    }
+
+    async fn find_by_client_id(
+        db: &DatabaseConnection,
+        client_id: &str,
+    ) -> Result<Option<Model>, AuthError> {
+        Ok(OauthClients::find()
+            .filter(oauth_clients::Column::ClientId.eq(client_id))
+            .one(db)
+            .await?)
+    }
+
+    async fn list(db: &DatabaseConnection) -> Result<Vec<Model>, AuthError> {
+        Ok(OauthClients::find()
+            .order_by_asc(oauth_clients::Column::ClientId)
+            .all(db)
+            .await?)
+    }
+
+    async fn delete_by_client_id(
+        db: &DatabaseConnection,
+        client_id: &str,
+    ) -> Result<bool, AuthError> {
+        let result = OauthClients::delete_many()
+            .filter(oauth_clients::Column::ClientId.eq(client_id))
+            .exec(db)
+            .await?;
+
+        Ok(result.rows_affected == 1)
+    }
+}
+
+impl OAuthClientModelExt for Model {
+    fn is_confidential(&self) -> bool {
+        self.client_secret_hash.is_some()
+    }
+
+    fn redirect_uri_list(&self) -> Vec<String> {
+        self.redirect_uris
+            .split_whitespace()
+            .map(str::to_string)
+            .collect()
+    }
+
+    fn allows_redirect_uri(&self, redirect_uri: &str) -> bool {
+        self.redirect_uris
+            .split_whitespace()
+            .any(|registered| registered == redirect_uri)
+    }
+
+    fn allows_grant(&self, grant: GrantType) -> bool {
+        self.grant_types
+            .split_whitespace()
+            .any(|allowed| allowed == grant.as_str())
+    }
+
+    fn scope_list(&self) -> Vec<String> {
+        split_scopes(&self.scopes)
+    }
+}
+
+#[async_trait::async_trait]
+impl AuthorizationCodeEntityExt for OauthAuthorizationCodes {
+    async fn issue(
+        db: &DatabaseConnection,
+        code: NewAuthorizationCode,
+    ) -> Result<AuthorizationCode, AuthError> {
+        let new_code = AuthorizationCodeActiveModel {
+            code_hash: Set(code.code_hash),
+            client_id: Set(code.client_id),
+            user_id: Set(code.user_id),
+            redirect_uri: Set(code.redirect_uri),
+            scopes: Set(code.scopes.join(" ")),
+            code_challenge: Set(code.code_challenge),
+            nonce: Set(code.nonce),
+            expires_at: Set(code.expires_at),
+            used_at: Set(None),
+            created_at: Set(chrono::Utc::now().naive_utc()),
+            ..Default::default()
+        };
+
+        Ok(new_code.insert(db).await?)
+    }
+
+    async fn find_by_hash(
+        db: &DatabaseConnection,
+        code_hash: &str,
+    ) -> Result<Option<AuthorizationCode>, AuthError> {
+        Ok(OauthAuthorizationCodes::find()
+            .filter(oauth_authorization_codes::Column::CodeHash.eq(code_hash))
+            .one(db)
+            .await?)
+    }
+
+    async fn consume(db: &DatabaseConnection, id: i32) -> Result<bool, AuthError> {
+        let result = OauthAuthorizationCodes::update_many()
+            .col_expr(
+                oauth_authorization_codes::Column::UsedAt,
+                Expr::value(chrono::Utc::now().naive_utc()),
+            )
+            .filter(oauth_authorization_codes::Column::Id.eq(id))
+            .filter(oauth_authorization_codes::Column::UsedAt.is_null())
+            .exec(db)
+            .await?;
+
+        Ok(result.rows_affected == 1)
+    }
+}
+
+#[async_trait::async_trait]
+impl OAuthTokenEntityExt for OauthTokens {
+    async fn issue(db: &DatabaseConnection, token: NewOAuthToken) -> Result<OAuthToken, AuthError> {
+        let new_token = TokenActiveModel {
+            client_id: Set(token.client_id),
+            user_id: Set(token.user_id),
+            access_token_hash: Set(token.access_token_hash),
+            refresh_token_hash: Set(token.refresh_token_hash),
+            scopes: Set(token.scopes.join(" ")),
+            access_expires_at: Set(token.access_expires_at),
+            refresh_expires_at: Set(token.refresh_expires_at),
+            revoked_at: Set(None),
+            created_at: Set(chrono::Utc::now().naive_utc()),
+            ..Default::default()
+        };
+
+        Ok(new_token.insert(db).await?)
+    }
+
+    async fn find_by_access_hash(
+        db: &DatabaseConnection,
+        access_token_hash: &str,
+    ) -> Result<Option<OAuthToken>, AuthError> {
+        Ok(OauthTokens::find()
+            .filter(oauth_tokens::Column::AccessTokenHash.eq(access_token_hash))
+            .one(db)
+            .await?)
+    }
+
+    async fn find_by_refresh_hash(
+        db: &DatabaseConnection,
+        refresh_token_hash: &str,
+    ) -> Result<Option<OAuthToken>, AuthError> {
+        Ok(OauthTokens::find()
+            .filter(oauth_tokens::Column::RefreshTokenHash.eq(refresh_token_hash))
+            .one(db)
+            .await?)
+    }
+
+    async fn revoke_all_for_grant(
+        db: &DatabaseConnection,
+        user_id: i32,
+        client_id: i32,
+    ) -> Result<u64, AuthError> {
+        let result = OauthTokens::update_many()
+            .col_expr(
+                oauth_tokens::Column::RevokedAt,
+                Expr::value(chrono::Utc::now().naive_utc()),
+            )
+            .filter(oauth_tokens::Column::UserId.eq(user_id))
+            .filter(oauth_tokens::Column::ClientId.eq(client_id))
+            .filter(oauth_tokens::Column::RevokedAt.is_null())
+            .exec(db)
+            .await?;
+
+        Ok(result.rows_affected)
+    }
+}
+
+#[async_trait::async_trait]
+impl OAuthTokenModelExt for OAuthToken {
+    fn is_access_active(&self) -> bool {
+        self.revoked_at.is_none() && self.access_expires_at > chrono::Utc::now().naive_utc()
+    }
+
+    fn is_refresh_active(&self) -> bool {
+        self.revoked_at.is_none()
+            && self
+                .refresh_expires_at
+                .is_some_and(|expires_at| expires_at > chrono::Utc::now().naive_utc())
+    }
+
+    fn scope_list(&self) -> Vec<String> {
+        split_scopes(&self.scopes)
+    }
+
+    async fn revoke(&self, db: &DatabaseConnection) -> Result<bool, AuthError> {
+        // Conditional update so that two concurrent refreshes of the same
+        // token cannot both succeed
+        let result = OauthTokens::update_many()
+            .col_expr(
+                oauth_tokens::Column::RevokedAt,
+                Expr::value(chrono::Utc::now().naive_utc()),
+            )
+            .filter(oauth_tokens::Column::Id.eq(self.id))
+            .filter(oauth_tokens::Column::RevokedAt.is_null())
+            .exec(db)
+            .await?;
+
+        Ok(result.rows_affected == 1)
+    }
+}
+
+#[async_trait::async_trait]
+impl ConsentEntityExt for OauthConsents {
+    async fn find_for(
+        db: &DatabaseConnection,
+        user_id: i32,
+        client_id: i32,
+    ) -> Result<Option<Consent>, AuthError> {
+        Ok(OauthConsents::find()
+            .filter(oauth_consents::Column::UserId.eq(user_id))
+            .filter(oauth_consents::Column::ClientId.eq(client_id))
+            .one(db)
+            .await?)
+    }
+
+    async fn grant(
+        db: &DatabaseConnection,
+        user_id: i32,
+        client_id: i32,
+        scopes: &[String],
+    ) -> Result<(), AuthError> {
+        let mut granted = match Self::find_for(db, user_id, client_id).await? {
+            Some(existing) => split_scopes(&existing.scopes),
+            None => Vec::new(),
+        };
+        granted.extend(scopes.iter().cloned());
+        granted.sort();
+        granted.dedup();
+
+        let now = chrono::Utc::now().naive_utc();
+        let consent = ConsentActiveModel {
+            user_id: Set(user_id),
+            client_id: Set(client_id),
+            scopes: Set(granted.join(" ")),
+            created_at: Set(now),
+            updated_at: Set(now),
+            ..Default::default()
+        };
+        OauthConsents::insert(consent)
+            .on_conflict(
+                OnConflict::columns([
+                    oauth_consents::Column::UserId,
+                    oauth_consents::Column::ClientId,
+                ])
+                .update_columns([
+                    oauth_consents::Column::Scopes,
+                    oauth_consents::Column::UpdatedAt,
+                ])
+                .to_owned(),
+            )
+            .exec(db)
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grant_type_round_trip() {
+        for grant in [
+            GrantType::AuthorizationCode,
+            GrantType::RefreshToken,
+            GrantType::ClientCredentials,
+        ] {
+            assert_eq!(grant.as_str().parse::<GrantType>(), Ok(grant));
+        }
+        assert!("password".parse::<GrantType>().is_err());
+    }
+
+    #[test]
+    fn test_has_scopes() {
+        let requested = split_scopes("profile:read users:read");
+        assert!(has_scopes(
+            "users:read profile:read profile:write",
+            &requested
+        ));
+        assert!(!has_scopes("profile:read", &requested));
+        assert!(has_scopes("", &[]));
+    }
+}
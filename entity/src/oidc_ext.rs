@@ -0,0 +1,125 @@
+use crate::auth_users_ext::AuthError;
+use crate::oidc_signing_keys::{self, ActiveModel, Entity as OidcSigningKeys, Model};
+use sea_orm::prelude::DateTime;
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, Condition, DatabaseConnection, EntityTrait, QueryFilter,
+    QueryOrder, Set, TransactionTrait,
+};
+
+pub struct NewSigningKey {
+    pub kid: String,
+    /// JWS algorithm, e.g. `RS256`
+    pub algorithm: String,
+    pub private_key_encrypted: String,
+    /// Public key parts as base64url JWK members
+    pub modulus: String,
+    pub exponent: String,
+}
+
+// Trait for signing key operations (static methods)
+#[async_trait::async_trait]
+pub trait SigningKeyEntityExt {
+    /// The key new tokens are signed with: the newest one not retired
+    async fn find_active(db: &DatabaseConnection) -> Result<Option<Model>, AuthError>;
+
+    async fn find_by_kid(db: &DatabaseConnection, kid: &str) -> Result<Option<Model>, AuthError>;
+
+    /// Keys to publish: the active one, and those retired after `since`
+    /// whose tokens may still be in use
+    async fn list_published(
+        db: &DatabaseConnection,
+        since: DateTime,
+    ) -> Result<Vec<Model>, AuthError>;
+
+    async fn list(db: &DatabaseConnection) -> Result<Vec<Model>, AuthError>;
+
+    /// Retire the active key and make `key` the active one
+    async fn rotate(db: &DatabaseConnection, key: NewSigningKey) -> Result<Model, AuthError>;
+
+    /// Delete keys retired before `before`, returning how many were
+    async fn delete_retired_before(
+        db: &DatabaseConnection,
+        before: DateTime,
+    ) -> Result<u64, AuthError>;
+}
+
+#[async_trait::async_trait]
+impl SigningKeyEntityExt for OidcSigningKeys {
+    async fn find_active(db: &DatabaseConnection) -> Result<Option<Model>, AuthError> {
+        Ok(OidcSigningKeys::find()
+            .filter(oidc_signing_keys::Column::RetiredAt.is_null())
+            .order_by_desc(oidc_signing_keys::Column::CreatedAt)
+            .order_by_desc(oidc_signing_keys::Column::Id)
+            .one(db)
+            .await?)
+    }
+
+    async fn find_by_kid(db: &DatabaseConnection, kid: &str) -> Result<Option<Model>, AuthError> {
+        Ok(OidcSigningKeys::find()
+            .filter(oidc_signing_keys::Column::Kid.eq(kid))
+            .one(db)
+            .await?)
+    }
+
+    async fn list_published(
+        db: &DatabaseConnection,
+        since: DateTime,
+    ) -> Result<Vec<Model>, AuthError> {
+        Ok(OidcSigningKeys::find()
+            .filter(
+                Condition::any()
+                    .add(oidc_signing_keys::Column::RetiredAt.is_null())
+                    .add(oidc_signing_keys::Column::RetiredAt.gt(since)),
+            )
+            .order_by_desc(oidc_signing_keys::Column::CreatedAt)
+            .all(db)
+            .await?)
+    }
+
+    async fn list(db: &DatabaseConnection) -> Result<Vec<Model>, AuthError> {
+        Ok(OidcSigningKeys::find()
+            .order_by_desc(oidc_signing_keys::Column::CreatedAt)
+            .all(db)
+            .await?)
+    }
+
+    async fn rotate(db: &DatabaseConnection, key: NewSigningKey) -> Result<Model, AuthError> {
+        let now = chrono::Utc::now().naive_utc();
+        let txn = db.begin().await?;
+
+        OidcSigningKeys::update_many()
+            .col_expr(oidc_signing_keys::Column::RetiredAt, Expr::value(now))
+            .filter(oidc_signing_keys::Column::RetiredAt.is_null())
+            .exec(&txn)
+            .await?;
+
+        let new_key = ActiveModel {
+            kid: Set(key.kid),
+            algorithm: Set(key.algorithm),
+            private_key_encrypted: Set(key.private_key_encrypted),
+            modulus: Set(key.modulus),
+            exponent: Set(key.exponent),
+            retired_at: Set(None),
+            created_at: Set(now),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+
+        txn.commit().await?;
+        Ok(new_key)
+    }
+
+    async fn delete_retired_before(
+        db: &DatabaseConnection,
+        before: DateTime,
+    ) -> Result<u64, AuthError> {
+        let result = OidcSigningKeys::delete_many()
+            .filter(oidc_signing_keys::Column::RetiredAt.lt(before))
+            .exec(db)
+            .await?;
+
+        Ok(result.rows_affected)
+    }
+}
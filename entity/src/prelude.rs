@@ -1,3 +1,24 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.1.14
 
+pub use super::auth_group::Entity as AuthGroup;
+pub use super::auth_group_permissions::Entity as AuthGroupPermissions;
+pub use super::auth_login_failures::Entity as AuthLoginFailures;
+pub use super::auth_magic_links::Entity as AuthMagicLinks;
+pub use super::auth_permission::Entity as AuthPermission;
+pub use super::auth_personal_access_tokens::Entity as AuthPersonalAccessTokens;
+pub use super::auth_refresh_tokens::Entity as AuthRefreshTokens;
+pub use super::auth_used_tokens::Entity as AuthUsedTokens;
+pub use super::auth_user_groups::Entity as AuthUserGroups;
+pub use super::auth_user_recovery_codes::Entity as AuthUserRecoveryCodes;
+pub use super::auth_user_sessions::Entity as AuthUserSessions;
+pub use super::auth_user_totp::Entity as AuthUserTotp;
+pub use super::auth_user_user_permissions::Entity as AuthUserUserPermissions;
 pub use super::auth_users::Entity as AuthUsers;
+pub use super::django_session::Entity as DjangoSession;
+pub use super::oauth_authorization_codes::Entity as OauthAuthorizationCodes;
+pub use super::oauth_clients::Entity as OauthClients;
+pub use super::oauth_consents::Entity as OauthConsents;
+pub use super::oauth_tokens::Entity as OauthTokens;
+pub use super::oidc_signing_keys::Entity as OidcSigningKeys;
+pub use super::user_identities::Entity as UserIdentities;
+pub use super::webauthn_credentials::Entity as WebauthnCredentials;
@@ -0,0 +1,109 @@
+use crate::auth_users_ext::AuthError;
+use crate::user_identities::{self, ActiveModel, Entity as UserIdentities, Model};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, Set,
+};
+
+pub struct NewUserIdentity {
+    pub user_id: i32,
+    /// Name of the provider in `sso.providers`
+    pub provider: String,
+    /// The provider's `sub` claim
+    pub subject: String,
+    pub email: Option<String>,
+}
+
+// Trait for external identity operations (static methods)
+#[async_trait::async_trait]
+pub trait UserIdentityEntityExt {
+    async fn find_by_subject(
+        db: &DatabaseConnection,
+        provider: &str,
+        subject: &str,
+    ) -> Result<Option<Model>, AuthError>;
+
+    async fn list_for_user(db: &DatabaseConnection, user_id: i32) -> Result<Vec<Model>, AuthError>;
+
+    /// Link an external identity to a user, who logs in with it from now on
+    async fn link(db: &DatabaseConnection, identity: NewUserIdentity) -> Result<Model, AuthError>;
+
+    /// Unlink one of the user's identities, returning whether it existed
+    async fn unlink(db: &DatabaseConnection, user_id: i32, id: i32) -> Result<bool, AuthError>;
+}
+
+// Trait for external identity operations (instance methods)
+#[async_trait::async_trait]
+pub trait UserIdentityModelExt {
+    /// Record a login with the identity and the email address the
+    /// provider currently reports
+    async fn touch(
+        &self,
+        db: &DatabaseConnection,
+        email: Option<String>,
+    ) -> Result<Model, AuthError>;
+}
+
+#[async_trait::async_trait]
+impl UserIdentityEntityExt for UserIdentities {
+    async fn find_by_subject(
+        db: &DatabaseConnection,
+        provider: &str,
+        subject: &str,
+    ) -> Result<Option<Model>, AuthError> {
+        Ok(UserIdentities::find()
+            .filter(user_identities::Column::Provider.eq(provider))
+            .filter(user_identities::Column::Subject.eq(subject))
+            .one(db)
+            .await?)
+    }
+
+    async fn list_for_user(db: &DatabaseConnection, user_id: i32) -> Result<Vec<Model>, AuthError> {
+        Ok(UserIdentities::find()
+            .filter(user_identities::Column::UserId.eq(user_id))
+            .order_by_asc(user_identities::Column::Provider)
+            .order_by_asc(user_identities::Column::CreatedAt)
+            .all(db)
+            .await?)
+    }
+
+    async fn link(db: &DatabaseConnection, identity: NewUserIdentity) -> Result<Model, AuthError> {
+        let now = chrono::Utc::now().naive_utc();
+        let new_identity = ActiveModel {
+            user_id: Set(identity.user_id),
+            provider: Set(identity.provider),
+            subject: Set(identity.subject),
+            email: Set(identity.email),
+            created_at: Set(now),
+            last_login_at: Set(Some(now)),
+            ..Default::default()
+        };
+
+        Ok(new_identity.insert(db).await?)
+    }
+
+    async fn unlink(db: &DatabaseConnection, user_id: i32, id: i32) -> Result<bool, AuthError> {
+        let result = UserIdentities::delete_many()
+            .filter(user_identities::Column::Id.eq(id))
+            .filter(user_identities::Column::UserId.eq(user_id))
+            .exec(db)
+            .await?;
+
+        Ok(result.rows_affected > 0)
+    }
+}
+
+#[async_trait::async_trait]
+impl UserIdentityModelExt for Model {
+    async fn touch(
+        &self,
+        db: &DatabaseConnection,
+        email: Option<String>,
+    ) -> Result<Model, AuthError> {
+        let mut active_model: ActiveModel = self.clone().into();
+        active_model.last_login_at = Set(Some(chrono::Utc::now().naive_utc()));
+        if email.is_some() {
+            active_model.email = Set(email);
+        }
+        Ok(active_model.update(db).await?)
+    }
+}
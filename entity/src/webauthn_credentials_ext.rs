@@ -0,0 +1,140 @@
+use crate::auth_users_ext::AuthError;
+use crate::webauthn_credentials::{self, ActiveModel, Entity as WebauthnCredentials, Model};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, PaginatorTrait, QueryFilter,
+    QueryOrder, Set,
+};
+
+pub struct NewWebauthnCredential {
+    pub user_id: i32,
+    pub name: String,
+    /// Base64url credential id, as browsers send it
+    pub credential_id: String,
+    /// COSE_Key of the credential
+    pub public_key: Vec<u8>,
+    pub sign_count: i64,
+    /// Space-separated transports the authenticator reported, e.g. `internal hybrid`
+    pub transports: String,
+    pub aaguid: String,
+    pub backup_eligible: bool,
+    pub backed_up: bool,
+}
+
+// Trait for WebAuthn credential operations (static methods)
+#[async_trait::async_trait]
+pub trait WebauthnCredentialEntityExt {
+    async fn register(
+        db: &DatabaseConnection,
+        credential: NewWebauthnCredential,
+    ) -> Result<Model, AuthError>;
+
+    async fn find_by_credential_id(
+        db: &DatabaseConnection,
+        credential_id: &str,
+    ) -> Result<Option<Model>, AuthError>;
+
+    async fn list_for_user(db: &DatabaseConnection, user_id: i32) -> Result<Vec<Model>, AuthError>;
+
+    async fn count_for_user(db: &DatabaseConnection, user_id: i32) -> Result<u64, AuthError>;
+
+    /// Delete one of the user's credentials, returning whether it existed
+    async fn remove(db: &DatabaseConnection, user_id: i32, id: i32) -> Result<bool, AuthError>;
+}
+
+// Trait for WebAuthn credential operations (instance methods)
+#[async_trait::async_trait]
+pub trait WebauthnCredentialModelExt {
+    /// Split `transports` into the values browsers expect
+    fn transport_list(&self) -> Vec<String>;
+
+    /// Record a successful assertion and the counter it reported
+    async fn record_use(
+        &self,
+        db: &DatabaseConnection,
+        sign_count: i64,
+        backed_up: bool,
+    ) -> Result<Model, AuthError>;
+}
+
+#[async_trait::async_trait]
+impl WebauthnCredentialEntityExt for WebauthnCredentials {
+    async fn register(
+        db: &DatabaseConnection,
+        credential: NewWebauthnCredential,
+    ) -> Result<Model, AuthError> {
+        let new_credential = ActiveModel {
+            user_id: Set(credential.user_id),
+            name: Set(credential.name),
+            credential_id: Set(credential.credential_id),
+            public_key: Set(credential.public_key),
+            sign_count: Set(credential.sign_count),
+            transports: Set(credential.transports),
+            aaguid: Set(credential.aaguid),
+            backup_eligible: Set(credential.backup_eligible),
+            backed_up: Set(credential.backed_up),
+            created_at: Set(chrono::Utc::now().naive_utc()),
+            last_used_at: Set(None),
+            ..Default::default()
+        };
+
+        Ok(new_credential.insert(db).await?)
+    }
+
+    async fn find_by_credential_id(
+        db: &DatabaseConnection,
+        credential_id: &str,
+    ) -> Result<Option<Model>, AuthError> {
+        Ok(WebauthnCredentials::find()
+            .filter(webauthn_credentials::Column::CredentialId.eq(credential_id))
+            .one(db)
+            .await?)
+    }
+
+    async fn list_for_user(db: &DatabaseConnection, user_id: i32) -> Result<Vec<Model>, AuthError> {
+        Ok(WebauthnCredentials::find()
+            .filter(webauthn_credentials::Column::UserId.eq(user_id))
+            .order_by_asc(webauthn_credentials::Column::CreatedAt)
+            .all(db)
+            .await?)
+    }
+
+    async fn count_for_user(db: &DatabaseConnection, user_id: i32) -> Result<u64, AuthError> {
+        Ok(WebauthnCredentials::find()
+            .filter(webauthn_credentials::Column::UserId.eq(user_id))
+            .count(db)
+            .await?)
+    }
+
+    async fn remove(db: &DatabaseConnection, user_id: i32, id: i32) -> Result<bool, AuthError> {
+        let result = WebauthnCredentials::delete_many()
+            .filter(webauthn_credentials::Column::Id.eq(id))
+            .filter(webauthn_credentials::Column::UserId.eq(user_id))
+            .exec(db)
+            .await?;
+
+        Ok(result.rows_affected > 0)
+    }
+}
+
+#[async_trait::async_trait]
+impl WebauthnCredentialModelExt for Model {
+    fn transport_list(&self) -> Vec<String> {
+        self.transports
+            .split_whitespace()
+            .map(str::to_string)
+            .collect()
+    }
+
+    async fn record_use(
+        &self,
+        db: &DatabaseConnection,
+        sign_count: i64,
+        backed_up: bool,
+    ) -> Result<Model, AuthError> {
+        let mut active_model: ActiveModel = self.clone().into();
+        active_model.sign_count = Set(sign_count);
+        active_model.backed_up = Set(backed_up);
+        active_model.last_used_at = Set(Some(chrono::Utc::now().naive_utc()));
+        Ok(active_model.update(db).await?)
+    }
+}
@@ -1,6 +1,20 @@
 pub use sea_orm_migration::prelude::*;
 mod m20250807_065844_create_users_table;
 mod m20250807_091101_add_auth_users_indexes;
+mod m20261017_090000_create_auth_refresh_tokens_table;
+mod m20261017_091000_create_auth_groups_and_permissions;
+mod m20261017_092000_create_auth_mfa_tables;
+mod m20261017_093000_create_auth_login_failures_table;
+mod m20261017_094000_create_auth_personal_access_tokens_table;
+mod m20261017_095000_create_django_session_table;
+mod m20261017_096000_create_auth_user_sessions_table;
+mod m20261017_097000_create_oauth_tables;
+mod m20261017_098000_create_oidc_signing_keys_table;
+mod m20261017_099000_create_user_identities_table;
+mod m20261017_100000_create_auth_magic_links_table;
+mod m20261017_101000_create_webauthn_credentials_table;
+mod m20261017_102000_add_rotated_to_auth_refresh_tokens;
+mod m20261017_103000_create_auth_used_tokens_table;
 
 pub struct Migrator;
 
@@ -10,6 +24,20 @@ impl MigratorTrait for Migrator {
         vec![
             Box::new(m20250807_065844_create_users_table::Migration),
             Box::new(m20250807_091101_add_auth_users_indexes::Migration),
+            Box::new(m20261017_090000_create_auth_refresh_tokens_table::Migration),
+            Box::new(m20261017_091000_create_auth_groups_and_permissions::Migration),
+            Box::new(m20261017_092000_create_auth_mfa_tables::Migration),
+            Box::new(m20261017_093000_create_auth_login_failures_table::Migration),
+            Box::new(m20261017_094000_create_auth_personal_access_tokens_table::Migration),
+            Box::new(m20261017_095000_create_django_session_table::Migration),
+            Box::new(m20261017_096000_create_auth_user_sessions_table::Migration),
+            Box::new(m20261017_097000_create_oauth_tables::Migration),
+            Box::new(m20261017_098000_create_oidc_signing_keys_table::Migration),
+            Box::new(m20261017_099000_create_user_identities_table::Migration),
+            Box::new(m20261017_100000_create_auth_magic_links_table::Migration),
+            Box::new(m20261017_101000_create_webauthn_credentials_table::Migration),
+            Box::new(m20261017_102000_add_rotated_to_auth_refresh_tokens::Migration),
+            Box::new(m20261017_103000_create_auth_used_tokens_table::Migration),
         ]
     }
 }
@@ -0,0 +1,74 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(AuthRefreshTokens::Table)
+                    .if_not_exists()
+                    .col(pk_auto(AuthRefreshTokens::Id))
+                    .col(integer(AuthRefreshTokens::UserId).not_null())
+                    .col(
+                        ColumnDef::new(AuthRefreshTokens::TokenHash)
+                            .string_len(64)
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(timestamp(AuthRefreshTokens::ExpiresAt).not_null())
+                    .col(timestamp_null(AuthRefreshTokens::RevokedAt))
+                    .col(
+                        timestamp(AuthRefreshTokens::CreatedAt)
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_auth_refresh_tokens_user_id")
+                            .from(AuthRefreshTokens::Table, AuthRefreshTokens::UserId)
+                            .to(AuthUsers::Table, AuthUsers::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_auth_refresh_tokens_user_id")
+                    .table(AuthRefreshTokens::Table)
+                    .col(AuthRefreshTokens::UserId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(AuthRefreshTokens::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum AuthRefreshTokens {
+    Table,
+    Id,
+    UserId,
+    TokenHash,
+    ExpiresAt,
+    RevokedAt,
+    CreatedAt,
+}
+
+/// Note: We only define what we need for this migration
+#[derive(DeriveIden)]
+enum AuthUsers {
+    Table,
+    Id,
+}
@@ -0,0 +1,247 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// Default model permissions, mirroring the ones Django creates for `auth`
+const DEFAULT_PERMISSIONS: &[(&str, &str)] = &[
+    ("Can add user", "auth.add_user"),
+    ("Can change user", "auth.change_user"),
+    ("Can delete user", "auth.delete_user"),
+    ("Can view user", "auth.view_user"),
+    ("Can add group", "auth.add_group"),
+    ("Can change group", "auth.change_group"),
+    ("Can delete group", "auth.delete_group"),
+    ("Can view group", "auth.view_group"),
+    ("Can add permission", "auth.add_permission"),
+    ("Can change permission", "auth.change_permission"),
+    ("Can delete permission", "auth.delete_permission"),
+    ("Can view permission", "auth.view_permission"),
+];
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(AuthGroup::Table)
+                    .if_not_exists()
+                    .col(pk_auto(AuthGroup::Id))
+                    .col(
+                        ColumnDef::new(AuthGroup::Name)
+                            .string_len(150)
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(AuthPermission::Table)
+                    .if_not_exists()
+                    .col(pk_auto(AuthPermission::Id))
+                    .col(string_len(AuthPermission::Name, 255))
+                    .col(
+                        ColumnDef::new(AuthPermission::Codename)
+                            .string_len(100)
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(AuthGroupPermissions::Table)
+                    .if_not_exists()
+                    .col(pk_auto(AuthGroupPermissions::Id))
+                    .col(integer(AuthGroupPermissions::GroupId).not_null())
+                    .col(integer(AuthGroupPermissions::PermissionId).not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_auth_group_permissions_group_id")
+                            .from(AuthGroupPermissions::Table, AuthGroupPermissions::GroupId)
+                            .to(AuthGroup::Table, AuthGroup::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_auth_group_permissions_permission_id")
+                            .from(
+                                AuthGroupPermissions::Table,
+                                AuthGroupPermissions::PermissionId,
+                            )
+                            .to(AuthPermission::Table, AuthPermission::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .index(
+                        Index::create()
+                            .name("uniq_auth_group_permissions_group_permission")
+                            .col(AuthGroupPermissions::GroupId)
+                            .col(AuthGroupPermissions::PermissionId)
+                            .unique(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(AuthUserGroups::Table)
+                    .if_not_exists()
+                    .col(pk_auto(AuthUserGroups::Id))
+                    .col(integer(AuthUserGroups::UserId).not_null())
+                    .col(integer(AuthUserGroups::GroupId).not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_auth_user_groups_user_id")
+                            .from(AuthUserGroups::Table, AuthUserGroups::UserId)
+                            .to(AuthUsers::Table, AuthUsers::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_auth_user_groups_group_id")
+                            .from(AuthUserGroups::Table, AuthUserGroups::GroupId)
+                            .to(AuthGroup::Table, AuthGroup::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .index(
+                        Index::create()
+                            .name("uniq_auth_user_groups_user_group")
+                            .col(AuthUserGroups::UserId)
+                            .col(AuthUserGroups::GroupId)
+                            .unique(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(AuthUserUserPermissions::Table)
+                    .if_not_exists()
+                    .col(pk_auto(AuthUserUserPermissions::Id))
+                    .col(integer(AuthUserUserPermissions::UserId).not_null())
+                    .col(integer(AuthUserUserPermissions::PermissionId).not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_auth_user_user_permissions_user_id")
+                            .from(
+                                AuthUserUserPermissions::Table,
+                                AuthUserUserPermissions::UserId,
+                            )
+                            .to(AuthUsers::Table, AuthUsers::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_auth_user_user_permissions_permission_id")
+                            .from(
+                                AuthUserUserPermissions::Table,
+                                AuthUserUserPermissions::PermissionId,
+                            )
+                            .to(AuthPermission::Table, AuthPermission::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .index(
+                        Index::create()
+                            .name("uniq_auth_user_user_permissions_user_permission")
+                            .col(AuthUserUserPermissions::UserId)
+                            .col(AuthUserUserPermissions::PermissionId)
+                            .unique(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        let mut insert = Query::insert()
+            .into_table(AuthPermission::Table)
+            .columns([AuthPermission::Name, AuthPermission::Codename])
+            .to_owned();
+        for (name, codename) in DEFAULT_PERMISSIONS {
+            insert.values_panic([(*name).into(), (*codename).into()]);
+        }
+        manager.exec_stmt(insert).await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(AuthUserUserPermissions::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(AuthUserGroups::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(AuthGroupPermissions::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(AuthPermission::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(AuthGroup::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum AuthGroup {
+    Table,
+    Id,
+    Name,
+}
+
+#[derive(DeriveIden)]
+pub enum AuthPermission {
+    Table,
+    Id,
+    Name,
+    Codename,
+}
+
+#[derive(DeriveIden)]
+pub enum AuthGroupPermissions {
+    Table,
+    Id,
+    GroupId,
+    PermissionId,
+}
+
+#[derive(DeriveIden)]
+pub enum AuthUserGroups {
+    Table,
+    Id,
+    UserId,
+    GroupId,
+}
+
+#[derive(DeriveIden)]
+pub enum AuthUserUserPermissions {
+    Table,
+    Id,
+    UserId,
+    PermissionId,
+}
+
+/// Note: We only define what we need for this migration
+#[derive(DeriveIden)]
+enum AuthUsers {
+    Table,
+    Id,
+}
@@ -0,0 +1,112 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(AuthUserTotp::Table)
+                    .if_not_exists()
+                    .col(pk_auto(AuthUserTotp::Id))
+                    .col(integer(AuthUserTotp::UserId).not_null().unique_key())
+                    .col(text(AuthUserTotp::SecretEncrypted).not_null())
+                    .col(timestamp_null(AuthUserTotp::ConfirmedAt))
+                    .col(big_integer_null(AuthUserTotp::LastUsedStep))
+                    .col(
+                        timestamp(AuthUserTotp::CreatedAt)
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_auth_user_totp_user_id")
+                            .from(AuthUserTotp::Table, AuthUserTotp::UserId)
+                            .to(AuthUsers::Table, AuthUsers::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(AuthUserRecoveryCodes::Table)
+                    .if_not_exists()
+                    .col(pk_auto(AuthUserRecoveryCodes::Id))
+                    .col(integer(AuthUserRecoveryCodes::UserId).not_null())
+                    .col(
+                        ColumnDef::new(AuthUserRecoveryCodes::CodeHash)
+                            .string_len(64)
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(timestamp_null(AuthUserRecoveryCodes::UsedAt))
+                    .col(
+                        timestamp(AuthUserRecoveryCodes::CreatedAt)
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_auth_user_recovery_codes_user_id")
+                            .from(AuthUserRecoveryCodes::Table, AuthUserRecoveryCodes::UserId)
+                            .to(AuthUsers::Table, AuthUsers::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_auth_user_recovery_codes_user_id")
+                    .table(AuthUserRecoveryCodes::Table)
+                    .col(AuthUserRecoveryCodes::UserId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(AuthUserRecoveryCodes::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(AuthUserTotp::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum AuthUserTotp {
+    Table,
+    Id,
+    UserId,
+    SecretEncrypted,
+    ConfirmedAt,
+    LastUsedStep,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+pub enum AuthUserRecoveryCodes {
+    Table,
+    Id,
+    UserId,
+    CodeHash,
+    UsedAt,
+    CreatedAt,
+}
+
+/// Note: We only define what we need for this migration
+#[derive(DeriveIden)]
+enum AuthUsers {
+    Table,
+    Id,
+}
@@ -0,0 +1,48 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(AuthLoginFailures::Table)
+                    .if_not_exists()
+                    .col(pk_auto(AuthLoginFailures::Id))
+                    .col(string_len(AuthLoginFailures::Scope, 16).not_null())
+                    .col(string_len(AuthLoginFailures::Key, 255).not_null())
+                    .col(integer(AuthLoginFailures::Failures).not_null().default(0))
+                    .col(timestamp(AuthLoginFailures::LastFailureAt).not_null())
+                    .col(timestamp_null(AuthLoginFailures::LockedUntil))
+                    .index(
+                        Index::create()
+                            .name("uniq_auth_login_failures_scope_key")
+                            .col(AuthLoginFailures::Scope)
+                            .col(AuthLoginFailures::Key)
+                            .unique(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(AuthLoginFailures::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum AuthLoginFailures {
+    Table,
+    Id,
+    Scope,
+    Key,
+    Failures,
+    LastFailureAt,
+    LockedUntil,
+}
@@ -0,0 +1,87 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(AuthPersonalAccessTokens::Table)
+                    .if_not_exists()
+                    .col(pk_auto(AuthPersonalAccessTokens::Id))
+                    .col(integer(AuthPersonalAccessTokens::UserId).not_null())
+                    .col(string_len(AuthPersonalAccessTokens::Name, 100).not_null())
+                    .col(string_len(AuthPersonalAccessTokens::TokenPrefix, 16).not_null())
+                    .col(
+                        ColumnDef::new(AuthPersonalAccessTokens::TokenHash)
+                            .string_len(64)
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(text(AuthPersonalAccessTokens::Scopes).not_null())
+                    .col(timestamp_null(AuthPersonalAccessTokens::ExpiresAt))
+                    .col(timestamp_null(AuthPersonalAccessTokens::LastUsedAt))
+                    .col(
+                        timestamp(AuthPersonalAccessTokens::CreatedAt)
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_auth_personal_access_tokens_user_id")
+                            .from(
+                                AuthPersonalAccessTokens::Table,
+                                AuthPersonalAccessTokens::UserId,
+                            )
+                            .to(AuthUsers::Table, AuthUsers::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_auth_personal_access_tokens_user_id")
+                    .table(AuthPersonalAccessTokens::Table)
+                    .col(AuthPersonalAccessTokens::UserId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(AuthPersonalAccessTokens::Table)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum AuthPersonalAccessTokens {
+    Table,
+    Id,
+    UserId,
+    Name,
+    TokenPrefix,
+    TokenHash,
+    Scopes,
+    ExpiresAt,
+    LastUsedAt,
+    CreatedAt,
+}
+
+/// Note: We only define what we need for this migration
+#[derive(DeriveIden)]
+enum AuthUsers {
+    Table,
+    Id,
+}
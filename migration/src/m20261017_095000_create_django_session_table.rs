@@ -0,0 +1,51 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Same shape as Django's `django_session`
+        manager
+            .create_table(
+                Table::create()
+                    .table(DjangoSession::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(DjangoSession::SessionKey)
+                            .string_len(40)
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(text(DjangoSession::SessionData).not_null())
+                    .col(timestamp(DjangoSession::ExpireDate).not_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_django_session_expire_date")
+                    .table(DjangoSession::Table)
+                    .col(DjangoSession::ExpireDate)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(DjangoSession::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum DjangoSession {
+    Table,
+    SessionKey,
+    SessionData,
+    ExpireDate,
+}
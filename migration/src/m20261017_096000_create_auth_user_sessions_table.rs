@@ -0,0 +1,114 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(AuthUserSessions::Table)
+                    .if_not_exists()
+                    .col(pk_auto(AuthUserSessions::Id))
+                    .col(integer(AuthUserSessions::UserId).not_null())
+                    .col(string_len(AuthUserSessions::Kind, 16).not_null())
+                    .col(string_len(AuthUserSessions::Label, 100).not_null())
+                    .col(text_null(AuthUserSessions::UserAgent))
+                    .col(string_len_null(AuthUserSessions::IpAddress, 45))
+                    .col(
+                        timestamp(AuthUserSessions::CreatedAt)
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(
+                        timestamp(AuthUserSessions::LastSeenAt)
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_auth_user_sessions_user_id")
+                            .from(AuthUserSessions::Table, AuthUserSessions::UserId)
+                            .to(AuthUsers::Table, AuthUsers::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_auth_user_sessions_user_id")
+                    .table(AuthUserSessions::Table)
+                    .col(AuthUserSessions::UserId)
+                    .to_owned(),
+            )
+            .await?;
+
+        // Refresh tokens rotate, the session they belong to stays. Ending a
+        // session deletes its tokens.
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(AuthRefreshTokens::Table)
+                    .add_column(integer_null(AuthRefreshTokens::SessionId))
+                    .add_foreign_key(
+                        TableForeignKey::new()
+                            .name("fk_auth_refresh_tokens_session_id")
+                            .from_tbl(AuthRefreshTokens::Table)
+                            .from_col(AuthRefreshTokens::SessionId)
+                            .to_tbl(AuthUserSessions::Table)
+                            .to_col(AuthUserSessions::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(AuthRefreshTokens::Table)
+                    .drop_foreign_key(Alias::new("fk_auth_refresh_tokens_session_id"))
+                    .drop_column(AuthRefreshTokens::SessionId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(AuthUserSessions::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum AuthUserSessions {
+    Table,
+    Id,
+    UserId,
+    Kind,
+    Label,
+    UserAgent,
+    IpAddress,
+    CreatedAt,
+    LastSeenAt,
+}
+
+/// Note: We only define what we need for this migration
+#[derive(DeriveIden)]
+enum AuthRefreshTokens {
+    Table,
+    SessionId,
+}
+
+/// Note: We only define what we need for this migration
+#[derive(DeriveIden)]
+enum AuthUsers {
+    Table,
+    Id,
+}
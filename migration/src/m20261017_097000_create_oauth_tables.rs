@@ -0,0 +1,272 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(OauthClients::Table)
+                    .if_not_exists()
+                    .col(pk_auto(OauthClients::Id))
+                    .col(
+                        ColumnDef::new(OauthClients::ClientId)
+                            .string_len(64)
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(string_len_null(OauthClients::ClientSecretHash, 64))
+                    .col(string_len(OauthClients::Name, 100).not_null())
+                    .col(text(OauthClients::RedirectUris).not_null())
+                    .col(text(OauthClients::GrantTypes).not_null())
+                    .col(text(OauthClients::Scopes).not_null())
+                    .col(boolean(OauthClients::SkipConsent).not_null().default(false))
+                    .col(
+                        timestamp(OauthClients::CreatedAt)
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(OauthAuthorizationCodes::Table)
+                    .if_not_exists()
+                    .col(pk_auto(OauthAuthorizationCodes::Id))
+                    .col(
+                        ColumnDef::new(OauthAuthorizationCodes::CodeHash)
+                            .string_len(64)
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(integer(OauthAuthorizationCodes::ClientId).not_null())
+                    .col(integer(OauthAuthorizationCodes::UserId).not_null())
+                    .col(text(OauthAuthorizationCodes::RedirectUri).not_null())
+                    .col(text(OauthAuthorizationCodes::Scopes).not_null())
+                    .col(string_len_null(OauthAuthorizationCodes::CodeChallenge, 128))
+                    .col(timestamp(OauthAuthorizationCodes::ExpiresAt).not_null())
+                    .col(timestamp_null(OauthAuthorizationCodes::UsedAt))
+                    .col(
+                        timestamp(OauthAuthorizationCodes::CreatedAt)
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_oauth_authorization_codes_client_id")
+                            .from(
+                                OauthAuthorizationCodes::Table,
+                                OauthAuthorizationCodes::ClientId,
+                            )
+                            .to(OauthClients::Table, OauthClients::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_oauth_authorization_codes_user_id")
+                            .from(
+                                OauthAuthorizationCodes::Table,
+                                OauthAuthorizationCodes::UserId,
+                            )
+                            .to(AuthUsers::Table, AuthUsers::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(OauthTokens::Table)
+                    .if_not_exists()
+                    .col(pk_auto(OauthTokens::Id))
+                    .col(integer(OauthTokens::ClientId).not_null())
+                    .col(integer_null(OauthTokens::UserId))
+                    .col(
+                        ColumnDef::new(OauthTokens::AccessTokenHash)
+                            .string_len(64)
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(
+                        ColumnDef::new(OauthTokens::RefreshTokenHash)
+                            .string_len(64)
+                            .null()
+                            .unique_key(),
+                    )
+                    .col(text(OauthTokens::Scopes).not_null())
+                    .col(timestamp(OauthTokens::AccessExpiresAt).not_null())
+                    .col(timestamp_null(OauthTokens::RefreshExpiresAt))
+                    .col(timestamp_null(OauthTokens::RevokedAt))
+                    .col(
+                        timestamp(OauthTokens::CreatedAt)
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_oauth_tokens_client_id")
+                            .from(OauthTokens::Table, OauthTokens::ClientId)
+                            .to(OauthClients::Table, OauthClients::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_oauth_tokens_user_id")
+                            .from(OauthTokens::Table, OauthTokens::UserId)
+                            .to(AuthUsers::Table, AuthUsers::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_oauth_tokens_user_id_client_id")
+                    .table(OauthTokens::Table)
+                    .col(OauthTokens::UserId)
+                    .col(OauthTokens::ClientId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(OauthConsents::Table)
+                    .if_not_exists()
+                    .col(pk_auto(OauthConsents::Id))
+                    .col(integer(OauthConsents::UserId).not_null())
+                    .col(integer(OauthConsents::ClientId).not_null())
+                    .col(text(OauthConsents::Scopes).not_null())
+                    .col(
+                        timestamp(OauthConsents::CreatedAt)
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(
+                        timestamp(OauthConsents::UpdatedAt)
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_oauth_consents_user_id")
+                            .from(OauthConsents::Table, OauthConsents::UserId)
+                            .to(AuthUsers::Table, AuthUsers::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_oauth_consents_client_id")
+                            .from(OauthConsents::Table, OauthConsents::ClientId)
+                            .to(OauthClients::Table, OauthClients::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_oauth_consents_user_id_client_id")
+                    .table(OauthConsents::Table)
+                    .col(OauthConsents::UserId)
+                    .col(OauthConsents::ClientId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(OauthConsents::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(OauthTokens::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(OauthAuthorizationCodes::Table)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .drop_table(Table::drop().table(OauthClients::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum OauthClients {
+    Table,
+    Id,
+    ClientId,
+    ClientSecretHash,
+    Name,
+    RedirectUris,
+    GrantTypes,
+    Scopes,
+    SkipConsent,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+pub enum OauthAuthorizationCodes {
+    Table,
+    Id,
+    CodeHash,
+    ClientId,
+    UserId,
+    RedirectUri,
+    Scopes,
+    CodeChallenge,
+    ExpiresAt,
+    UsedAt,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+pub enum OauthTokens {
+    Table,
+    Id,
+    ClientId,
+    UserId,
+    AccessTokenHash,
+    RefreshTokenHash,
+    Scopes,
+    AccessExpiresAt,
+    RefreshExpiresAt,
+    RevokedAt,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+pub enum OauthConsents {
+    Table,
+    Id,
+    UserId,
+    ClientId,
+    Scopes,
+    CreatedAt,
+    UpdatedAt,
+}
+
+/// Note: We only define what we need for this migration
+#[derive(DeriveIden)]
+enum AuthUsers {
+    Table,
+    Id,
+}
@@ -0,0 +1,80 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(OidcSigningKeys::Table)
+                    .if_not_exists()
+                    .col(pk_auto(OidcSigningKeys::Id))
+                    .col(
+                        ColumnDef::new(OidcSigningKeys::Kid)
+                            .string_len(64)
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(string_len(OidcSigningKeys::Algorithm, 16).not_null())
+                    .col(text(OidcSigningKeys::PrivateKeyEncrypted).not_null())
+                    .col(text(OidcSigningKeys::Modulus).not_null())
+                    .col(string_len(OidcSigningKeys::Exponent, 16).not_null())
+                    .col(timestamp_null(OidcSigningKeys::RetiredAt))
+                    .col(
+                        timestamp(OidcSigningKeys::CreatedAt)
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // ID tokens repeat the nonce the client sent to /oauth/authorize
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(OauthAuthorizationCodes::Table)
+                    .add_column(text_null(OauthAuthorizationCodes::Nonce))
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(OauthAuthorizationCodes::Table)
+                    .drop_column(OauthAuthorizationCodes::Nonce)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(OidcSigningKeys::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum OidcSigningKeys {
+    Table,
+    Id,
+    Kid,
+    Algorithm,
+    PrivateKeyEncrypted,
+    Modulus,
+    Exponent,
+    RetiredAt,
+    CreatedAt,
+}
+
+/// Note: We only define what we need for this migration
+#[derive(DeriveIden)]
+enum OauthAuthorizationCodes {
+    Table,
+    Nonce,
+}
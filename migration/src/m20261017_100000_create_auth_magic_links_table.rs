@@ -0,0 +1,76 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(AuthMagicLinks::Table)
+                    .if_not_exists()
+                    .col(pk_auto(AuthMagicLinks::Id))
+                    .col(integer(AuthMagicLinks::UserId).not_null())
+                    .col(
+                        ColumnDef::new(AuthMagicLinks::TokenHash)
+                            .string_len(64)
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(string_len_null(AuthMagicLinks::BrowserHash, 64))
+                    .col(timestamp(AuthMagicLinks::ExpiresAt).not_null())
+                    .col(timestamp_null(AuthMagicLinks::UsedAt))
+                    .col(
+                        timestamp(AuthMagicLinks::CreatedAt)
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_auth_magic_links_user_id")
+                            .from(AuthMagicLinks::Table, AuthMagicLinks::UserId)
+                            .to(AuthUsers::Table, AuthUsers::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_auth_magic_links_user_id")
+                    .table(AuthMagicLinks::Table)
+                    .col(AuthMagicLinks::UserId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(AuthMagicLinks::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum AuthMagicLinks {
+    Table,
+    Id,
+    UserId,
+    TokenHash,
+    BrowserHash,
+    ExpiresAt,
+    UsedAt,
+    CreatedAt,
+}
+
+/// Note: We only define what we need for this migration
+#[derive(DeriveIden)]
+enum AuthUsers {
+    Table,
+    Id,
+}
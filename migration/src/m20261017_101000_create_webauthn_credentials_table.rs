@@ -0,0 +1,102 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(WebauthnCredentials::Table)
+                    .if_not_exists()
+                    .col(pk_auto(WebauthnCredentials::Id))
+                    .col(integer(WebauthnCredentials::UserId).not_null())
+                    .col(string_len(WebauthnCredentials::Name, 100).not_null())
+                    .col(
+                        ColumnDef::new(WebauthnCredentials::CredentialId)
+                            .text()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(blob(WebauthnCredentials::PublicKey).not_null())
+                    .col(
+                        big_integer(WebauthnCredentials::SignCount)
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(
+                        string_len(WebauthnCredentials::Transports, 100)
+                            .not_null()
+                            .default(""),
+                    )
+                    .col(string_len(WebauthnCredentials::Aaguid, 36).not_null())
+                    .col(
+                        boolean(WebauthnCredentials::BackupEligible)
+                            .not_null()
+                            .default(false),
+                    )
+                    .col(
+                        boolean(WebauthnCredentials::BackedUp)
+                            .not_null()
+                            .default(false),
+                    )
+                    .col(
+                        timestamp(WebauthnCredentials::CreatedAt)
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(timestamp_null(WebauthnCredentials::LastUsedAt))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_webauthn_credentials_user_id")
+                            .from(WebauthnCredentials::Table, WebauthnCredentials::UserId)
+                            .to(AuthUsers::Table, AuthUsers::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_webauthn_credentials_user_id")
+                    .table(WebauthnCredentials::Table)
+                    .col(WebauthnCredentials::UserId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(WebauthnCredentials::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum WebauthnCredentials {
+    Table,
+    Id,
+    UserId,
+    Name,
+    CredentialId,
+    PublicKey,
+    SignCount,
+    Transports,
+    Aaguid,
+    BackupEligible,
+    BackedUp,
+    CreatedAt,
+    LastUsedAt,
+}
+
+/// Note: We only define what we need for this migration
+#[derive(DeriveIden)]
+enum AuthUsers {
+    Table,
+    Id,
+}
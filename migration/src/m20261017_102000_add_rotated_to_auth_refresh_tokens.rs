@@ -0,0 +1,42 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Only tokens replaced by a rotation point at theft when presented
+        // again; logouts and password changes revoke without rotating
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(AuthRefreshTokens::Table)
+                    .add_column(
+                        boolean(AuthRefreshTokens::Rotated)
+                            .not_null()
+                            .default(false),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(AuthRefreshTokens::Table)
+                    .drop_column(AuthRefreshTokens::Rotated)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+/// Note: We only define what we need for this migration
+#[derive(DeriveIden)]
+enum AuthRefreshTokens {
+    Table,
+    Rotated,
+}
@@ -0,0 +1,50 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // MFA tokens are JWTs; their `jti` is kept until they expire so that
+        // each finishes at most one login
+        manager
+            .create_table(
+                Table::create()
+                    .table(AuthUsedTokens::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(AuthUsedTokens::Jti)
+                            .string_len(64)
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(timestamp(AuthUsedTokens::ExpiresAt).not_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_auth_used_tokens_expires_at")
+                    .table(AuthUsedTokens::Table)
+                    .col(AuthUsedTokens::ExpiresAt)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(AuthUsedTokens::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum AuthUsedTokens {
+    Table,
+    Jti,
+    ExpiresAt,
+}
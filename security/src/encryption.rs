@@ -0,0 +1,102 @@
+//! Authenticated encryption for secrets that must be stored recoverably,
+//! such as TOTP seeds.
+
+use aes_gcm::aead::{Aead, KeyInit, OsRng};
+use aes_gcm::{AeadCore, Aes256Gcm, Key, Nonce};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use std::fmt;
+
+use crate::signing::salted_hmac;
+
+const NONCE_LEN: usize = 12;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecryptionError;
+
+impl fmt::Display for DecryptionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Ciphertext is corrupt or was encrypted with another key")
+    }
+}
+
+impl std::error::Error for DecryptionError {}
+
+/// AES-256-GCM with a random nonce per message.
+///
+/// Ciphertexts are URL-safe base64 of `nonce || ciphertext || tag`.
+#[derive(Clone)]
+pub struct SecretBox {
+    cipher: Aes256Gcm,
+}
+
+impl SecretBox {
+    pub fn new(key: &[u8; 32]) -> Self {
+        Self {
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key)),
+        }
+    }
+
+    /// Derive a key from an application secret; `salt` separates purposes
+    pub fn from_secret(secret: &str, salt: &str) -> Self {
+        let key = salted_hmac(salt, b"encryption-key", secret);
+        Self::new(
+            key.as_slice()
+                .try_into()
+                .expect("SHA-256 output is 32 bytes"),
+        )
+    }
+
+    pub fn encrypt(&self, plaintext: &[u8]) -> String {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plaintext)
+            .expect("AES-GCM encryption cannot fail for in-memory data");
+
+        let mut bytes = nonce.to_vec();
+        bytes.extend_from_slice(&ciphertext);
+        URL_SAFE_NO_PAD.encode(bytes)
+    }
+
+    pub fn decrypt(&self, encoded: &str) -> Result<Vec<u8>, DecryptionError> {
+        let bytes = URL_SAFE_NO_PAD
+            .decode(encoded)
+            .map_err(|_| DecryptionError)?;
+        if bytes.len() < NONCE_LEN {
+            return Err(DecryptionError);
+        }
+
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+        self.cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| DecryptionError)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_and_tampering() {
+        let secret_box = SecretBox::from_secret("secret", "r-web.mfa.totp");
+        let ciphertext = secret_box.encrypt(b"12345678901234567890");
+
+        assert_ne!(ciphertext, secret_box.encrypt(b"12345678901234567890"));
+        assert_eq!(
+            secret_box.decrypt(&ciphertext).unwrap(),
+            b"12345678901234567890"
+        );
+
+        let other = SecretBox::from_secret("other-secret", "r-web.mfa.totp");
+        assert_eq!(other.decrypt(&ciphertext), Err(DecryptionError));
+
+        let mut tampered = ciphertext.into_bytes();
+        let last = tampered.len() - 1;
+        tampered[last] = if tampered[last] == b'A' { b'B' } else { b'A' };
+        assert_eq!(
+            secret_box.decrypt(&String::from_utf8(tampered).unwrap()),
+            Err(DecryptionError)
+        );
+    }
+}
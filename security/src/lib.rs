@@ -1 +1,8 @@
-pub mod password;
\ No newline at end of file
+pub mod encryption;
+pub mod password;
+pub mod reset_token;
+pub mod rsa_key;
+pub mod signing;
+pub mod token;
+pub mod totp;
+pub mod webauthn;
@@ -0,0 +1,164 @@
+use sha1::{Digest, Sha1};
+use std::fmt;
+use std::path::Path;
+
+const MAGIC: &[u8; 8] = b"RWBLOOM1";
+const HEADER_LEN: usize = 8 + 4 + 8;
+
+#[derive(Debug)]
+pub enum BloomError {
+    Io(std::io::Error),
+    InvalidFormat,
+}
+
+impl fmt::Display for BloomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BloomError::Io(e) => write!(f, "Failed to read bloom filter: {}", e),
+            BloomError::InvalidFormat => write!(f, "Not a password bloom filter file"),
+        }
+    }
+}
+
+impl std::error::Error for BloomError {}
+
+impl From<std::io::Error> for BloomError {
+    fn from(err: std::io::Error) -> Self {
+        BloomError::Io(err)
+    }
+}
+
+/// Bloom filter over SHA-1 password digests, the format breach corpora such
+/// as Have I Been Pwned are published in.
+///
+/// Lookups never leave the machine. The digests are uniformly distributed,
+/// so the bit positions are derived from them directly by double hashing.
+#[derive(Debug, Clone)]
+pub struct BloomFilter {
+    bits: Vec<u8>,
+    num_bits: u64,
+    num_hashes: u32,
+}
+
+impl BloomFilter {
+    /// Size a filter for `items` entries at the given false positive rate
+    pub fn with_capacity(items: u64, false_positive_rate: f64) -> Self {
+        let items = items.max(1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let num_bits = (-(items * false_positive_rate.ln()) / (ln2 * ln2)).ceil() as u64;
+        let num_bits = num_bits.max(8);
+        let num_hashes = ((num_bits as f64 / items) * ln2).round().max(1.0) as u32;
+
+        Self {
+            bits: vec![0; num_bits.div_ceil(8) as usize],
+            num_bits,
+            num_hashes,
+        }
+    }
+
+    fn positions(&self, digest: &[u8; 20]) -> impl Iterator<Item = u64> + '_ {
+        let h1 = u64::from_le_bytes(digest[0..8].try_into().expect("8 bytes"));
+        let h2 = u64::from_le_bytes(digest[8..16].try_into().expect("8 bytes")) | 1;
+        (0..self.num_hashes as u64)
+            .map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % self.num_bits)
+    }
+
+    pub fn insert_digest(&mut self, digest: &[u8; 20]) {
+        let positions: Vec<u64> = self.positions(digest).collect();
+        for position in positions {
+            self.bits[(position / 8) as usize] |= 1 << (position % 8);
+        }
+    }
+
+    pub fn contains_digest(&self, digest: &[u8; 20]) -> bool {
+        self.positions(digest)
+            .all(|position| self.bits[(position / 8) as usize] & (1 << (position % 8)) != 0)
+    }
+
+    pub fn insert_password(&mut self, password: &str) {
+        self.insert_digest(&Sha1::digest(password.as_bytes()).into());
+    }
+
+    /// Whether the password is probably in the set; never a false negative
+    pub fn contains_password(&self, password: &str) -> bool {
+        self.contains_digest(&Sha1::digest(password.as_bytes()).into())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN + self.bits.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&self.num_hashes.to_le_bytes());
+        bytes.extend_from_slice(&self.num_bits.to_le_bytes());
+        bytes.extend_from_slice(&self.bits);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BloomError> {
+        if bytes.len() < HEADER_LEN || &bytes[..8] != MAGIC {
+            return Err(BloomError::InvalidFormat);
+        }
+        let num_hashes = u32::from_le_bytes(bytes[8..12].try_into().expect("4 bytes"));
+        let num_bits = u64::from_le_bytes(bytes[12..20].try_into().expect("8 bytes"));
+        let bits = bytes[HEADER_LEN..].to_vec();
+
+        if num_hashes == 0 || num_bits == 0 || bits.len() as u64 != num_bits.div_ceil(8) {
+            return Err(BloomError::InvalidFormat);
+        }
+
+        Ok(Self {
+            bits,
+            num_bits,
+            num_hashes,
+        })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, BloomError> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), BloomError> {
+        Ok(std::fs::write(path, self.to_bytes())?)
+    }
+}
+
+/// Parse a SHA-1 line of a Have I Been Pwned dump, `HEXDIGEST[:count]`
+pub fn parse_sha1_line(line: &str) -> Option<[u8; 20]> {
+    let hex_digest = line.split(':').next()?.trim();
+    let mut digest = [0u8; 20];
+    hex::decode_to_slice(hex_digest, &mut digest).ok()?;
+    Some(digest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_membership_and_round_trip() {
+        let mut filter = BloomFilter::with_capacity(1_000, 0.001);
+        for i in 0..1_000 {
+            filter.insert_password(&format!("breached-{}", i));
+        }
+
+        let filter = BloomFilter::from_bytes(&filter.to_bytes()).unwrap();
+        assert!((0..1_000).all(|i| filter.contains_password(&format!("breached-{}", i))));
+
+        let false_positives = (0..10_000)
+            .filter(|i| filter.contains_password(&format!("fresh-{}", i)))
+            .count();
+        assert!(false_positives < 50, "{} false positives", false_positives);
+
+        assert!(BloomFilter::from_bytes(b"garbage").is_err());
+    }
+
+    #[test]
+    fn test_parse_hibp_line() {
+        // SHA-1 of "password"
+        let digest = parse_sha1_line("5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8:10434004").unwrap();
+        let mut filter = BloomFilter::with_capacity(1, 0.001);
+        filter.insert_digest(&digest);
+
+        assert!(filter.contains_password("password"));
+        assert_eq!(parse_sha1_line("not-a-digest"), None);
+    }
+}
@@ -0,0 +1,455 @@
+mod bloom;
+mod hashers;
+mod pool;
+mod validation;
+
+pub use bloom::{BloomError, BloomFilter, parse_sha1_line};
+pub use hashers::{
+    Argon2Hasher, BcryptSha256Hasher, PasswordHasher, Pbkdf2Sha1Hasher, Pbkdf2Sha256Hasher,
+    ScryptHasher,
+};
+pub use pool::{
+    HashingPool, PoolConfig, PoolError, configure_pool, hash_password_async, pool,
+    verify_password_async,
+};
+pub use validation::{
+    BreachedPasswordValidator, CommonPasswordValidator, MinimumLengthValidator,
+    NumericPasswordValidator, PasswordValidator, PasswordValidators,
+    UserAttributeSimilarityValidator, UserAttributes, ValidationError, configure_validators,
+    validate_password, validators,
+};
+
+use std::fmt;
+use std::sync::OnceLock;
+
+#[cfg(test)]
+use hashers::SALT_LENGTH;
+
+const ITERATIONS: u32 = 150000;
+
+/// Tunable parameters of the built-in hashers. Defaults follow Django,
+/// except for the PBKDF2 iteration count which this project has always used.
+#[derive(Debug, Clone)]
+pub struct HasherParams {
+    pub pbkdf2_iterations: u32,
+    pub argon2_time_cost: u32,
+    /// Argon2 memory cost in KiB
+    pub argon2_memory_cost: u32,
+    pub argon2_parallelism: u32,
+    pub bcrypt_rounds: u32,
+    pub scrypt_work_factor: u32,
+    pub scrypt_block_size: u32,
+    pub scrypt_parallelism: u32,
+}
+
+impl Default for HasherParams {
+    fn default() -> Self {
+        Self {
+            pbkdf2_iterations: ITERATIONS,
+            argon2_time_cost: 2,
+            argon2_memory_cost: 102_400,
+            argon2_parallelism: 8,
+            bcrypt_rounds: 12,
+            scrypt_work_factor: 1 << 14,
+            scrypt_block_size: 8,
+            scrypt_parallelism: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HasherConfigError {
+    UnknownHasher(String),
+    Empty,
+    /// A hasher's parameters are out of range
+    InvalidParams {
+        algorithm: &'static str,
+        reason: String,
+    },
+}
+
+impl fmt::Display for HasherConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HasherConfigError::UnknownHasher(name) => {
+                write!(f, "Unknown password hasher: {}", name)
+            }
+            HasherConfigError::Empty => write!(f, "At least one password hasher is required"),
+            HasherConfigError::InvalidParams { algorithm, reason } => {
+                write!(f, "Invalid {} parameters: {}", algorithm, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for HasherConfigError {}
+
+/// Ordered list of hashers, like Django's `PASSWORD_HASHERS`.
+///
+/// The first hasher is the preferred one and is used for every new hash.
+/// The others are only used to verify existing hashes, which are flagged by
+/// [`needs_rehash`](Self::needs_rehash) so they can be upgraded.
+pub struct PasswordHashers {
+    hashers: Vec<Box<dyn PasswordHasher>>,
+}
+
+impl PasswordHashers {
+    pub fn new(hashers: Vec<Box<dyn PasswordHasher>>) -> Result<Self, HasherConfigError> {
+        if hashers.is_empty() {
+            return Err(HasherConfigError::Empty);
+        }
+        for hasher in &hashers {
+            hasher
+                .check_params()
+                .map_err(|reason| HasherConfigError::InvalidParams {
+                    algorithm: hasher.algorithm(),
+                    reason,
+                })?;
+        }
+        Ok(Self { hashers })
+    }
+
+    /// Build the registry from algorithm names, preferred first
+    pub fn from_names<S: AsRef<str>>(
+        names: &[S],
+        params: &HasherParams,
+    ) -> Result<Self, HasherConfigError> {
+        let hashers = names
+            .iter()
+            .map(|name| hasher_by_name(name.as_ref(), params))
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::new(hashers)
+    }
+
+    /// The hasher used for new hashes
+    pub fn preferred(&self) -> &dyn PasswordHasher {
+        self.hashers[0].as_ref()
+    }
+
+    /// Find the hasher for an algorithm name
+    pub fn get(&self, algorithm: &str) -> Option<&dyn PasswordHasher> {
+        self.hashers
+            .iter()
+            .find(|hasher| hasher.algorithm() == algorithm)
+            .map(|hasher| hasher.as_ref())
+    }
+
+    /// Find the hasher that produced an encoded hash
+    pub fn identify(&self, encoded: &str) -> Option<&dyn PasswordHasher> {
+        self.get(algorithm_of(encoded)?)
+    }
+
+    pub fn hash(&self, password: &str) -> String {
+        let hasher = self.preferred();
+        hasher.encode(password, &hasher.salt())
+    }
+
+    pub fn hash_with_salt(&self, password: &str, salt: &str) -> String {
+        self.preferred().encode(password, salt)
+    }
+
+    pub fn verify(&self, password: &str, encoded: &str) -> bool {
+        self.identify(encoded)
+            .is_some_and(|hasher| hasher.verify(password, encoded))
+    }
+
+    pub fn needs_rehash(&self, encoded: &str) -> bool {
+        let preferred = self.preferred();
+        algorithm_of(encoded) != Some(preferred.algorithm()) || preferred.must_update(encoded)
+    }
+}
+
+impl Default for PasswordHashers {
+    /// Django's default `PASSWORD_HASHERS` order
+    fn default() -> Self {
+        Self::from_names(
+            &[
+                "pbkdf2_sha256",
+                "pbkdf2_sha1",
+                "argon2",
+                "bcrypt_sha256",
+                "scrypt",
+            ],
+            &HasherParams::default(),
+        )
+        .expect("default hashers are valid")
+    }
+}
+
+/// Instantiate a built-in hasher by algorithm name
+pub fn hasher_by_name(
+    name: &str,
+    params: &HasherParams,
+) -> Result<Box<dyn PasswordHasher>, HasherConfigError> {
+    let hasher: Box<dyn PasswordHasher> = match name {
+        "pbkdf2_sha256" => Box::new(Pbkdf2Sha256Hasher {
+            iterations: params.pbkdf2_iterations,
+        }),
+        "pbkdf2_sha1" => Box::new(Pbkdf2Sha1Hasher {
+            iterations: params.pbkdf2_iterations,
+        }),
+        "argon2" => Box::new(Argon2Hasher {
+            time_cost: params.argon2_time_cost,
+            memory_cost: params.argon2_memory_cost,
+            parallelism: params.argon2_parallelism,
+        }),
+        "bcrypt_sha256" => Box::new(BcryptSha256Hasher {
+            rounds: params.bcrypt_rounds,
+        }),
+        "scrypt" => Box::new(ScryptHasher {
+            work_factor: params.scrypt_work_factor,
+            block_size: params.scrypt_block_size,
+            parallelism: params.scrypt_parallelism,
+        }),
+        other => return Err(HasherConfigError::UnknownHasher(other.to_string())),
+    };
+    Ok(hasher)
+}
+
+/// Algorithm prefix of an encoded hash, e.g. `argon2` for `argon2$argon2id$...`
+pub fn algorithm_of(encoded: &str) -> Option<&str> {
+    encoded
+        .split_once('$')
+        .map(|(algorithm, _)| algorithm)
+        .filter(|algorithm| !algorithm.is_empty())
+}
+
+static HASHERS: OnceLock<PasswordHashers> = OnceLock::new();
+
+/// Install the process-wide hasher registry. Must run before the first
+/// password is hashed; returns the registry back if one is already set.
+pub fn configure(hashers: PasswordHashers) -> Result<(), PasswordHashers> {
+    HASHERS.set(hashers)
+}
+
+/// The process-wide hasher registry, Django's defaults unless configured
+pub fn hashers() -> &'static PasswordHashers {
+    HASHERS.get_or_init(PasswordHashers::default)
+}
+
+pub fn hash_password(password: &str) -> String {
+    hashers().hash(password)
+}
+
+pub fn hash_password_with_salt(password: &str, salt: &str) -> String {
+    hashers().hash_with_salt(password, salt)
+}
+
+/// Verify a password against a hash of any configured algorithm
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    hashers().verify(password, hash)
+}
+
+/// Whether a hash was made with another algorithm or outdated parameters
+pub fn needs_rehash(hash: &str) -> bool {
+    hashers().needs_rehash(hash)
+}
+
+/// Prefix of Django's unusable passwords
+pub const UNUSABLE_PASSWORD_PREFIX: &str = "!";
+const UNUSABLE_PASSWORD_SUFFIX_LENGTH: usize = 40;
+const UNUSABLE_PASSWORD_CHARS: &[u8] =
+    b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+
+/// Django's `make_password(None)`: a value no password verifies against,
+/// for accounts that log in by other means
+pub fn make_unusable_password() -> String {
+    format!(
+        "{}{}",
+        UNUSABLE_PASSWORD_PREFIX,
+        crate::token::get_random_string(UNUSABLE_PASSWORD_SUFFIX_LENGTH, UNUSABLE_PASSWORD_CHARS)
+    )
+}
+
+/// Django's `is_password_usable`
+pub fn is_password_usable(encoded: &str) -> bool {
+    !encoded.starts_with(UNUSABLE_PASSWORD_PREFIX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unusable_password() {
+        let encoded = make_unusable_password();
+        assert_eq!(encoded.len(), 41);
+        assert!(!is_password_usable(&encoded));
+        assert!(!verify_password("", &encoded));
+        assert!(!verify_password(&encoded, &encoded));
+        assert!(is_password_usable(&hash_password("secret")));
+    }
+
+    #[test]
+    fn test_hash_password_format() {
+        let password = "MySecurePassword123!";
+        let hash = hash_password(password);
+
+        // Check Django format
+        assert!(hash.starts_with("pbkdf2_sha256$150000$"));
+        let parts: Vec<&str> = hash.split('$').collect();
+        assert_eq!(parts.len(), 4);
+        assert_eq!(parts[0], "pbkdf2_sha256");
+        assert_eq!(parts[1], "150000");
+        assert_eq!(parts[2].len(), SALT_LENGTH);
+    }
+
+    #[test]
+    fn test_verify_password_correct() {
+        let password = "MySecurePassword123!";
+        let hash = hash_password(password);
+
+        assert!(
+            verify_password(password, &hash),
+            "Password verification should succeed"
+        );
+    }
+
+    #[test]
+    fn test_verify_password_incorrect() {
+        let password = "MySecurePassword123!";
+        let wrong_password = "WrongPassword456!";
+        let hash = hash_password(password);
+
+        assert!(
+            !verify_password(wrong_password, &hash),
+            "Password verification should fail"
+        );
+    }
+
+    #[test]
+    fn test_hash_with_known_salt() {
+        let password = "testpassword";
+        let salt = "knownsalt123";
+        let hash1 = hash_password_with_salt(password, salt);
+        let hash2 = hash_password_with_salt(password, salt);
+
+        // Same password and salt should produce same hash
+        assert_eq!(hash1, hash2);
+    }
+
+    #[test]
+    fn test_different_salts() {
+        let password = "MySecurePassword123!";
+        let hash1 = hash_password(password);
+        let hash2 = hash_password(password);
+
+        // Different salts should produce different hashes
+        assert_ne!(hash1, hash2);
+    }
+
+    #[test]
+    fn test_needs_rehash() {
+        let current_hash = hash_password("password");
+        assert!(!needs_rehash(&current_hash));
+
+        // Old hash with different iterations
+        let old_hash = "pbkdf2_sha256$100000$oldsalt$somehash";
+        assert!(needs_rehash(old_hash));
+    }
+
+    #[test]
+    fn test_invalid_hash_format() {
+        assert!(!verify_password("password", "invalid_hash"));
+        assert!(!verify_password(
+            "password",
+            "pbkdf2_sha256$notanumber$salt$hash"
+        ));
+    }
+
+    #[test]
+    fn test_unicode_password() {
+        let password = "パスワード123!🔒";
+        let hash = hash_password(password);
+
+        assert!(verify_password(password, &hash));
+    }
+
+    #[test]
+    fn test_dispatches_on_algorithm_prefix() {
+        let params = HasherParams {
+            pbkdf2_iterations: 1000,
+            argon2_time_cost: 1,
+            argon2_memory_cost: 64,
+            argon2_parallelism: 1,
+            bcrypt_rounds: 4,
+            scrypt_work_factor: 1024,
+            ..HasherParams::default()
+        };
+        let legacy = PasswordHashers::from_names(&["pbkdf2_sha1"], &params).unwrap();
+        let registry =
+            PasswordHashers::from_names(&["argon2", "bcrypt_sha256", "pbkdf2_sha1"], &params)
+                .unwrap();
+
+        let legacy_hash = legacy.hash("password");
+        assert!(registry.verify("password", &legacy_hash));
+        assert!(registry.needs_rehash(&legacy_hash));
+
+        let new_hash = registry.hash("password");
+        assert!(new_hash.starts_with("argon2$argon2id$"));
+        assert!(registry.verify("password", &new_hash));
+        assert!(!registry.needs_rehash(&new_hash));
+
+        // Algorithms outside the registry are rejected
+        let scrypt = PasswordHashers::from_names(&["scrypt"], &params).unwrap();
+        assert!(!registry.verify("password", &scrypt.hash("password")));
+    }
+
+    #[test]
+    fn test_unknown_hasher_name() {
+        let result = PasswordHashers::from_names(&["md5"], &HasherParams::default());
+        assert_eq!(
+            result.err(),
+            Some(HasherConfigError::UnknownHasher("md5".to_string()))
+        );
+        assert_eq!(
+            PasswordHashers::from_names::<&str>(&[], &HasherParams::default()).err(),
+            Some(HasherConfigError::Empty)
+        );
+    }
+
+    #[test]
+    fn test_invalid_hasher_params() {
+        for (name, params) in [
+            (
+                "argon2",
+                HasherParams {
+                    argon2_memory_cost: 1,
+                    ..Default::default()
+                },
+            ),
+            (
+                "argon2",
+                HasherParams {
+                    argon2_parallelism: 0,
+                    ..Default::default()
+                },
+            ),
+            (
+                "scrypt",
+                HasherParams {
+                    scrypt_work_factor: 1000,
+                    ..Default::default()
+                },
+            ),
+            (
+                "bcrypt_sha256",
+                HasherParams {
+                    bcrypt_rounds: 40,
+                    ..Default::default()
+                },
+            ),
+        ] {
+            // Only verifying old hashes still needs working parameters
+            let result = PasswordHashers::from_names(&["pbkdf2_sha256", name], &params);
+            assert!(
+                matches!(
+                    result,
+                    Err(HasherConfigError::InvalidParams { algorithm, .. }) if algorithm == name
+                ),
+                "{}",
+                name
+            );
+        }
+    }
+}
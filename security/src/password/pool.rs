@@ -0,0 +1,178 @@
+use super::{hash_password, verify_password};
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::sync::Semaphore;
+
+/// Limits for hashing work moved off the async executor
+#[derive(Debug, Clone)]
+pub struct PoolConfig {
+    /// Hashes computed at the same time; further calls wait in a queue
+    pub max_concurrency: usize,
+    /// How long a call may wait for a free slot before giving up
+    pub queue_timeout: Duration,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrency: std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(4),
+            queue_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PoolError {
+    /// No slot became free within the queue timeout
+    QueueTimeout,
+    /// The hashing task panicked or the runtime is shutting down
+    Cancelled,
+}
+
+impl fmt::Display for PoolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PoolError::QueueTimeout => write!(f, "Timed out waiting for a password hashing slot"),
+            PoolError::Cancelled => write!(f, "Password hashing task was cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for PoolError {}
+
+/// Runs CPU-heavy hashing on Tokio's blocking threads so it never stalls
+/// the async workers, with at most `max_concurrency` hashes in flight.
+pub struct HashingPool {
+    permits: Arc<Semaphore>,
+    queue_timeout: Duration,
+    inline: bool,
+}
+
+impl HashingPool {
+    pub fn new(config: &PoolConfig) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(config.max_concurrency.max(1))),
+            queue_timeout: config.queue_timeout,
+            inline: false,
+        }
+    }
+
+    /// Hash right on the calling async worker, as before there was a pool.
+    /// Only meant for comparisons such as `benches/login_load.rs`.
+    #[cfg(any(test, feature = "bench"))]
+    pub fn inline() -> Self {
+        Self {
+            inline: true,
+            ..Self::default()
+        }
+    }
+
+    /// Run `work` on the blocking pool once a slot is free
+    pub async fn run<F, T>(&self, work: F) -> Result<T, PoolError>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        if self.inline {
+            return Ok(work());
+        }
+
+        let permit = tokio::time::timeout(self.queue_timeout, self.permits.clone().acquire_owned())
+            .await
+            .map_err(|_| PoolError::QueueTimeout)?
+            .map_err(|_| PoolError::Cancelled)?;
+
+        // The permit moves into the task so the slot stays taken until the
+        // hash is done, even if the caller stops waiting for it
+        tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            work()
+        })
+        .await
+        .map_err(|_| PoolError::Cancelled)
+    }
+}
+
+impl Default for HashingPool {
+    fn default() -> Self {
+        Self::new(&PoolConfig::default())
+    }
+}
+
+static POOL: OnceLock<HashingPool> = OnceLock::new();
+
+/// Install the process-wide hashing pool; returns it back if one is already set
+pub fn configure_pool(pool: HashingPool) -> Result<(), HashingPool> {
+    POOL.set(pool)
+}
+
+/// The process-wide hashing pool, sized to the CPU count unless configured
+pub fn pool() -> &'static HashingPool {
+    POOL.get_or_init(HashingPool::default)
+}
+
+/// [`hash_password`] on the hashing pool
+pub async fn hash_password_async(password: &str) -> Result<String, PoolError> {
+    let password = password.to_owned();
+    pool().run(move || hash_password(&password)).await
+}
+
+/// [`verify_password`] on the hashing pool
+pub async fn verify_password_async(password: &str, hash: &str) -> Result<bool, PoolError> {
+    let password = password.to_owned();
+    let hash = hash.to_owned();
+    pool().run(move || verify_password(&password, &hash)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_runs_work_off_the_executor() {
+        let pool = HashingPool::default();
+        let result = pool.run(|| 21 * 2).await;
+        assert_eq!(result, Ok(42));
+    }
+
+    #[tokio::test]
+    async fn test_inline_runs_on_the_caller() {
+        let caller = std::thread::current().id();
+        let pool = HashingPool::inline();
+        assert_eq!(
+            pool.run(move || std::thread::current().id()).await,
+            Ok(caller)
+        );
+        assert_ne!(
+            HashingPool::default()
+                .run(move || std::thread::current().id())
+                .await,
+            Ok(caller)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_queue_timeout_when_saturated() {
+        let pool = Arc::new(HashingPool::new(&PoolConfig {
+            max_concurrency: 1,
+            queue_timeout: Duration::from_millis(20),
+        }));
+        let (release, wait) = std::sync::mpsc::channel::<()>();
+
+        let busy = tokio::spawn({
+            let pool = pool.clone();
+            async move { pool.run(move || wait.recv().ok()).await }
+        });
+        // Let the first task take the only slot
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        assert_eq!(pool.run(|| ()).await, Err(PoolError::QueueTimeout));
+
+        release.send(()).unwrap();
+        assert_eq!(busy.await.unwrap(), Ok(Some(())));
+        assert_eq!(pool.run(|| ()).await, Ok(()));
+    }
+}
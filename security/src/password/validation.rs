@@ -0,0 +1,455 @@
+use super::bloom::{BloomError, BloomFilter};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// The most common passwords, lowercased, one per line. Taken from the
+/// password frequency list of the zxcvbn project (MIT licensed).
+const COMMON_PASSWORDS: &str = include_str!("common-passwords.txt");
+
+/// A rule a password broke
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    /// Stable identifier of the rule, e.g. `password_too_short`
+    pub code: &'static str,
+    pub message: String,
+}
+
+/// Account fields a password is compared against
+#[derive(Debug, Clone, Default)]
+pub struct UserAttributes<'a> {
+    pub username: &'a str,
+    pub email: &'a str,
+    pub first_name: Option<&'a str>,
+    pub last_name: Option<&'a str>,
+}
+
+impl UserAttributes<'_> {
+    fn get(&self, attribute: &str) -> Option<&str> {
+        match attribute {
+            "username" => Some(self.username),
+            "email" => Some(self.email),
+            "first_name" => self.first_name,
+            "last_name" => self.last_name,
+            _ => None,
+        }
+    }
+}
+
+/// A password rule, in the style of Django's `AUTH_PASSWORD_VALIDATORS`
+pub trait PasswordValidator: Send + Sync {
+    fn validate(
+        &self,
+        password: &str,
+        user: Option<&UserAttributes>,
+    ) -> Result<(), ValidationError>;
+
+    /// Requirement shown to users before they pick a password
+    fn help_text(&self) -> String;
+}
+
+/// Require at least `min_length` characters
+pub struct MinimumLengthValidator {
+    pub min_length: usize,
+}
+
+impl Default for MinimumLengthValidator {
+    fn default() -> Self {
+        Self { min_length: 8 }
+    }
+}
+
+impl PasswordValidator for MinimumLengthValidator {
+    fn validate(
+        &self,
+        password: &str,
+        _user: Option<&UserAttributes>,
+    ) -> Result<(), ValidationError> {
+        if password.chars().count() < self.min_length {
+            return Err(ValidationError {
+                code: "password_too_short",
+                message: format!(
+                    "This password is too short. It must contain at least {} {}.",
+                    self.min_length,
+                    if self.min_length == 1 {
+                        "character"
+                    } else {
+                        "characters"
+                    }
+                ),
+            });
+        }
+        Ok(())
+    }
+
+    fn help_text(&self) -> String {
+        format!(
+            "Your password must contain at least {} characters.",
+            self.min_length
+        )
+    }
+}
+
+/// Reject passwords too similar to the user's own details
+pub struct UserAttributeSimilarityValidator {
+    pub user_attributes: Vec<String>,
+    /// Between 0.1 and 1.0; lower is stricter
+    pub max_similarity: f64,
+}
+
+impl Default for UserAttributeSimilarityValidator {
+    fn default() -> Self {
+        Self {
+            user_attributes: ["username", "first_name", "last_name", "email"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            max_similarity: 0.7,
+        }
+    }
+}
+
+impl PasswordValidator for UserAttributeSimilarityValidator {
+    fn validate(
+        &self,
+        password: &str,
+        user: Option<&UserAttributes>,
+    ) -> Result<(), ValidationError> {
+        let Some(user) = user else {
+            return Ok(());
+        };
+        let password = password.to_lowercase();
+
+        for attribute in &self.user_attributes {
+            let Some(value) = user.get(attribute).filter(|value| !value.is_empty()) else {
+                continue;
+            };
+            let value = value.to_lowercase();
+            let parts = value
+                .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .chain(std::iter::once(value.as_str()));
+
+            for part in parts {
+                if exceeds_maximum_length_ratio(&password, self.max_similarity, part) {
+                    continue;
+                }
+                if quick_ratio(&password, part) >= self.max_similarity {
+                    return Err(ValidationError {
+                        code: "password_too_similar",
+                        message: format!(
+                            "The password is too similar to the {}.",
+                            attribute.replace('_', " ")
+                        ),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn help_text(&self) -> String {
+        "Your password can't be too similar to your other personal information.".to_string()
+    }
+}
+
+/// Skip comparing a long password with a much shorter value; they cannot
+/// reach `max_similarity` and the check would only cost time
+fn exceeds_maximum_length_ratio(password: &str, max_similarity: f64, value: &str) -> bool {
+    let password_len = password.chars().count();
+    let value_len = value.chars().count();
+    let length_bound_similarity = max_similarity / 2.0 * password_len as f64;
+    password_len >= 10 * value_len && (value_len as f64) < length_bound_similarity
+}
+
+/// Python's `difflib.SequenceMatcher.quick_ratio`, an upper bound on similarity
+fn quick_ratio(a: &str, b: &str) -> f64 {
+    let total = a.chars().count() + b.chars().count();
+    if total == 0 {
+        return 1.0;
+    }
+
+    let mut available: HashMap<char, usize> = HashMap::new();
+    for c in b.chars() {
+        *available.entry(c).or_default() += 1;
+    }
+    let matches = a
+        .chars()
+        .filter(|c| match available.get_mut(c) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                true
+            }
+            _ => false,
+        })
+        .count();
+
+    2.0 * matches as f64 / total as f64
+}
+
+/// Reject passwords from a list of commonly used ones
+pub struct CommonPasswordValidator {
+    passwords: HashSet<String>,
+}
+
+impl CommonPasswordValidator {
+    /// Use a custom list, one password per line
+    pub fn from_file(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        Ok(Self::from_list(&std::fs::read_to_string(path)?))
+    }
+
+    pub fn from_list(list: &str) -> Self {
+        Self {
+            passwords: list
+                .lines()
+                .map(|line| line.trim().to_lowercase())
+                .filter(|line| !line.is_empty())
+                .collect(),
+        }
+    }
+}
+
+impl Default for CommonPasswordValidator {
+    /// The bundled list of 20,000 common passwords
+    fn default() -> Self {
+        Self::from_list(COMMON_PASSWORDS)
+    }
+}
+
+impl PasswordValidator for CommonPasswordValidator {
+    fn validate(
+        &self,
+        password: &str,
+        _user: Option<&UserAttributes>,
+    ) -> Result<(), ValidationError> {
+        if self.passwords.contains(&password.trim().to_lowercase()) {
+            return Err(ValidationError {
+                code: "password_too_common",
+                message: "This password is too common.".to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    fn help_text(&self) -> String {
+        "Your password can't be a commonly used password.".to_string()
+    }
+}
+
+/// Reject passwords made only of digits
+pub struct NumericPasswordValidator;
+
+impl PasswordValidator for NumericPasswordValidator {
+    fn validate(
+        &self,
+        password: &str,
+        _user: Option<&UserAttributes>,
+    ) -> Result<(), ValidationError> {
+        if !password.is_empty() && password.chars().all(|c| c.is_numeric()) {
+            return Err(ValidationError {
+                code: "password_entirely_numeric",
+                message: "This password is entirely numeric.".to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    fn help_text(&self) -> String {
+        "Your password can't be entirely numeric.".to_string()
+    }
+}
+
+/// Reject passwords found in an offline bloom filter of breached passwords
+pub struct BreachedPasswordValidator {
+    filter: BloomFilter,
+}
+
+impl BreachedPasswordValidator {
+    pub fn new(filter: BloomFilter) -> Self {
+        Self { filter }
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, BloomError> {
+        Ok(Self::new(BloomFilter::load(path)?))
+    }
+}
+
+impl PasswordValidator for BreachedPasswordValidator {
+    fn validate(
+        &self,
+        password: &str,
+        _user: Option<&UserAttributes>,
+    ) -> Result<(), ValidationError> {
+        if self.filter.contains_password(password) {
+            return Err(ValidationError {
+                code: "password_breached",
+                message: "This password has appeared in a data breach.".to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    fn help_text(&self) -> String {
+        "Your password can't be one that has appeared in a known data breach.".to_string()
+    }
+}
+
+/// The configured validators; every one of them runs so that users see all
+/// problems at once
+#[derive(Default)]
+pub struct PasswordValidators {
+    validators: Vec<Box<dyn PasswordValidator>>,
+}
+
+impl PasswordValidators {
+    pub fn new(validators: Vec<Box<dyn PasswordValidator>>) -> Self {
+        Self { validators }
+    }
+
+    /// Django's default `AUTH_PASSWORD_VALIDATORS`
+    pub fn django_defaults() -> Self {
+        Self::new(vec![
+            Box::new(UserAttributeSimilarityValidator::default()),
+            Box::new(MinimumLengthValidator::default()),
+            Box::new(CommonPasswordValidator::default()),
+            Box::new(NumericPasswordValidator),
+        ])
+    }
+
+    pub fn validate(
+        &self,
+        password: &str,
+        user: Option<&UserAttributes>,
+    ) -> Result<(), Vec<ValidationError>> {
+        let errors: Vec<ValidationError> = self
+            .validators
+            .iter()
+            .filter_map(|validator| validator.validate(password, user).err())
+            .collect();
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    pub fn help_texts(&self) -> Vec<String> {
+        self.validators
+            .iter()
+            .map(|validator| validator.help_text())
+            .collect()
+    }
+}
+
+static VALIDATORS: OnceLock<PasswordValidators> = OnceLock::new();
+
+/// Install the process-wide validators; returns them back if already set
+pub fn configure_validators(validators: PasswordValidators) -> Result<(), PasswordValidators> {
+    VALIDATORS.set(validators)
+}
+
+/// The process-wide validators, Django's defaults unless configured
+pub fn validators() -> &'static PasswordValidators {
+    VALIDATORS.get_or_init(PasswordValidators::django_defaults)
+}
+
+/// Check a password against the process-wide validators
+pub fn validate_password(
+    password: &str,
+    user: Option<&UserAttributes>,
+) -> Result<(), Vec<ValidationError>> {
+    validators().validate(password, user)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn codes(result: Result<(), Vec<ValidationError>>) -> Vec<&'static str> {
+        result
+            .err()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|error| error.code)
+            .collect()
+    }
+
+    fn ada() -> UserAttributes<'static> {
+        UserAttributes {
+            username: "ada",
+            email: "ada.lovelace@example.com",
+            first_name: Some("Ada"),
+            last_name: Some("Lovelace"),
+        }
+    }
+
+    #[test]
+    fn test_django_defaults() {
+        let validators = PasswordValidators::django_defaults();
+        let user = ada();
+
+        assert_eq!(
+            codes(validators.validate("c0rrect-h0rse-battery", Some(&user))),
+            Vec::<&str>::new()
+        );
+        assert_eq!(
+            codes(validators.validate("1234", Some(&user))),
+            [
+                "password_too_short",
+                "password_too_common",
+                "password_entirely_numeric"
+            ]
+        );
+        assert_eq!(
+            codes(validators.validate("Password", None)),
+            ["password_too_common"]
+        );
+        assert_eq!(
+            codes(validators.validate("lovelace1", Some(&user))),
+            ["password_too_similar"]
+        );
+    }
+
+    #[test]
+    fn test_similarity_matches_django() {
+        let validator = UserAttributeSimilarityValidator::default();
+        let user = ada();
+
+        let error = validator.validate("ada.lovelace", Some(&user)).unwrap_err();
+        // Attributes are checked in order, last_name matches before email
+        assert_eq!(
+            error.message,
+            "The password is too similar to the last name."
+        );
+        assert!(validator.validate("ada.lovelace", None).is_ok());
+        assert!(validator.validate("xkcd-936-staple", Some(&user)).is_ok());
+
+        // difflib.SequenceMatcher(a="abcd", b="bcde").quick_ratio() == 0.75
+        assert_eq!(quick_ratio("abcd", "bcde"), 0.75);
+    }
+
+    #[test]
+    fn test_breached_password_filter() {
+        let mut filter = BloomFilter::with_capacity(10, 0.001);
+        filter.insert_password("Tr0ub4dor&3");
+        let validator = BreachedPasswordValidator::new(filter);
+
+        assert_eq!(
+            validator.validate("Tr0ub4dor&3", None).unwrap_err().code,
+            "password_breached"
+        );
+        assert!(
+            validator
+                .validate("correct horse battery staple", None)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_minimum_length_counts_characters() {
+        let validator = MinimumLengthValidator { min_length: 3 };
+
+        assert!(validator.validate("äöü", None).is_ok());
+        assert!(validator.validate("äö", None).is_err());
+    }
+}
@@ -0,0 +1,76 @@
+//! RSA key pairs for signing JWTs with RS256, such as OpenID Connect ID
+//! tokens that other parties verify with the public key.
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use rsa::pkcs1::{DecodeRsaPrivateKey, EncodeRsaPrivateKey, LineEnding};
+use rsa::traits::PublicKeyParts;
+use rsa::{RsaPrivateKey, RsaPublicKey};
+use std::fmt;
+
+const KEY_BITS: usize = 2048;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyError;
+
+impl fmt::Display for KeyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Not a valid RSA private key")
+    }
+}
+
+impl std::error::Error for KeyError {}
+
+pub struct RsaKeyPair {
+    private_key: RsaPrivateKey,
+}
+
+impl RsaKeyPair {
+    /// Generate a new 2048-bit key pair
+    pub fn generate() -> Self {
+        let private_key = RsaPrivateKey::new(&mut rand::thread_rng(), KEY_BITS)
+            .expect("2048-bit RSA key generation cannot fail");
+        Self { private_key }
+    }
+
+    /// Load a private key saved with [`RsaKeyPair::to_pem`]
+    pub fn from_pem(pem: &str) -> Result<Self, KeyError> {
+        let private_key = RsaPrivateKey::from_pkcs1_pem(pem).map_err(|_| KeyError)?;
+        Ok(Self { private_key })
+    }
+
+    /// The private key as PKCS#1 PEM, as `jsonwebtoken` reads it
+    pub fn to_pem(&self) -> String {
+        self.private_key
+            .to_pkcs1_pem(LineEnding::LF)
+            .expect("RSA keys encode to PEM")
+            .to_string()
+    }
+
+    /// Modulus of the public key, base64url-encoded as the JWK `n`
+    pub fn modulus(&self) -> String {
+        URL_SAFE_NO_PAD.encode(RsaPublicKey::from(&self.private_key).n().to_bytes_be())
+    }
+
+    /// Exponent of the public key, base64url-encoded as the JWK `e`
+    pub fn exponent(&self) -> String {
+        URL_SAFE_NO_PAD.encode(RsaPublicKey::from(&self.private_key).e().to_bytes_be())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pem_round_trip() {
+        let key = RsaKeyPair::generate();
+        let loaded = RsaKeyPair::from_pem(&key.to_pem()).unwrap();
+
+        assert_eq!(loaded.modulus(), key.modulus());
+        // 65537
+        assert_eq!(key.exponent(), "AQAB");
+        // 2048 bits in unpadded base64url
+        assert_eq!(key.modulus().len(), 342);
+        assert!(RsaKeyPair::from_pem("not a key").is_err());
+    }
+}
@@ -0,0 +1,63 @@
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use rand::{Rng, RngCore};
+use sha2::{Digest, Sha256};
+
+const TOKEN_BYTES: usize = 32;
+
+/// Generate a random, URL-safe opaque token
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; TOKEN_BYTES];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Django's `get_random_string`: `length` characters drawn from `allowed_chars`
+pub fn get_random_string(length: usize, allowed_chars: &[u8]) -> String {
+    let mut rng = rand::thread_rng();
+    (0..length)
+        .map(|_| allowed_chars[rng.gen_range(0..allowed_chars.len())] as char)
+        .collect()
+}
+
+/// Hash an opaque token for storage. Tokens carry enough entropy that a
+/// single SHA-256 round is sufficient, unlike passwords.
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// PKCE `S256` code challenge of a code verifier (RFC 7636)
+pub fn pkce_challenge(code_verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_token_is_unique() {
+        let token1 = generate_token();
+        let token2 = generate_token();
+
+        assert_ne!(token1, token2);
+        assert_eq!(token1.len(), 43);
+    }
+
+    #[test]
+    fn test_hash_token_is_stable() {
+        let token = generate_token();
+
+        assert_eq!(hash_token(&token), hash_token(&token));
+        assert_eq!(hash_token(&token).len(), 64);
+        assert_ne!(hash_token(&token), token);
+    }
+
+    #[test]
+    fn test_pkce_challenge_matches_rfc_7636() {
+        // Appendix B of RFC 7636
+        assert_eq!(
+            pkce_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+}
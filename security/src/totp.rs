@@ -0,0 +1,235 @@
+//! Time-based one-time passwords (RFC 6238), as used by authenticator apps.
+
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha1::Sha1;
+use std::time::{SystemTime, UNIX_EPOCH};
+use subtle::ConstantTimeEq;
+
+const SECRET_BYTES: usize = 20;
+const RECOVERY_CODE_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+
+/// A TOTP generator with the parameters every authenticator app supports:
+/// HMAC-SHA1, 6 digits and 30 second steps
+#[derive(Clone)]
+pub struct Totp {
+    secret: Vec<u8>,
+    digits: u32,
+    step: u64,
+}
+
+impl Totp {
+    pub fn new(secret: Vec<u8>) -> Self {
+        Self {
+            secret,
+            digits: 6,
+            step: 30,
+        }
+    }
+
+    pub fn with_digits(mut self, digits: u32) -> Self {
+        self.digits = digits;
+        self
+    }
+
+    /// Fresh random 160-bit secret, the length RFC 4226 recommends
+    pub fn generate() -> Self {
+        let mut secret = vec![0u8; SECRET_BYTES];
+        rand::thread_rng().fill_bytes(&mut secret);
+        Self::new(secret)
+    }
+
+    /// Parse a base32 secret as shown to users, ignoring case and spaces
+    pub fn from_base32(encoded: &str) -> Option<Self> {
+        let normalized: String = encoded
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != '=')
+            .map(|c| c.to_ascii_uppercase())
+            .collect();
+        BASE32_NOPAD
+            .decode(normalized.as_bytes())
+            .ok()
+            .map(Self::new)
+    }
+
+    pub fn secret(&self) -> &[u8] {
+        &self.secret
+    }
+
+    /// The secret as base32, for manual entry into an authenticator app
+    pub fn secret_base32(&self) -> String {
+        BASE32_NOPAD.encode(&self.secret)
+    }
+
+    /// Time step a Unix timestamp falls in
+    pub fn time_step(&self, unix_time: u64) -> u64 {
+        unix_time / self.step
+    }
+
+    /// Code for a time step (RFC 4226 HOTP)
+    pub fn code_at_step(&self, step: u64) -> String {
+        let mut mac =
+            Hmac::<Sha1>::new_from_slice(&self.secret).expect("HMAC accepts any key length");
+        mac.update(&step.to_be_bytes());
+        let digest = mac.finalize().into_bytes();
+
+        let offset = (digest[19] & 0x0f) as usize;
+        let binary = u32::from_be_bytes(digest[offset..offset + 4].try_into().expect("4 bytes"))
+            & 0x7fff_ffff;
+        format!(
+            "{:0width$}",
+            binary % 10u32.pow(self.digits),
+            width = self.digits as usize
+        )
+    }
+
+    pub fn code_at(&self, unix_time: u64) -> String {
+        self.code_at_step(self.time_step(unix_time))
+    }
+
+    /// Check a code at `unix_time`, accepting `skew` steps of clock drift either
+    /// way. Returns the matching time step so callers can refuse to accept it
+    /// twice.
+    pub fn verify_at(&self, code: &str, unix_time: u64, skew: u64) -> Option<u64> {
+        let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+        if code.len() != self.digits as usize {
+            return None;
+        }
+
+        let current = self.time_step(unix_time);
+        (current.saturating_sub(skew)..=current + skew)
+            .find(|step| bool::from(self.code_at_step(*step).as_bytes().ct_eq(code.as_bytes())))
+    }
+
+    /// Check a code against the current time with one step of drift
+    pub fn verify(&self, code: &str) -> Option<u64> {
+        self.verify_at(code, unix_now(), 1)
+    }
+
+    /// `otpauth://` URI that authenticator apps import from a QR code
+    pub fn provisioning_uri(&self, issuer: &str, account: &str) -> String {
+        let label = format!("{}:{}", issuer, account);
+        format!(
+            "otpauth://totp/{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+            percent_encode(&label),
+            self.secret_base32(),
+            percent_encode(issuer),
+            self.digits,
+            self.step
+        )
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system clock is after 1970")
+        .as_secs()
+}
+
+/// Percent-encode everything but unreserved characters (RFC 3986)
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Random single-use recovery code such as `k7qm-2xwp-9cfh-tz3d`, without
+/// characters that are easily confused
+pub fn generate_recovery_code() -> String {
+    let mut rng = rand::thread_rng();
+    let chars: Vec<char> = (0..16)
+        .map(|_| {
+            let index = (rng.next_u32() as usize) % RECOVERY_CODE_ALPHABET.len();
+            RECOVERY_CODE_ALPHABET[index] as char
+        })
+        .collect();
+    chars
+        .chunks(4)
+        .map(|chunk| chunk.iter().collect::<String>())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Canonical form of a recovery code as typed by a user
+pub fn normalize_recovery_code(code: &str) -> String {
+    let chars: Vec<char> = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    chars
+        .chunks(4)
+        .map(|chunk| chunk.iter().collect::<String>())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rfc6238_vectors() {
+        // RFC 6238 appendix B, SHA-1 secret "12345678901234567890"
+        let totp = Totp::new(b"12345678901234567890".to_vec()).with_digits(8);
+
+        assert_eq!(totp.code_at(59), "94287082");
+        assert_eq!(totp.code_at(1_111_111_109), "07081804");
+        assert_eq!(totp.code_at(1_234_567_890), "89005924");
+        assert_eq!(totp.code_at(20_000_000_000), "65353130");
+    }
+
+    #[test]
+    fn test_verify_allows_drift_and_reports_step() {
+        let totp = Totp::generate();
+        let now = 1_700_000_000;
+        let previous = totp.code_at(now - 30);
+
+        assert_eq!(
+            totp.verify_at(&previous, now, 1),
+            Some(totp.time_step(now) - 1)
+        );
+        assert_eq!(totp.verify_at(&previous, now, 0), None);
+        assert_eq!(totp.verify_at(&totp.code_at(now - 90), now, 1), None);
+        assert_eq!(totp.verify_at("12345", now, 1), None);
+    }
+
+    #[test]
+    fn test_base32_and_uri() {
+        let totp = Totp::new(b"12345678901234567890".to_vec());
+        let encoded = totp.secret_base32();
+
+        assert_eq!(encoded, "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+        assert_eq!(
+            Totp::from_base32("gezd gnbv gy3t qojq gezd gnbv gy3t qojq")
+                .unwrap()
+                .secret(),
+            totp.secret()
+        );
+        assert_eq!(
+            totp.provisioning_uri("R-Web", "ada@example.com"),
+            "otpauth://totp/R-Web%3Aada%40example.com?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ\
+             &issuer=R-Web&algorithm=SHA1&digits=6&period=30"
+        );
+    }
+
+    #[test]
+    fn test_recovery_code_format() {
+        let code = generate_recovery_code();
+
+        assert_eq!(code.len(), 19);
+        assert_eq!(
+            normalize_recovery_code(&code.to_uppercase().replace('-', " ")),
+            code
+        );
+        assert_ne!(code, generate_recovery_code());
+    }
+}
@@ -0,0 +1,642 @@
+//! The relying party side of WebAuthn (passkeys): checking the responses of
+//! the registration and authentication ceremonies.
+//!
+//! Credentials are ES256 or RS256 keys, which every platform and roaming
+//! authenticator supports. Attestation statements are not checked; the
+//! relying party asks for `none`, so a credential is trusted for who
+//! registered it, not for the make of the authenticator.
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use ciborium::Value;
+use p256::ecdsa::signature::Verifier;
+use rsa::traits::SignatureScheme;
+use rsa::{BigUint, Pkcs1v15Sign, RsaPublicKey};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use subtle::ConstantTimeEq;
+
+/// COSE algorithm identifiers
+pub const ES256: i64 = -7;
+pub const RS256: i64 = -257;
+
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_BACKUP_ELIGIBLE: u8 = 0x08;
+const FLAG_BACKED_UP: u8 = 0x10;
+const FLAG_ATTESTED_CREDENTIAL: u8 = 0x40;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WebauthnError {
+    /// `clientDataJSON` is malformed, or for another ceremony, challenge or
+    /// origin
+    InvalidClientData(&'static str),
+    /// Authenticator data, attestation object or key could not be parsed
+    Malformed(&'static str),
+    /// The authenticator scoped the credential to another relying party
+    RpIdMismatch,
+    UserNotPresent,
+    UserNotVerified,
+    /// The credential key is not ES256 on P-256 or RS256
+    UnsupportedKey,
+    BadSignature,
+    /// The signature counter did not increase, a sign of a cloned
+    /// authenticator
+    CounterRegression,
+}
+
+impl fmt::Display for WebauthnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WebauthnError::InvalidClientData(reason) => {
+                write!(f, "Invalid client data: {}", reason)
+            }
+            WebauthnError::Malformed(reason) => write!(f, "Malformed response: {}", reason),
+            WebauthnError::RpIdMismatch => write!(f, "Credential belongs to another site"),
+            WebauthnError::UserNotPresent => write!(f, "User presence was not confirmed"),
+            WebauthnError::UserNotVerified => write!(f, "User verification is required"),
+            WebauthnError::UnsupportedKey => write!(f, "Unsupported credential key"),
+            WebauthnError::BadSignature => write!(f, "Signature does not match"),
+            WebauthnError::CounterRegression => write!(f, "Signature counter went backwards"),
+        }
+    }
+}
+
+impl std::error::Error for WebauthnError {}
+
+/// Response of `navigator.credentials.create()`, decoded from base64url
+pub struct RegistrationResponse {
+    pub client_data_json: Vec<u8>,
+    pub attestation_object: Vec<u8>,
+}
+
+/// Response of `navigator.credentials.get()`, decoded from base64url
+pub struct AssertionResponse {
+    pub client_data_json: Vec<u8>,
+    pub authenticator_data: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+/// A credential created by a successful registration
+#[derive(Debug, Clone)]
+pub struct NewCredential {
+    pub credential_id: Vec<u8>,
+    /// COSE_Key of the credential, kept to verify later assertions
+    pub public_key: Vec<u8>,
+    pub sign_count: u32,
+    /// Model of the authenticator, all zeros when it does not say
+    pub aaguid: [u8; 16],
+    pub user_verified: bool,
+    /// The credential may be synced to other devices, as passkeys are
+    pub backup_eligible: bool,
+    pub backed_up: bool,
+}
+
+/// What a successful assertion reported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VerifiedAssertion {
+    pub sign_count: u32,
+    pub user_verified: bool,
+    pub backed_up: bool,
+}
+
+#[derive(Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    kind: String,
+    challenge: String,
+    origin: String,
+    #[serde(rename = "crossOrigin", default)]
+    cross_origin: bool,
+}
+
+struct AuthenticatorData<'a> {
+    rp_id_hash: &'a [u8],
+    flags: u8,
+    sign_count: u32,
+    attested: Option<AttestedCredential>,
+}
+
+struct AttestedCredential {
+    aaguid: [u8; 16],
+    credential_id: Vec<u8>,
+    public_key: Vec<u8>,
+}
+
+impl<'a> AuthenticatorData<'a> {
+    fn parse(data: &'a [u8]) -> Result<Self, WebauthnError> {
+        if data.len() < 37 {
+            return Err(WebauthnError::Malformed("authenticator data is too short"));
+        }
+        let flags = data[32];
+        let sign_count = u32::from_be_bytes(data[33..37].try_into().expect("4 bytes"));
+
+        let attested = if flags & FLAG_ATTESTED_CREDENTIAL != 0 {
+            let rest = &data[37..];
+            if rest.len() < 18 {
+                return Err(WebauthnError::Malformed("attested credential is too short"));
+            }
+            let aaguid: [u8; 16] = rest[..16].try_into().expect("16 bytes");
+            let id_len = u16::from_be_bytes([rest[16], rest[17]]) as usize;
+            let rest = &rest[18..];
+            if rest.len() < id_len {
+                return Err(WebauthnError::Malformed("credential id is truncated"));
+            }
+            let (credential_id, mut key) = rest.split_at(id_len);
+
+            // The key is followed by extensions, so only its own bytes are kept
+            let before = key.len();
+            let _: Value = ciborium::from_reader(&mut key)
+                .map_err(|_| WebauthnError::Malformed("credential key is not CBOR"))?;
+            let key_len = before - key.len();
+
+            Some(AttestedCredential {
+                aaguid,
+                credential_id: credential_id.to_vec(),
+                public_key: rest[id_len..id_len + key_len].to_vec(),
+            })
+        } else {
+            None
+        };
+
+        Ok(Self {
+            rp_id_hash: &data[..32],
+            flags,
+            sign_count,
+            attested,
+        })
+    }
+
+    fn has(&self, flag: u8) -> bool {
+        self.flags & flag != 0
+    }
+}
+
+/// A credential public key decoded from its COSE_Key
+enum PublicKey {
+    Es256(p256::ecdsa::VerifyingKey),
+    Rs256(RsaPublicKey),
+}
+
+fn cose_field(map: &[(Value, Value)], label: i64) -> Option<&Value> {
+    map.iter()
+        .find(|(key, _)| {
+            key.as_integer()
+                .is_some_and(|key| i128::from(key) == label as i128)
+        })
+        .map(|(_, value)| value)
+}
+
+fn cose_int(map: &[(Value, Value)], label: i64) -> Option<i128> {
+    cose_field(map, label)?.as_integer().map(i128::from)
+}
+
+fn cose_bytes(map: &[(Value, Value)], label: i64) -> Option<&[u8]> {
+    cose_field(map, label)?.as_bytes().map(Vec::as_slice)
+}
+
+impl PublicKey {
+    fn from_cose(bytes: &[u8]) -> Result<Self, WebauthnError> {
+        let value: Value = ciborium::from_reader(bytes)
+            .map_err(|_| WebauthnError::Malformed("credential key is not CBOR"))?;
+        let map = value
+            .as_map()
+            .ok_or(WebauthnError::Malformed("credential key is not a map"))?;
+
+        // kty, alg and, for EC2 keys, crv
+        match (cose_int(map, 1), cose_int(map, 3)) {
+            (Some(2), Some(alg)) if alg == ES256 as i128 => {
+                if cose_int(map, -1) != Some(1) {
+                    return Err(WebauthnError::UnsupportedKey);
+                }
+                let (Some(x), Some(y)) = (cose_bytes(map, -2), cose_bytes(map, -3)) else {
+                    return Err(WebauthnError::Malformed("EC2 key lacks coordinates"));
+                };
+                let point = [&[0x04], x, y].concat();
+                p256::ecdsa::VerifyingKey::from_sec1_bytes(&point)
+                    .map(PublicKey::Es256)
+                    .map_err(|_| WebauthnError::Malformed("EC2 key is not on P-256"))
+            }
+            (Some(3), Some(alg)) if alg == RS256 as i128 => {
+                let (Some(n), Some(e)) = (cose_bytes(map, -1), cose_bytes(map, -2)) else {
+                    return Err(WebauthnError::Malformed(
+                        "RSA key lacks modulus or exponent",
+                    ));
+                };
+                RsaPublicKey::new(BigUint::from_bytes_be(n), BigUint::from_bytes_be(e))
+                    .map(PublicKey::Rs256)
+                    .map_err(|_| WebauthnError::UnsupportedKey)
+            }
+            _ => Err(WebauthnError::UnsupportedKey),
+        }
+    }
+
+    fn verify(&self, message: &[u8], signature: &[u8]) -> Result<(), WebauthnError> {
+        match self {
+            PublicKey::Es256(key) => {
+                let signature = p256::ecdsa::Signature::from_der(signature)
+                    .map_err(|_| WebauthnError::BadSignature)?;
+                key.verify(message, &signature)
+                    .map_err(|_| WebauthnError::BadSignature)
+            }
+            PublicKey::Rs256(key) => Pkcs1v15Sign::new::<Sha256>()
+                .verify(key, &Sha256::digest(message), signature)
+                .map_err(|_| WebauthnError::BadSignature),
+        }
+    }
+}
+
+/// The site credentials are scoped to, and the origins allowed to use them
+#[derive(Debug, Clone)]
+pub struct RelyingParty {
+    /// Registrable domain, e.g. `example.com`
+    pub id: String,
+    /// Origins the browser may report, e.g. `https://app.example.com`
+    pub origins: Vec<String>,
+}
+
+impl RelyingParty {
+    pub fn new(id: impl Into<String>, origins: Vec<String>) -> Self {
+        Self {
+            id: id.into(),
+            origins,
+        }
+    }
+
+    fn check_client_data(
+        &self,
+        client_data_json: &[u8],
+        kind: &str,
+        challenge: &str,
+    ) -> Result<(), WebauthnError> {
+        let client_data: ClientData = serde_json::from_slice(client_data_json)
+            .map_err(|_| WebauthnError::InvalidClientData("not JSON"))?;
+        if client_data.kind != kind {
+            return Err(WebauthnError::InvalidClientData("wrong ceremony"));
+        }
+        if !bool::from(client_data.challenge.as_bytes().ct_eq(challenge.as_bytes())) {
+            return Err(WebauthnError::InvalidClientData("wrong challenge"));
+        }
+        if !self.origins.contains(&client_data.origin) || client_data.cross_origin {
+            return Err(WebauthnError::InvalidClientData("origin is not allowed"));
+        }
+        Ok(())
+    }
+
+    fn check_authenticator_data(
+        &self,
+        data: &AuthenticatorData<'_>,
+        require_user_verification: bool,
+    ) -> Result<(), WebauthnError> {
+        if data.rp_id_hash != Sha256::digest(self.id.as_bytes()).as_slice() {
+            return Err(WebauthnError::RpIdMismatch);
+        }
+        if !data.has(FLAG_USER_PRESENT) {
+            return Err(WebauthnError::UserNotPresent);
+        }
+        if require_user_verification && !data.has(FLAG_USER_VERIFIED) {
+            return Err(WebauthnError::UserNotVerified);
+        }
+        Ok(())
+    }
+
+    /// Check the response to a registration with `challenge`, returning the
+    /// new credential
+    pub fn verify_registration(
+        &self,
+        challenge: &str,
+        response: &RegistrationResponse,
+        require_user_verification: bool,
+    ) -> Result<NewCredential, WebauthnError> {
+        self.check_client_data(&response.client_data_json, "webauthn.create", challenge)?;
+
+        let attestation: Value = ciborium::from_reader(response.attestation_object.as_slice())
+            .map_err(|_| WebauthnError::Malformed("attestation object is not CBOR"))?;
+        let auth_data = attestation
+            .as_map()
+            .and_then(|map| {
+                map.iter()
+                    .find(|(key, _)| key.as_text() == Some("authData"))
+                    .and_then(|(_, value)| value.as_bytes())
+            })
+            .ok_or(WebauthnError::Malformed(
+                "attestation object lacks authData",
+            ))?;
+
+        let data = AuthenticatorData::parse(auth_data)?;
+        self.check_authenticator_data(&data, require_user_verification)?;
+        let attested = data
+            .attested
+            .as_ref()
+            .ok_or(WebauthnError::Malformed("no attested credential"))?;
+        // Only keys that can be verified later are accepted
+        PublicKey::from_cose(&attested.public_key)?;
+
+        Ok(NewCredential {
+            credential_id: attested.credential_id.clone(),
+            public_key: attested.public_key.clone(),
+            sign_count: data.sign_count,
+            aaguid: attested.aaguid,
+            user_verified: data.has(FLAG_USER_VERIFIED),
+            backup_eligible: data.has(FLAG_BACKUP_ELIGIBLE),
+            backed_up: data.has(FLAG_BACKED_UP),
+        })
+    }
+
+    /// Check the response to an authentication with `challenge`, signed by
+    /// the credential with COSE key `public_key` last seen at
+    /// `stored_sign_count`
+    pub fn verify_assertion(
+        &self,
+        challenge: &str,
+        response: &AssertionResponse,
+        public_key: &[u8],
+        stored_sign_count: u32,
+        require_user_verification: bool,
+    ) -> Result<VerifiedAssertion, WebauthnError> {
+        self.check_client_data(&response.client_data_json, "webauthn.get", challenge)?;
+
+        let data = AuthenticatorData::parse(&response.authenticator_data)?;
+        self.check_authenticator_data(&data, require_user_verification)?;
+
+        let message = [
+            response.authenticator_data.as_slice(),
+            Sha256::digest(&response.client_data_json).as_slice(),
+        ]
+        .concat();
+        PublicKey::from_cose(public_key)?.verify(&message, &response.signature)?;
+
+        // Authenticators without a counter always report zero
+        if (data.sign_count != 0 || stored_sign_count != 0) && data.sign_count <= stored_sign_count
+        {
+            return Err(WebauthnError::CounterRegression);
+        }
+
+        Ok(VerifiedAssertion {
+            sign_count: data.sign_count,
+            user_verified: data.has(FLAG_USER_VERIFIED),
+            backed_up: data.has(FLAG_BACKED_UP),
+        })
+    }
+}
+
+/// Encode binary values the way the WebAuthn JSON serialization does
+pub fn encode(bytes: &[u8]) -> String {
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+pub fn decode(value: &str) -> Option<Vec<u8>> {
+    URL_SAFE_NO_PAD.decode(value.trim_end_matches('=')).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use p256::ecdsa::SigningKey;
+    use p256::ecdsa::signature::Signer;
+    use rand::rngs::OsRng;
+
+    const ORIGIN: &str = "https://example.com";
+
+    fn rp() -> RelyingParty {
+        RelyingParty::new("example.com", vec![ORIGIN.to_string()])
+    }
+
+    fn cbor(value: &Value) -> Vec<u8> {
+        let mut out = Vec::new();
+        ciborium::into_writer(value, &mut out).unwrap();
+        out
+    }
+
+    fn client_data(kind: &str, challenge: &str, origin: &str) -> Vec<u8> {
+        serde_json::json!({ "type": kind, "challenge": challenge, "origin": origin })
+            .to_string()
+            .into_bytes()
+    }
+
+    /// A software authenticator holding one ES256 credential
+    struct SoftAuthenticator {
+        key: SigningKey,
+        credential_id: Vec<u8>,
+        sign_count: u32,
+        flags: u8,
+    }
+
+    impl SoftAuthenticator {
+        fn new() -> Self {
+            Self {
+                key: SigningKey::random(&mut OsRng),
+                credential_id: b"soft-credential".to_vec(),
+                sign_count: 0,
+                flags: FLAG_USER_PRESENT | FLAG_USER_VERIFIED | FLAG_BACKUP_ELIGIBLE,
+            }
+        }
+
+        fn cose_key(&self) -> Vec<u8> {
+            let point = self.key.verifying_key().to_encoded_point(false);
+            cbor(&Value::Map(vec![
+                (Value::from(1), Value::from(2)),
+                (Value::from(3), Value::from(ES256)),
+                (Value::from(-1), Value::from(1)),
+                (Value::from(-2), Value::Bytes(point.x().unwrap().to_vec())),
+                (Value::from(-3), Value::Bytes(point.y().unwrap().to_vec())),
+            ]))
+        }
+
+        fn authenticator_data(&self, rp_id: &str, attested: bool) -> Vec<u8> {
+            let mut data = Sha256::digest(rp_id.as_bytes()).to_vec();
+            let flags = if attested {
+                self.flags | FLAG_ATTESTED_CREDENTIAL
+            } else {
+                self.flags
+            };
+            data.push(flags);
+            data.extend(self.sign_count.to_be_bytes());
+            if attested {
+                data.extend([7u8; 16]);
+                data.extend((self.credential_id.len() as u16).to_be_bytes());
+                data.extend(&self.credential_id);
+                data.extend(self.cose_key());
+            }
+            data
+        }
+
+        /// `navigator.credentials.create()` with `none` attestation
+        fn register(&self, rp_id: &str, challenge: &str, origin: &str) -> RegistrationResponse {
+            let attestation = Value::Map(vec![
+                (Value::from("fmt"), Value::from("none")),
+                (Value::from("attStmt"), Value::Map(Vec::new())),
+                (
+                    Value::from("authData"),
+                    Value::Bytes(self.authenticator_data(rp_id, true)),
+                ),
+            ]);
+            RegistrationResponse {
+                client_data_json: client_data("webauthn.create", challenge, origin),
+                attestation_object: cbor(&attestation),
+            }
+        }
+
+        /// `navigator.credentials.get()`
+        fn assert(&mut self, rp_id: &str, challenge: &str, origin: &str) -> AssertionResponse {
+            self.sign_count += 1;
+            let client_data_json = client_data("webauthn.get", challenge, origin);
+            let authenticator_data = self.authenticator_data(rp_id, false);
+            let message = [
+                authenticator_data.as_slice(),
+                Sha256::digest(&client_data_json).as_slice(),
+            ]
+            .concat();
+            let signature: p256::ecdsa::Signature = self.key.sign(&message);
+
+            AssertionResponse {
+                client_data_json,
+                authenticator_data,
+                signature: signature.to_der().as_bytes().to_vec(),
+            }
+        }
+    }
+
+    #[test]
+    fn test_registration_ceremony() {
+        let authenticator = SoftAuthenticator::new();
+
+        let response = authenticator.register("example.com", "challenge-1", ORIGIN);
+        let credential = rp()
+            .verify_registration("challenge-1", &response, true)
+            .unwrap();
+        assert_eq!(credential.credential_id, b"soft-credential");
+        assert_eq!(credential.public_key, authenticator.cose_key());
+        assert_eq!(credential.aaguid, [7u8; 16]);
+        assert!(credential.user_verified && credential.backup_eligible);
+        assert!(!credential.backed_up);
+
+        assert_eq!(
+            rp().verify_registration("challenge-2", &response, true)
+                .unwrap_err(),
+            WebauthnError::InvalidClientData("wrong challenge")
+        );
+
+        let phished = authenticator.register("example.com", "challenge-1", "https://evil.test");
+        assert_eq!(
+            rp().verify_registration("challenge-1", &phished, true)
+                .unwrap_err(),
+            WebauthnError::InvalidClientData("origin is not allowed")
+        );
+
+        let other_rp = authenticator.register("evil.test", "challenge-1", ORIGIN);
+        assert_eq!(
+            rp().verify_registration("challenge-1", &other_rp, true)
+                .unwrap_err(),
+            WebauthnError::RpIdMismatch
+        );
+    }
+
+    #[test]
+    fn test_authentication_ceremony() {
+        let mut authenticator = SoftAuthenticator::new();
+        let credential = rp()
+            .verify_registration(
+                "register",
+                &authenticator.register("example.com", "register", ORIGIN),
+                true,
+            )
+            .unwrap();
+
+        let response = authenticator.assert("example.com", "login-1", ORIGIN);
+        let verified = rp()
+            .verify_assertion("login-1", &response, &credential.public_key, 0, true)
+            .unwrap();
+        assert_eq!(
+            verified,
+            VerifiedAssertion {
+                sign_count: 1,
+                user_verified: true,
+                backed_up: false,
+            }
+        );
+
+        // A replayed response does not advance the counter
+        assert_eq!(
+            rp().verify_assertion("login-1", &response, &credential.public_key, 1, true)
+                .unwrap_err(),
+            WebauthnError::CounterRegression
+        );
+
+        let mut tampered = authenticator.assert("example.com", "login-2", ORIGIN);
+        tampered.client_data_json = client_data("webauthn.get", "login-2", ORIGIN);
+        tampered.client_data_json.push(b' ');
+        assert_eq!(
+            rp().verify_assertion("login-2", &tampered, &credential.public_key, 1, true)
+                .unwrap_err(),
+            WebauthnError::BadSignature
+        );
+
+        let other_key = SoftAuthenticator::new().cose_key();
+        let response = authenticator.assert("example.com", "login-3", ORIGIN);
+        assert_eq!(
+            rp().verify_assertion("login-3", &response, &other_key, 1, true)
+                .unwrap_err(),
+            WebauthnError::BadSignature
+        );
+
+        let registration = client_data("webauthn.create", "login-4", ORIGIN);
+        let mut response = authenticator.assert("example.com", "login-4", ORIGIN);
+        response.client_data_json = registration;
+        assert_eq!(
+            rp().verify_assertion("login-4", &response, &credential.public_key, 1, true)
+                .unwrap_err(),
+            WebauthnError::InvalidClientData("wrong ceremony")
+        );
+    }
+
+    #[test]
+    fn test_user_verification_is_enforced_when_required() {
+        let mut authenticator = SoftAuthenticator::new();
+        authenticator.flags = FLAG_USER_PRESENT;
+        let credential = rp()
+            .verify_registration(
+                "register",
+                &authenticator.register("example.com", "register", ORIGIN),
+                false,
+            )
+            .unwrap();
+        assert!(!credential.user_verified);
+
+        let response = authenticator.assert("example.com", "login", ORIGIN);
+        assert_eq!(
+            rp().verify_assertion("login", &response, &credential.public_key, 0, true)
+                .unwrap_err(),
+            WebauthnError::UserNotVerified
+        );
+        assert!(
+            rp().verify_assertion("login", &response, &credential.public_key, 0, false)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_rs256_keys_are_supported() {
+        use rsa::RsaPrivateKey;
+        use rsa::traits::PublicKeyParts;
+
+        let private_key = RsaPrivateKey::new(&mut OsRng, 2048).unwrap();
+        let public_key = private_key.to_public_key();
+        let cose_key = cbor(&Value::Map(vec![
+            (Value::from(1), Value::from(3)),
+            (Value::from(3), Value::from(RS256)),
+            (Value::from(-1), Value::Bytes(public_key.n().to_bytes_be())),
+            (Value::from(-2), Value::Bytes(public_key.e().to_bytes_be())),
+        ]));
+
+        let key = PublicKey::from_cose(&cose_key).unwrap();
+        let signature = private_key
+            .sign(Pkcs1v15Sign::new::<Sha256>(), &Sha256::digest(b"message"))
+            .unwrap();
+        assert!(key.verify(b"message", &signature).is_ok());
+        assert_eq!(
+            key.verify(b"other message", &signature),
+            Err(WebauthnError::BadSignature)
+        );
+    }
+}
@@ -0,0 +1,381 @@
+use super::{AuthBackend, Credentials};
+use crate::config::LdapSettings;
+use crate::error::ApiError;
+use entity::auth_users::Model as User;
+use entity::{AuthError, AuthUserEntityExt, AuthUsers, CreateUserData};
+use ldap3::{LdapConnAsync, LdapConnSettings, LdapError, Scope, SearchEntry, dn_escape};
+use log::{error, warn};
+use sea_orm::DatabaseConnection;
+use std::time::Duration;
+
+/// django-auth-ldap's backend, which Django must have installed to accept
+/// cookie sessions this backend logged in
+pub const LDAP_BACKEND: &str = "django_auth_ldap.backend.LDAPBackend";
+
+/// `invalidCredentials` result code of a bind
+const INVALID_CREDENTIALS: u32 = 49;
+
+/// Attributes of a directory entry that are copied into a new account
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct DirectoryUser {
+    pub email: Option<String>,
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+}
+
+/// Binds to an LDAP directory as the user, like django-auth-ldap with
+/// `AUTH_LDAP_USER_DN_TEMPLATE`. The account is matched by username and
+/// created on the first login when `ldap.create_users` is set.
+pub struct LdapBackend {
+    settings: LdapSettings,
+}
+
+impl LdapBackend {
+    pub fn new(settings: LdapSettings) -> Self {
+        Self { settings }
+    }
+
+    fn user_dn(&self, username: &str) -> String {
+        self.settings
+            .user_dn_template
+            .replace("{username}", &dn_escape(username))
+    }
+
+    /// Bind as the user and read their entry; `None` when the directory
+    /// refuses the password
+    pub async fn bind(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<Option<DirectoryUser>, LdapError> {
+        let timeout = Duration::from_secs(self.settings.timeout);
+        let conn_settings = LdapConnSettings::new()
+            .set_conn_timeout(timeout)
+            .set_starttls(self.settings.start_tls);
+        let (conn, mut ldap) =
+            LdapConnAsync::with_settings(conn_settings, &self.settings.url).await?;
+        ldap3::drive!(conn);
+
+        let dn = self.user_dn(username);
+        let bind = ldap
+            .with_timeout(timeout)
+            .simple_bind(&dn, password)
+            .await?;
+        if bind.rc == INVALID_CREDENTIALS {
+            return Ok(None);
+        }
+        bind.success()?;
+
+        let attributes = [
+            self.settings.email_attribute.as_str(),
+            self.settings.first_name_attribute.as_str(),
+            self.settings.last_name_attribute.as_str(),
+        ];
+        let (entries, _) = ldap
+            .with_timeout(timeout)
+            .search(&dn, Scope::Base, "(objectClass=*)", attributes)
+            .await?
+            .success()?;
+        let _ = ldap.unbind().await;
+
+        let mut entry = entries
+            .into_iter()
+            .next()
+            .map(SearchEntry::construct)
+            .map(|entry| entry.attrs)
+            .unwrap_or_default();
+        let mut take = |attribute: &str| {
+            entry
+                .remove(attribute)
+                .and_then(|values| values.into_iter().next())
+        };
+
+        Ok(Some(DirectoryUser {
+            email: take(&self.settings.email_attribute),
+            first_name: take(&self.settings.first_name_attribute),
+            last_name: take(&self.settings.last_name_attribute),
+        }))
+    }
+
+    async fn create_user(
+        &self,
+        db: &DatabaseConnection,
+        username: &str,
+        directory_user: DirectoryUser,
+    ) -> Result<Option<User>, ApiError> {
+        let Some(email) = directory_user.email else {
+            warn!(
+                "LDAP user {} has no email address to create an account with",
+                username
+            );
+            return Ok(None);
+        };
+
+        let created = AuthUsers::create_user_without_password(
+            db,
+            CreateUserData {
+                email,
+                username: username.to_string(),
+                first_name: directory_user.first_name,
+                last_name: directory_user.last_name,
+                // The directory vouches for the address
+                is_verified: true,
+                ..Default::default()
+            },
+        )
+        .await;
+
+        match created {
+            Ok(user) => Ok(Some(user)),
+            Err(AuthError::EmailExists) => {
+                warn!(
+                    "LDAP user {} has the email address of another account",
+                    username
+                );
+                Ok(None)
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl AuthBackend for LdapBackend {
+    fn path(&self) -> &'static str {
+        LDAP_BACKEND
+    }
+
+    async fn authenticate(
+        &self,
+        db: &DatabaseConnection,
+        credentials: &Credentials<'_>,
+    ) -> Result<Option<User>, ApiError> {
+        let Credentials::Password { username, password } = credentials else {
+            return Ok(None);
+        };
+        // Directories take a bind without password as an anonymous bind
+        if username.is_empty() || password.is_empty() {
+            return Ok(None);
+        }
+
+        // An unreachable directory must not keep local accounts out, so the
+        // login goes on to the next backend, as with django-auth-ldap
+        let directory_user = match self.bind(username, password).await {
+            Ok(Some(directory_user)) => directory_user,
+            Ok(None) => return Ok(None),
+            Err(err) => {
+                error!("LDAP bind as {} failed: {}", username, err);
+                return Ok(None);
+            }
+        };
+
+        match AuthUsers::find_by_username(db, username).await? {
+            Some(user) => Ok(Some(user)),
+            None if self.settings.create_users => {
+                self.create_user(db, username, directory_user).await
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ldap3::asn1::{PL, StructureTag, TagClass, parse_tag};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    const ADA_DN: &str = "uid=ada,ou=people,dc=example,dc=com";
+
+    /// BER type-length-value
+    fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+        let mut out = vec![tag];
+        let len = content.len();
+        if len < 0x80 {
+            out.push(len as u8);
+        } else {
+            let bytes: Vec<u8> = len
+                .to_be_bytes()
+                .into_iter()
+                .skip_while(|byte| *byte == 0)
+                .collect();
+            out.push(0x80 | bytes.len() as u8);
+            out.extend(bytes);
+        }
+        out.extend_from_slice(content);
+        out
+    }
+
+    fn octets(value: &str) -> Vec<u8> {
+        tlv(0x04, value.as_bytes())
+    }
+
+    /// An LDAPMessage with the response `op`
+    fn message(id: &[u8], op: Vec<u8>) -> Vec<u8> {
+        tlv(0x30, &[tlv(0x02, id), op].concat())
+    }
+
+    /// LDAPResult with `code`, as in BindResponse and SearchResultDone
+    fn result(tag: u8, code: u8) -> Vec<u8> {
+        tlv(tag, &[tlv(0x0a, &[code]), octets(""), octets("")].concat())
+    }
+
+    fn children(tag: StructureTag) -> Vec<StructureTag> {
+        match tag.payload {
+            PL::C(children) => children,
+            PL::P(_) => Vec::new(),
+        }
+    }
+
+    fn primitive(tag: &StructureTag) -> &[u8] {
+        match &tag.payload {
+            PL::P(bytes) => bytes,
+            PL::C(_) => &[],
+        }
+    }
+
+    /// Answer one LDAPMessage of the client
+    fn respond(request: StructureTag) -> Option<Vec<u8>> {
+        let mut parts = children(request).into_iter();
+        let id = parts.next()?;
+        let op = parts.next()?;
+        let id = primitive(&id).to_vec();
+        assert_eq!(op.class, TagClass::Application);
+
+        match op.id {
+            // BindRequest: version, name, simple password
+            0 => {
+                let fields = children(op);
+                let dn = String::from_utf8_lossy(primitive(&fields[1])).to_string();
+                let password = primitive(&fields[2]);
+                let code = if dn == ADA_DN && password == b"analytical" {
+                    0
+                } else {
+                    49
+                };
+                Some(message(&id, result(0x61, code)))
+            }
+            // SearchRequest of the bound user's own entry
+            3 => {
+                let fields = children(op);
+                let base = String::from_utf8_lossy(primitive(&fields[0])).to_string();
+                let attribute = |name: &str, value: &str| {
+                    tlv(0x30, &[octets(name), tlv(0x31, &octets(value))].concat())
+                };
+                let attributes = [
+                    attribute("mail", "ada@example.com"),
+                    attribute("givenName", "Ada"),
+                    attribute("sn", "Lovelace"),
+                ]
+                .concat();
+                let entry = tlv(0x64, &[octets(&base), tlv(0x30, &attributes)].concat());
+
+                let mut out = message(&id, entry);
+                out.extend(message(&id, result(0x65, 0)));
+                Some(out)
+            }
+            // UnbindRequest
+            _ => None,
+        }
+    }
+
+    /// Serve a directory with one user, `ada`, on a free local port
+    async fn start_directory() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ldap://{}", listener.local_addr().unwrap());
+
+        tokio::spawn(async move {
+            loop {
+                let Ok((mut socket, _)) = listener.accept().await else {
+                    return;
+                };
+                tokio::spawn(async move {
+                    let mut buffer = Vec::new();
+                    let mut chunk = [0u8; 4096];
+                    loop {
+                        let Ok(read) = socket.read(&mut chunk).await else {
+                            return;
+                        };
+                        if read == 0 {
+                            return;
+                        }
+                        buffer.extend_from_slice(&chunk[..read]);
+
+                        while let Ok((rest, request)) = parse_tag(&buffer) {
+                            let consumed = buffer.len() - rest.len();
+                            let response = respond(request);
+                            buffer.drain(..consumed);
+                            match response {
+                                Some(bytes) => socket.write_all(&bytes).await.unwrap(),
+                                None => return,
+                            }
+                        }
+                    }
+                });
+            }
+        });
+        url
+    }
+
+    fn settings(url: String) -> LdapSettings {
+        LdapSettings {
+            url,
+            start_tls: false,
+            user_dn_template: "uid={username},ou=people,dc=example,dc=com".to_string(),
+            email_attribute: "mail".to_string(),
+            first_name_attribute: "givenName".to_string(),
+            last_name_attribute: "sn".to_string(),
+            create_users: true,
+            timeout: 5,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bind_against_mock_directory() {
+        let backend = LdapBackend::new(settings(start_directory().await));
+
+        let user = backend.bind("ada", "analytical").await.unwrap();
+        assert_eq!(
+            user,
+            Some(DirectoryUser {
+                email: Some("ada@example.com".to_string()),
+                first_name: Some("Ada".to_string()),
+                last_name: Some("Lovelace".to_string()),
+            })
+        );
+
+        assert_eq!(backend.bind("ada", "wrong").await.unwrap(), None);
+        assert_eq!(backend.bind("grace", "analytical").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_directory_passes_on() {
+        // Nothing listens on the discard port
+        let backend = LdapBackend::new(settings("ldap://127.0.0.1:9".to_string()));
+        assert!(backend.bind("ada", "analytical").await.is_err());
+
+        let credentials = Credentials::Password {
+            username: "ada",
+            password: "analytical",
+        };
+        let db = DatabaseConnection::Disconnected;
+        assert!(
+            backend
+                .authenticate(&db, &credentials)
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_user_dn_is_escaped() {
+        let backend = LdapBackend::new(settings("ldap://localhost".to_string()));
+        assert_eq!(backend.user_dn("ada"), ADA_DN);
+        assert_eq!(
+            backend.user_dn("ada,ou=admins"),
+            "uid=ada\\2cou\\3dadmins,ou=people,dc=example,dc=com"
+        );
+    }
+}
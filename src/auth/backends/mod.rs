@@ -0,0 +1,263 @@
+//! Authentication backends, like Django's `AUTHENTICATION_BACKENDS`.
+//!
+//! `auth.backends` lists the backends in the order they are asked. Each
+//! one either vouches for the credentials, or passes them on to the next;
+//! the first that authenticates the user wins and is recorded with the
+//! login. Failed password logins are counted against the lockout policy
+//! once for the whole chain, not per backend.
+
+pub mod ldap;
+pub mod model;
+pub mod remote_user;
+
+pub use ldap::LdapBackend;
+pub use model::ModelBackend;
+pub use remote_user::RemoteUserBackend;
+
+use crate::config::Settings;
+use crate::error::ApiError;
+use entity::auth_users::Model as User;
+use entity::{AuthError, AuthUserEntityExt, AuthUsers, LoginAttempt};
+use sea_orm::DatabaseConnection;
+
+/// What a login presents, like the keyword arguments of Django's
+/// `authenticate`. Backends pass on the kinds they do not handle.
+#[derive(Debug, Clone, Copy)]
+pub enum Credentials<'a> {
+    /// Username or email address and password of a login form
+    Password {
+        username: &'a str,
+        password: &'a str,
+    },
+    /// A user a trusted reverse proxy authenticated
+    RemoteUser {
+        username: &'a str,
+        email: Option<&'a str>,
+    },
+}
+
+#[async_trait::async_trait]
+pub trait AuthBackend: Send + Sync {
+    /// Dotted path of the Django backend doing the same, stored in cookie
+    /// sessions so that Django accepts them
+    fn path(&self) -> &'static str;
+
+    /// The user the credentials belong to, or `None` to let the next
+    /// backend try. An error ends the login.
+    async fn authenticate(
+        &self,
+        db: &DatabaseConnection,
+        credentials: &Credentials<'_>,
+    ) -> Result<Option<User>, ApiError>;
+}
+
+/// A user and the backend that authenticated them
+#[derive(Debug, Clone)]
+pub struct Authenticated {
+    pub user: User,
+    /// [`AuthBackend::path`] of the backend
+    pub backend: &'static str,
+}
+
+/// The configured backends, in order
+pub struct BackendChain {
+    backends: Vec<Box<dyn AuthBackend>>,
+}
+
+impl BackendChain {
+    pub fn new(backends: Vec<Box<dyn AuthBackend>>) -> Self {
+        Self { backends }
+    }
+
+    pub fn from_settings(config: &Settings) -> anyhow::Result<Self> {
+        let backends = config
+            .auth
+            .backends
+            .iter()
+            .map(|name| -> anyhow::Result<Box<dyn AuthBackend>> {
+                Ok(match name.as_str() {
+                    "model" => Box::new(ModelBackend),
+                    "ldap" => Box::new(LdapBackend::new(config.ldap.clone())),
+                    "remote_user" => Box::new(RemoteUserBackend::new(config.remote_user.clone())),
+                    other => anyhow::bail!("Unknown authentication backend {}", other),
+                })
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self::new(backends))
+    }
+
+    /// Whether a backend with this path is configured
+    pub fn has(&self, path: &str) -> bool {
+        self.backends.iter().any(|backend| backend.path() == path)
+    }
+
+    /// Ask each backend in turn
+    async fn first_match(
+        &self,
+        db: &DatabaseConnection,
+        credentials: &Credentials<'_>,
+    ) -> Result<Option<Authenticated>, ApiError> {
+        for backend in &self.backends {
+            if let Some(user) = backend.authenticate(db, credentials).await? {
+                return Ok(Some(Authenticated {
+                    user,
+                    backend: backend.path(),
+                }));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Authenticate with the first backend that accepts the credentials.
+    /// Password logins are throttled per account and per `client_ip`.
+    pub async fn authenticate(
+        &self,
+        db: &DatabaseConnection,
+        credentials: &Credentials<'_>,
+        client_ip: Option<&str>,
+    ) -> Result<Authenticated, ApiError> {
+        let authenticated = match credentials {
+            Credentials::Password { username, .. } => {
+                self.authenticate_password(db, credentials, username, client_ip)
+                    .await?
+            }
+            Credentials::RemoteUser { .. } => self
+                .first_match(db, credentials)
+                .await?
+                .ok_or(AuthError::InvalidCredentials)?,
+        };
+
+        if !authenticated.user.is_active {
+            return Err(AuthError::InactiveAccount.into());
+        }
+        Ok(authenticated)
+    }
+
+    async fn authenticate_password(
+        &self,
+        db: &DatabaseConnection,
+        credentials: &Credentials<'_>,
+        username: &str,
+        client_ip: Option<&str>,
+    ) -> Result<Authenticated, ApiError> {
+        let user = AuthUsers::find_by_login(db, username).await?;
+        LoginAttempt::new(user.as_ref(), username)
+            .from_ip(client_ip)
+            .check(db, self.first_match(db, credentials))
+            .await?
+            .ok_or_else(|| AuthError::InvalidCredentials.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// Accepts one username, as the user with id `id`
+    struct Fake {
+        path: &'static str,
+        username: &'static str,
+        id: i32,
+    }
+
+    fn user(id: i32) -> User {
+        User {
+            id,
+            email: format!("user{}@example.com", id),
+            username: format!("user{}", id),
+            ..User::fixture()
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl AuthBackend for Fake {
+        fn path(&self) -> &'static str {
+            self.path
+        }
+
+        async fn authenticate(
+            &self,
+            _db: &DatabaseConnection,
+            credentials: &Credentials<'_>,
+        ) -> Result<Option<User>, ApiError> {
+            match credentials {
+                Credentials::Password { username, .. } if *username == self.username => {
+                    Ok(Some(user(self.id)))
+                }
+                Credentials::Password { username, .. } if *username == "locked" => {
+                    Err(ApiError::too_many_requests(
+                        "Too many failed attempts",
+                        Duration::from_secs(60),
+                    ))
+                }
+                _ => Ok(None),
+            }
+        }
+    }
+
+    fn password(username: &str) -> Credentials<'_> {
+        Credentials::Password {
+            username,
+            password: "secret",
+        }
+    }
+
+    #[tokio::test]
+    async fn test_first_backend_that_succeeds_wins() {
+        let chain = BackendChain::new(vec![
+            Box::new(Fake {
+                path: "first",
+                username: "ada",
+                id: 1,
+            }),
+            Box::new(Fake {
+                path: "second",
+                username: "ada",
+                id: 2,
+            }),
+            Box::new(Fake {
+                path: "third",
+                username: "grace",
+                id: 3,
+            }),
+        ]);
+        let db = DatabaseConnection::Disconnected;
+
+        let found = chain.first_match(&db, &password("ada")).await.unwrap();
+        let found = found.unwrap();
+        assert_eq!((found.user.id, found.backend), (1, "first"));
+
+        let found = chain.first_match(&db, &password("grace")).await.unwrap();
+        assert_eq!(found.unwrap().backend, "third");
+
+        assert!(
+            chain
+                .first_match(&db, &password("alan"))
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            chain
+                .first_match(
+                    &db,
+                    &Credentials::RemoteUser {
+                        username: "ada",
+                        email: None
+                    }
+                )
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        // An error ends the chain
+        assert!(matches!(
+            chain.first_match(&db, &password("locked")).await,
+            Err(ApiError::TooManyRequests { .. })
+        ));
+        assert!(chain.has("second"));
+        assert!(!chain.has("model"));
+    }
+}
@@ -0,0 +1,27 @@
+use super::{AuthBackend, Credentials};
+use crate::auth::session::MODEL_BACKEND;
+use crate::error::ApiError;
+use entity::auth_users::Model as User;
+use entity::{AuthUserEntityExt, AuthUsers};
+use sea_orm::DatabaseConnection;
+
+/// Passwords stored in `auth_users`, Django's `ModelBackend`
+pub struct ModelBackend;
+
+#[async_trait::async_trait]
+impl AuthBackend for ModelBackend {
+    fn path(&self) -> &'static str {
+        MODEL_BACKEND
+    }
+
+    async fn authenticate(
+        &self,
+        db: &DatabaseConnection,
+        credentials: &Credentials<'_>,
+    ) -> Result<Option<User>, ApiError> {
+        let Credentials::Password { username, password } = credentials else {
+            return Ok(None);
+        };
+        Ok(AuthUsers::verify_credentials(db, username, password).await?)
+    }
+}
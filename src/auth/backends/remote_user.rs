@@ -0,0 +1,141 @@
+use super::{AuthBackend, Credentials};
+use crate::auth::throttle::is_trusted_proxy;
+use crate::config::RemoteUserSettings;
+use crate::error::ApiError;
+use actix_web::HttpRequest;
+use entity::auth_users::Model as User;
+use entity::{AuthError, AuthUserEntityExt, AuthUsers, CreateUserData};
+use log::warn;
+use sea_orm::DatabaseConnection;
+
+pub const REMOTE_USER_BACKEND: &str = "django.contrib.auth.backends.RemoteUserBackend";
+
+/// Trusts the user a reverse proxy in front of the service authenticated,
+/// Django's `RemoteUserBackend`
+pub struct RemoteUserBackend {
+    settings: RemoteUserSettings,
+}
+
+impl RemoteUserBackend {
+    pub fn new(settings: RemoteUserSettings) -> Self {
+        Self { settings }
+    }
+}
+
+/// Username and email address the proxy sent, when the request came
+/// straight from one of `remote_user.trusted_proxies`. Anyone else could
+/// set the headers.
+pub fn remote_user(
+    req: &HttpRequest,
+    settings: &RemoteUserSettings,
+) -> Option<(String, Option<String>)> {
+    let peer = req.peer_addr()?.ip();
+    if !is_trusted_proxy(peer, &settings.trusted_proxies) {
+        return None;
+    }
+
+    let header = |name: &str| {
+        req.headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(str::to_string)
+    };
+    let username = header(&settings.header)?;
+    Some((username, header(&settings.email_header)))
+}
+
+#[async_trait::async_trait]
+impl AuthBackend for RemoteUserBackend {
+    fn path(&self) -> &'static str {
+        REMOTE_USER_BACKEND
+    }
+
+    async fn authenticate(
+        &self,
+        db: &DatabaseConnection,
+        credentials: &Credentials<'_>,
+    ) -> Result<Option<User>, ApiError> {
+        let Credentials::RemoteUser { username, email } = credentials else {
+            return Ok(None);
+        };
+
+        if let Some(user) = AuthUsers::find_by_username(db, username).await? {
+            return Ok(Some(user));
+        }
+        if !self.settings.create_users {
+            return Ok(None);
+        }
+
+        let Some(email) = email.or_else(|| username.contains('@').then_some(*username)) else {
+            warn!(
+                "Remote user {} has no email address to create an account with",
+                username
+            );
+            return Ok(None);
+        };
+
+        let created = AuthUsers::create_user_without_password(
+            db,
+            CreateUserData {
+                email: email.to_string(),
+                username: username.to_string(),
+                // The proxy vouches for the address
+                is_verified: true,
+                ..Default::default()
+            },
+        )
+        .await;
+
+        match created {
+            Ok(user) => Ok(Some(user)),
+            Err(AuthError::EmailExists) => {
+                warn!(
+                    "Remote user {} has the email address of another account",
+                    username
+                );
+                Ok(None)
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn settings() -> RemoteUserSettings {
+        RemoteUserSettings {
+            header: "X-Remote-User".to_string(),
+            email_header: "X-Remote-Email".to_string(),
+            trusted_proxies: vec!["10.0.0.1".to_string()],
+            create_users: true,
+        }
+    }
+
+    #[test]
+    fn test_headers_only_trusted_from_proxies() {
+        let request = |peer: &str| {
+            TestRequest::default()
+                .peer_addr(peer.parse().unwrap())
+                .insert_header(("X-Remote-User", "ada"))
+                .insert_header(("X-Remote-Email", "ada@example.com"))
+                .to_http_request()
+        };
+
+        assert_eq!(
+            remote_user(&request("10.0.0.1:4000"), &settings()),
+            Some(("ada".to_string(), Some("ada@example.com".to_string())))
+        );
+        assert_eq!(remote_user(&request("10.0.0.2:4000"), &settings()), None);
+
+        let without_user = TestRequest::default()
+            .peer_addr("10.0.0.1:4000".parse().unwrap())
+            .insert_header(("X-Remote-User", " "))
+            .to_http_request();
+        assert_eq!(remote_user(&without_user, &settings()), None);
+    }
+}
@@ -0,0 +1,184 @@
+//! Login sessions per device, listed and ended through `/me/sessions`.
+//!
+//! Every token login and every cookie session gets an `auth_user_sessions`
+//! row. Access tokens carry its id and refresh tokens point to it, so ending
+//! the row logs the device out.
+
+use crate::auth::throttle::client_ip;
+use crate::config::AuthSettings;
+use crate::error::ApiError;
+use crate::session::Session;
+use crate::state::AppState;
+use actix_web::HttpRequest;
+use actix_web::http::header;
+use entity::auth_user_sessions::Model as UserSession;
+use entity::auth_users::Model as User;
+use entity::{
+    AuthUserSessions, NewUserSession, SessionKind, UserSessionEntityExt, UserSessionModelExt,
+};
+use sea_orm::DatabaseConnection;
+
+/// Session data key holding the `auth_user_sessions` id of a cookie session
+pub const USER_SESSION_KEY: &str = "_auth_user_session_id";
+
+/// What is recorded about the device a login comes from
+#[derive(Debug, Clone, Default)]
+pub struct DeviceInfo {
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+}
+
+impl DeviceInfo {
+    pub fn from_request(req: &HttpRequest, trusted_proxies: &[String]) -> Self {
+        Self {
+            user_agent: req
+                .headers()
+                .get(header::USER_AGENT)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.chars().take(512).collect()),
+            ip_address: client_ip(req, trusted_proxies),
+        }
+    }
+
+    /// Readable name of the device, such as `Firefox on Linux`
+    pub fn label(&self) -> String {
+        device_label(self.user_agent.as_deref().unwrap_or_default())
+    }
+}
+
+fn device_label(user_agent: &str) -> String {
+    // Order matters: Edge and Opera also claim Chrome, Chrome also claims
+    // Safari, and Android also claims Linux
+    const BROWSERS: &[(&str, &str)] = &[
+        ("Edg/", "Edge"),
+        ("OPR/", "Opera"),
+        ("Firefox/", "Firefox"),
+        ("Chrome/", "Chrome"),
+        ("Safari/", "Safari"),
+        ("curl/", "curl"),
+    ];
+    const SYSTEMS: &[(&str, &str)] = &[
+        ("Windows", "Windows"),
+        ("Android", "Android"),
+        ("iPhone", "iOS"),
+        ("iPad", "iPadOS"),
+        ("Mac OS X", "macOS"),
+        ("CrOS", "ChromeOS"),
+        ("Linux", "Linux"),
+    ];
+    let find = |table: &[(&str, &'static str)]| {
+        table
+            .iter()
+            .find(|(marker, _)| user_agent.contains(marker))
+            .map(|(_, name)| *name)
+    };
+
+    match (find(BROWSERS), find(SYSTEMS)) {
+        (Some(browser), Some(system)) => format!("{} on {}", browser, system),
+        (Some(name), None) | (None, Some(name)) => name.to_string(),
+        (None, None) => "Unknown device".to_string(),
+    }
+}
+
+/// Record a new login from `device`, ending the oldest sessions of the user
+/// beyond `auth.max_sessions_per_user`
+pub async fn start(
+    db: &DatabaseConnection,
+    config: &AuthSettings,
+    user_id: i32,
+    kind: SessionKind,
+    device: DeviceInfo,
+) -> Result<UserSession, ApiError> {
+    let session = NewUserSession {
+        user_id,
+        kind,
+        label: device.label(),
+        user_agent: device.user_agent,
+        ip_address: device.ip_address,
+    };
+    Ok(AuthUserSessions::start(db, session, config.max_sessions_per_user).await?)
+}
+
+/// Record a cookie login of `user` and remember it in the session
+pub async fn start_cookie_session(
+    app_state: &AppState,
+    req: &HttpRequest,
+    session: &Session,
+    user: &User,
+) -> Result<UserSession, ApiError> {
+    let device = DeviceInfo::from_request(req, &app_state.config.lockout.trusted_proxies);
+    let started = start(
+        &app_state.db,
+        &app_state.config.auth,
+        user.id,
+        SessionKind::Cookie,
+        device,
+    )
+    .await?;
+    session
+        .insert(USER_SESSION_KEY, started.id)
+        .map_err(|e| ApiError::InternalServerError(e.to_string()))?;
+    Ok(started)
+}
+
+/// The recorded login of a cookie session, `None` once it was ended.
+///
+/// Sessions logged in by Django have no record yet and get one here.
+pub async fn cookie_session(
+    app_state: &AppState,
+    req: &HttpRequest,
+    session: &Session,
+    user: &User,
+) -> Result<Option<UserSession>, ApiError> {
+    let Some(id) = session.get::<i32>(USER_SESSION_KEY) else {
+        return Ok(Some(
+            start_cookie_session(app_state, req, session, user).await?,
+        ));
+    };
+
+    let Some(found) = AuthUserSessions::find_for_user(&app_state.db, user.id, id).await? else {
+        return Ok(None);
+    };
+    let ip = client_ip(req, &app_state.config.lockout.trusted_proxies);
+    found.touch(&app_state.db, ip.as_deref()).await?;
+    Ok(Some(found))
+}
+
+/// End the recorded login of a cookie session, if any
+pub async fn end_cookie_session(
+    app_state: &AppState,
+    session: &Session,
+    user_id: i32,
+) -> Result<(), ApiError> {
+    if let Some(id) = session.get::<i32>(USER_SESSION_KEY) {
+        AuthUserSessions::end(&app_state.db, user_id, id).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_device_label() {
+        assert_eq!(
+            device_label("Mozilla/5.0 (X11; Linux x86_64; rv:131.0) Gecko/20100101 Firefox/131.0"),
+            "Firefox on Linux"
+        );
+        assert_eq!(
+            device_label(
+                "Mozilla/5.0 (Linux; Android 14) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/130.0 Mobile Safari/537.36"
+            ),
+            "Chrome on Android"
+        );
+        assert_eq!(
+            device_label(
+                "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/130.0 Safari/537.36 Edg/130.0"
+            ),
+            "Edge on Windows"
+        );
+        assert_eq!(device_label("curl/8.5.0"), "curl");
+        assert_eq!(device_label(""), "Unknown device");
+    }
+}
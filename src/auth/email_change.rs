@@ -0,0 +1,117 @@
+use crate::config::Settings;
+use crate::mail::{Mail, MailError};
+use entity::auth_users::Model as User;
+use security::signing::{SignatureError, TimestampSigner};
+use std::time::Duration;
+use tera::Context;
+
+const SALT: &str = "r-web.auth.email-change";
+
+fn signer(secret_key: &str) -> TimestampSigner {
+    TimestampSigner::new(secret_key, SALT)
+}
+
+/// Signed token proving control of `new_email`, for the user's current
+/// address.
+///
+/// The current address is part of the signed value, so the link stops
+/// working once the address changes, whichever link changed it.
+pub fn make_token(secret_key: &str, user: &User, new_email: &str) -> String {
+    let value = serde_json::to_string(&(user.id, &user.email, new_email))
+        .expect("id and addresses serialize");
+    signer(secret_key).sign(&value)
+}
+
+/// Check an email change token, returning the user id, the address it was
+/// issued for and the new address
+pub fn check_token(
+    secret_key: &str,
+    token: &str,
+    max_age: Duration,
+) -> Result<(i32, String, String), SignatureError> {
+    let value = signer(secret_key).unsign(token, Some(max_age))?;
+    serde_json::from_str(&value).map_err(|_| SignatureError::BadSignature)
+}
+
+/// Link the user follows from the new address to confirm it
+pub fn confirmation_link(config: &Settings, token: &str) -> String {
+    let query = serde_urlencoded::to_string([("token", token)]).expect("token is a plain string");
+    format!("{}?{}", config.email_change.confirm_url, query)
+}
+
+/// Email a confirmation link to `new_email`
+pub async fn send_confirmation_email(
+    mail: &Mail,
+    config: &Settings,
+    user: &User,
+    new_email: &str,
+    locale: Option<&str>,
+) -> Result<(), MailError> {
+    let token = make_token(&config.auth.secret_key, user, new_email);
+
+    let mut context = Context::new();
+    context.insert("username", &user.username);
+    context.insert("link", &confirmation_link(config, &token));
+    context.insert("expires_hours", &(config.email_change.timeout / 3600));
+
+    mail.send_template(new_email, "email_change", locale, context)
+        .await
+}
+
+/// Tell the current address that a change to `new_email` was asked for,
+/// so that its owner notices when someone else did
+pub async fn send_notice_email(
+    mail: &Mail,
+    user: &User,
+    new_email: &str,
+    locale: Option<&str>,
+) -> Result<(), MailError> {
+    let mut context = Context::new();
+    context.insert("username", &user.username);
+    context.insert("new_email", new_email);
+
+    mail.send_template(&user.email, "email_change_notice", locale, context)
+        .await
+}
+
+/// Tell `owner` that someone asked to move another account to their
+/// address, instead of telling the one who asked that it is taken
+pub async fn send_taken_email(
+    mail: &Mail,
+    owner: &User,
+    locale: Option<&str>,
+) -> Result<(), MailError> {
+    let mut context = Context::new();
+    context.insert("username", &owner.username);
+
+    mail.send_template(&owner.email, "email_change_taken", locale, context)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_round_trip() {
+        let token = make_token("secret", &User::fixture(), "ada:new@example.com");
+        let ttl = Duration::from_secs(60);
+
+        assert_eq!(
+            check_token("secret", &token, ttl),
+            Ok((
+                42,
+                "ada@example.com".to_string(),
+                "ada:new@example.com".to_string()
+            ))
+        );
+        assert_eq!(
+            check_token("other-secret", &token, ttl),
+            Err(SignatureError::BadSignature)
+        );
+        assert_eq!(
+            check_token("secret", &token.replacen("42", "43", 1), ttl),
+            Err(SignatureError::BadSignature)
+        );
+    }
+}
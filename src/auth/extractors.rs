@@ -0,0 +1,474 @@
+use crate::auth::backends::Credentials;
+use crate::auth::backends::remote_user::{self, REMOTE_USER_BACKEND};
+use crate::auth::devices;
+use crate::auth::jwt::{self, TokenType};
+use crate::auth::mfa::{self, MfaStatus};
+use crate::auth::personal_access_tokens;
+use crate::auth::session;
+use crate::auth::throttle::client_ip;
+use crate::error::ApiError;
+use crate::oauth;
+use crate::session::Session;
+use crate::state::AppState;
+use actix_web::dev::Payload;
+use actix_web::http::header;
+use actix_web::{FromRequest, HttpMessage, HttpRequest, web};
+use apistos::ApiSecurity;
+use entity::auth_users::{Entity as AuthUsers, Model as User};
+use entity::{
+    AuthUsedTokens, AuthUserSessions, OAuthTokenModelExt, PersonalAccessTokenModelExt,
+    UsedTokenEntityExt, UserSessionEntityExt, UserSessionModelExt,
+};
+use sea_orm::EntityTrait;
+use std::future::Future;
+use std::ops::Deref;
+use std::pin::Pin;
+
+/// How the caller proved who they are
+#[derive(Debug, Clone)]
+pub enum Credential {
+    AccessToken {
+        jti: String,
+        /// Recorded login the token belongs to
+        session_id: i32,
+    },
+    /// Password only, the second factor is still due
+    MfaToken { jti: String },
+    /// Long-lived token limited to its scopes
+    PersonalAccessToken { id: i32, scopes: Vec<String> },
+    /// Token a user granted to an OAuth client, limited to its scopes
+    OAuthToken {
+        id: i32,
+        client_id: i32,
+        scopes: Vec<String>,
+    },
+    /// Session cookie of a browser login
+    Session {
+        session_key: String,
+        session_id: i32,
+    },
+    /// Headers of a trusted reverse proxy, see
+    /// [`RemoteUserBackend`](crate::auth::backends::RemoteUserBackend)
+    RemoteUser,
+}
+
+/// The authenticated, active user making the request.
+///
+/// Resolved from an `Authorization: Bearer <access token>` header, or from
+/// the headers of a trusted proxy or the session cookie when no such
+/// header is sent. The result is cached in the request extensions so that
+/// stacking several guards on one handler only hits the database once.
+/// Personal access tokens and OAuth tokens are refused unless the route
+/// asks for a scope, see [`RequireScope`](crate::auth::scopes::RequireScope).
+#[derive(Debug, Clone, ApiSecurity)]
+#[openapi_security(
+    name = "bearer_auth",
+    scheme(security_type(http(scheme = "bearer", bearer_format = "JWT")))
+)]
+pub struct CurrentUser {
+    pub user: User,
+    pub credential: Credential,
+}
+
+impl Deref for CurrentUser {
+    type Target = User;
+
+    fn deref(&self) -> &Self::Target {
+        &self.user
+    }
+}
+
+impl CurrentUser {
+    /// Whether the credential may be used where `scope` is required.
+    /// Access tokens carry every scope.
+    pub fn has_scope(&self, scope: &str) -> bool {
+        match &self.credential {
+            Credential::AccessToken { .. }
+            | Credential::Session { .. }
+            | Credential::RemoteUser => true,
+            Credential::MfaToken { .. } => false,
+            Credential::PersonalAccessToken { scopes, .. }
+            | Credential::OAuthToken { scopes, .. } => {
+                scopes.iter().any(|granted| granted == scope)
+            }
+        }
+    }
+
+    /// The recorded login behind the credential, as listed at `/me/sessions`
+    pub fn session_id(&self) -> Option<i32> {
+        match &self.credential {
+            Credential::AccessToken { session_id, .. } | Credential::Session { session_id, .. } => {
+                Some(*session_id)
+            }
+            Credential::MfaToken { .. }
+            | Credential::PersonalAccessToken { .. }
+            | Credential::OAuthToken { .. }
+            | Credential::RemoteUser => None,
+        }
+    }
+}
+
+pub(crate) fn bearer_token(req: &HttpRequest) -> Option<String> {
+    let value = req.headers().get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+    scheme
+        .eq_ignore_ascii_case("bearer")
+        .then(|| token.trim().to_string())
+}
+
+async fn resolve_credentials(req: &HttpRequest) -> Result<CurrentUser, ApiError> {
+    if let Some(current) = req.extensions().get::<CurrentUser>() {
+        return Ok(current.clone());
+    }
+
+    let app_state = req
+        .app_data::<web::Data<AppState>>()
+        .ok_or_else(|| ApiError::InternalServerError("AppState is not configured".to_string()))?;
+
+    let Some(token) = bearer_token(req) else {
+        let current = match resolve_remote_user(req, app_state).await? {
+            Some(current) => current,
+            None => resolve_session_user(req, app_state).await?,
+        };
+        req.extensions_mut().insert(current.clone());
+        return Ok(current);
+    };
+
+    let current = if personal_access_tokens::is_personal_access_token(&token) {
+        let (user, stored) = personal_access_tokens::authenticate(&app_state.db, &token).await?;
+        CurrentUser {
+            user,
+            credential: Credential::PersonalAccessToken {
+                scopes: stored.scope_list(),
+                id: stored.id,
+            },
+        }
+    } else if oauth::is_access_token(&token) {
+        let (user, stored) = oauth::authenticate(&app_state.db, &token).await?;
+        CurrentUser {
+            user,
+            credential: Credential::OAuthToken {
+                scopes: stored.scope_list(),
+                id: stored.id,
+                client_id: stored.client_id,
+            },
+        }
+    } else {
+        let claims = jwt::decode_token(&app_state.config.auth, &token, TokenType::Access)
+            .map_err(|_| ApiError::Unauthorized("Invalid or expired token".to_string()))?;
+        let user_id = claims
+            .user_id()
+            .ok_or_else(|| ApiError::Unauthorized("Invalid or expired token".to_string()))?;
+
+        let user = AuthUsers::find_by_id(user_id)
+            .one(app_state.db.as_ref())
+            .await?
+            .ok_or_else(|| ApiError::Unauthorized("Invalid or expired token".to_string()))?;
+
+        if !user.is_active {
+            return Err(ApiError::Unauthorized("Account is inactive".to_string()));
+        }
+
+        // Every access token is issued for a recorded login
+        let session_id = claims
+            .sid
+            .ok_or_else(|| ApiError::Unauthorized("Invalid or expired token".to_string()))?;
+        let session = AuthUserSessions::find_for_user(&app_state.db, user.id, session_id)
+            .await?
+            .ok_or_else(|| ApiError::Unauthorized("Session has ended".to_string()))?;
+        let ip = client_ip(req, &app_state.config.lockout.trusted_proxies);
+        session.touch(&app_state.db, ip.as_deref()).await?;
+
+        CurrentUser {
+            user,
+            credential: Credential::AccessToken {
+                jti: claims.jti,
+                session_id,
+            },
+        }
+    };
+
+    req.extensions_mut().insert(current.clone());
+    Ok(current)
+}
+
+/// The user a trusted proxy sent, when `auth.backends` has `remote_user`,
+/// like Django's `RemoteUserMiddleware`
+async fn resolve_remote_user(
+    req: &HttpRequest,
+    app_state: &AppState,
+) -> Result<Option<CurrentUser>, ApiError> {
+    if !app_state.auth_backends.has(REMOTE_USER_BACKEND) {
+        return Ok(None);
+    }
+    let Some((username, email)) = remote_user::remote_user(req, &app_state.config.remote_user)
+    else {
+        return Ok(None);
+    };
+
+    let credentials = Credentials::RemoteUser {
+        username: &username,
+        email: email.as_deref(),
+    };
+    let authenticated = app_state
+        .auth_backends
+        .authenticate(&app_state.db, &credentials, None)
+        .await?;
+
+    Ok(Some(CurrentUser {
+        user: authenticated.user,
+        credential: Credential::RemoteUser,
+    }))
+}
+
+async fn resolve_session_user(
+    req: &HttpRequest,
+    app_state: &AppState,
+) -> Result<CurrentUser, ApiError> {
+    let unauthorized = || ApiError::Unauthorized("Authentication required".to_string());
+    let Some(session) = req.extensions().get::<Session>().cloned() else {
+        return Err(unauthorized());
+    };
+
+    let user = session::session_user(&app_state.db, &session, &app_state.config.auth.secret_key)
+        .await?
+        .ok_or_else(unauthorized)?;
+
+    // The login was ended from another device
+    let Some(device) = devices::cookie_session(app_state, req, &session, &user).await? else {
+        session.flush();
+        return Err(unauthorized());
+    };
+
+    Ok(CurrentUser {
+        user,
+        credential: Credential::Session {
+            session_key: session.session_key().unwrap_or_default(),
+            session_id: device.id,
+        },
+    })
+}
+
+/// Resolve the caller, accepting a personal access token only when `scope`
+/// is given and granted to it
+pub(crate) async fn resolve_scoped_user(
+    req: &HttpRequest,
+    scope: Option<&str>,
+) -> Result<CurrentUser, ApiError> {
+    let current = resolve_credentials(req).await?;
+    match (&current.credential, scope) {
+        (Credential::PersonalAccessToken { .. }, None) => Err(ApiError::Forbidden(
+            "Personal access tokens are not accepted here".to_string(),
+        )),
+        (Credential::OAuthToken { .. }, None) => Err(ApiError::Forbidden(
+            "OAuth tokens are not accepted here".to_string(),
+        )),
+        (_, Some(scope)) if !current.has_scope(scope) => Err(ApiError::Forbidden(format!(
+            "Token is missing the {} scope",
+            scope
+        ))),
+        _ => Ok(current),
+    }
+}
+
+pub(crate) async fn resolve_current_user(req: &HttpRequest) -> Result<CurrentUser, ApiError> {
+    resolve_scoped_user(req, None).await
+}
+
+impl FromRequest for CurrentUser {
+    type Error = ApiError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        Box::pin(async move { resolve_current_user(&req).await })
+    }
+}
+
+/// The user of an access token or of an MFA pending token.
+///
+/// Two-factor enrollment accepts both, so that users who must enroll
+/// before logging in can do so. The MFA pending token is refused once the
+/// user has a second factor, which has to be passed instead. Never cached
+/// in the request extensions, where it could be mistaken for a fully
+/// authenticated [`CurrentUser`].
+#[derive(Debug, Clone, ApiSecurity)]
+#[openapi_security(
+    name = "bearer_auth",
+    scheme(security_type(http(scheme = "bearer", bearer_format = "JWT")))
+)]
+pub struct MfaUser(pub CurrentUser);
+
+impl Deref for MfaUser {
+    type Target = CurrentUser;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl MfaUser {
+    /// `jti` of the MFA token while only the password has been checked
+    pub fn pending_token(&self) -> Option<&str> {
+        match &self.credential {
+            Credential::MfaToken { jti } => Some(jti),
+            _ => None,
+        }
+    }
+}
+
+async fn resolve_mfa_user(req: &HttpRequest) -> Result<MfaUser, ApiError> {
+    let app_state = req
+        .app_data::<web::Data<AppState>>()
+        .ok_or_else(|| ApiError::InternalServerError("AppState is not configured".to_string()))?;
+
+    let token = bearer_token(req)
+        .ok_or_else(|| ApiError::Unauthorized("Authentication required".to_string()))?;
+    let Ok(claims) = jwt::decode_token(&app_state.config.auth, &token, TokenType::MfaPending)
+    else {
+        return Ok(MfaUser(resolve_current_user(req).await?));
+    };
+
+    let user = mfa_token_user(app_state, &claims).await?;
+    // Users with a second factor must pass it before changing their devices
+    if mfa::status(&app_state.db, &app_state.config, &user).await? != MfaStatus::EnrollmentRequired
+    {
+        return Err(ApiError::Unauthorized(
+            "Verify your second factor to finish logging in".to_string(),
+        ));
+    }
+    Ok(MfaUser(CurrentUser {
+        user,
+        credential: Credential::MfaToken { jti: claims.jti },
+    }))
+}
+
+/// Active user an MFA pending token was issued to
+pub(crate) async fn mfa_token_user(
+    app_state: &AppState,
+    claims: &jwt::Claims,
+) -> Result<User, ApiError> {
+    let invalid = || ApiError::Unauthorized("Invalid or expired token".to_string());
+    let user_id = claims.user_id().ok_or_else(invalid)?;
+
+    let user = AuthUsers::find_by_id(user_id)
+        .one(app_state.db.as_ref())
+        .await?
+        .ok_or_else(invalid)?;
+
+    if !user.is_active {
+        return Err(ApiError::Unauthorized("Account is inactive".to_string()));
+    }
+    Ok(user)
+}
+
+/// Refuse the MFA token `jti` if it finished a login already, before any
+/// recovery code is spent on it
+pub(crate) async fn ensure_mfa_token_unused(
+    app_state: &AppState,
+    jti: &str,
+) -> Result<(), ApiError> {
+    if AuthUsedTokens::is_used(&app_state.db, jti).await? {
+        return Err(ApiError::Unauthorized(
+            "Invalid or expired MFA token".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Use up the MFA token `jti`, so that it finishes at most one login
+pub(crate) async fn consume_mfa_token(app_state: &AppState, jti: &str) -> Result<(), ApiError> {
+    // Remembered for at least as long as the token is valid
+    let expires_at =
+        chrono::Utc::now().naive_utc() + chrono::Duration::seconds(app_state.config.mfa.token_ttl);
+    if !AuthUsedTokens::consume(&app_state.db, jti, expires_at).await? {
+        return Err(ApiError::Unauthorized(
+            "Invalid or expired MFA token".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+impl FromRequest for MfaUser {
+    type Error = ApiError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        Box::pin(async move { resolve_mfa_user(&req).await })
+    }
+}
+
+/// Refuse callers who are neither staff nor superusers
+pub(crate) fn ensure_staff(current: &CurrentUser) -> Result<(), ApiError> {
+    if !(current.is_staff || current.is_superuser) {
+        return Err(ApiError::Forbidden("Staff access required".to_string()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::app_state;
+    use actix_web::http::StatusCode;
+    use actix_web::test::{self, TestRequest};
+    use actix_web::{App, HttpResponse};
+    use jsonwebtoken::{Algorithm, EncodingKey, Header};
+    use sea_orm::{DbBackend, MockDatabase};
+
+    #[test]
+    fn test_bearer_token_parsing() {
+        let req = TestRequest::default()
+            .insert_header((header::AUTHORIZATION, "Bearer abc.def.ghi"))
+            .to_http_request();
+        assert_eq!(bearer_token(&req).as_deref(), Some("abc.def.ghi"));
+
+        let req = TestRequest::default()
+            .insert_header((header::AUTHORIZATION, "Basic dXNlcjpwYXNz"))
+            .to_http_request();
+        assert_eq!(bearer_token(&req), None);
+
+        let req = TestRequest::default().to_http_request();
+        assert_eq!(bearer_token(&req), None);
+    }
+
+    #[actix_web::test]
+    async fn test_access_token_without_session_is_refused() {
+        let user = User::fixture();
+        let db = MockDatabase::new(DbBackend::Postgres)
+            .append_query_results([vec![user.clone()]])
+            .into_connection();
+        let (state, _) = app_state(db);
+        let now = chrono::Utc::now().timestamp();
+        let claims = jwt::Claims {
+            sub: user.id.to_string(),
+            iss: state.config.auth.issuer.clone(),
+            iat: now,
+            exp: now + 60,
+            jti: "no-session".to_string(),
+            typ: TokenType::Access,
+            sid: None,
+        };
+        let token = jsonwebtoken::encode(
+            &Header::new(Algorithm::HS256),
+            &claims,
+            &EncodingKey::from_secret(state.config.auth.secret_key.as_bytes()),
+        )
+        .unwrap();
+
+        let app = test::init_service(App::new().app_data(web::Data::new(state)).route(
+            "/me",
+            web::get().to(|_user: CurrentUser| async { HttpResponse::Ok().finish() }),
+        ))
+        .await;
+        let req = test::TestRequest::get()
+            .uri("/me")
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::UNAUTHORIZED
+        );
+    }
+}
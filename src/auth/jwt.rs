@@ -0,0 +1,169 @@
+use crate::config::AuthSettings;
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenType {
+    Access,
+    /// Password was checked, a second factor is still due
+    MfaPending,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Claims {
+    /// User id
+    pub sub: String,
+    pub iss: String,
+    pub iat: i64,
+    pub exp: i64,
+    pub jti: String,
+    pub typ: TokenType,
+    /// Login session an access token belongs to, see `/me/sessions`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<i32>,
+}
+
+impl Claims {
+    pub fn user_id(&self) -> Option<i32> {
+        self.sub.parse().ok()
+    }
+}
+
+/// Sign an access token for the given user and login session, returning the
+/// token and its lifetime in seconds
+pub fn encode_access_token(
+    config: &AuthSettings,
+    user_id: i32,
+    session_id: i32,
+) -> Result<(String, i64), jsonwebtoken::errors::Error> {
+    encode_token(
+        config,
+        user_id,
+        TokenType::Access,
+        config.access_token_ttl,
+        Some(session_id),
+    )
+}
+
+/// Sign a token that only lets the user finish logging in with a second factor
+pub fn encode_mfa_token(
+    config: &AuthSettings,
+    user_id: i32,
+    ttl: i64,
+) -> Result<(String, i64), jsonwebtoken::errors::Error> {
+    encode_token(config, user_id, TokenType::MfaPending, ttl, None)
+}
+
+fn encode_token(
+    config: &AuthSettings,
+    user_id: i32,
+    typ: TokenType,
+    ttl: i64,
+    sid: Option<i32>,
+) -> Result<(String, i64), jsonwebtoken::errors::Error> {
+    let now = chrono::Utc::now().timestamp();
+    let claims = Claims {
+        sub: user_id.to_string(),
+        iss: config.issuer.clone(),
+        iat: now,
+        exp: now + ttl,
+        jti: uuid::Uuid::new_v4().to_string(),
+        typ,
+        sid,
+    };
+
+    let token = jsonwebtoken::encode(
+        &Header::new(Algorithm::HS256),
+        &claims,
+        &EncodingKey::from_secret(config.secret_key.as_bytes()),
+    )?;
+
+    Ok((token, ttl))
+}
+
+/// Verify signature, expiry, issuer and type of a token
+pub fn decode_token(
+    config: &AuthSettings,
+    token: &str,
+    expected: TokenType,
+) -> Result<Claims, jsonwebtoken::errors::Error> {
+    let mut validation = Validation::new(Algorithm::HS256);
+    validation.set_issuer(&[&config.issuer]);
+    validation.leeway = 0;
+
+    let data = jsonwebtoken::decode::<Claims>(
+        token,
+        &DecodingKey::from_secret(config.secret_key.as_bytes()),
+        &validation,
+    )?;
+
+    if data.claims.typ != expected {
+        return Err(jsonwebtoken::errors::ErrorKind::InvalidToken.into());
+    }
+
+    Ok(data.claims)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> AuthSettings {
+        AuthSettings {
+            secret_key: "test-secret".to_string(),
+            issuer: "r-web-test".to_string(),
+            access_token_ttl: 60,
+            refresh_token_ttl: 3600,
+            max_sessions_per_user: None,
+            backends: vec!["model".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_access_token_round_trip() {
+        let config = settings();
+        let (token, expires_in) = encode_access_token(&config, 42, 7).unwrap();
+        let claims = decode_token(&config, &token, TokenType::Access).unwrap();
+
+        assert_eq!(expires_in, 60);
+        assert_eq!(claims.user_id(), Some(42));
+        assert_eq!(claims.sid, Some(7));
+        assert_eq!(claims.iss, "r-web-test");
+    }
+
+    #[test]
+    fn test_token_signed_with_other_secret_is_rejected() {
+        let config = settings();
+        let (token, _) = encode_access_token(&config, 42, 7).unwrap();
+        let other = AuthSettings {
+            secret_key: "other-secret".to_string(),
+            ..settings()
+        };
+
+        assert!(decode_token(&other, &token, TokenType::Access).is_err());
+    }
+
+    #[test]
+    fn test_mfa_token_is_not_an_access_token() {
+        let config = settings();
+        let (token, expires_in) = encode_mfa_token(&config, 42, 300).unwrap();
+
+        assert_eq!(expires_in, 300);
+        assert!(decode_token(&config, &token, TokenType::Access).is_err());
+        let claims = decode_token(&config, &token, TokenType::MfaPending).unwrap();
+        assert_eq!(claims.user_id(), Some(42));
+        assert_eq!(claims.sid, None);
+    }
+
+    #[test]
+    fn test_expired_token_is_rejected() {
+        let config = AuthSettings {
+            access_token_ttl: -10,
+            ..settings()
+        };
+        let (token, _) = encode_access_token(&config, 42, 7).unwrap();
+
+        assert!(decode_token(&config, &token, TokenType::Access).is_err());
+    }
+}
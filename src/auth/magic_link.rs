@@ -0,0 +1,165 @@
+//! Passwordless login with single-use links sent by email.
+//!
+//! Only the SHA-256 hash of a link's token is stored. With
+//! `magic_link.bind_browser` the link also remembers the hash of a secret
+//! kept in the requesting browser's session, and is refused in any other.
+
+use crate::config::Settings;
+use crate::error::ApiError;
+use crate::mail::{Mail, MailError};
+use crate::session::Session;
+use entity::auth_magic_links::Model as MagicLink;
+use entity::auth_users::Model as User;
+use entity::{
+    AuthError, AuthMagicLinks, AuthUserModelExt, AuthUsers, MagicLinkEntityExt, NewMagicLink,
+};
+use sea_orm::{DatabaseConnection, EntityTrait};
+use security::signing::constant_time_compare;
+use security::token::{generate_token, hash_token};
+use tera::Context;
+
+/// Session key of the secret binding links to the browser
+pub const BROWSER_SESSION_KEY: &str = "_magic_link_browser";
+
+/// Secret of the browser session, created on first use, when links are
+/// bound to browsers
+pub fn browser_secret(config: &Settings, session: &Session) -> Result<Option<String>, ApiError> {
+    if !config.magic_link.bind_browser {
+        return Ok(None);
+    }
+    if let Some(secret) = session.get::<String>(BROWSER_SESSION_KEY) {
+        return Ok(Some(secret));
+    }
+
+    let secret = generate_token();
+    session
+        .insert(BROWSER_SESSION_KEY, &secret)
+        .map_err(|e| ApiError::InternalServerError(e.to_string()))?;
+    Ok(Some(secret))
+}
+
+/// Whether a link may be used by a browser holding `secret`
+fn browser_matches(browser_hash: Option<&str>, secret: Option<&str>) -> bool {
+    match (browser_hash, secret) {
+        (None, _) => true,
+        (Some(expected), Some(secret)) => constant_time_compare(expected, &hash_token(secret)),
+        (Some(_), None) => false,
+    }
+}
+
+/// Link the user follows to log in
+pub fn login_link(config: &Settings, token: &str) -> String {
+    let query = serde_urlencoded::to_string([("token", token)]).expect("token is a plain string");
+    format!("{}?{}", config.magic_link.login_url, query)
+}
+
+/// Store a new login link for the user, returning its token in clear text
+pub async fn issue(
+    db: &DatabaseConnection,
+    config: &Settings,
+    user: &User,
+    browser_secret: Option<&str>,
+) -> Result<String, ApiError> {
+    let token = generate_token();
+    let expires_at =
+        chrono::Utc::now().naive_utc() + chrono::Duration::seconds(config.magic_link.ttl);
+    AuthMagicLinks::issue(
+        db,
+        NewMagicLink {
+            user_id: user.id,
+            token_hash: hash_token(&token),
+            browser_hash: browser_secret.map(hash_token),
+            expires_at,
+        },
+    )
+    .await?;
+
+    Ok(token)
+}
+
+/// Email the login link with `token` to the user
+pub async fn send_login_email(
+    mail: &Mail,
+    config: &Settings,
+    user: &User,
+    token: &str,
+    locale: Option<&str>,
+) -> Result<(), MailError> {
+    let mut context = Context::new();
+    context.insert("username", &user.username);
+    context.insert("link", &login_link(config, token));
+    context.insert("expires_minutes", &(config.magic_link.ttl / 60));
+
+    mail.send_template(&user.email, "magic_link", locale, context)
+        .await
+}
+
+/// Find the unused link `token` belongs to and its active owner, without
+/// using it up yet
+pub async fn check(
+    db: &DatabaseConnection,
+    session: &Session,
+    token: &str,
+) -> Result<(MagicLink, User), ApiError> {
+    let invalid = || ApiError::Unauthorized("Invalid or expired login link".to_string());
+
+    let link = AuthMagicLinks::find_active_by_hash(db, &hash_token(token))
+        .await?
+        .ok_or_else(invalid)?;
+
+    let secret = session.get::<String>(BROWSER_SESSION_KEY);
+    if !browser_matches(link.browser_hash.as_deref(), secret.as_deref()) {
+        return Err(ApiError::Forbidden(
+            "Open the login link in the browser that asked for it".to_string(),
+        ));
+    }
+
+    let user = AuthUsers::find_by_id(link.user_id)
+        .one(db)
+        .await?
+        .ok_or_else(invalid)?;
+    if !user.is_active {
+        return Err(AuthError::InactiveAccount.into());
+    }
+
+    Ok((link, user))
+}
+
+/// Use up the link. Following it proves control of the email address, so
+/// the address is verified, and the user's other unused links are revoked.
+pub async fn consume(
+    db: &DatabaseConnection,
+    link: &MagicLink,
+    user: User,
+) -> Result<User, ApiError> {
+    if !AuthMagicLinks::consume(db, link.id).await? {
+        return Err(ApiError::Unauthorized(
+            "Invalid or expired login link".to_string(),
+        ));
+    }
+
+    let user = if user.is_verified {
+        user
+    } else {
+        user.verify_email(db).await?
+    };
+    AuthMagicLinks::revoke_unused(db, user.id).await?;
+    Ok(user)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_browser_binding() {
+        let secret = "browser-secret";
+        let bound = hash_token(secret);
+
+        assert!(browser_matches(None, None));
+        assert!(browser_matches(None, Some(secret)));
+        assert!(browser_matches(Some(&bound), Some(secret)));
+        assert!(!browser_matches(Some(&bound), Some("other-browser")));
+        assert!(!browser_matches(Some(&bound), None));
+    }
+}
@@ -0,0 +1,155 @@
+use crate::auth::passkeys;
+use crate::config::Settings;
+use crate::error::ApiError;
+use entity::auth_user_totp::Model as TotpDevice;
+use entity::auth_users::Model as User;
+use entity::{
+    AuthUserRecoveryCodes, AuthUserTotp, RecoveryCodeEntityExt, TotpDeviceEntityExt,
+    TotpDeviceModelExt,
+};
+use sea_orm::DatabaseConnection;
+use security::encryption::SecretBox;
+use security::token::hash_token;
+use security::totp::{Totp, generate_recovery_code, normalize_recovery_code};
+
+const SALT: &str = "r-web.auth.mfa.totp";
+
+/// Where a user stands with two-factor authentication at login
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MfaStatus {
+    /// No second factor needed
+    NotRequired,
+    /// A confirmed TOTP device must be used
+    Required,
+    /// The user must enroll a device before logging in
+    EnrollmentRequired,
+}
+
+fn secret_box(config: &Settings) -> SecretBox {
+    let key = config
+        .mfa
+        .encryption_key
+        .as_deref()
+        .unwrap_or(&config.auth.secret_key);
+    SecretBox::from_secret(key, SALT)
+}
+
+/// Whether the account type forces two-factor authentication
+pub fn is_mandatory(config: &Settings, user: &User) -> bool {
+    config.mfa.required_for_staff && (user.is_staff || user.is_superuser)
+}
+
+/// A confirmed TOTP device or any passkey turns two-factor authentication on
+pub async fn status(
+    db: &DatabaseConnection,
+    config: &Settings,
+    user: &User,
+) -> Result<MfaStatus, ApiError> {
+    let device = AuthUserTotp::find_for_user(db, user.id).await?;
+    Ok(match device {
+        Some(device) if device.is_confirmed() => MfaStatus::Required,
+        _ if passkeys::has_passkeys(db, user).await? => MfaStatus::Required,
+        _ if is_mandatory(config, user) => MfaStatus::EnrollmentRequired,
+        _ => MfaStatus::NotRequired,
+    })
+}
+
+/// Generate and store a new unconfirmed secret for the user
+pub async fn begin_enrollment(
+    db: &DatabaseConnection,
+    config: &Settings,
+    user: &User,
+) -> Result<Totp, ApiError> {
+    let totp = Totp::generate();
+    let secret_encrypted = secret_box(config).encrypt(totp.secret());
+    AuthUserTotp::begin_enrollment(db, user.id, secret_encrypted).await?;
+    Ok(totp)
+}
+
+fn decrypt(config: &Settings, device: &TotpDevice) -> Result<Totp, ApiError> {
+    let secret = secret_box(config)
+        .decrypt(&device.secret_encrypted)
+        .map_err(|e| ApiError::InternalServerError(e.to_string()))?;
+    Ok(Totp::new(secret))
+}
+
+/// Check a TOTP code of the user's device. Each code is accepted only once.
+async fn check_code(
+    db: &DatabaseConnection,
+    config: &Settings,
+    device: &TotpDevice,
+    code: &str,
+) -> Result<bool, ApiError> {
+    let Some(step) = decrypt(config, device)?.verify(code) else {
+        return Ok(false);
+    };
+    Ok(device.record_use(db, step as i64).await?)
+}
+
+/// Finish enrollment with a code from the new device, returning fresh
+/// recovery codes
+pub async fn confirm_enrollment(
+    db: &DatabaseConnection,
+    config: &Settings,
+    user: &User,
+    code: &str,
+) -> Result<Vec<String>, ApiError> {
+    let device = AuthUserTotp::find_for_user(db, user.id)
+        .await?
+        .ok_or_else(|| ApiError::BadRequest("Start two-factor enrollment first".to_string()))?;
+    if device.is_confirmed() {
+        return Err(ApiError::BadRequest(
+            "Two-factor authentication is already enabled".to_string(),
+        ));
+    }
+    if !check_code(db, config, &device, code).await? {
+        return Err(ApiError::BadRequest("Invalid code".to_string()));
+    }
+
+    device.confirm(db).await?;
+    regenerate_recovery_codes(db, config, user).await
+}
+
+/// Check a TOTP code or a recovery code for a user with a confirmed device
+pub async fn verify(
+    db: &DatabaseConnection,
+    config: &Settings,
+    user: &User,
+    code: Option<&str>,
+    recovery_code: Option<&str>,
+) -> Result<bool, ApiError> {
+    let Some(device) = AuthUserTotp::find_for_user(db, user.id)
+        .await?
+        .filter(|device| device.is_confirmed())
+    else {
+        return Ok(false);
+    };
+
+    match (code, recovery_code) {
+        (Some(code), _) => check_code(db, config, &device, code).await,
+        (None, Some(recovery_code)) => {
+            let code_hash = hash_token(&normalize_recovery_code(recovery_code));
+            Ok(AuthUserRecoveryCodes::consume(db, user.id, &code_hash).await?)
+        }
+        (None, None) => Ok(false),
+    }
+}
+
+/// Replace the user's recovery codes, returning the new codes in clear text.
+/// They are stored hashed and cannot be shown again.
+pub async fn regenerate_recovery_codes(
+    db: &DatabaseConnection,
+    config: &Settings,
+    user: &User,
+) -> Result<Vec<String>, ApiError> {
+    let codes: Vec<String> = (0..config.mfa.recovery_codes)
+        .map(|_| generate_recovery_code())
+        .collect();
+    let hashes = codes.iter().map(|code| hash_token(code)).collect();
+    AuthUserRecoveryCodes::replace_for_user(db, user.id, hashes).await?;
+    Ok(codes)
+}
+
+pub async fn disable(db: &DatabaseConnection, user: &User) -> Result<(), ApiError> {
+    Ok(AuthUserTotp::disable_for_user(db, user.id).await?)
+}
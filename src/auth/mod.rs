@@ -0,0 +1,16 @@
+pub mod backends;
+pub mod devices;
+pub mod email_change;
+pub mod extractors;
+pub mod jwt;
+pub mod magic_link;
+pub mod mfa;
+pub mod passkeys;
+pub mod password_reset;
+pub mod permissions;
+pub mod personal_access_tokens;
+pub mod scopes;
+pub mod session;
+pub mod throttle;
+pub mod tokens;
+pub mod verification;
@@ -0,0 +1,259 @@
+//! Passkeys: WebAuthn credentials used to log in without a password, or as
+//! a second factor after one.
+//!
+//! Each ceremony starts with options holding a random challenge, which is
+//! kept in the session until the browser's response comes back. A
+//! challenge is taken out of the session when it is checked, so every
+//! response is accepted at most once.
+
+use crate::config::Settings;
+use crate::error::ApiError;
+use crate::session::Session;
+use entity::auth_users::Model as User;
+use entity::webauthn_credentials::Model as Passkey;
+use entity::{
+    AuthError, AuthUsers, NewWebauthnCredential, WebauthnCredentialEntityExt,
+    WebauthnCredentialModelExt, WebauthnCredentials,
+};
+use log::warn;
+use sea_orm::{DatabaseConnection, EntityTrait};
+use security::signing::{constant_time_compare, salted_hmac};
+use security::token::generate_token;
+use security::webauthn::{self, AssertionResponse, RegistrationResponse, RelyingParty};
+use serde::{Deserialize, Serialize};
+
+/// Session data key of the ceremony in progress
+pub const CHALLENGE_SESSION_KEY: &str = "_passkey_challenge";
+
+const USER_HANDLE_SALT: &str = "r-web.auth.passkeys.user_handle";
+
+/// Transports browsers report, per the WebAuthn `AuthenticatorTransport` enum
+const KNOWN_TRANSPORTS: &[&str] = &["ble", "hybrid", "internal", "nfc", "smart-card", "usb"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Ceremony {
+    /// Adding a passkey to the account
+    Registration,
+    /// Logging in with a passkey alone
+    Login,
+    /// Using a passkey after the password
+    SecondFactor,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PendingCeremony {
+    ceremony: Ceremony,
+    challenge: String,
+    /// Who the ceremony is for; unknown until the response of a login
+    user_id: Option<i32>,
+    /// Unix timestamp of the options
+    started_at: i64,
+}
+
+pub fn relying_party(config: &Settings) -> RelyingParty {
+    RelyingParty::new(&config.webauthn.rp_id, config.webauthn.origins.clone())
+}
+
+/// Opaque id of the user in their passkeys, which must not reveal personal
+/// information
+pub fn user_handle(config: &Settings, user: &User) -> String {
+    webauthn::encode(&salted_hmac(
+        USER_HANDLE_SALT,
+        user.id.to_string().as_bytes(),
+        &config.auth.secret_key,
+    ))
+}
+
+/// Start a ceremony, returning the challenge for its options. A ceremony
+/// started earlier in the session is abandoned.
+pub fn begin(
+    session: &Session,
+    ceremony: Ceremony,
+    user_id: Option<i32>,
+) -> Result<String, ApiError> {
+    let pending = PendingCeremony {
+        ceremony,
+        challenge: generate_token(),
+        user_id,
+        started_at: chrono::Utc::now().timestamp(),
+    };
+    session
+        .insert(CHALLENGE_SESSION_KEY, &pending)
+        .map_err(|e| ApiError::InternalServerError(e.to_string()))?;
+    Ok(pending.challenge)
+}
+
+/// Take the pending ceremony out of the session, returning its challenge
+fn take(
+    config: &Settings,
+    session: &Session,
+    ceremony: Ceremony,
+    user_id: Option<i32>,
+) -> Result<String, ApiError> {
+    let pending = session
+        .remove(CHALLENGE_SESSION_KEY)
+        .and_then(|value| serde_json::from_value::<PendingCeremony>(value).ok())
+        .filter(|pending| pending.ceremony == ceremony && pending.user_id == user_id)
+        .ok_or_else(|| ApiError::BadRequest("Request passkey options first".to_string()))?;
+
+    if chrono::Utc::now().timestamp() - pending.started_at > config.webauthn.timeout {
+        return Err(ApiError::BadRequest(
+            "The passkey prompt timed out, please try again".to_string(),
+        ));
+    }
+    Ok(pending.challenge)
+}
+
+fn decode(field: &str, value: &str) -> Result<Vec<u8>, ApiError> {
+    webauthn::decode(value)
+        .ok_or_else(|| ApiError::BadRequest(format!("{} is not base64url", field)))
+}
+
+/// Check the response to a registration and store the new passkey
+#[allow(clippy::too_many_arguments)]
+pub async fn register(
+    db: &DatabaseConnection,
+    config: &Settings,
+    session: &Session,
+    user: &User,
+    name: &str,
+    client_data_json: &str,
+    attestation_object: &str,
+    transports: &[String],
+) -> Result<Passkey, ApiError> {
+    let challenge = take(config, session, Ceremony::Registration, Some(user.id))?;
+    let response = RegistrationResponse {
+        client_data_json: decode("clientDataJSON", client_data_json)?,
+        attestation_object: decode("attestationObject", attestation_object)?,
+    };
+    let credential = relying_party(config).verify_registration(&challenge, &response, false)?;
+
+    let credential_id = webauthn::encode(&credential.credential_id);
+    if WebauthnCredentials::find_by_credential_id(db, &credential_id)
+        .await?
+        .is_some()
+    {
+        return Err(ApiError::BadRequest(
+            "This passkey is already registered".to_string(),
+        ));
+    }
+
+    let transports: Vec<&str> = transports
+        .iter()
+        .map(String::as_str)
+        .filter(|transport| KNOWN_TRANSPORTS.contains(transport))
+        .collect();
+    Ok(WebauthnCredentials::register(
+        db,
+        NewWebauthnCredential {
+            user_id: user.id,
+            name: name.to_string(),
+            credential_id,
+            public_key: credential.public_key,
+            sign_count: credential.sign_count.into(),
+            transports: transports.join(" "),
+            aaguid: uuid::Uuid::from_bytes(credential.aaguid).to_string(),
+            backup_eligible: credential.backup_eligible,
+            backed_up: credential.backed_up,
+        },
+    )
+    .await?)
+}
+
+/// A `navigator.credentials.get()` response, still base64url encoded
+pub struct Assertion<'a> {
+    pub credential_id: &'a str,
+    pub client_data_json: &'a str,
+    pub authenticator_data: &'a str,
+    pub signature: &'a str,
+    pub user_handle: Option<&'a str>,
+}
+
+/// Check the response to a login, or to a second factor challenge of
+/// `user`, returning the active user the passkey belongs to.
+///
+/// Logging in with a passkey alone requires user verification, a PIN or
+/// biometric, so that the passkey is two factors on its own.
+pub async fn authenticate(
+    db: &DatabaseConnection,
+    config: &Settings,
+    session: &Session,
+    ceremony: Ceremony,
+    user: Option<&User>,
+    assertion: Assertion<'_>,
+) -> Result<User, ApiError> {
+    let invalid = || ApiError::Unauthorized("Invalid passkey".to_string());
+
+    let challenge = take(config, session, ceremony, user.map(|user| user.id))?;
+    let passkey = WebauthnCredentials::find_by_credential_id(db, assertion.credential_id)
+        .await?
+        .filter(|passkey| user.is_none_or(|user| user.id == passkey.user_id))
+        .ok_or_else(invalid)?;
+
+    let owner = match user {
+        Some(user) => user.clone(),
+        None => AuthUsers::find_by_id(passkey.user_id)
+            .one(db)
+            .await?
+            .ok_or_else(invalid)?,
+    };
+    if let Some(handle) = assertion.user_handle.filter(|handle| !handle.is_empty())
+        && !constant_time_compare(handle, &user_handle(config, &owner))
+    {
+        return Err(invalid());
+    }
+
+    let response = AssertionResponse {
+        client_data_json: decode("clientDataJSON", assertion.client_data_json)?,
+        authenticator_data: decode("authenticatorData", assertion.authenticator_data)?,
+        signature: decode("signature", assertion.signature)?,
+    };
+    let verified = relying_party(config)
+        .verify_assertion(
+            &challenge,
+            &response,
+            &passkey.public_key,
+            passkey.sign_count as u32,
+            ceremony == Ceremony::Login,
+        )
+        .map_err(|e| {
+            warn!("Passkey {} of user {} refused: {}", passkey.id, owner.id, e);
+            invalid()
+        })?;
+    passkey
+        .record_use(db, verified.sign_count.into(), verified.backed_up)
+        .await?;
+
+    if !owner.is_active {
+        return Err(AuthError::InactiveAccount.into());
+    }
+    Ok(owner)
+}
+
+/// Passkeys of the user, to exclude from a registration or allow in a
+/// second factor challenge
+pub async fn list(db: &DatabaseConnection, user: &User) -> Result<Vec<Passkey>, ApiError> {
+    Ok(WebauthnCredentials::list_for_user(db, user.id).await?)
+}
+
+pub async fn has_passkeys(db: &DatabaseConnection, user: &User) -> Result<bool, ApiError> {
+    Ok(WebauthnCredentials::count_for_user(db, user.id).await? > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_user_handle_is_opaque_and_stable() {
+        let mut config = Settings::new().unwrap();
+        let user = User::fixture();
+
+        let handle = user_handle(&config, &user);
+        assert_eq!(handle, user_handle(&config, &user));
+        assert!(webauthn::decode(&handle).unwrap().len() <= 64);
+
+        config.auth.secret_key = "another-secret".to_string();
+        assert_ne!(handle, user_handle(&config, &user));
+    }
+}
@@ -0,0 +1,52 @@
+use crate::config::Settings;
+use crate::mail::{Mail, MailError};
+use entity::auth_users::Model as User;
+use security::reset_token::{PasswordResetTokenGenerator, TokenUser, encode_uid};
+use tera::Context;
+
+fn generator(config: &Settings) -> PasswordResetTokenGenerator {
+    PasswordResetTokenGenerator::new(&config.auth.secret_key, config.password_reset.timeout)
+}
+
+fn token_user(user: &User) -> TokenUser<'_> {
+    TokenUser {
+        id: user.id,
+        password: &user.password,
+        last_login: Some(user.last_login),
+        email: &user.email,
+    }
+}
+
+/// Reset token bound to the user's current password and last login
+pub fn make_token(config: &Settings, user: &User) -> String {
+    generator(config).make_token(&token_user(user))
+}
+
+pub fn check_token(config: &Settings, user: &User, token: &str) -> bool {
+    generator(config).check_token(&token_user(user), token)
+}
+
+/// Link the user follows to choose a new password
+pub fn reset_link(config: &Settings, user: &User) -> String {
+    let uid = encode_uid(user.id);
+    let token = make_token(config, user);
+    let query = serde_urlencoded::to_string([("uid", uid.as_str()), ("token", token.as_str())])
+        .expect("uid and token are plain strings");
+    format!("{}?{}", config.password_reset.reset_url, query)
+}
+
+/// Email a password reset link to the user
+pub async fn send_reset_email(
+    mail: &Mail,
+    config: &Settings,
+    user: &User,
+    locale: Option<&str>,
+) -> Result<(), MailError> {
+    let mut context = Context::new();
+    context.insert("username", &user.username);
+    context.insert("link", &reset_link(config, user));
+    context.insert("expires_hours", &(config.password_reset.timeout / 3600));
+
+    mail.send_template(&user.email, "password_reset", locale, context)
+        .await
+}
@@ -0,0 +1,221 @@
+use crate::auth::extractors::{CurrentUser, ensure_staff, resolve_scoped_user};
+use crate::auth::scopes::{UsersRead, UsersWrite};
+use crate::error::ApiError;
+use crate::state::AppState;
+use actix_web::dev::Payload;
+use actix_web::{FromRequest, HttpRequest, web};
+use apistos::ApiSecurity;
+use entity::AuthUserModelExt;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::pin::Pin;
+
+/// A permission codename that a handler can require through [`RequirePermission`]
+pub trait Permission {
+    const CODENAME: &'static str;
+    /// Scope that lets personal access tokens use the permission; without
+    /// one they are refused
+    const SCOPE: Option<&'static str> = None;
+    /// Whether the caller must be staff as well, see [`Staff`]
+    const STAFF: bool = false;
+}
+
+/// The permission `P`, held by a staff member, like the views of Django's
+/// admin site: `RequirePermission<Staff<ViewUser>>`
+pub struct Staff<P>(PhantomData<P>);
+
+impl<P: Permission> Permission for Staff<P> {
+    const CODENAME: &'static str = P::CODENAME;
+    const SCOPE: Option<&'static str> = P::SCOPE;
+    const STAFF: bool = true;
+}
+
+/// Declare a marker type for a permission codename, optionally with the
+/// [`Scope`](crate::auth::scopes::Scope) personal access tokens need for it.
+///
+/// ```ignore
+/// permission!(ViewUser, "auth.view_user", UsersRead);
+///
+/// async fn list_users(user: RequirePermission<ViewUser>) { ... }
+/// ```
+#[macro_export]
+macro_rules! permission {
+    ($(#[$meta:meta])* $name:ident, $codename:expr) => {
+        $(#[$meta])*
+        pub struct $name;
+
+        impl $crate::auth::permissions::Permission for $name {
+            const CODENAME: &'static str = $codename;
+        }
+    };
+    ($(#[$meta:meta])* $name:ident, $codename:expr, $scope:ty) => {
+        $(#[$meta])*
+        pub struct $name;
+
+        impl $crate::auth::permissions::Permission for $name {
+            const CODENAME: &'static str = $codename;
+            const SCOPE: Option<&'static str> =
+                Some(<$scope as $crate::auth::scopes::Scope>::NAME);
+        }
+    };
+}
+
+permission!(AddUser, "auth.add_user", UsersWrite);
+permission!(ChangeUser, "auth.change_user", UsersWrite);
+permission!(DeleteUser, "auth.delete_user", UsersWrite);
+permission!(ViewUser, "auth.view_user", UsersRead);
+
+/// Requires the caller to hold the permission `P`, either directly or
+/// through one of their groups. Superusers always pass. Personal access
+/// tokens must also carry the scope of `P`.
+#[derive(ApiSecurity)]
+#[openapi_security(
+    name = "bearer_auth",
+    scheme(security_type(http(scheme = "bearer", bearer_format = "JWT")))
+)]
+pub struct RequirePermission<P: Permission> {
+    pub user: CurrentUser,
+    _permission: PhantomData<P>,
+}
+
+impl<P: Permission> Deref for RequirePermission<P> {
+    type Target = CurrentUser;
+
+    fn deref(&self) -> &Self::Target {
+        &self.user
+    }
+}
+
+impl<P: Permission + 'static> FromRequest for RequirePermission<P> {
+    type Error = ApiError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        Box::pin(async move {
+            let current = resolve_scoped_user(&req, P::SCOPE).await?;
+            let app_state = req.app_data::<web::Data<AppState>>().ok_or_else(|| {
+                ApiError::InternalServerError("AppState is not configured".to_string())
+            })?;
+
+            if P::STAFF {
+                ensure_staff(&current)?;
+            }
+            if !current.user.has_perm(&app_state.db, P::CODENAME).await? {
+                return Err(ApiError::Forbidden(format!(
+                    "Missing permission: {}",
+                    P::CODENAME
+                )));
+            }
+
+            Ok(RequirePermission {
+                user: current,
+                _permission: PhantomData,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{access_token, app_state, device_session};
+    use actix_web::http::{StatusCode, header};
+    use actix_web::{App, HttpResponse, test};
+    use entity::auth_users::Model as User;
+    use sea_orm::{DbBackend, MockDatabase, Value};
+    use std::collections::BTreeMap;
+
+    async fn view_users(_user: RequirePermission<ViewUser>) -> HttpResponse {
+        HttpResponse::Ok().finish()
+    }
+
+    async fn staff_view_users(_user: RequirePermission<Staff<ViewUser>>) -> HttpResponse {
+        HttpResponse::Ok().finish()
+    }
+
+    /// Status of a request for a `ViewUser` route by `user`, whose groups
+    /// grant `group_permissions`
+    async fn status(user: User, group_permissions: &[&str]) -> StatusCode {
+        status_of("/users", user, group_permissions).await
+    }
+
+    /// Like [`status`], for the route at `uri`
+    async fn status_of(uri: &str, user: User, group_permissions: &[&str]) -> StatusCode {
+        let groups: Vec<BTreeMap<&str, Value>> = group_permissions
+            .iter()
+            .map(|codename| BTreeMap::from([("codename", Value::from(*codename))]))
+            .collect();
+        let db = MockDatabase::new(DbBackend::Postgres)
+            .append_query_results([vec![user.clone()]])
+            .append_query_results([vec![device_session(&user, 1)]])
+            .append_query_results([Vec::<entity::auth_permission::Model>::new()])
+            .append_query_results([groups])
+            .into_connection();
+        let (state, _) = app_state(db);
+        let token = access_token(&state.config, &user, 1);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(state))
+                .route("/users", web::get().to(view_users))
+                .route("/staff/users", web::get().to(staff_view_users)),
+        )
+        .await;
+        let req = test::TestRequest::get()
+            .uri(uri)
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+            .to_request();
+        test::call_service(&app, req).await.status()
+    }
+
+    #[actix_web::test]
+    async fn test_require_permission() {
+        assert_eq!(
+            status(User::fixture(), &["auth.view_user"]).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(User::fixture(), &["auth.change_user"]).await,
+            StatusCode::FORBIDDEN
+        );
+
+        let superuser = User {
+            is_superuser: true,
+            ..User::fixture()
+        };
+        assert_eq!(status(superuser, &[]).await, StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn test_require_staff_permission() {
+        let staff = User {
+            is_staff: true,
+            ..User::fixture()
+        };
+        let granted = &["auth.view_user"];
+        assert_eq!(
+            status_of("/staff/users", staff.clone(), granted).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status_of("/staff/users", staff, &[]).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status_of("/staff/users", User::fixture(), granted).await,
+            StatusCode::FORBIDDEN,
+            "The permission alone is not enough"
+        );
+
+        let superuser = User {
+            is_superuser: true,
+            ..User::fixture()
+        };
+        assert_eq!(
+            status_of("/staff/users", superuser, &[]).await,
+            StatusCode::OK
+        );
+    }
+}
@@ -0,0 +1,70 @@
+use crate::error::ApiError;
+use entity::auth_personal_access_tokens::Model as PersonalAccessToken;
+use entity::auth_users::{Entity as AuthUsers, Model as User};
+use entity::{
+    AuthPersonalAccessTokens, NewPersonalAccessToken, PersonalAccessTokenEntityExt,
+    PersonalAccessTokenModelExt,
+};
+use sea_orm::prelude::DateTime;
+use sea_orm::{DatabaseConnection, EntityTrait};
+use security::token::{generate_token, hash_token};
+
+/// Marks personal access tokens, so they are told apart from JWTs and can be
+/// found by secret scanners
+pub const TOKEN_PREFIX: &str = "rsw_pat_";
+
+/// Characters kept in clear text to identify a token in listings
+const DISPLAY_PREFIX_LEN: usize = TOKEN_PREFIX.len() + 8;
+
+pub fn is_personal_access_token(token: &str) -> bool {
+    token.starts_with(TOKEN_PREFIX)
+}
+
+/// Create a token for a user, returning it in clear text. Only its hash is
+/// stored, it cannot be shown again.
+pub async fn issue(
+    db: &DatabaseConnection,
+    user: &User,
+    name: String,
+    scopes: Vec<String>,
+    expires_at: Option<DateTime>,
+) -> Result<(String, PersonalAccessToken), ApiError> {
+    let token = format!("{}{}", TOKEN_PREFIX, generate_token());
+    let stored = AuthPersonalAccessTokens::issue(
+        db,
+        NewPersonalAccessToken {
+            user_id: user.id,
+            name,
+            token_prefix: token[..DISPLAY_PREFIX_LEN].to_string(),
+            token_hash: hash_token(&token),
+            scopes,
+            expires_at,
+        },
+    )
+    .await?;
+
+    Ok((token, stored))
+}
+
+/// Resolve a personal access token to its active owner, recording the use
+pub async fn authenticate(
+    db: &DatabaseConnection,
+    token: &str,
+) -> Result<(User, PersonalAccessToken), ApiError> {
+    let invalid = || ApiError::Unauthorized("Invalid or expired token".to_string());
+
+    let stored = AuthPersonalAccessTokens::find_active_by_hash(db, &hash_token(token))
+        .await?
+        .ok_or_else(invalid)?;
+    let user = AuthUsers::find_by_id(stored.user_id)
+        .one(db)
+        .await?
+        .ok_or_else(invalid)?;
+
+    if !user.is_active {
+        return Err(ApiError::Unauthorized("Account is inactive".to_string()));
+    }
+
+    stored.touch(db).await?;
+    Ok((user, stored))
+}
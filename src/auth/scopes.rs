@@ -0,0 +1,114 @@
+use crate::auth::extractors::{CurrentUser, resolve_scoped_user};
+use crate::error::ApiError;
+use actix_web::dev::Payload;
+use actix_web::{FromRequest, HttpRequest};
+use apistos::ApiSecurity;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::pin::Pin;
+
+/// A scope that personal access tokens must carry to reach a route, see
+/// [`RequireScope`]
+pub trait Scope {
+    const NAME: &'static str;
+}
+
+/// Declare a marker type for a token scope.
+///
+/// ```ignore
+/// scope!(ReportsRead, "reports:read");
+///
+/// async fn list_reports(user: RequireScope<ReportsRead>) { ... }
+/// ```
+///
+/// New scopes must also be listed in [`SCOPES`] before tokens can be
+/// issued with them.
+#[macro_export]
+macro_rules! scope {
+    ($(#[$meta:meta])* $name:ident, $scope:expr) => {
+        $(#[$meta])*
+        pub struct $name;
+
+        impl $crate::auth::scopes::Scope for $name {
+            const NAME: &'static str = $scope;
+        }
+    };
+}
+
+scope!(ProfileRead, "profile:read");
+scope!(ProfileWrite, "profile:write");
+scope!(UsersRead, "users:read");
+scope!(UsersWrite, "users:write");
+
+/// Every scope a personal access token may be issued with
+pub const SCOPES: &[(&str, &str)] = &[
+    (ProfileRead::NAME, "Read the owner's profile"),
+    (ProfileWrite::NAME, "Change the owner's profile"),
+    (
+        UsersRead::NAME,
+        "View users and login lockouts, within the owner's permissions",
+    ),
+    (
+        UsersWrite::NAME,
+        "Change users and clear login lockouts, within the owner's permissions",
+    ),
+];
+
+pub fn is_known(scope: &str) -> bool {
+    SCOPES.iter().any(|(name, _)| *name == scope)
+}
+
+/// Accepts access tokens, and personal access tokens that carry the scope
+/// `S`. Routes that only take [`CurrentUser`] refuse personal access tokens.
+#[derive(ApiSecurity)]
+#[openapi_security(
+    name = "bearer_auth",
+    scheme(security_type(http(scheme = "bearer", bearer_format = "JWT")))
+)]
+pub struct RequireScope<S: Scope> {
+    pub user: CurrentUser,
+    _scope: PhantomData<S>,
+}
+
+impl<S: Scope> Deref for RequireScope<S> {
+    type Target = CurrentUser;
+
+    fn deref(&self) -> &Self::Target {
+        &self.user
+    }
+}
+
+impl<S: Scope + 'static> FromRequest for RequireScope<S> {
+    type Error = ApiError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        Box::pin(async move {
+            Ok(RequireScope {
+                user: resolve_scoped_user(&req, Some(S::NAME)).await?,
+                _scope: PhantomData,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::permissions::{AddUser, ChangeUser, DeleteUser, Permission, ViewUser};
+
+    #[test]
+    fn test_permission_scopes_can_be_issued() {
+        for scope in [
+            AddUser::SCOPE,
+            ChangeUser::SCOPE,
+            DeleteUser::SCOPE,
+            ViewUser::SCOPE,
+        ] {
+            assert!(scope.is_some_and(is_known));
+        }
+        assert!(!is_known("users:admin"));
+    }
+}
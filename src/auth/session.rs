@@ -0,0 +1,180 @@
+//! Logging users in and out of cookie sessions, storing the same keys as
+//! `django.contrib.auth` so either service recognises the other's logins.
+
+use crate::error::ApiError;
+use crate::session::Session;
+use entity::auth_users::{Entity as AuthUsers, Model as User};
+use sea_orm::{DatabaseConnection, EntityTrait};
+use security::signing::{constant_time_compare, salted_hmac};
+
+pub const SESSION_KEY: &str = "_auth_user_id";
+pub const BACKEND_SESSION_KEY: &str = "_auth_user_backend";
+pub const HASH_SESSION_KEY: &str = "_auth_user_hash";
+
+/// Backend of passwords stored in `auth_users`. Django refuses sessions
+/// naming a backend it does not have.
+pub const MODEL_BACKEND: &str = "django.contrib.auth.backends.ModelBackend";
+
+const SESSION_AUTH_HASH_SALT: &str =
+    "django.contrib.auth.models.AbstractBaseUser.get_session_auth_hash";
+
+/// Django's `get_session_auth_hash`. It changes with the password, which
+/// logs out every session when the password changes.
+pub fn session_auth_hash(user: &User, secret: &str) -> String {
+    hex::encode(salted_hmac(
+        SESSION_AUTH_HASH_SALT,
+        user.password.as_bytes(),
+        secret,
+    ))
+}
+
+/// Log `user` into the session, like Django's `login`, recording the
+/// dotted path of the `backend` that authenticated them. The session key is
+/// always replaced; data is dropped when the session belonged to another
+/// user.
+pub fn login(session: &Session, user: &User, backend: &str, secret: &str) -> Result<(), ApiError> {
+    let hash = session_auth_hash(user, secret);
+    let other_user = session.get::<String>(SESSION_KEY).is_some_and(|id| {
+        id != user.id.to_string()
+            || !session
+                .get::<String>(HASH_SESSION_KEY)
+                .is_some_and(|stored| constant_time_compare(&stored, &hash))
+    });
+
+    if other_user {
+        session.flush();
+    } else {
+        session.cycle_key();
+    }
+
+    let store = |key: &str, value: String| {
+        session
+            .insert(key, value)
+            .map_err(|e| ApiError::InternalServerError(e.to_string()))
+    };
+    store(SESSION_KEY, user.id.to_string())?;
+    store(BACKEND_SESSION_KEY, backend.to_string())?;
+    store(HASH_SESSION_KEY, hash)
+}
+
+/// Keep `user` logged into the session after their password changed, like
+/// Django's `update_session_auth_hash`. Sessions of other users are left
+/// alone.
+pub fn update_session_auth_hash(
+    session: &Session,
+    user: &User,
+    secret: &str,
+) -> Result<(), ApiError> {
+    if session_user_id(session) != Some(user.id) {
+        return Ok(());
+    }
+    session.cycle_key();
+    session
+        .insert(HASH_SESSION_KEY, session_auth_hash(user, secret))
+        .map_err(|e| ApiError::InternalServerError(e.to_string()))
+}
+
+/// Remove the user and everything else from the session
+pub fn logout(session: &Session) {
+    session.flush();
+}
+
+/// Id of the user logged into the session, without checking it
+pub fn session_user_id(session: &Session) -> Option<i32> {
+    session
+        .get::<String>(SESSION_KEY)
+        .and_then(|id| id.parse::<i32>().ok())
+}
+
+/// The active user logged into the session, like Django's `get_user`.
+/// A session whose hash no longer matches, because the password changed,
+/// is flushed.
+pub async fn session_user(
+    db: &DatabaseConnection,
+    session: &Session,
+    secret: &str,
+) -> Result<Option<User>, ApiError> {
+    let Some(user_id) = session_user_id(session) else {
+        return Ok(None);
+    };
+
+    let Some(user) = AuthUsers::find_by_id(user_id).one(db).await? else {
+        return Ok(None);
+    };
+
+    let verified = session
+        .get::<String>(HASH_SESSION_KEY)
+        .is_some_and(|stored| constant_time_compare(&stored, &session_auth_hash(&user, secret)));
+    if !verified {
+        session.flush();
+        return Ok(None);
+    }
+
+    Ok(user.is_active.then_some(user))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::SessionState;
+    use serde_json::Map;
+
+    fn user(id: i32, password: &str) -> User {
+        User {
+            id,
+            password: password.to_string(),
+            ..User::fixture()
+        }
+    }
+
+    #[test]
+    fn test_session_auth_hash_matches_django() {
+        // AbstractBaseUser.get_session_auth_hash() with SECRET_KEY = "secret"
+        assert_eq!(
+            session_auth_hash(&user(1, "pbkdf2_sha256$1$salt$hash"), "secret"),
+            "67b8a2db9a11f20a0a7107b74c79f0f7cf7c6da789d5741f6b65b93c65a418f6"
+        );
+    }
+
+    #[test]
+    fn test_login_drops_data_of_another_user() {
+        let session =
+            Session::from_state(SessionState::new(Some("anonymous".to_string()), Map::new()));
+        session.insert("cart", 3).unwrap();
+
+        login(&session, &user(1, "hash"), MODEL_BACKEND, "secret").unwrap();
+        assert_eq!(session.session_key(), None);
+        assert_eq!(session.get::<i32>("cart"), Some(3));
+        assert_eq!(session.get::<String>(SESSION_KEY).as_deref(), Some("1"));
+        assert_eq!(
+            session.get::<String>(BACKEND_SESSION_KEY).as_deref(),
+            Some(MODEL_BACKEND)
+        );
+
+        login(&session, &user(1, "hash"), MODEL_BACKEND, "secret").unwrap();
+        assert_eq!(session.get::<i32>("cart"), Some(3));
+
+        login(&session, &user(2, "hash"), MODEL_BACKEND, "secret").unwrap();
+        assert_eq!(session.get::<i32>("cart"), None);
+        assert_eq!(session.get::<String>(SESSION_KEY).as_deref(), Some("2"));
+    }
+
+    #[test]
+    fn test_update_session_auth_hash_keeps_the_user_logged_in() {
+        let session =
+            Session::from_state(SessionState::new(Some("anonymous".to_string()), Map::new()));
+        login(&session, &user(1, "old"), MODEL_BACKEND, "secret").unwrap();
+
+        update_session_auth_hash(&session, &user(2, "new"), "secret").unwrap();
+        assert_eq!(
+            session.get::<String>(HASH_SESSION_KEY),
+            Some(session_auth_hash(&user(1, "old"), "secret"))
+        );
+
+        update_session_auth_hash(&session, &user(1, "new"), "secret").unwrap();
+        assert_eq!(
+            session.get::<String>(HASH_SESSION_KEY),
+            Some(session_auth_hash(&user(1, "new"), "secret"))
+        );
+    }
+}
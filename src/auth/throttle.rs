@@ -0,0 +1,199 @@
+use actix_web::HttpRequest;
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Sliding-window rate limiter kept in process memory.
+///
+/// Each key may be hit `limit` times per `window`. Counts are per server
+/// process and are lost on restart, which is fine for throttling email.
+pub struct RateLimiter {
+    limit: usize,
+    window: Duration,
+    hits: Mutex<Hits>,
+}
+
+struct Hits {
+    times: HashMap<String, VecDeque<Instant>>,
+    last_sweep: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(limit: u32, window: Duration) -> Self {
+        Self {
+            limit: limit as usize,
+            window,
+            hits: Mutex::new(Hits {
+                times: HashMap::new(),
+                last_sweep: Instant::now(),
+            }),
+        }
+    }
+
+    /// Drop the hits of `times` that left the window
+    fn prune(&self, times: &mut VecDeque<Instant>, now: Instant) {
+        while times
+            .front()
+            .is_some_and(|&hit| now.duration_since(hit) >= self.window)
+        {
+            times.pop_front();
+        }
+    }
+
+    /// Record a hit for `key`, or return how long to wait when over the limit
+    pub fn check(&self, key: &str) -> Result<(), Duration> {
+        self.check_at(key, Instant::now())
+    }
+
+    fn check_at(&self, key: &str, now: Instant) -> Result<(), Duration> {
+        let mut hits = self.hits.lock().unwrap_or_else(|e| e.into_inner());
+
+        // Forget keys whose window has passed so the map does not grow
+        // forever, at most once per window so each check stays cheap
+        if now.duration_since(hits.last_sweep) >= self.window {
+            hits.times.retain(|_, times| {
+                self.prune(times, now);
+                !times.is_empty()
+            });
+            hits.last_sweep = now;
+        }
+
+        let times = hits.times.entry(key.to_string()).or_default();
+        self.prune(times, now);
+        if times.len() >= self.limit {
+            let oldest = times.front().copied().unwrap_or(now);
+            return Err(self.window.saturating_sub(now.duration_since(oldest)));
+        }
+
+        times.push_back(now);
+        Ok(())
+    }
+}
+
+/// Whether `ip` is one of the configured proxy addresses
+pub fn is_trusted_proxy(ip: IpAddr, trusted_proxies: &[String]) -> bool {
+    trusted_proxies
+        .iter()
+        .any(|proxy| proxy.parse::<IpAddr>().is_ok_and(|proxy| proxy == ip))
+}
+
+/// Address of the client. Requests from one of `trusted_proxies` are
+/// attributed to the rightmost `X-Forwarded-For` hop that is not a trusted
+/// proxy; entries left of it were sent by the client and could be anything.
+pub fn client_ip(req: &HttpRequest, trusted_proxies: &[String]) -> Option<String> {
+    let peer = req.peer_addr()?.ip();
+    if !is_trusted_proxy(peer, trusted_proxies) {
+        return Some(peer.to_string());
+    }
+
+    let forwarded = req
+        .headers()
+        .get_all("X-Forwarded-For")
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect::<Vec<_>>();
+    let mut client = peer;
+    for hop in forwarded.iter().rev() {
+        let Ok(ip) = hop.parse::<IpAddr>() else {
+            break;
+        };
+        client = ip;
+        if !is_trusted_proxy(ip, trusted_proxies) {
+            break;
+        }
+    }
+    Some(client.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn forwarded(peer: &str, forwarded_for: &str) -> HttpRequest {
+        TestRequest::default()
+            .peer_addr(peer.parse().unwrap())
+            .insert_header(("X-Forwarded-For", forwarded_for))
+            .to_http_request()
+    }
+
+    #[test]
+    fn test_client_ip_trusts_forwarded_for_only_from_proxies() {
+        let proxies = vec!["10.0.0.1".to_string(), "10.0.0.2".to_string()];
+        let req = forwarded("10.0.0.1:4711", "203.0.113.7, 10.0.0.2");
+
+        assert_eq!(client_ip(&req, &[]).as_deref(), Some("10.0.0.1"));
+        assert_eq!(client_ip(&req, &proxies).as_deref(), Some("203.0.113.7"));
+
+        // Anyone else sending the header is taken at their address
+        let req = forwarded("198.51.100.9:4711", "203.0.113.7");
+        assert_eq!(client_ip(&req, &proxies).as_deref(), Some("198.51.100.9"));
+    }
+
+    #[test]
+    fn test_spoofed_forwarded_for_does_not_change_client_ip() {
+        let proxies = vec!["10.0.0.1".to_string()];
+        // The proxy appends the address it saw to whatever the client sent
+        for sent in ["", "1.2.3.4, ", "5.6.7.8, 9.9.9.9, ", "not-an-ip, "] {
+            let req = forwarded("10.0.0.1:4711", &format!("{}203.0.113.7", sent));
+            assert_eq!(
+                client_ip(&req, &proxies).as_deref(),
+                Some("203.0.113.7"),
+                "{}",
+                sent
+            );
+        }
+    }
+
+    #[test]
+    fn test_limits_per_key_within_window() {
+        let limiter = RateLimiter::new(2, Duration::from_secs(60));
+        let start = Instant::now();
+
+        assert!(limiter.check_at("a", start).is_ok());
+        assert!(
+            limiter
+                .check_at("a", start + Duration::from_secs(1))
+                .is_ok()
+        );
+        assert_eq!(
+            limiter.check_at("a", start + Duration::from_secs(10)),
+            Err(Duration::from_secs(50))
+        );
+        assert!(
+            limiter
+                .check_at("b", start + Duration::from_secs(10))
+                .is_ok()
+        );
+
+        // The first hit leaves the window after 60 seconds
+        assert!(
+            limiter
+                .check_at("a", start + Duration::from_secs(60))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_sweeps_idle_keys_once_per_window() {
+        let limiter = RateLimiter::new(2, Duration::from_secs(60));
+        let start = Instant::now();
+        let keys = |limiter: &RateLimiter| limiter.hits.lock().unwrap().times.len();
+
+        for i in 0..100 {
+            limiter.check_at(&format!("key-{}", i), start).unwrap();
+        }
+        limiter
+            .check_at("a", start + Duration::from_secs(30))
+            .unwrap();
+        assert_eq!(keys(&limiter), 101);
+
+        // Only "a" is still within its window
+        limiter
+            .check_at("b", start + Duration::from_secs(61))
+            .unwrap();
+        assert_eq!(keys(&limiter), 2);
+    }
+}
@@ -0,0 +1,231 @@
+use crate::auth::devices::DeviceInfo;
+use crate::auth::jwt;
+use crate::config::AuthSettings;
+use crate::error::ApiError;
+use entity::auth_users::{Entity as AuthUsers, Model as User};
+use entity::{
+    AuthRefreshTokens, AuthUserSessions, RefreshTokenEntityExt, RefreshTokenModelExt,
+    UserSessionEntityExt, UserSessionModelExt,
+};
+use log::warn;
+use sea_orm::{DatabaseConnection, EntityTrait};
+use security::token::{generate_token, hash_token};
+
+pub struct TokenPair {
+    pub access_token: String,
+    pub refresh_token: String,
+    /// Access token lifetime in seconds
+    pub expires_in: i64,
+}
+
+/// Issue a signed access token and a fresh refresh token for a user,
+/// both belonging to the recorded login `session_id`
+pub async fn issue_token_pair(
+    db: &DatabaseConnection,
+    config: &AuthSettings,
+    user: &User,
+    session_id: i32,
+) -> Result<TokenPair, ApiError> {
+    let (access_token, expires_in) = jwt::encode_access_token(config, user.id, session_id)
+        .map_err(|e| ApiError::InternalServerError(e.to_string()))?;
+
+    let refresh_token = generate_token();
+    let expires_at =
+        chrono::Utc::now().naive_utc() + chrono::Duration::seconds(config.refresh_token_ttl);
+    AuthRefreshTokens::issue(
+        db,
+        user.id,
+        session_id,
+        hash_token(&refresh_token),
+        expires_at,
+    )
+    .await?;
+
+    Ok(TokenPair {
+        access_token,
+        refresh_token,
+        expires_in,
+    })
+}
+
+/// Exchange a refresh token for a new token pair, revoking the presented token.
+///
+/// The new pair stays in the login of the presented token, whose last-seen
+/// time and address are updated from `device`. Presenting a token that was
+/// already rotated is treated as theft: every session and refresh token of
+/// that user is ended. Tokens revoked otherwise, e.g. by a logout, are only
+/// refused.
+pub async fn rotate_refresh_token(
+    db: &DatabaseConnection,
+    config: &AuthSettings,
+    refresh_token: &str,
+    device: DeviceInfo,
+) -> Result<(User, TokenPair), ApiError> {
+    let invalid = || ApiError::Unauthorized("Invalid refresh token".to_string());
+
+    let stored = AuthRefreshTokens::find_by_hash(db, &hash_token(refresh_token))
+        .await?
+        .ok_or_else(invalid)?;
+
+    if stored.rotated {
+        warn!(
+            "Rotated refresh token reused for user {}, revoking all sessions",
+            stored.user_id
+        );
+        AuthUserSessions::end_all(db, stored.user_id, None).await?;
+        AuthRefreshTokens::revoke_all_for_user(db, stored.user_id).await?;
+        return Err(invalid());
+    }
+
+    if !stored.is_active() {
+        return Err(invalid());
+    }
+    let session_id = stored.session_id.ok_or_else(invalid)?;
+
+    let user = AuthUsers::find_by_id(stored.user_id)
+        .one(db)
+        .await?
+        .ok_or_else(invalid)?;
+
+    if !user.is_active {
+        return Err(ApiError::Unauthorized("Account is inactive".to_string()));
+    }
+
+    let session = AuthUserSessions::find_for_user(db, user.id, session_id)
+        .await?
+        .ok_or_else(invalid)?;
+
+    // Only a token that can be exchanged is used up, so a refused one is
+    // not mistaken for reuse when it comes back
+    if !stored.rotate(db).await? {
+        return Err(invalid());
+    }
+    session.touch(db, device.ip_address.as_deref()).await?;
+
+    let tokens = issue_token_pair(db, config, &user, session.id).await?;
+    Ok((user, tokens))
+}
+
+/// Revoke a refresh token owned by `user_id` and end its session. Unknown,
+/// foreign or already revoked tokens are ignored.
+pub async fn revoke_refresh_token(
+    db: &DatabaseConnection,
+    user_id: i32,
+    refresh_token: &str,
+) -> Result<(), ApiError> {
+    let stored = AuthRefreshTokens::find_by_hash(db, &hash_token(refresh_token)).await?;
+    if let Some(stored) = stored.filter(|stored| stored.user_id == user_id && stored.is_active()) {
+        match stored.session_id {
+            Some(session_id) => {
+                AuthUserSessions::end(db, user_id, session_id).await?;
+            }
+            None => {
+                stored.revoke(db).await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Settings;
+    use entity::auth_refresh_tokens::Model as RefreshToken;
+    use sea_orm::{DbBackend, MockDatabase, MockExecResult, Transaction};
+
+    fn refresh_token(session_id: Option<i32>) -> RefreshToken {
+        let now = chrono::Utc::now().naive_utc();
+        RefreshToken {
+            id: 1,
+            user_id: 42,
+            token_hash: hash_token("refresh"),
+            expires_at: now + chrono::Duration::days(1),
+            revoked_at: None,
+            created_at: now,
+            session_id,
+            rotated: false,
+        }
+    }
+
+    fn revoked(rotated: bool) -> RefreshToken {
+        RefreshToken {
+            revoked_at: Some(chrono::Utc::now().naive_utc()),
+            rotated,
+            ..refresh_token(Some(1))
+        }
+    }
+
+    fn exec(rows_affected: u64) -> MockExecResult {
+        MockExecResult {
+            last_insert_id: 0,
+            rows_affected,
+        }
+    }
+
+    /// Statements run by rotating `refresh` against `db`, which must fail
+    async fn refused_rotation(db: MockDatabase) -> Vec<Transaction> {
+        let db = db.into_connection();
+        let config = Settings::new().unwrap().auth;
+
+        let result = rotate_refresh_token(&db, &config, "refresh", DeviceInfo::default()).await;
+        assert!(matches!(result, Err(ApiError::Unauthorized(_))));
+        db.into_transaction_log()
+    }
+
+    #[tokio::test]
+    async fn test_reused_rotated_token_ends_every_session() {
+        let log = refused_rotation(
+            MockDatabase::new(DbBackend::Postgres)
+                .append_query_results([vec![revoked(true)]])
+                .append_exec_results([exec(2), exec(1)]),
+        )
+        .await;
+
+        let log = format!("{:?}", log);
+        assert!(
+            log.contains(r#"DELETE FROM \"auth_user_sessions\""#),
+            "{}",
+            log
+        );
+        assert!(log.contains(r#"UPDATE \"auth_refresh_tokens\""#), "{}", log);
+    }
+
+    #[tokio::test]
+    async fn test_token_revoked_by_logout_is_only_refused() {
+        let log = refused_rotation(
+            MockDatabase::new(DbBackend::Postgres).append_query_results([vec![revoked(false)]]),
+        )
+        .await;
+
+        // Only the lookup of the token
+        assert_eq!(log.len(), 1, "{:?}", log);
+    }
+
+    #[tokio::test]
+    async fn test_token_without_session_is_refused() {
+        let log = refused_rotation(
+            MockDatabase::new(DbBackend::Postgres)
+                .append_query_results([vec![refresh_token(None)]]),
+        )
+        .await;
+
+        // Refused before it is rotated
+        assert_eq!(log.len(), 1, "{:?}", log);
+    }
+
+    #[tokio::test]
+    async fn test_token_of_ended_session_is_not_rotated() {
+        let log = refused_rotation(
+            MockDatabase::new(DbBackend::Postgres)
+                .append_query_results([vec![refresh_token(Some(1))]])
+                .append_query_results([vec![User::fixture()]])
+                .append_query_results([Vec::<entity::auth_user_sessions::Model>::new()]),
+        )
+        .await;
+
+        let log = format!("{:?}", log);
+        assert!(!log.contains("UPDATE"), "{}", log);
+    }
+}
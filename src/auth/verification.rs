@@ -0,0 +1,90 @@
+use crate::config::Settings;
+use crate::mail::{Mail, MailError};
+use entity::auth_users::Model as User;
+use security::signing::{SignatureError, TimestampSigner};
+use std::time::Duration;
+use tera::Context;
+
+const SALT: &str = "r-web.auth.email-verification";
+
+fn signer(secret_key: &str) -> TimestampSigner {
+    TimestampSigner::new(secret_key, SALT)
+}
+
+/// Signed token proving control of the user's current email address.
+///
+/// The address is part of the signed value, so changing it invalidates
+/// links sent to the previous one.
+pub fn make_token(secret_key: &str, user: &User) -> String {
+    signer(secret_key).sign(&format!("{}:{}", user.id, user.email))
+}
+
+/// Check a verification token, returning the user id and email it was issued for
+pub fn check_token(
+    secret_key: &str,
+    token: &str,
+    max_age: Duration,
+) -> Result<(i32, String), SignatureError> {
+    let value = signer(secret_key).unsign(token, Some(max_age))?;
+    let (user_id, email) = value.split_once(':').ok_or(SignatureError::BadSignature)?;
+    let user_id = user_id.parse().map_err(|_| SignatureError::BadSignature)?;
+    Ok((user_id, email.to_string()))
+}
+
+/// Link the user follows to verify their address
+pub fn verification_link(config: &Settings, token: &str) -> String {
+    let query = serde_urlencoded::to_string([("token", token)]).expect("token is a plain string");
+    format!("{}?{}", config.registration.verification_url, query)
+}
+
+/// Email a fresh verification link to the user
+pub async fn send_verification_email(
+    mail: &Mail,
+    config: &Settings,
+    user: &User,
+    locale: Option<&str>,
+) -> Result<(), MailError> {
+    let token = make_token(&config.auth.secret_key, user);
+
+    let mut context = Context::new();
+    context.insert("username", &user.username);
+    context.insert("link", &verification_link(config, &token));
+    context.insert(
+        "expires_hours",
+        &(config.registration.verification_ttl / 3600),
+    );
+
+    mail.send_template(&user.email, "verify_email", locale, context)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user() -> User {
+        User {
+            is_verified: false,
+            ..User::fixture()
+        }
+    }
+
+    #[test]
+    fn test_token_round_trip() {
+        let token = make_token("secret", &user());
+        let ttl = Duration::from_secs(60);
+
+        assert_eq!(
+            check_token("secret", &token, ttl),
+            Ok((42, "ada@example.com".to_string()))
+        );
+        assert_eq!(
+            check_token("other-secret", &token, ttl),
+            Err(SignatureError::BadSignature)
+        );
+        assert_eq!(
+            check_token("secret", &token.replacen("42", "43", 1), ttl),
+            Err(SignatureError::BadSignature)
+        );
+    }
+}
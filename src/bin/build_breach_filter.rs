@@ -0,0 +1,47 @@
+use log::info;
+use security::password::{BloomFilter, parse_sha1_line};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+const DEFAULT_FALSE_POSITIVE_RATE: f64 = 0.001;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
+
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 3 || args.len() > 4 {
+        eprintln!(
+            "Usage: {} <pwned-passwords-sha1.txt> <output.bloom> [false-positive-rate]",
+            args[0]
+        );
+        std::process::exit(2);
+    }
+    let false_positive_rate = match args.get(3) {
+        Some(rate) => rate.parse()?,
+        None => DEFAULT_FALSE_POSITIVE_RATE,
+    };
+
+    // A first pass sizes the filter
+    info!("Counting hashes in {}...", args[1]);
+    let lines = BufReader::new(File::open(&args[1])?).lines().count() as u64;
+    let mut filter = BloomFilter::with_capacity(lines, false_positive_rate);
+
+    let mut inserted = 0u64;
+    let mut skipped = 0u64;
+    for line in BufReader::new(File::open(&args[1])?).lines() {
+        match parse_sha1_line(&line?) {
+            Some(digest) => {
+                filter.insert_digest(&digest);
+                inserted += 1;
+            }
+            None => skipped += 1,
+        }
+    }
+
+    filter.save(&args[2])?;
+    info!(
+        "Wrote {} hashes to {} ({} unreadable lines skipped)",
+        inserted, args[2], skipped
+    );
+    Ok(())
+}
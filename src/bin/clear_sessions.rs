@@ -0,0 +1,35 @@
+use entity::{AuthUsedTokens, DjangoSession, SessionEntityExt, UsedTokenEntityExt};
+use log::info;
+
+use service::config::Settings;
+use service::db;
+
+#[tokio::main]
+async fn main() {
+    env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
+
+    let settings = Settings::new().expect("Failed to read configuration");
+    let db_conn = db::init_db(&settings)
+        .await
+        .expect("Failed to connect to database");
+
+    info!("Deleting expired sessions...");
+
+    match DjangoSession::clear_expired(&db_conn).await {
+        Ok(deleted) => println!("Deleted {} expired sessions", deleted),
+        Err(e) => {
+            eprintln!("Failed: {:?}", e);
+            std::process::exit(1);
+        }
+    }
+
+    info!("Deleting expired MFA tokens...");
+
+    match AuthUsedTokens::clear_expired(&db_conn).await {
+        Ok(deleted) => println!("Deleted {} expired MFA tokens", deleted),
+        Err(e) => {
+            eprintln!("Failed: {:?}", e);
+            std::process::exit(1);
+        }
+    }
+}
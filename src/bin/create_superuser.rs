@@ -1,5 +1,5 @@
 use entity::auth_users::Entity as AuthUsers;
-use entity::auth_users_ext::AuthUserEntityExt;
+use entity::auth_users_ext::{AuthError, AuthUserEntityExt};
 use log::info;
 use std::io;
 
@@ -13,6 +13,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     info!("Creating superuser...");
 
     let settings = Settings::new().expect("Failed to read configuration");
+    settings
+        .passwords
+        .configure()
+        .expect("Invalid password configuration");
 
     let db_conn = db::init_db(&settings)
         .await
@@ -31,6 +35,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             info!("Username: {}", user.username);
             info!("ID: {}", user.id);
         }
+        Err(AuthError::PasswordValidation(errors)) => {
+            for error in errors {
+                eprintln!("{}", error.message);
+            }
+            std::process::exit(1);
+        }
         Err(e) => {
             eprintln!("Failed to create superuser: {:?}", e);
             std::process::exit(1);
@@ -0,0 +1,135 @@
+use entity::auth_users::Entity as AuthUsers;
+use entity::{
+    AuthError, AuthLoginFailures, AuthUserEntityExt, LockoutScope, LoginFailureEntityExt,
+    LoginFailureModelExt,
+};
+use sea_orm::DatabaseConnection;
+
+use service::config::Settings;
+use service::db;
+
+const USAGE: &str = "Usage: lockouts <command>
+
+Commands:
+    list [--locked]               Show failed login counters, or only active lockouts
+    clear <id>                    Forget one entry
+    unlock <username|email|ip>    Forget the failures of an account, login or IP
+    clear-all [user|login|ip]     Forget every entry, or those of one scope
+    clear-expired                 Delete entries past the failure window that lock nothing out";
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    env_logger::init_from_env(env_logger::Env::new().default_filter_or("warn"));
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    let settings = Settings::new().expect("Failed to read configuration");
+    settings.lockout.configure();
+    let db_conn = db::init_db(&settings)
+        .await
+        .expect("Failed to connect to database");
+
+    let result = match args.as_slice() {
+        ["list"] => list(&db_conn, false).await,
+        ["list", "--locked"] => list(&db_conn, true).await,
+        ["clear", id] => clear(&db_conn, id.parse()?).await,
+        ["unlock", name] => unlock(&db_conn, name).await,
+        ["clear-all"] => clear_all(&db_conn, None).await,
+        ["clear-all", scope] => clear_all(&db_conn, Some(scope.parse()?)).await,
+        ["clear-expired"] => clear_expired(&db_conn).await,
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    if let Err(e) = result {
+        eprintln!("Failed: {:?}", e);
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+async fn list(db: &DatabaseConnection, locked_only: bool) -> Result<(), AuthError> {
+    let failures = AuthLoginFailures::list(db, None, locked_only).await?;
+    if failures.is_empty() {
+        println!("No failed logins recorded");
+        return Ok(());
+    }
+
+    println!(
+        "{:>6}  {:<6} {:<32} {:>8}  {:<20} LOCKED UNTIL",
+        "ID", "SCOPE", "KEY", "FAILURES", "LAST FAILURE"
+    );
+    for failure in &failures {
+        let locked_until = match failure.locked_until {
+            Some(locked_until) if failure.is_locked() => {
+                locked_until.format("%Y-%m-%d %H:%M:%S").to_string()
+            }
+            _ => "-".to_string(),
+        };
+        println!(
+            "{:>6}  {:<6} {:<32} {:>8}  {:<20} {}",
+            failure.id,
+            failure.scope,
+            failure.key,
+            failure.failures,
+            failure.last_failure_at.format("%Y-%m-%d %H:%M:%S"),
+            locked_until
+        );
+    }
+    Ok(())
+}
+
+async fn clear(db: &DatabaseConnection, id: i32) -> Result<(), AuthError> {
+    if AuthLoginFailures::clear_by_id(db, id).await? {
+        println!("Cleared entry {}", id);
+    } else {
+        println!("No entry with id {}", id);
+    }
+    Ok(())
+}
+
+async fn clear_all(db: &DatabaseConnection, scope: Option<LockoutScope>) -> Result<(), AuthError> {
+    let cleared = AuthLoginFailures::clear_all(db, scope).await?;
+    match scope {
+        Some(scope) => println!("Cleared {} {} entries", cleared, scope),
+        None => println!("Cleared {} entries", cleared),
+    }
+    Ok(())
+}
+
+async fn clear_expired(db: &DatabaseConnection) -> Result<(), AuthError> {
+    let deleted = AuthLoginFailures::clear_expired(db).await?;
+    println!("Deleted {} expired entries", deleted);
+    Ok(())
+}
+
+/// Clear whatever `name` can refer to: an account, an unknown login or an IP
+async fn unlock(db: &DatabaseConnection, name: &str) -> Result<(), AuthError> {
+    let mut cleared = Vec::new();
+
+    let user = match AuthUsers::find_by_username(db, name).await? {
+        Some(user) => Some(user),
+        None => AuthUsers::find_by_email(db, name).await?,
+    };
+    if let Some(user) = user
+        && AuthLoginFailures::clear(db, LockoutScope::User, &user.id.to_string()).await?
+    {
+        cleared.push(format!("user {} (id {})", user.username, user.id));
+    }
+    if AuthLoginFailures::clear(db, LockoutScope::Login, &name.to_lowercase()).await? {
+        cleared.push(format!("login {}", name));
+    }
+    if AuthLoginFailures::clear(db, LockoutScope::Ip, name).await? {
+        cleared.push(format!("ip {}", name));
+    }
+
+    if cleared.is_empty() {
+        println!("No failed logins recorded for {}", name);
+    } else {
+        println!("Cleared {}", cleared.join(", "));
+    }
+    Ok(())
+}
@@ -0,0 +1,176 @@
+use entity::{AuthError, GrantType, NewOAuthClient, OAuthClientEntityExt, OauthClients};
+use sea_orm::DatabaseConnection;
+
+use security::token::{generate_token, get_random_string, hash_token};
+use service::config::Settings;
+use service::db;
+use service::oauth;
+
+const USAGE: &str = "Usage: oauth_clients <command>
+
+Commands:
+    list                          Show registered clients
+    create <name> [options]       Register a client and print its credentials
+    delete <client_id>            Remove a client with its tokens and consents
+
+Options of create:
+    --redirect-uri <uri>          Allowed redirect URI, may be repeated
+    --grant <type>                authorization_code, refresh_token or
+                                  client_credentials, may be repeated
+                                  (default: authorization_code refresh_token)
+    --scope <scope>               Scope the client may request, may be repeated
+    --public                      No secret, for SPAs and mobile apps; PKCE is required
+    --skip-consent                Do not ask users to approve the client";
+
+const CLIENT_ID_CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789";
+const CLIENT_ID_LEN: usize = 24;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    env_logger::init_from_env(env_logger::Env::new().default_filter_or("warn"));
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    let settings = Settings::new().expect("Failed to read configuration");
+    let db_conn = db::init_db(&settings)
+        .await
+        .expect("Failed to connect to database");
+
+    let result = match args.as_slice() {
+        ["list"] => list(&db_conn).await,
+        ["create", name, options @ ..] => match parse_create(name, options) {
+            Ok(client) => create(&db_conn, client).await,
+            Err(message) => {
+                eprintln!("{}\n\n{}", message, USAGE);
+                std::process::exit(2);
+            }
+        },
+        ["delete", client_id] => delete(&db_conn, client_id).await,
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    if let Err(e) = result {
+        eprintln!("Failed: {:?}", e);
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// The client to register, and its secret unless it is public
+struct ClientSpec {
+    client: NewOAuthClient,
+    client_secret: Option<String>,
+}
+
+fn parse_create(name: &str, options: &[&str]) -> Result<ClientSpec, String> {
+    let mut redirect_uris = Vec::new();
+    let mut grant_types = Vec::new();
+    let mut scopes = Vec::new();
+    let mut public = false;
+    let mut skip_consent = false;
+
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        let mut value = || {
+            options
+                .next()
+                .map(|value| value.to_string())
+                .ok_or_else(|| format!("{} needs a value", option))
+        };
+        match *option {
+            "--redirect-uri" => redirect_uris.push(value()?),
+            "--grant" => grant_types.push(
+                value()?
+                    .parse::<GrantType>()
+                    .map_err(|_| "Unknown grant type".to_string())?,
+            ),
+            "--scope" => {
+                let scope = value()?;
+                if !oauth::is_known_scope(&scope) {
+                    return Err(format!("Unknown scope: {}", scope));
+                }
+                scopes.push(scope);
+            }
+            "--public" => public = true,
+            "--skip-consent" => skip_consent = true,
+            other => return Err(format!("Unknown option: {}", other)),
+        }
+    }
+
+    if grant_types.is_empty() {
+        grant_types = vec![GrantType::AuthorizationCode, GrantType::RefreshToken];
+    }
+    if grant_types.contains(&GrantType::AuthorizationCode) && redirect_uris.is_empty() {
+        return Err("The authorization code grant needs a --redirect-uri".to_string());
+    }
+    if public && grant_types.contains(&GrantType::ClientCredentials) {
+        return Err("Public clients cannot use client_credentials".to_string());
+    }
+    if scopes.is_empty() {
+        return Err("At least one --scope is required".to_string());
+    }
+
+    let client_secret = (!public).then(generate_token);
+    Ok(ClientSpec {
+        client: NewOAuthClient {
+            client_id: get_random_string(CLIENT_ID_LEN, CLIENT_ID_CHARS),
+            client_secret_hash: client_secret.as_deref().map(hash_token),
+            name: name.to_string(),
+            redirect_uris,
+            grant_types,
+            scopes,
+            skip_consent,
+        },
+        client_secret,
+    })
+}
+
+async fn list(db: &DatabaseConnection) -> Result<(), AuthError> {
+    let clients = OauthClients::list(db).await?;
+    if clients.is_empty() {
+        println!("No OAuth clients registered");
+        return Ok(());
+    }
+
+    println!(
+        "{:<24}  {:<24} {:<12} {:<40} SCOPES",
+        "CLIENT ID", "NAME", "TYPE", "GRANTS"
+    );
+    for client in &clients {
+        let kind = if client.client_secret_hash.is_some() {
+            "confidential"
+        } else {
+            "public"
+        };
+        println!(
+            "{:<24}  {:<24} {:<12} {:<40} {}",
+            client.client_id, client.name, kind, client.grant_types, client.scopes
+        );
+    }
+    Ok(())
+}
+
+async fn create(db: &DatabaseConnection, spec: ClientSpec) -> Result<(), AuthError> {
+    let client = OauthClients::create(db, spec.client).await?;
+
+    println!("Registered {}", client.name);
+    println!("client_id:     {}", client.client_id);
+    if let Some(client_secret) = spec.client_secret {
+        println!("client_secret: {}", client_secret);
+        println!("The secret is not stored and cannot be shown again.");
+    }
+    Ok(())
+}
+
+async fn delete(db: &DatabaseConnection, client_id: &str) -> Result<(), AuthError> {
+    if OauthClients::delete_by_client_id(db, client_id).await? {
+        println!("Deleted client {}", client_id);
+    } else {
+        println!("No client with id {}", client_id);
+    }
+    Ok(())
+}
@@ -0,0 +1,92 @@
+use entity::{OidcSigningKeys, SigningKeyEntityExt};
+use sea_orm::DatabaseConnection;
+
+use service::config::Settings;
+use service::db;
+use service::error::ApiError;
+use service::oidc::keys;
+
+const USAGE: &str = "Usage: oidc_keys <command>
+
+Commands:
+    list                          Show signing keys and whether they are published
+    rotate                        Sign with a new key; the current one stays published
+    prune                         Delete keys retired longer than oidc.key_retention";
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    env_logger::init_from_env(env_logger::Env::new().default_filter_or("warn"));
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    let settings = Settings::new().expect("Failed to read configuration");
+    let db_conn = db::init_db(&settings)
+        .await
+        .expect("Failed to connect to database");
+
+    let result = match args.as_slice() {
+        ["list"] => list(&db_conn, &settings).await,
+        ["rotate"] => rotate(&db_conn, &settings).await,
+        ["prune"] => prune(&db_conn, &settings).await,
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    if let Err(e) = result {
+        eprintln!("Failed: {:?}", e);
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+async fn list(db: &DatabaseConnection, settings: &Settings) -> Result<(), ApiError> {
+    let signing_keys = OidcSigningKeys::list(db).await?;
+    if signing_keys.is_empty() {
+        println!("No signing keys yet, one is generated on first use");
+        return Ok(());
+    }
+
+    let retention = chrono::Duration::seconds(settings.oidc.key_retention);
+    let now = chrono::Utc::now().naive_utc();
+    println!(
+        "{:<20}  {:<6} {:<20} {:<20} STATUS",
+        "KID", "ALG", "CREATED", "RETIRED"
+    );
+    for key in &signing_keys {
+        let (retired, status) = match key.retired_at {
+            None => ("-".to_string(), "signing"),
+            Some(retired_at) => (
+                retired_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+                if retired_at + retention > now {
+                    "published"
+                } else {
+                    "expired"
+                },
+            ),
+        };
+        println!(
+            "{:<20}  {:<6} {:<20} {:<20} {}",
+            key.kid,
+            key.algorithm,
+            key.created_at.format("%Y-%m-%d %H:%M:%S"),
+            retired,
+            status
+        );
+    }
+    Ok(())
+}
+
+async fn rotate(db: &DatabaseConnection, settings: &Settings) -> Result<(), ApiError> {
+    let key = keys::rotate(db, settings).await?;
+    println!("Now signing with key {}", key.kid);
+    Ok(())
+}
+
+async fn prune(db: &DatabaseConnection, settings: &Settings) -> Result<(), ApiError> {
+    let deleted = keys::prune(db, settings).await?;
+    println!("Deleted {} expired keys", deleted);
+    Ok(())
+}
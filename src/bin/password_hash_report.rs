@@ -0,0 +1,74 @@
+use entity::auth_users::{self, Entity as AuthUsers};
+use log::info;
+use sea_orm::{EntityTrait, PaginatorTrait, QuerySelect};
+use security::password::{algorithm_of, hashers, needs_rehash};
+use std::collections::BTreeMap;
+
+use service::config::Settings;
+use service::db;
+
+const PAGE_SIZE: u64 = 1000;
+
+#[derive(Default)]
+struct AlgorithmCount {
+    total: u64,
+    outdated: u64,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
+
+    let settings = Settings::new().expect("Failed to read configuration");
+    settings
+        .passwords
+        .configure()
+        .expect("Invalid password configuration");
+
+    let db_conn = db::init_db(&settings)
+        .await
+        .expect("Failed to connect to database");
+
+    info!("Scanning password hashes...");
+
+    let mut counts: BTreeMap<String, AlgorithmCount> = BTreeMap::new();
+    let mut pages = AuthUsers::find()
+        .select_only()
+        .column(auth_users::Column::Password)
+        .into_tuple::<String>()
+        .paginate(&db_conn, PAGE_SIZE);
+
+    while let Some(hashes) = pages.fetch_and_next().await? {
+        for hash in hashes {
+            let algorithm = match algorithm_of(&hash) {
+                Some(algorithm) if hashers().get(algorithm).is_some() => algorithm.to_string(),
+                _ => "unknown".to_string(),
+            };
+            let count = counts.entry(algorithm).or_default();
+            count.total += 1;
+            if needs_rehash(&hash) {
+                count.outdated += 1;
+            }
+        }
+    }
+
+    let total: u64 = counts.values().map(|count| count.total).sum();
+    let outdated: u64 = counts.values().map(|count| count.outdated).sum();
+
+    println!("Preferred hasher: {}", hashers().preferred().algorithm());
+    println!();
+    println!("{:<16} {:>10} {:>10}", "ALGORITHM", "USERS", "OUTDATED");
+    for (algorithm, count) in &counts {
+        println!(
+            "{:<16} {:>10} {:>10}",
+            algorithm, count.total, count.outdated
+        );
+    }
+    println!();
+    println!(
+        "{} of {} users have an outdated password hash; they are upgraded on their next login.",
+        outdated, total
+    );
+
+    Ok(())
+}
@@ -1,4 +1,5 @@
 use actix_cors::Cors;
+use actix_web::http::header;
 use actix_web::{App, HttpServer, middleware::Logger, web};
 use apistos::app::{BuildConfig, OpenApiWrapper};
 use apistos::info::Info;
@@ -8,6 +9,8 @@ use log::info;
 use std::io;
 
 use service::config::Settings;
+use service::csrf::CsrfMiddleware;
+use service::session::SessionMiddleware;
 use service::{db, routes, state};
 
 #[tokio::main]
@@ -22,6 +25,15 @@ async fn main() -> io::Result<()> {
     let bind_address = settings.get_bind_address();
     let base_url = format!("http://{}", bind_address);
     let api_ver = settings.application.api_version.clone();
+    settings
+        .passwords
+        .configure()
+        .expect("Invalid password configuration");
+    settings.lockout.configure();
+    settings
+        .cors
+        .validate()
+        .expect("Invalid CORS configuration");
 
     info!("Environment: {}", settings.application.environment);
     info!("Connecting to database...");
@@ -55,13 +67,16 @@ async fn main() -> io::Result<()> {
         App::new()
             .document(spec)
             .app_data(web::Data::new(app_state.clone()))
+            .wrap(CsrfMiddleware::new(
+                app_state.config.clone(),
+                routes::CSRF_EXEMPT,
+            ))
+            .wrap(SessionMiddleware::new(
+                app_state.db.clone(),
+                app_state.config.clone(),
+            ))
             .wrap(Logger::default())
-            .wrap(
-                Cors::default()
-                    .allow_any_origin()
-                    .allow_any_method()
-                    .allow_any_header(),
-            )
+            .wrap(cors(&app_state.config))
             .configure(routes::configure)
             .build_with(
                 "/openapi.json",
@@ -74,3 +89,24 @@ async fn main() -> io::Result<()> {
     .run()
     .await
 }
+
+/// Cross-origin access for the configured origins only
+fn cors(config: &Settings) -> Cors {
+    let settings = &config.cors;
+    let mut cors = Cors::default()
+        .allow_any_method()
+        .allowed_headers([header::AUTHORIZATION, header::ACCEPT, header::CONTENT_TYPE])
+        .allowed_header(config.csrf.header_name.as_str())
+        .max_age(settings.max_age);
+
+    if settings.allows_any_origin() {
+        return cors.allow_any_origin();
+    }
+    for origin in &settings.allowed_origins {
+        cors = cors.allowed_origin(origin);
+    }
+    if settings.allow_credentials {
+        cors = cors.supports_credentials();
+    }
+    cors
+}
@@ -1,11 +1,38 @@
+use anyhow::Context;
 use config::{Config, ConfigError, Environment, File};
+use entity::{LockoutPolicy, LockoutRule};
+use security::password::{
+    BreachedPasswordValidator, CommonPasswordValidator, HasherConfigError, HasherParams,
+    HashingPool, MinimumLengthValidator, NumericPasswordValidator, PasswordHashers,
+    PasswordValidator, PasswordValidators, PoolConfig, UserAttributeSimilarityValidator,
+};
 use serde::{Deserialize, Serialize};
 use std::env;
+use std::time::Duration;
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
+    pub auth: AuthSettings,
+    pub passwords: PasswordSettings,
+    pub registration: RegistrationSettings,
+    pub password_reset: PasswordResetSettings,
+    pub email_change: EmailChangeSettings,
+    pub magic_link: MagicLinkSettings,
+    pub mail: MailSettings,
+    pub mfa: MfaSettings,
+    pub webauthn: WebauthnSettings,
+    pub lockout: LockoutSettings,
+    pub personal_access_tokens: PersonalAccessTokenSettings,
+    pub session: SessionSettings,
+    pub csrf: CsrfSettings,
+    pub cors: CorsSettings,
+    pub oauth: OAuthSettings,
+    pub oidc: OidcSettings,
+    pub sso: SsoSettings,
+    pub ldap: LdapSettings,
+    pub remote_user: RemoteUserSettings,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -27,6 +54,391 @@ pub struct ApplicationSettings {
     pub api_version: String,
 }
 
+#[derive(Debug, Deserialize, Serialize)]
+pub struct AuthSettings {
+    pub secret_key: String,
+    pub issuer: String,
+    /// Access token lifetime in seconds
+    pub access_token_ttl: i64,
+    /// Refresh token lifetime in seconds
+    pub refresh_token_ttl: i64,
+    /// Logged-in devices a user may have at once; the oldest sessions are
+    /// ended beyond that. Unset allows any number
+    pub max_sessions_per_user: Option<usize>,
+    /// Backends asked in turn to authenticate a login, like Django's
+    /// `AUTHENTICATION_BACKENDS`: `model`, `ldap` and `remote_user`
+    pub backends: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PasswordSettings {
+    /// Hasher algorithms, preferred first (Django's `PASSWORD_HASHERS`)
+    pub hashers: Vec<String>,
+    pub pbkdf2_iterations: u32,
+    pub argon2_time_cost: u32,
+    /// Argon2 memory cost in KiB
+    pub argon2_memory_cost: u32,
+    pub argon2_parallelism: u32,
+    pub bcrypt_rounds: u32,
+    pub scrypt_work_factor: u32,
+    pub scrypt_block_size: u32,
+    pub scrypt_parallelism: u32,
+    /// Hashes computed concurrently, off the async workers
+    pub max_concurrency: usize,
+    /// Milliseconds a login may wait for a hashing slot before a 503
+    pub queue_timeout_ms: u64,
+    /// Rules new passwords must pass (Django's `AUTH_PASSWORD_VALIDATORS`)
+    #[serde(default = "default_validators")]
+    pub validators: Vec<ValidatorSettings>,
+}
+
+/// One password validator, selected by `name`
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "name", rename_all = "snake_case")]
+pub enum ValidatorSettings {
+    UserAttributeSimilarity {
+        #[serde(default = "default_user_attributes")]
+        user_attributes: Vec<String>,
+        #[serde(default = "default_max_similarity")]
+        max_similarity: f64,
+    },
+    MinimumLength {
+        #[serde(default = "default_min_length")]
+        min_length: usize,
+    },
+    CommonPassword {
+        /// Replaces the bundled list, one password per line
+        password_list_path: Option<String>,
+    },
+    NumericPassword,
+    BreachedPassword {
+        /// Bloom filter built with the `build_breach_filter` binary
+        filter_path: String,
+    },
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RegistrationSettings {
+    /// Whether `POST /auth/register` accepts new accounts
+    pub enabled: bool,
+    /// Whether accounts may log in before verifying their email address
+    pub allow_unverified_login: bool,
+    /// Verification link lifetime in seconds
+    pub verification_ttl: i64,
+    /// Page or endpoint the verification link points to; `?token=...` is appended
+    pub verification_url: String,
+    /// Verification emails a single address may request per window
+    pub resend_limit: u32,
+    /// Resend window in seconds
+    pub resend_window: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PasswordResetSettings {
+    /// Reset link lifetime in seconds, Django's `PASSWORD_RESET_TIMEOUT`
+    pub timeout: i64,
+    /// Page the reset link points to; `?uid=...&token=...` is appended
+    pub reset_url: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct EmailChangeSettings {
+    /// Confirmation link lifetime in seconds
+    pub timeout: i64,
+    /// Page the confirmation link points to; `?token=...` is appended
+    pub confirm_url: String,
+    /// Email changes a single user may start per window
+    pub request_limit: u32,
+    /// Request window in seconds
+    pub request_window: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct MagicLinkSettings {
+    /// Whether users may ask for a login link instead of a password
+    pub enabled: bool,
+    /// Login link lifetime in seconds
+    pub ttl: i64,
+    /// Page the login link points to; `?token=...` is appended
+    pub login_url: String,
+    /// Only accept a link in the browser session that asked for it
+    pub bind_browser: bool,
+    /// Login links a single address may request per window
+    pub request_limit: u32,
+    /// Request window in seconds
+    pub request_window: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct MailSettings {
+    /// `console`, `file` or `smtp`
+    pub transport: String,
+    /// Sender of every email, e.g. `R-Web <no-reply@example.com>`
+    pub from_email: String,
+    /// Available to templates as `site_name`
+    pub site_name: String,
+    /// Locale used when the request asks for none that has subjects
+    pub default_locale: String,
+    /// Directory whose templates and `subjects.toml` override the bundled ones
+    pub template_dir: Option<String>,
+    /// Where the `file` transport writes `.eml` files
+    pub file_path: String,
+    pub smtp: SmtpSettings,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct MfaSettings {
+    /// Shown next to the account name in authenticator apps
+    pub issuer: String,
+    /// Whether staff and superusers must enroll before they can log in
+    pub required_for_staff: bool,
+    /// Lifetime in seconds of the token between password and second factor
+    pub token_ttl: i64,
+    /// Key for TOTP secrets at rest; derived from `auth.secret_key` when unset
+    pub encryption_key: Option<String>,
+    /// Recovery codes issued per user
+    pub recovery_codes: usize,
+    /// Second factor attempts a user may make per window
+    pub max_attempts: u32,
+    /// Attempt window in seconds
+    pub attempt_window: u64,
+}
+
+/// Passkeys, for passwordless login and as a second factor
+#[derive(Debug, Deserialize, Serialize)]
+pub struct WebauthnSettings {
+    /// Domain passkeys are bound to: the site's registrable domain, or a
+    /// parent of it. Passkeys stop working if this changes.
+    pub rp_id: String,
+    /// Shown by the browser when creating a passkey
+    pub rp_name: String,
+    /// Origins of the pages that run the ceremonies, e.g. `https://example.com`
+    pub origins: Vec<String>,
+    /// Seconds a user may take to answer the browser prompt
+    pub timeout: i64,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct LockoutSettings {
+    /// Whether failed logins are counted and slowed down at all
+    pub enabled: bool,
+    /// Failures of one account before each attempt is delayed
+    pub account_free_attempts: u32,
+    /// Failures of one account that lock it for `lockout_duration`
+    pub account_lockout_threshold: u32,
+    /// Failures from one IP before each attempt is delayed
+    pub ip_free_attempts: u32,
+    /// Failures from one IP that lock it for `lockout_duration`
+    pub ip_lockout_threshold: u32,
+    /// First delay in seconds, doubled with each further failure
+    pub base_delay: u64,
+    /// Longest delay in seconds short of a lockout
+    pub max_delay: u64,
+    /// Lockout length in seconds
+    pub lockout_duration: u64,
+    /// Seconds after which failures are forgotten
+    pub failure_window: u64,
+    /// Reverse proxies whose `X-Forwarded-For` header gives the client IP
+    pub trusted_proxies: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PersonalAccessTokenSettings {
+    /// Longest lifetime in days a token may be given; unset allows tokens
+    /// that never expire
+    pub max_lifetime_days: Option<u32>,
+    /// Tokens a user may hold at once
+    pub max_per_user: usize,
+}
+
+/// Cookie sessions, named after Django's `SESSION_*` settings
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SessionSettings {
+    pub cookie_name: String,
+    /// Session lifetime in seconds
+    pub cookie_age: i64,
+    /// Domain the cookie is sent to, e.g. `.example.com` to share it with
+    /// subdomains; unset limits it to the host that set it
+    pub cookie_domain: Option<String>,
+    pub cookie_path: String,
+    /// Only send the cookie over HTTPS
+    pub cookie_secure: bool,
+    /// Hide the cookie from JavaScript
+    pub cookie_httponly: bool,
+    pub cookie_samesite: SameSite,
+    /// Save the session and renew its expiry on every request, not only
+    /// when it changed
+    pub save_every_request: bool,
+    /// Use a browser-session cookie rather than one lasting `cookie_age`
+    pub expire_at_browser_close: bool,
+}
+
+/// CSRF protection, named after Django's `CSRF_*` settings
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CsrfSettings {
+    pub cookie_name: String,
+    /// Cookie lifetime in seconds
+    pub cookie_age: i64,
+    /// Set to share the cookie with subdomains; also the domain `Referer`
+    /// headers must match
+    pub cookie_domain: Option<String>,
+    pub cookie_path: String,
+    pub cookie_secure: bool,
+    /// Leave off so JavaScript can read the cookie
+    pub cookie_httponly: bool,
+    pub cookie_samesite: SameSite,
+    /// Header carrying the token on unsafe requests
+    pub header_name: String,
+    /// Origins other than the API's own that may send unsafe requests, e.g.
+    /// `https://app.example.com` or `https://*.example.com`
+    pub trusted_origins: Vec<String>,
+}
+
+/// Which browser origins may call the API across origins
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CorsSettings {
+    /// Exact origins such as `https://app.example.com`; `*` allows any
+    /// origin but no credentials
+    pub allowed_origins: Vec<String>,
+    /// Let browsers send cookies with cross-origin requests
+    pub allow_credentials: bool,
+    /// Seconds browsers may cache a preflight response
+    pub max_age: usize,
+}
+
+/// The OAuth2 authorization server under `/oauth`
+#[derive(Debug, Deserialize, Serialize)]
+pub struct OAuthSettings {
+    /// Authorization code lifetime in seconds
+    pub code_ttl: i64,
+    /// Access token lifetime in seconds
+    pub access_token_ttl: i64,
+    /// Refresh token lifetime in seconds
+    pub refresh_token_ttl: i64,
+    /// Page where users log into a cookie session; `/oauth/authorize` sends
+    /// logged-out users there with the authorize URL in `next`
+    pub login_url: String,
+    /// Page asking users to approve a client; it gets the authorize query
+    /// string and calls `/oauth/authorize/consent`
+    pub consent_url: String,
+}
+
+/// OpenID Connect on top of the OAuth2 server
+#[derive(Debug, Deserialize, Serialize)]
+pub struct OidcSettings {
+    /// Public base URL of this service, the `iss` of ID tokens; discovery is
+    /// served under it at `/.well-known/openid-configuration`
+    pub issuer: String,
+    /// ID token lifetime in seconds
+    pub id_token_ttl: i64,
+    /// Seconds a rotated signing key stays in the JWKS, so tokens it signed
+    /// can still be verified
+    pub key_retention: i64,
+    /// Key for signing keys at rest; derived from `auth.secret_key` when unset
+    pub encryption_key: Option<String>,
+}
+
+/// Logging in with external OpenID Connect providers
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SsoSettings {
+    /// Seconds a user may take to log in at the provider
+    pub state_ttl: i64,
+    /// Page users land on once logged in, or once an identity was linked
+    pub login_redirect_url: String,
+    /// Seconds provider metadata and signing keys are cached
+    pub metadata_ttl: u64,
+    #[serde(default)]
+    pub providers: Vec<SsoProviderSettings>,
+}
+
+/// One OpenID Connect provider, e.g. a corporate IdP
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SsoProviderSettings {
+    /// Identifies the provider in URLs and in `user_identities`
+    pub name: String,
+    /// Shown on the login button
+    pub display_name: String,
+    /// The provider's `iss`; its metadata is read from
+    /// `{issuer}/.well-known/openid-configuration`
+    pub issuer: String,
+    pub client_id: String,
+    /// Unset for public clients, which rely on PKCE alone
+    pub client_secret: Option<String>,
+    /// `/api/v1/auth/sso/{name}/callback` of this service, as registered
+    /// at the provider
+    pub redirect_uri: String,
+    #[serde(default = "default_sso_scopes")]
+    pub scopes: Vec<String>,
+    /// Create an account on the first login of an unknown identity
+    #[serde(default)]
+    pub auto_provision: bool,
+    /// Link an unknown identity to the account with its verified email
+    /// address. Only safe with providers that own the address's domain
+    #[serde(default)]
+    pub link_by_email: bool,
+    /// Let the provider's login stand in for the second factor of accounts
+    /// with two-factor authentication. Only for providers that enforce
+    /// their own
+    #[serde(default)]
+    pub trusted_for_mfa: bool,
+    /// Email domains that may be provisioned or linked by email; any when
+    /// empty
+    #[serde(default)]
+    pub allowed_domains: Vec<String>,
+}
+
+/// The `ldap` authentication backend, which binds as the user
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LdapSettings {
+    /// `ldap://` or `ldaps://` URL of the directory server
+    pub url: String,
+    /// Upgrade `ldap://` connections with StartTLS
+    pub start_tls: bool,
+    /// DN to bind as, `{username}` being replaced with the escaped username,
+    /// e.g. `uid={username},ou=people,dc=example,dc=com`
+    pub user_dn_template: String,
+    /// Attributes of the user's entry copied into new accounts
+    pub email_attribute: String,
+    pub first_name_attribute: String,
+    pub last_name_attribute: String,
+    /// Create an account on the first login of a directory user
+    pub create_users: bool,
+    /// Seconds to wait for the directory server
+    pub timeout: u64,
+}
+
+/// The `remote_user` authentication backend, for a reverse proxy that
+/// authenticates users itself and names them in a header
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RemoteUserSettings {
+    /// Header with the username, Django's `REMOTE_USER`
+    pub header: String,
+    /// Header with the email address, used when creating accounts
+    pub email_header: String,
+    /// Addresses of the proxies; headers from any other peer are ignored
+    pub trusted_proxies: Vec<String>,
+    /// Create an account for unknown users, Django's `create_unknown_user`
+    pub create_users: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SameSite {
+    Lax,
+    Strict,
+    None,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SmtpSettings {
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// `tls`, `starttls` or `none`
+    pub security: String,
+}
+
 impl DatabaseSettings {
     pub fn get_url(&self) -> String {
         format!(
@@ -36,6 +448,208 @@ impl DatabaseSettings {
     }
 }
 
+impl LockoutSettings {
+    pub fn policy(&self) -> LockoutPolicy {
+        LockoutPolicy {
+            enabled: self.enabled,
+            account: LockoutRule {
+                free_attempts: self.account_free_attempts,
+                lockout_threshold: self.account_lockout_threshold,
+            },
+            ip: LockoutRule {
+                free_attempts: self.ip_free_attempts,
+                lockout_threshold: self.ip_lockout_threshold,
+            },
+            base_delay: Duration::from_secs(self.base_delay),
+            max_delay: Duration::from_secs(self.max_delay),
+            lockout_duration: Duration::from_secs(self.lockout_duration),
+            failure_window: Duration::from_secs(self.failure_window),
+        }
+    }
+
+    /// Install the lockout policy for the whole process
+    pub fn configure(&self) {
+        // A second call keeps what was installed first
+        let _ = entity::configure_lockout(self.policy());
+    }
+}
+
+impl SsoSettings {
+    pub fn provider(&self, name: &str) -> Option<&SsoProviderSettings> {
+        self.providers.iter().find(|provider| provider.name == name)
+    }
+}
+
+impl SsoProviderSettings {
+    /// Environment variable overriding `client_secret`, e.g.
+    /// `SSO_CORP_CLIENT_SECRET` for `corp`
+    pub fn client_secret_var(&self) -> String {
+        format!(
+            "SSO_{}_CLIENT_SECRET",
+            self.name.to_uppercase().replace('-', "_")
+        )
+    }
+
+    /// Whether accounts with this email address may be provisioned or
+    /// linked by email
+    pub fn allows_email(&self, email: &str) -> bool {
+        let Some((_, domain)) = email.rsplit_once('@') else {
+            return false;
+        };
+        self.allowed_domains.is_empty()
+            || self
+                .allowed_domains
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(domain))
+    }
+}
+
+impl CorsSettings {
+    pub fn allows_any_origin(&self) -> bool {
+        self.allowed_origins.iter().any(|origin| origin == "*")
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.allows_any_origin() && self.allow_credentials {
+            anyhow::bail!("cors.allow_credentials cannot be combined with the \"*\" origin");
+        }
+        Ok(())
+    }
+}
+
+impl PasswordSettings {
+    pub fn build_hashers(&self) -> Result<PasswordHashers, HasherConfigError> {
+        let params = HasherParams {
+            pbkdf2_iterations: self.pbkdf2_iterations,
+            argon2_time_cost: self.argon2_time_cost,
+            argon2_memory_cost: self.argon2_memory_cost,
+            argon2_parallelism: self.argon2_parallelism,
+            bcrypt_rounds: self.bcrypt_rounds,
+            scrypt_work_factor: self.scrypt_work_factor,
+            scrypt_block_size: self.scrypt_block_size,
+            scrypt_parallelism: self.scrypt_parallelism,
+        };
+        PasswordHashers::from_names(&self.hashers, &params)
+    }
+
+    pub fn pool_config(&self) -> PoolConfig {
+        PoolConfig {
+            max_concurrency: self.max_concurrency,
+            queue_timeout: Duration::from_millis(self.queue_timeout_ms),
+        }
+    }
+
+    pub fn build_validators(&self) -> anyhow::Result<PasswordValidators> {
+        let validators = self
+            .validators
+            .iter()
+            .map(ValidatorSettings::build)
+            .collect::<anyhow::Result<_>>()?;
+        Ok(PasswordValidators::new(validators))
+    }
+
+    /// Install the configured hashers, hashing pool and password validators
+    /// for the whole process
+    pub fn configure(&self) -> anyhow::Result<()> {
+        let hashers = self.build_hashers()?;
+        let validators = self.build_validators()?;
+
+        // A second call keeps what was installed first
+        let _ = security::password::configure(hashers);
+        let _ = security::password::configure_pool(HashingPool::new(&self.pool_config()));
+        let _ = security::password::configure_validators(validators);
+        Ok(())
+    }
+}
+
+impl ValidatorSettings {
+    fn build(&self) -> anyhow::Result<Box<dyn PasswordValidator>> {
+        Ok(match self {
+            ValidatorSettings::UserAttributeSimilarity {
+                user_attributes,
+                max_similarity,
+            } => Box::new(UserAttributeSimilarityValidator {
+                user_attributes: user_attributes.clone(),
+                max_similarity: *max_similarity,
+            }),
+            ValidatorSettings::MinimumLength { min_length } => Box::new(MinimumLengthValidator {
+                min_length: *min_length,
+            }),
+            ValidatorSettings::CommonPassword {
+                password_list_path: None,
+            } => Box::new(CommonPasswordValidator::default()),
+            ValidatorSettings::CommonPassword {
+                password_list_path: Some(path),
+            } => Box::new(
+                CommonPasswordValidator::from_file(path)
+                    .with_context(|| format!("Failed to read password list {}", path))?,
+            ),
+            ValidatorSettings::NumericPassword => Box::new(NumericPasswordValidator),
+            ValidatorSettings::BreachedPassword { filter_path } => Box::new(
+                BreachedPasswordValidator::from_file(filter_path)
+                    .with_context(|| format!("Failed to load {}", filter_path))?,
+            ),
+        })
+    }
+}
+
+/// Django's default validators
+fn default_validators() -> Vec<ValidatorSettings> {
+    vec![
+        ValidatorSettings::UserAttributeSimilarity {
+            user_attributes: default_user_attributes(),
+            max_similarity: default_max_similarity(),
+        },
+        ValidatorSettings::MinimumLength {
+            min_length: default_min_length(),
+        },
+        ValidatorSettings::CommonPassword {
+            password_list_path: None,
+        },
+        ValidatorSettings::NumericPassword,
+    ]
+}
+
+fn default_user_attributes() -> Vec<String> {
+    UserAttributeSimilarityValidator::default().user_attributes
+}
+
+fn default_max_similarity() -> f64 {
+    UserAttributeSimilarityValidator::default().max_similarity
+}
+
+fn default_min_length() -> usize {
+    MinimumLengthValidator::default().min_length
+}
+
+fn default_sso_scopes() -> Vec<String> {
+    vec![
+        "openid".to_string(),
+        "email".to_string(),
+        "profile".to_string(),
+    ]
+}
+
+/// The `auth.secret_key` default, public and only fit for development
+const DEVELOPMENT_SECRET_KEY: &str = "insecure-development-secret-key";
+
+/// Outside development, refuse to sign with an empty or the public default key
+fn check_secret_key(environment: &str, secret_key: &str) -> Result<(), ConfigError> {
+    if environment != "development"
+        && (secret_key.is_empty() || secret_key == DEVELOPMENT_SECRET_KEY)
+    {
+        return Err(ConfigError::Message(format!(
+            "auth.secret_key must be set through SECRET_KEY in the {} environment",
+            environment
+        )));
+    }
+    Ok(())
+}
+
+fn default_hashing_concurrency() -> u64 {
+    PoolConfig::default().max_concurrency as u64
+}
+
 impl Settings {
     pub fn new() -> Result<Self, ConfigError> {
         // Detect the running environment
@@ -54,7 +668,152 @@ impl Settings {
             .set_default("database.username", "postgres")?
             .set_default("database.password", "password")?
             .set_default("database.max_connections", 10)?
-            .set_default("database.min_connections", 5)?;
+            .set_default("database.min_connections", 5)?
+            // Auth defaults
+            .set_default("auth.secret_key", DEVELOPMENT_SECRET_KEY)?
+            .set_default("auth.issuer", "r-web")?
+            .set_default("auth.access_token_ttl", 900)?
+            .set_default("auth.refresh_token_ttl", 1_209_600)?
+            .set_default("auth.backends", vec!["model"])?
+            // Password hashing defaults
+            .set_default(
+                "passwords.hashers",
+                vec![
+                    "pbkdf2_sha256",
+                    "pbkdf2_sha1",
+                    "argon2",
+                    "bcrypt_sha256",
+                    "scrypt",
+                ],
+            )?
+            .set_default("passwords.pbkdf2_iterations", 150_000)?
+            .set_default("passwords.argon2_time_cost", 2)?
+            .set_default("passwords.argon2_memory_cost", 102_400)?
+            .set_default("passwords.argon2_parallelism", 8)?
+            .set_default("passwords.bcrypt_rounds", 12)?
+            .set_default("passwords.scrypt_work_factor", 16_384)?
+            .set_default("passwords.scrypt_block_size", 8)?
+            .set_default("passwords.scrypt_parallelism", 1)?
+            .set_default("passwords.max_concurrency", default_hashing_concurrency())?
+            .set_default("passwords.queue_timeout_ms", 5_000)?
+            // Registration defaults
+            .set_default("registration.enabled", true)?
+            .set_default("registration.allow_unverified_login", true)?
+            .set_default("registration.verification_ttl", 259_200)?
+            .set_default(
+                "registration.verification_url",
+                "http://127.0.0.1:8080/api/v1/auth/verify-email",
+            )?
+            .set_default("registration.resend_limit", 3)?
+            .set_default("registration.resend_window", 3_600)?
+            // Password reset defaults
+            .set_default("password_reset.timeout", 259_200)?
+            .set_default(
+                "password_reset.reset_url",
+                "http://127.0.0.1:8080/reset-password",
+            )?
+            // Email change defaults
+            .set_default("email_change.timeout", 259_200)?
+            .set_default(
+                "email_change.confirm_url",
+                "http://127.0.0.1:8080/confirm-email",
+            )?
+            .set_default("email_change.request_limit", 3)?
+            .set_default("email_change.request_window", 3_600)?
+            // Magic link defaults
+            .set_default("magic_link.enabled", false)?
+            .set_default("magic_link.ttl", 900)?
+            .set_default("magic_link.login_url", "http://127.0.0.1:8080/magic-link")?
+            .set_default("magic_link.bind_browser", false)?
+            .set_default("magic_link.request_limit", 3)?
+            .set_default("magic_link.request_window", 3_600)?
+            // Mail defaults
+            .set_default("mail.transport", "console")?
+            .set_default("mail.from_email", "R-Web <no-reply@localhost>")?
+            .set_default("mail.site_name", "R-Web")?
+            .set_default("mail.default_locale", "en")?
+            .set_default("mail.file_path", "tmp/mail")?
+            .set_default("mail.smtp.host", "localhost")?
+            .set_default("mail.smtp.port", 25)?
+            .set_default("mail.smtp.security", "starttls")?
+            // Two-factor authentication defaults
+            .set_default("mfa.issuer", "R-Web")?
+            .set_default("mfa.required_for_staff", true)?
+            .set_default("mfa.token_ttl", 300)?
+            .set_default("mfa.recovery_codes", 10)?
+            .set_default("mfa.max_attempts", 5)?
+            .set_default("mfa.attempt_window", 300)?
+            // Passkey defaults
+            .set_default("webauthn.rp_id", "localhost")?
+            .set_default("webauthn.rp_name", "R-Web")?
+            .set_default("webauthn.origins", vec!["http://localhost:8080"])?
+            .set_default("webauthn.timeout", 300)?
+            // Brute-force protection defaults
+            .set_default("lockout.enabled", true)?
+            .set_default("lockout.account_free_attempts", 3)?
+            .set_default("lockout.account_lockout_threshold", 10)?
+            .set_default("lockout.ip_free_attempts", 10)?
+            .set_default("lockout.ip_lockout_threshold", 50)?
+            .set_default("lockout.base_delay", 1)?
+            .set_default("lockout.max_delay", 60)?
+            .set_default("lockout.lockout_duration", 900)?
+            .set_default("lockout.failure_window", 3_600)?
+            .set_default("lockout.trusted_proxies", Vec::<String>::new())?
+            // Personal access token defaults
+            .set_default("personal_access_tokens.max_per_user", 50)?
+            // Session defaults, as in Django
+            .set_default("session.cookie_name", "sessionid")?
+            .set_default("session.cookie_age", 1_209_600)?
+            .set_default("session.cookie_path", "/")?
+            .set_default("session.cookie_secure", false)?
+            .set_default("session.cookie_httponly", true)?
+            .set_default("session.cookie_samesite", "lax")?
+            .set_default("session.save_every_request", false)?
+            .set_default("session.expire_at_browser_close", false)?
+            // CSRF defaults, as in Django
+            .set_default("csrf.cookie_name", "csrftoken")?
+            .set_default("csrf.cookie_age", 31_449_600)?
+            .set_default("csrf.cookie_path", "/")?
+            .set_default("csrf.cookie_secure", false)?
+            .set_default("csrf.cookie_httponly", false)?
+            .set_default("csrf.cookie_samesite", "lax")?
+            .set_default("csrf.header_name", "X-CSRFToken")?
+            .set_default("csrf.trusted_origins", Vec::<String>::new())?
+            // CORS defaults: same-origin only
+            .set_default("cors.allowed_origins", Vec::<String>::new())?
+            .set_default("cors.allow_credentials", false)?
+            .set_default("cors.max_age", 3_600)?
+            // OAuth2 authorization server defaults
+            .set_default("oauth.code_ttl", 600)?
+            .set_default("oauth.access_token_ttl", 3_600)?
+            .set_default("oauth.refresh_token_ttl", 2_592_000)?
+            .set_default("oauth.login_url", "http://127.0.0.1:8080/login")?
+            .set_default("oauth.consent_url", "http://127.0.0.1:8080/oauth/consent")?
+            // OpenID Connect defaults
+            .set_default("oidc.issuer", "http://127.0.0.1:8080")?
+            .set_default("oidc.id_token_ttl", 3_600)?
+            .set_default("oidc.key_retention", 604_800)?
+            // External OpenID Connect provider defaults
+            .set_default("sso.state_ttl", 600)?
+            .set_default("sso.login_redirect_url", "http://127.0.0.1:8080/")?
+            .set_default("sso.metadata_ttl", 3_600)?
+            // LDAP backend defaults
+            .set_default("ldap.url", "ldap://localhost:389")?
+            .set_default("ldap.start_tls", false)?
+            .set_default(
+                "ldap.user_dn_template",
+                "uid={username},ou=people,dc=example,dc=com",
+            )?
+            .set_default("ldap.email_attribute", "mail")?
+            .set_default("ldap.first_name_attribute", "givenName")?
+            .set_default("ldap.last_name_attribute", "sn")?
+            .set_default("ldap.create_users", true)?
+            .set_default("ldap.timeout", 5)?
+            // Trusted proxy backend defaults
+            .set_default("remote_user.header", "X-Remote-User")?
+            .set_default("remote_user.email_header", "X-Remote-Email")?
+            .set_default("remote_user.trusted_proxies", Vec::<String>::new())?
+            .set_default("remote_user.create_users", true)?;
 
         // Add environment-specific configuration file if it exists
         let config_file = format!("config/{}.toml", environment);
@@ -85,11 +844,48 @@ impl Settings {
         if let Ok(db_password) = env::var("DB_PASSWORD") {
             builder = builder.set_override("database.password", db_password)?;
         }
+        if let Ok(secret_key) = env::var("SECRET_KEY") {
+            builder = builder.set_override("auth.secret_key", secret_key)?;
+        }
+        if let Ok(smtp_password) = env::var("SMTP_PASSWORD") {
+            builder = builder.set_override("mail.smtp.password", smtp_password)?;
+        }
+        if let Ok(encryption_key) = env::var("MFA_ENCRYPTION_KEY") {
+            builder = builder.set_override("mfa.encryption_key", encryption_key)?;
+        }
+        if let Ok(encryption_key) = env::var("OIDC_ENCRYPTION_KEY") {
+            builder = builder.set_override("oidc.encryption_key", encryption_key)?;
+        }
+
+        let mut settings: Settings = builder.build()?.try_deserialize()?;
+        check_secret_key(&environment, &settings.auth.secret_key)?;
+
+        // Providers are a list, out of reach of APP_ variables
+        for provider in &mut settings.sso.providers {
+            if let Ok(client_secret) = env::var(provider.client_secret_var()) {
+                provider.client_secret = Some(client_secret);
+            }
+        }
 
-        builder.build()?.try_deserialize()
+        Ok(settings)
     }
 
     pub fn get_bind_address(&self) -> String {
         format!("{}:{}", self.application.host, self.application.port)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secret_key_required_outside_development() {
+        assert!(check_secret_key("development", DEVELOPMENT_SECRET_KEY).is_ok());
+        assert!(check_secret_key("development", "").is_ok());
+        assert!(check_secret_key("production", DEVELOPMENT_SECRET_KEY).is_err());
+        assert!(check_secret_key("production", "").is_err());
+        assert!(check_secret_key("staging", DEVELOPMENT_SECRET_KEY).is_err());
+        assert!(check_secret_key("production", "a-real-secret").is_ok());
+    }
+}
@@ -0,0 +1,361 @@
+use super::{CsrfCookie, CsrfState, is_well_formed, token_matches, unmask};
+use crate::auth::extractors::bearer_token;
+use crate::config::{CsrfSettings, SameSite, Settings};
+use crate::error::ApiError;
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::cookie::{Cookie, SameSite as CookieSameSite, time};
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform, forward_ready};
+use actix_web::http::Method;
+use actix_web::http::header::{self, AsHeaderName};
+use actix_web::{Error, HttpMessage, ResponseError};
+use std::cell::RefCell;
+use std::future::{Future, Ready, ready};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// Rejects unsafe requests that could have been forged by another site,
+/// like Django's `CsrfViewMiddleware`.
+///
+/// `POST`, `PUT`, `PATCH` and `DELETE` requests must come from the API's own
+/// origin or a trusted one, and carry the token of the CSRF cookie in the
+/// CSRF header. Requests with an `Authorization: Bearer` header are exempt,
+/// since browsers never attach one on their own, and so are the paths given
+/// to [`CsrfMiddleware::new`].
+#[derive(Clone)]
+pub struct CsrfMiddleware {
+    inner: Rc<Inner>,
+}
+
+struct Inner {
+    config: Arc<Settings>,
+    exempt_paths: &'static [&'static str],
+}
+
+impl CsrfMiddleware {
+    pub fn new(config: Arc<Settings>, exempt_paths: &'static [&'static str]) -> Self {
+        Self {
+            inner: Rc::new(Inner {
+                config,
+                exempt_paths,
+            }),
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for CsrfMiddleware
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = CsrfService<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(CsrfService {
+            service: Rc::new(service),
+            inner: self.inner.clone(),
+        }))
+    }
+}
+
+pub struct CsrfService<S> {
+    service: Rc<S>,
+    inner: Rc<Inner>,
+}
+
+impl<S, B> Service<ServiceRequest> for CsrfService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let inner = self.inner.clone();
+
+        Box::pin(async move {
+            // A malformed cookie is replaced once a token is asked for
+            let secret = req
+                .cookie(&inner.settings().cookie_name)
+                .map(|cookie| cookie.value().to_string())
+                .filter(|value| is_well_formed(value))
+                .map(|value| unmask(&value));
+
+            if inner.must_check(&req)
+                && let Err(reason) = inner.check(&req, secret.as_deref())
+            {
+                let error = ApiError::Forbidden(format!("CSRF verification failed: {}", reason));
+                return Ok(req
+                    .into_response(error.error_response())
+                    .map_into_right_body());
+            }
+
+            let cookie = CsrfCookie(Rc::new(RefCell::new(CsrfState {
+                secret,
+                changed: false,
+            })));
+            req.extensions_mut().insert(cookie.clone());
+
+            let mut res = service.call(req).await?;
+            let state = cookie.0.borrow();
+            if state.changed
+                && let Some(secret) = &state.secret
+            {
+                res.response_mut()
+                    .add_cookie(&inner.cookie(secret.clone()))
+                    .map_err(|e| ApiError::InternalServerError(e.to_string()))?;
+            }
+            Ok(res.map_into_left_body())
+        })
+    }
+}
+
+fn header_value(req: &ServiceRequest, name: impl AsHeaderName) -> Option<&str> {
+    req.headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+}
+
+/// Django's `is_same_domain`: `pattern` either equals `host`, or starts
+/// with a dot and matches `host` and all of its subdomains
+fn is_same_domain(host: &str, pattern: &str) -> bool {
+    let host = host.to_ascii_lowercase();
+    let pattern = pattern.to_ascii_lowercase();
+    match pattern.strip_prefix('.') {
+        Some(domain) => host == domain || host.ends_with(&pattern),
+        None => host == pattern,
+    }
+}
+
+/// Scheme and `host[:port]` of an absolute URL
+fn split_url(url: &str) -> Option<(&str, &str)> {
+    let (scheme, rest) = url.split_once("://")?;
+    let netloc = rest.split(['/', '?', '#']).next()?;
+    (!scheme.is_empty() && !netloc.is_empty()).then_some((scheme, netloc))
+}
+
+/// Whether `origin` is allowed by a trusted origin, which may start its
+/// host with `*.` to allow every subdomain
+fn origin_is_trusted(origin: &str, trusted: &str) -> bool {
+    match (split_url(origin), split_url(trusted)) {
+        (Some((scheme, netloc)), Some((trusted_scheme, trusted_netloc)))
+            if trusted_netloc.starts_with("*.") =>
+        {
+            scheme.eq_ignore_ascii_case(trusted_scheme)
+                && is_same_domain(netloc, &trusted_netloc[1..])
+        }
+        _ => origin == trusted,
+    }
+}
+
+impl Inner {
+    fn settings(&self) -> &CsrfSettings {
+        &self.config.csrf
+    }
+
+    fn must_check(&self, req: &ServiceRequest) -> bool {
+        let safe = matches!(
+            *req.method(),
+            Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE
+        );
+        !safe && bearer_token(req.request()).is_none() && !self.exempt_paths.contains(&req.path())
+    }
+
+    /// The reason to reject the request, worded like Django's
+    fn check(&self, req: &ServiceRequest, secret: Option<&str>) -> Result<(), String> {
+        let info = req.connection_info().clone();
+
+        if let Some(origin) = header_value(req, header::ORIGIN) {
+            let own_origin = format!("{}://{}", info.scheme(), info.host());
+            let trusted = origin == own_origin
+                || self
+                    .settings()
+                    .trusted_origins
+                    .iter()
+                    .any(|trusted| origin_is_trusted(origin, trusted));
+            if !trusted {
+                return Err(format!(
+                    "Origin checking failed - {} does not match any trusted origins.",
+                    origin
+                ));
+            }
+        } else if info.scheme() == "https" {
+            // Without an Origin header, HTTPS requests must at least come
+            // from a page of a trusted host
+            let referer = header_value(req, header::REFERER)
+                .ok_or_else(|| "Referer checking failed - no Referer.".to_string())?;
+            let (scheme, netloc) = split_url(referer)
+                .ok_or_else(|| "Referer checking failed - Referer is malformed.".to_string())?;
+            if scheme != "https" {
+                return Err(
+                    "Referer checking failed - Referer is insecure while host is secure."
+                        .to_string(),
+                );
+            }
+
+            let good_host = self
+                .settings()
+                .cookie_domain
+                .clone()
+                .unwrap_or_else(|| info.host().to_string());
+            let trusted = is_same_domain(netloc, &good_host)
+                || self.settings().trusted_origins.iter().any(|trusted| {
+                    split_url(trusted).is_some_and(|(_, host)| {
+                        is_same_domain(netloc, host.trim_start_matches('*'))
+                    })
+                });
+            if !trusted {
+                return Err(format!(
+                    "Referer checking failed - {} does not match any trusted origins.",
+                    referer
+                ));
+            }
+        }
+
+        let secret = secret.ok_or_else(|| "CSRF cookie not set.".to_string())?;
+        let header_name = &self.settings().header_name;
+        let token = header_value(req, header_name.as_str())
+            .ok_or_else(|| "CSRF token missing.".to_string())?;
+        if !token_matches(token, secret) {
+            return Err(format!(
+                "CSRF token from the '{}' HTTP header incorrect.",
+                header_name
+            ));
+        }
+        Ok(())
+    }
+
+    fn cookie(&self, secret: String) -> Cookie<'static> {
+        let settings = self.settings();
+        let mut cookie = Cookie::build(settings.cookie_name.clone(), secret)
+            .path(settings.cookie_path.clone())
+            .max_age(time::Duration::seconds(settings.cookie_age))
+            .secure(settings.cookie_secure)
+            .http_only(settings.cookie_httponly)
+            .same_site(match settings.cookie_samesite {
+                SameSite::Lax => CookieSameSite::Lax,
+                SameSite::Strict => CookieSameSite::Strict,
+                SameSite::None => CookieSameSite::None,
+            })
+            .finish();
+        if let Some(domain) = &settings.cookie_domain {
+            cookie.set_domain(domain.clone());
+        }
+        cookie
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::csrf::get_token;
+    use actix_web::http::StatusCode;
+    use actix_web::test::{TestRequest, call_service, init_service, read_body};
+    use actix_web::{App, HttpRequest, web};
+
+    #[tokio::test]
+    async fn test_unsafe_requests_need_the_token() {
+        let config = Arc::new(Settings::new().unwrap());
+        let app = init_service(
+            App::new()
+                .wrap(CsrfMiddleware::new(config, &["/exempt"]))
+                .route(
+                    "/token",
+                    web::get().to(|req: HttpRequest| async move { get_token(&req) }),
+                )
+                .route("/form", web::post().to(|| async { "ok" }))
+                .route("/exempt", web::post().to(|| async { "ok" })),
+        )
+        .await;
+
+        let res = call_service(&app, TestRequest::get().uri("/token").to_request()).await;
+        let cookie = res
+            .response()
+            .cookies()
+            .find(|cookie| cookie.name() == "csrftoken")
+            .unwrap()
+            .into_owned();
+        let token = String::from_utf8(read_body(res).await.to_vec()).unwrap();
+
+        let post = || TestRequest::post().uri("/form");
+        let status = |req: TestRequest| {
+            let app = &app;
+            async move { call_service(app, req.to_request()).await.status() }
+        };
+
+        assert_eq!(status(post()).await, StatusCode::FORBIDDEN);
+        assert_eq!(
+            status(post().cookie(cookie.clone())).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status(
+                post()
+                    .cookie(cookie.clone())
+                    .insert_header(("X-CSRFToken", token.as_str()))
+            )
+            .await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(
+                post()
+                    .cookie(cookie)
+                    .insert_header(("X-CSRFToken", token.as_str()))
+                    .insert_header((header::ORIGIN, "https://evil.example"))
+            )
+            .await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status(post().insert_header((header::AUTHORIZATION, "Bearer abc"))).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(TestRequest::post().uri("/exempt")).await,
+            StatusCode::OK
+        );
+    }
+
+    #[test]
+    fn test_is_same_domain() {
+        assert!(is_same_domain("example.com", "example.com"));
+        assert!(is_same_domain("Example.com", ".example.com"));
+        assert!(is_same_domain("app.example.com", ".example.com"));
+        assert!(!is_same_domain("app.example.com", "example.com"));
+        assert!(!is_same_domain("badexample.com", ".example.com"));
+    }
+
+    #[test]
+    fn test_origin_is_trusted() {
+        assert!(origin_is_trusted(
+            "https://app.example.com",
+            "https://app.example.com"
+        ));
+        assert!(origin_is_trusted(
+            "https://app.example.com",
+            "https://*.example.com"
+        ));
+        assert!(!origin_is_trusted(
+            "http://app.example.com",
+            "https://*.example.com"
+        ));
+        assert!(!origin_is_trusted(
+            "https://evil.com",
+            "https://app.example.com"
+        ));
+        assert_eq!(
+            split_url("https://example.com:8443/path?q"),
+            Some(("https", "example.com:8443"))
+        );
+    }
+}
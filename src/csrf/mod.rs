@@ -0,0 +1,169 @@
+//! Cross-site request forgery protection, compatible with Django's
+//! `CsrfViewMiddleware`.
+//!
+//! The CSRF cookie holds a secret and clients echo a token derived from it
+//! in the `X-CSRFToken` header of unsafe requests. Tokens are masked with a
+//! fresh random mask each time they are handed out, so tokens and cookies
+//! issued by Django are accepted here and the other way around.
+
+mod middleware;
+
+pub use middleware::CsrfMiddleware;
+
+use crate::error::ApiError;
+use actix_web::dev::Payload;
+use actix_web::{FromRequest, HttpMessage, HttpRequest};
+use apistos::ApiComponent;
+use apistos::Schema;
+use apistos::reference_or::ReferenceOr;
+use security::signing::constant_time_compare;
+use security::token::get_random_string;
+use std::cell::RefCell;
+use std::future::{Ready, ready};
+use std::rc::Rc;
+
+const ALLOWED_CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+const SECRET_LENGTH: usize = 32;
+const TOKEN_LENGTH: usize = 2 * SECRET_LENGTH;
+
+fn new_secret() -> String {
+    get_random_string(SECRET_LENGTH, ALLOWED_CHARS)
+}
+
+fn char_index(c: u8) -> usize {
+    ALLOWED_CHARS
+        .iter()
+        .position(|&allowed| allowed == c)
+        .expect("checked by is_well_formed")
+}
+
+fn is_well_formed(token: &str) -> bool {
+    (token.len() == SECRET_LENGTH || token.len() == TOKEN_LENGTH)
+        && token.bytes().all(|c| ALLOWED_CHARS.contains(&c))
+}
+
+fn mask_with(secret: &str, mask: &str) -> String {
+    let cipher: String = secret
+        .bytes()
+        .zip(mask.bytes())
+        .map(|(s, m)| ALLOWED_CHARS[(char_index(s) + char_index(m)) % ALLOWED_CHARS.len()] as char)
+        .collect();
+    format!("{}{}", mask, cipher)
+}
+
+/// A token for `secret`, masked so it differs on every response
+fn mask_secret(secret: &str) -> String {
+    mask_with(secret, &new_secret())
+}
+
+/// The secret of a well-formed token; secrets are returned as they are
+fn unmask(token: &str) -> String {
+    if token.len() == SECRET_LENGTH {
+        return token.to_string();
+    }
+    let (mask, cipher) = token.split_at(SECRET_LENGTH);
+    cipher
+        .bytes()
+        .zip(mask.bytes())
+        .map(|(c, m)| {
+            let len = ALLOWED_CHARS.len();
+            ALLOWED_CHARS[(char_index(c) + len - char_index(m)) % len] as char
+        })
+        .collect()
+}
+
+/// Whether a token sent by the client belongs to the cookie's secret
+fn token_matches(token: &str, secret: &str) -> bool {
+    is_well_formed(token) && constant_time_compare(&unmask(token), secret)
+}
+
+#[derive(Debug)]
+struct CsrfState {
+    /// Secret of the CSRF cookie, `None` until a token is asked for
+    secret: Option<String>,
+    /// The secret was created or rotated and the cookie must be sent
+    changed: bool,
+}
+
+/// The CSRF secret of the current request, shared with
+/// [`CsrfMiddleware`] which sets the cookie when it changes
+#[derive(Clone)]
+struct CsrfCookie(Rc<RefCell<CsrfState>>);
+
+fn cookie_state(req: &HttpRequest) -> Result<CsrfCookie, ApiError> {
+    req.extensions()
+        .get::<CsrfCookie>()
+        .cloned()
+        .ok_or_else(|| ApiError::InternalServerError("CsrfMiddleware is not installed".to_string()))
+}
+
+/// A masked token to send back in the `X-CSRFToken` header, like Django's
+/// `get_token`. The CSRF cookie is created, or sent again to renew it.
+pub fn get_token(req: &HttpRequest) -> Result<String, ApiError> {
+    let cookie = cookie_state(req)?;
+    let mut state = cookie.0.borrow_mut();
+    let secret = state.secret.get_or_insert_with(new_secret).clone();
+    state.changed = true;
+    Ok(mask_secret(&secret))
+}
+
+/// Replace the CSRF secret, like Django's `rotate_token`. Called on login
+/// so that a token planted before login is useless afterwards.
+pub fn rotate_token(req: &HttpRequest) -> Result<(), ApiError> {
+    let cookie = cookie_state(req)?;
+    let mut state = cookie.0.borrow_mut();
+    state.secret = Some(new_secret());
+    state.changed = true;
+    Ok(())
+}
+
+/// A fresh CSRF token for the `X-CSRFToken` header; the CSRF cookie is set
+/// along with it
+pub struct CsrfToken(pub String);
+
+impl FromRequest for CsrfToken {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(get_token(req).map(CsrfToken))
+    }
+}
+
+impl ApiComponent for CsrfToken {
+    fn child_schemas() -> Vec<(String, ReferenceOr<Schema>)> {
+        vec![]
+    }
+
+    fn schema() -> Option<(String, ReferenceOr<Schema>)> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "abcdefghijklmnopqrstuvwxyzABCDEF";
+
+    #[test]
+    fn test_masking_matches_django() {
+        // _mask_cipher_secret() with the mask fixed
+        let token = mask_with(SECRET, "0123456789ZYXWVUTSRQPONMLKJIHGFE");
+        assert_eq!(
+            token,
+            "0123456789ZYXWVUTSRQPONMLKJIHGFE02468acegi9999999999999999999999"
+        );
+        assert_eq!(unmask(&token), SECRET);
+    }
+
+    #[test]
+    fn test_token_matches() {
+        let token = mask_secret(SECRET);
+        assert_ne!(token, mask_secret(SECRET));
+        assert!(token_matches(&token, SECRET));
+        assert!(token_matches(SECRET, SECRET));
+        assert!(!token_matches(&mask_secret(&new_secret()), SECRET));
+        assert!(!token_matches("not-a-token", SECRET));
+    }
+}
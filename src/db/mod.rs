@@ -18,7 +18,7 @@ pub async fn init_db(config: &Settings) -> Result<DatabaseConnection, sea_orm::D
 
     let db = Database::connect(opt).await?;
     info!("Database connected successfully");
-    let _ = db.ping().await?;
+    db.ping().await?;
     info!("Database ping successful");
 
     Ok(db)
@@ -1,13 +1,59 @@
-use actix_web::{HttpResponse, error::ResponseError};
+use actix_web::{HttpResponse, error::ResponseError, http::header};
+use apistos::ApiErrorComponent;
+use entity::AuthError;
 use log::error;
+use security::password::ValidationError;
+use security::webauthn::WebauthnError;
+use serde_json::json;
 use std::fmt;
+use std::time::Duration;
 
-#[derive(Debug)]
+// `openapi_error` repeats `status(...)` by design
+#[allow(clippy::duplicated_attributes)]
+#[derive(Debug, ApiErrorComponent)]
+#[openapi_error(
+    status(code = 400),
+    status(code = 401),
+    status(code = 403),
+    status(code = 404),
+    status(code = 429),
+    status(code = 500),
+    status(code = 503)
+)]
 pub enum ApiError {
     DatabaseError(String),
     BadRequest(String),
+    Unauthorized(String),
+    Forbidden(String),
     NotFound(String),
+    /// Rate limited; the client may try again after `retry_after`
+    TooManyRequests {
+        message: String,
+        retry_after: Duration,
+    },
     InternalServerError(String),
+    ServiceUnavailable(String),
+    /// A password broke the configured validators, one error per rule
+    InvalidPassword(Vec<ValidationError>),
+}
+
+impl ApiError {
+    /// A 429 telling the client what was refused and when to try again
+    pub fn too_many_requests(what: &str, retry_after: Duration) -> Self {
+        ApiError::TooManyRequests {
+            message: format!(
+                "{}, try again in {} seconds",
+                what,
+                retry_after_secs(retry_after)
+            ),
+            retry_after,
+        }
+    }
+}
+
+/// Whole seconds for `Retry-After`, rounded up so retrying on time succeeds
+fn retry_after_secs(retry_after: Duration) -> u64 {
+    (retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0)).max(1)
 }
 
 impl fmt::Display for ApiError {
@@ -15,8 +61,18 @@ impl fmt::Display for ApiError {
         match self {
             ApiError::DatabaseError(msg) => write!(f, "Database error: {}", msg),
             ApiError::BadRequest(msg) => write!(f, "Bad request: {}", msg),
+            ApiError::Unauthorized(msg) => write!(f, "Unauthorized: {}", msg),
+            ApiError::Forbidden(msg) => write!(f, "Forbidden: {}", msg),
             ApiError::NotFound(msg) => write!(f, "Not found: {}", msg),
+            ApiError::TooManyRequests { message, .. } => {
+                write!(f, "Too many requests: {}", message)
+            }
             ApiError::InternalServerError(msg) => write!(f, "Internal server error: {}", msg),
+            ApiError::ServiceUnavailable(msg) => write!(f, "Service unavailable: {}", msg),
+            ApiError::InvalidPassword(errors) => {
+                let messages: Vec<&str> = errors.iter().map(|e| e.message.as_str()).collect();
+                write!(f, "Invalid password: {}", messages.join(" "))
+            }
         }
     }
 }
@@ -30,12 +86,38 @@ impl ResponseError for ApiError {
                 HttpResponse::InternalServerError().json("Internal server error")
             }
             ApiError::BadRequest(msg) => HttpResponse::BadRequest().json(msg),
+            ApiError::Unauthorized(msg) => HttpResponse::Unauthorized()
+                .insert_header((header::WWW_AUTHENTICATE, "Bearer"))
+                .json(msg),
+            ApiError::Forbidden(msg) => HttpResponse::Forbidden().json(msg),
             ApiError::NotFound(msg) => HttpResponse::NotFound().json(msg),
+            ApiError::TooManyRequests {
+                message,
+                retry_after,
+            } => HttpResponse::TooManyRequests()
+                .insert_header((
+                    header::RETRY_AFTER,
+                    retry_after_secs(*retry_after).to_string(),
+                ))
+                .json(message),
             ApiError::InternalServerError(msg) => {
                 // Log the actual error internally
                 error!("Internal server error: {}", msg);
                 HttpResponse::InternalServerError().json("Internal server error")
             }
+            ApiError::ServiceUnavailable(msg) => HttpResponse::ServiceUnavailable()
+                .insert_header((header::RETRY_AFTER, "1"))
+                .json(msg),
+            ApiError::InvalidPassword(errors) => {
+                let errors: Vec<_> = errors
+                    .iter()
+                    .map(|e| json!({ "code": e.code, "message": e.message }))
+                    .collect();
+                HttpResponse::BadRequest().json(json!({
+                    "detail": "Password does not meet the requirements",
+                    "errors": errors,
+                }))
+            }
         }
     }
 }
@@ -48,6 +130,39 @@ impl From<sea_orm::DbErr> for ApiError {
     }
 }
 
+impl From<AuthError> for ApiError {
+    fn from(err: AuthError) -> Self {
+        match err {
+            AuthError::EmailExists => ApiError::BadRequest("Email already exists".to_string()),
+            AuthError::UsernameExists => {
+                ApiError::BadRequest("Username already exists".to_string())
+            }
+            AuthError::InvalidCredentials => {
+                ApiError::Unauthorized("Invalid credentials".to_string())
+            }
+            AuthError::InactiveAccount => ApiError::Unauthorized("Account is inactive".to_string()),
+            AuthError::DatabaseError(msg) => ApiError::DatabaseError(msg),
+            AuthError::HashingError(msg) => ApiError::InternalServerError(msg),
+            AuthError::HashingUnavailable => {
+                ApiError::ServiceUnavailable("Server is busy, try again later".to_string())
+            }
+            AuthError::PasswordValidation(errors) => ApiError::InvalidPassword(errors),
+            AuthError::MfaAlreadyEnabled => {
+                ApiError::BadRequest("Two-factor authentication is already enabled".to_string())
+            }
+            AuthError::TooManyAttempts { retry_after } => {
+                ApiError::too_many_requests("Too many failed login attempts", retry_after)
+            }
+        }
+    }
+}
+
+impl From<WebauthnError> for ApiError {
+    fn from(err: WebauthnError) -> Self {
+        ApiError::BadRequest(err.to_string())
+    }
+}
+
 // You can also add more conversions for common errors
 impl From<anyhow::Error> for ApiError {
     fn from(err: anyhow::Error) -> Self {
@@ -55,3 +170,24 @@ impl From<anyhow::Error> for ApiError {
         ApiError::InternalServerError(err.to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+
+    #[test]
+    fn test_too_many_attempts_sets_retry_after() {
+        let error = ApiError::from(AuthError::TooManyAttempts {
+            retry_after: Duration::from_millis(2_500),
+        });
+        let response = error.error_response();
+
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers().get(header::RETRY_AFTER).unwrap(), "3");
+        assert_eq!(
+            error.to_string(),
+            "Too many requests: Too many failed login attempts, try again in 3 seconds"
+        );
+    }
+}
@@ -0,0 +1,198 @@
+use crate::auth::backends::{Authenticated, Credentials};
+use crate::auth::devices::{self, DeviceInfo};
+use crate::auth::extractors::CurrentUser;
+use crate::auth::jwt;
+use crate::auth::mfa::{self, MfaStatus};
+use crate::auth::throttle::client_ip;
+use crate::auth::tokens::{self, TokenPair};
+use crate::error::ApiError;
+use crate::state::AppState;
+use actix_web::{HttpRequest, web};
+use apistos::actix::NoContent;
+use apistos::{ApiComponent, api_operation};
+use entity::auth_users::Model as User;
+use entity::{AuthUserModelExt, SessionKind};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, JsonSchema, ApiComponent)]
+pub struct LoginRequest {
+    /// Username or email address
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Deserialize, JsonSchema, ApiComponent)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
+#[derive(Deserialize, JsonSchema, ApiComponent)]
+pub struct LogoutRequest {
+    pub refresh_token: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, ApiComponent)]
+pub struct TokenResponse {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub token_type: String,
+    /// Access token lifetime in seconds
+    pub expires_in: i64,
+}
+
+/// Returned instead of tokens when the password was right but a second
+/// factor is still due
+#[derive(Serialize, Deserialize, JsonSchema, ApiComponent)]
+pub struct MfaChallengeResponse {
+    /// Exchange at `/auth/mfa/verify` or `/auth/mfa/passkey/verify`, or use
+    /// to enroll when `enrollment_required`
+    pub mfa_token: String,
+    /// MFA token lifetime in seconds
+    pub expires_in: i64,
+    /// The account must set up two-factor authentication before logging in
+    pub enrollment_required: bool,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, ApiComponent)]
+#[serde(untagged)]
+pub enum LoginResponse {
+    Tokens(TokenResponse),
+    MfaRequired(MfaChallengeResponse),
+}
+
+impl From<TokenPair> for TokenResponse {
+    fn from(pair: TokenPair) -> Self {
+        Self {
+            access_token: pair.access_token,
+            refresh_token: pair.refresh_token,
+            token_type: "Bearer".to_string(),
+            expires_in: pair.expires_in,
+        }
+    }
+}
+
+/// Issue tokens to a user who passed the first factor, or an MFA token when
+/// a second factor is still due
+pub(crate) async fn first_factor_passed(
+    req: &HttpRequest,
+    app_state: &AppState,
+    user: &User,
+) -> Result<LoginResponse, ApiError> {
+    let status = mfa::status(&app_state.db, &app_state.config, user).await?;
+    if status != MfaStatus::NotRequired {
+        let (mfa_token, expires_in) = jwt::encode_mfa_token(
+            &app_state.config.auth,
+            user.id,
+            app_state.config.mfa.token_ttl,
+        )
+        .map_err(|e| ApiError::InternalServerError(e.to_string()))?;
+
+        return Ok(LoginResponse::MfaRequired(MfaChallengeResponse {
+            mfa_token,
+            expires_in,
+            enrollment_required: status == MfaStatus::EnrollmentRequired,
+        }));
+    }
+
+    Ok(LoginResponse::Tokens(
+        complete_login(req, app_state, user).await?,
+    ))
+}
+
+/// Record the login and the device it came from, and issue a token pair,
+/// once every factor was checked
+pub(crate) async fn complete_login(
+    req: &HttpRequest,
+    app_state: &AppState,
+    user: &User,
+) -> Result<TokenResponse, ApiError> {
+    let user = user.update_last_login(&app_state.db).await?;
+    let device = DeviceInfo::from_request(req, &app_state.config.lockout.trusted_proxies);
+    let session = devices::start(
+        &app_state.db,
+        &app_state.config.auth,
+        user.id,
+        SessionKind::Token,
+        device,
+    )
+    .await?;
+    let pair =
+        tokens::issue_token_pair(&app_state.db, &app_state.config.auth, &user, session.id).await?;
+    Ok(pair.into())
+}
+
+/// Authenticate a password login with `auth.backends`, refusing unverified
+/// accounts unless `registration.allow_unverified_login` is set
+pub(crate) async fn check_password(
+    req: &HttpRequest,
+    app_state: &AppState,
+    username: &str,
+    password: &str,
+) -> Result<Authenticated, ApiError> {
+    let ip = client_ip(req, &app_state.config.lockout.trusted_proxies);
+    let credentials = Credentials::Password { username, password };
+    let authenticated = app_state
+        .auth_backends
+        .authenticate(&app_state.db, &credentials, ip.as_deref())
+        .await?;
+    if !authenticated.user.is_verified && !app_state.config.registration.allow_unverified_login {
+        return Err(ApiError::Forbidden(
+            "Email address is not verified".to_string(),
+        ));
+    }
+    Ok(authenticated)
+}
+
+#[api_operation(
+    summary = "Log in",
+    description = "Authenticate with username or email and password, returning a signed access token and a refresh token. Unverified accounts are refused unless `registration.allow_unverified_login` is set.\n\nAccounts with two-factor authentication, and staff who must enroll, get an MFA token instead of tokens.\n\nRepeated failures per account and per client IP are delayed and then locked out, answered with 429",
+    tag = "auth"
+)]
+pub async fn login(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    body: web::Json<LoginRequest>,
+) -> Result<web::Json<LoginResponse>, ApiError> {
+    let user = check_password(&req, &app_state, &body.username, &body.password)
+        .await?
+        .user;
+    Ok(web::Json(
+        first_factor_passed(&req, &app_state, &user).await?,
+    ))
+}
+
+#[api_operation(
+    summary = "Refresh tokens",
+    description = "Exchange a refresh token for a new token pair. The presented refresh token is revoked",
+    tag = "auth"
+)]
+pub async fn refresh(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    body: web::Json<RefreshRequest>,
+) -> Result<web::Json<TokenResponse>, ApiError> {
+    let device = DeviceInfo::from_request(&req, &app_state.config.lockout.trusted_proxies);
+    let (_, pair) = tokens::rotate_refresh_token(
+        &app_state.db,
+        &app_state.config.auth,
+        &body.refresh_token,
+        device,
+    )
+    .await?;
+    Ok(web::Json(pair.into()))
+}
+
+#[api_operation(
+    summary = "Log out",
+    description = "Revoke a refresh token of the calling user and end its session. Access tokens of the session are refused from then on",
+    tag = "auth"
+)]
+pub async fn logout(
+    app_state: web::Data<AppState>,
+    current_user: CurrentUser,
+    body: web::Json<LogoutRequest>,
+) -> Result<NoContent, ApiError> {
+    tokens::revoke_refresh_token(&app_state.db, current_user.id, &body.refresh_token).await?;
+    Ok(NoContent)
+}
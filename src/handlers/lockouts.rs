@@ -0,0 +1,189 @@
+use crate::auth::permissions::{ChangeUser, RequirePermission, Staff, ViewUser};
+use crate::error::ApiError;
+use crate::state::AppState;
+use actix_web::web;
+use apistos::actix::NoContent;
+use apistos::{ApiComponent, api_operation};
+use chrono::NaiveDateTime;
+use entity::auth_login_failures::Model as LoginFailure;
+use entity::{AuthLoginFailures, LockoutScope, LoginFailureEntityExt, LoginFailureModelExt};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// What failed logins are counted against
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, ApiComponent)]
+#[serde(rename_all = "lowercase")]
+pub enum LockoutScopeParam {
+    /// An existing account; the key is the user id
+    User,
+    /// A username or email matching no account
+    Login,
+    /// A client IP address
+    Ip,
+}
+
+impl From<LockoutScopeParam> for LockoutScope {
+    fn from(scope: LockoutScopeParam) -> Self {
+        match scope {
+            LockoutScopeParam::User => LockoutScope::User,
+            LockoutScopeParam::Login => LockoutScope::Login,
+            LockoutScopeParam::Ip => LockoutScope::Ip,
+        }
+    }
+}
+
+#[derive(Deserialize, JsonSchema, ApiComponent)]
+pub struct LockoutListQuery {
+    pub scope: Option<LockoutScopeParam>,
+    /// Only entries that refuse attempts right now
+    #[serde(default)]
+    pub locked_only: bool,
+}
+
+#[derive(Deserialize, JsonSchema, ApiComponent)]
+pub struct LockoutClearQuery {
+    /// Clear only this scope instead of everything
+    pub scope: Option<LockoutScopeParam>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, ApiComponent)]
+pub struct LockoutResponse {
+    pub id: i32,
+    /// `user`, `login` or `ip`
+    pub scope: String,
+    /// User id, lowercased login or IP address, depending on `scope`
+    pub key: String,
+    /// Consecutive failures within the failure window
+    pub failures: i32,
+    pub last_failure_at: NaiveDateTime,
+    pub locked_until: Option<NaiveDateTime>,
+    /// Whether attempts are refused right now
+    pub locked: bool,
+}
+
+impl From<LoginFailure> for LockoutResponse {
+    fn from(failure: LoginFailure) -> Self {
+        Self {
+            locked: failure.is_locked(),
+            id: failure.id,
+            scope: failure.scope,
+            key: failure.key,
+            failures: failure.failures,
+            last_failure_at: failure.last_failure_at,
+            locked_until: failure.locked_until,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, ApiComponent)]
+pub struct LockoutClearResponse {
+    /// Entries removed
+    pub cleared: u64,
+}
+
+#[api_operation(
+    summary = "List login failures",
+    description = "Failed login counters per account, unknown login and client IP, most recent first. Requires staff status and `auth.view_user`",
+    tag = "admin"
+)]
+pub async fn list(
+    app_state: web::Data<AppState>,
+    _admin: RequirePermission<Staff<ViewUser>>,
+    query: web::Query<LockoutListQuery>,
+) -> Result<web::Json<Vec<LockoutResponse>>, ApiError> {
+    let failures = AuthLoginFailures::list(
+        &app_state.db,
+        query.scope.map(Into::into),
+        query.locked_only,
+    )
+    .await?;
+
+    Ok(web::Json(failures.into_iter().map(Into::into).collect()))
+}
+
+#[api_operation(
+    summary = "Clear a lockout",
+    description = "Forget the failures of one entry, lifting its delay or lockout. Requires staff status and `auth.change_user`",
+    tag = "admin"
+)]
+pub async fn clear(
+    app_state: web::Data<AppState>,
+    _admin: RequirePermission<Staff<ChangeUser>>,
+    path: web::Path<i32>,
+) -> Result<NoContent, ApiError> {
+    if !AuthLoginFailures::clear_by_id(&app_state.db, path.into_inner()).await? {
+        return Err(ApiError::NotFound("Lockout not found".to_string()));
+    }
+    Ok(NoContent)
+}
+
+#[api_operation(
+    summary = "Clear all lockouts",
+    description = "Forget every failed login, or those of one scope. Requires staff status and `auth.change_user`",
+    tag = "admin"
+)]
+pub async fn clear_all(
+    app_state: web::Data<AppState>,
+    _admin: RequirePermission<Staff<ChangeUser>>,
+    query: web::Query<LockoutClearQuery>,
+) -> Result<web::Json<LockoutClearResponse>, ApiError> {
+    let cleared = AuthLoginFailures::clear_all(&app_state.db, query.scope.map(Into::into)).await?;
+    Ok(web::Json(LockoutClearResponse { cleared }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{access_token, app_state, device_session};
+    use actix_web::http::{Method, StatusCode, header};
+    use actix_web::{App, test};
+    use entity::auth_users::Model as User;
+    use sea_orm::{DbBackend, MockDatabase, MockExecResult, Value};
+    use std::collections::BTreeMap;
+
+    #[actix_web::test]
+    async fn test_permission_without_staff_status_is_refused() {
+        let user = User::fixture();
+        for (method, uri) in [
+            (Method::GET, "/admin/lockouts"),
+            (Method::DELETE, "/admin/lockouts/1"),
+            (Method::DELETE, "/admin/lockouts"),
+        ] {
+            // The groups of the user grant both permissions
+            let db = MockDatabase::new(DbBackend::Postgres)
+                .append_query_results([vec![user.clone()]])
+                .append_query_results([vec![device_session(&user, 1)]])
+                .append_query_results([Vec::<entity::auth_permission::Model>::new()])
+                .append_query_results([["auth.view_user", "auth.change_user"]
+                    .map(|codename| BTreeMap::from([("codename", Value::from(codename))]))])
+                .append_query_results([Vec::<LoginFailure>::new()])
+                .append_exec_results([MockExecResult {
+                    last_insert_id: 0,
+                    rows_affected: 1,
+                }])
+                .into_connection();
+            let (state, _) = app_state(db);
+            let token = access_token(&state.config, &user, 1);
+
+            let app = test::init_service(
+                App::new()
+                    .app_data(web::Data::new(state))
+                    .route("/admin/lockouts", web::get().to(list))
+                    .route("/admin/lockouts", web::delete().to(clear_all))
+                    .route("/admin/lockouts/{id}", web::delete().to(clear)),
+            )
+            .await;
+            let req = test::TestRequest::default()
+                .method(method)
+                .uri(uri)
+                .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+                .to_request();
+            assert_eq!(
+                test::call_service(&app, req).await.status(),
+                StatusCode::FORBIDDEN,
+                "{}",
+                uri
+            );
+        }
+    }
+}
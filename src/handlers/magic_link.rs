@@ -0,0 +1,394 @@
+use crate::auth::magic_link;
+use crate::auth::session::MODEL_BACKEND;
+use crate::error::ApiError;
+use crate::handlers::auth::{LoginResponse, first_factor_passed};
+use crate::handlers::registration::MessageResponse;
+use crate::handlers::session::{check_second_factor, start_session};
+use crate::session::Session;
+use crate::state::AppState;
+use actix_web::{HttpRequest, web};
+use apistos::actix::{AcceptedJson, NoContent};
+use apistos::{ApiComponent, api_operation};
+use entity::{AuthUserEntityExt, AuthUsers};
+use log::error;
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+#[derive(Deserialize, JsonSchema, ApiComponent)]
+pub struct MagicLinkRequest {
+    pub email: String,
+}
+
+#[derive(Deserialize, JsonSchema, ApiComponent)]
+pub struct MagicLinkLoginRequest {
+    /// `token` from the login link
+    pub token: String,
+}
+
+#[derive(Deserialize, JsonSchema, ApiComponent)]
+pub struct MagicLinkSessionRequest {
+    /// `token` from the login link
+    pub token: String,
+    /// Current code of the authenticator app, for accounts with two-factor
+    /// authentication
+    pub code: Option<String>,
+    /// Single-use recovery code, when the authenticator app is lost
+    pub recovery_code: Option<String>,
+}
+
+fn ensure_enabled(app_state: &AppState) -> Result<(), ApiError> {
+    if !app_state.config.magic_link.enabled {
+        return Err(ApiError::Forbidden("Login links are disabled".to_string()));
+    }
+    Ok(())
+}
+
+#[api_operation(
+    summary = "Request a login link",
+    description = "Email a single-use link that logs in without a password. The response is the same whether or not the address belongs to an active account.\n\nWith `magic_link.bind_browser` the link only works in the browser session that asked for it",
+    tag = "auth"
+)]
+pub async fn request_link(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    session: Session,
+    body: web::Json<MagicLinkRequest>,
+) -> Result<AcceptedJson<MessageResponse>, ApiError> {
+    ensure_enabled(&app_state)?;
+    let email = body.email.trim();
+
+    app_state
+        .magic_link_requests
+        .check(&email.to_lowercase())
+        .map_err(|retry_after| {
+            ApiError::too_many_requests("Too many login links requested", retry_after)
+        })?;
+
+    // Set up before looking the address up, so the session does not
+    // reveal whether it belongs to an account either
+    let browser_secret = magic_link::browser_secret(&app_state.config, &session)?;
+
+    let user = AuthUsers::find_by_email(&app_state.db, email).await?;
+    if let Some(user) = user.filter(|user| user.is_active) {
+        // Send in the background so the response time does not reveal
+        // whether an email went out
+        let app_state = app_state.clone();
+        let locale = app_state.mail.request_locale(&req);
+        actix_web::rt::spawn(async move {
+            let sent = match magic_link::issue(
+                &app_state.db,
+                &app_state.config,
+                &user,
+                browser_secret.as_deref(),
+            )
+            .await
+            {
+                Ok(token) => magic_link::send_login_email(
+                    &app_state.mail,
+                    &app_state.config,
+                    &user,
+                    &token,
+                    locale.as_deref(),
+                )
+                .await
+                .map_err(|e| e.to_string()),
+                Err(e) => Err(e.to_string()),
+            };
+            if let Err(e) = sent {
+                error!("Failed to send login link to user {}: {}", user.id, e);
+            }
+        });
+    }
+
+    Ok(AcceptedJson(MessageResponse {
+        detail: "If the address belongs to an account, a login link has been sent".to_string(),
+    }))
+}
+
+#[api_operation(
+    summary = "Log in with a login link",
+    description = "Exchange the token of a login link for the same response as `/auth/login`: tokens, or an MFA token for accounts with two-factor authentication. The link works once, and verifies the email address it was sent to",
+    tag = "auth"
+)]
+pub async fn login(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    session: Session,
+    body: web::Json<MagicLinkLoginRequest>,
+) -> Result<web::Json<LoginResponse>, ApiError> {
+    ensure_enabled(&app_state)?;
+
+    let (link, user) = magic_link::check(&app_state.db, &session, &body.token).await?;
+    let user = magic_link::consume(&app_state.db, &link, user).await?;
+    Ok(web::Json(
+        first_factor_passed(&req, &app_state, &user).await?,
+    ))
+}
+
+#[api_operation(
+    summary = "Log in to a session with a login link",
+    description = "Like `/auth/magic-link/login`, but log the browser into a cookie session as `/auth/session/login` does. Accounts with two-factor authentication must send `code` or `recovery_code` along with the token; the link is only used up once they are right",
+    tag = "auth"
+)]
+pub async fn session_login(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    session: Session,
+    body: web::Json<MagicLinkSessionRequest>,
+) -> Result<NoContent, ApiError> {
+    ensure_enabled(&app_state)?;
+
+    let (link, user) = magic_link::check(&app_state.db, &session, &body.token).await?;
+    check_second_factor(
+        &app_state,
+        &user,
+        body.code.as_deref(),
+        body.recovery_code.as_deref(),
+    )
+    .await?;
+    let user = magic_link::consume(&app_state.db, &link, user).await?;
+
+    start_session(&req, &app_state, &session, user, MODEL_BACKEND).await?;
+    Ok(NoContent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Settings;
+    use crate::test_support::{anonymous_session, app_state_with};
+    use actix_web::dev::Service;
+    use actix_web::http::StatusCode;
+    use actix_web::{App, HttpMessage, test};
+    use entity::auth_magic_links::Model as MagicLink;
+    use entity::auth_user_totp::Model as TotpDevice;
+    use entity::auth_users::Model as User;
+    use sea_orm::{DbBackend, MockDatabase, MockExecResult, Transaction};
+
+    fn link(expires_in: chrono::Duration) -> MagicLink {
+        let now = chrono::Utc::now().naive_utc();
+        MagicLink {
+            id: 1,
+            user_id: 42,
+            token_hash: security::token::hash_token("link-token"),
+            browser_hash: None,
+            expires_at: now + expires_in,
+            used_at: None,
+            created_at: now,
+        }
+    }
+
+    fn valid_link() -> MagicLink {
+        link(chrono::Duration::minutes(15))
+    }
+
+    /// A confirmed TOTP device of the fixture user
+    fn totp_device() -> TotpDevice {
+        let now = chrono::Utc::now().naive_utc();
+        TotpDevice {
+            id: 1,
+            user_id: 42,
+            secret_encrypted: String::new(),
+            confirmed_at: Some(now),
+            last_used_step: None,
+            created_at: now,
+        }
+    }
+
+    fn exec(rows_affected: u64) -> MockExecResult {
+        MockExecResult {
+            last_insert_id: 0,
+            rows_affected,
+        }
+    }
+
+    /// Status and body of `POST uri` with the link token in `body`, and the
+    /// statements that ran
+    async fn post(
+        db: MockDatabase,
+        uri: &str,
+        body: serde_json::Value,
+    ) -> (StatusCode, String, Vec<Transaction>) {
+        let mut config = Settings::new().unwrap();
+        config.magic_link.enabled = true;
+        let (state, _) = app_state_with(db.into_connection(), config);
+        let state = web::Data::new(state);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .wrap_fn(|req, srv| {
+                    req.extensions_mut().insert(anonymous_session());
+                    srv.call(req)
+                })
+                .route("/magic-link/login", web::post().to(login))
+                .route("/magic-link/session", web::post().to(session_login)),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri(uri)
+            .set_json(body)
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        let status = response.status();
+        let body = String::from_utf8(test::read_body(response).await.to_vec()).unwrap();
+        drop(app);
+
+        let db = web::Data::into_inner(state).db.clone();
+        let log = std::sync::Arc::try_unwrap(db)
+            .ok()
+            .unwrap()
+            .into_transaction_log();
+        (status, body, log)
+    }
+
+    fn token() -> serde_json::Value {
+        serde_json::json!({ "token": "link-token" })
+    }
+
+    #[actix_web::test]
+    async fn test_link_works_once() {
+        let (status, body, _) = post(
+            MockDatabase::new(DbBackend::Postgres)
+                .append_query_results([vec![valid_link()]])
+                .append_query_results([vec![User::fixture()]])
+                .append_exec_results([exec(1), exec(0)])
+                .append_query_results([vec![totp_device()]]),
+            "/magic-link/login",
+            token(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+
+        // A second request racing the first finds the link already used
+        let (status, _, log) = post(
+            MockDatabase::new(DbBackend::Postgres)
+                .append_query_results([vec![valid_link()]])
+                .append_query_results([vec![User::fixture()]])
+                .append_exec_results([exec(0)]),
+            "/magic-link/login",
+            token(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let log = format!("{:?}", log);
+        assert!(log.contains(r#"\"used_at\" IS NULL"#), "{}", log);
+    }
+
+    #[actix_web::test]
+    async fn test_expired_link_is_refused() {
+        let (status, _, log) = post(
+            MockDatabase::new(DbBackend::Postgres)
+                .append_query_results([vec![link(-chrono::Duration::minutes(1))]]),
+            "/magic-link/login",
+            token(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(log.len(), 1, "{:?}", log);
+    }
+
+    #[actix_web::test]
+    async fn test_inactive_user_is_refused() {
+        let inactive = User {
+            is_active: false,
+            ..User::fixture()
+        };
+        let (status, _, log) = post(
+            MockDatabase::new(DbBackend::Postgres)
+                .append_query_results([vec![valid_link()]])
+                .append_query_results([vec![inactive]]),
+            "/magic-link/login",
+            token(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        // The link is not used up
+        assert_eq!(log.len(), 2, "{:?}", log);
+    }
+
+    #[actix_web::test]
+    async fn test_second_factor_still_required() {
+        // The token login answers with an MFA challenge instead of tokens
+        let (status, body, _) = post(
+            MockDatabase::new(DbBackend::Postgres)
+                .append_query_results([vec![valid_link()]])
+                .append_query_results([vec![User::fixture()]])
+                .append_exec_results([exec(1), exec(0)])
+                .append_query_results([vec![totp_device()]]),
+            "/magic-link/login",
+            token(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert!(body.contains("mfa_token"), "{}", body);
+        assert!(!body.contains("access_token"), "{}", body);
+
+        // The session login needs the code, and keeps the link until then
+        let (status, _, log) = post(
+            MockDatabase::new(DbBackend::Postgres)
+                .append_query_results([vec![valid_link()]])
+                .append_query_results([vec![User::fixture()]])
+                .append_query_results([vec![totp_device()]]),
+            "/magic-link/session",
+            token(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(log.len(), 3, "{:?}", log);
+    }
+
+    #[actix_web::test]
+    async fn test_link_of_another_browser_is_refused() {
+        let bound = MagicLink {
+            browser_hash: Some(security::token::hash_token("other-browser")),
+            ..valid_link()
+        };
+        let (status, _, log) = post(
+            MockDatabase::new(DbBackend::Postgres).append_query_results([vec![bound]]),
+            "/magic-link/login",
+            token(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(log.len(), 1, "{:?}", log);
+    }
+
+    #[actix_web::test]
+    async fn test_disabled_links_are_refused() {
+        let mut config = Settings::new().unwrap();
+        config.magic_link.enabled = false;
+        let db = MockDatabase::new(DbBackend::Postgres).into_connection();
+        let (state, _) = app_state_with(db, config);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(state))
+                .wrap_fn(|req, srv| {
+                    req.extensions_mut().insert(anonymous_session());
+                    srv.call(req)
+                })
+                .route("/magic-link", web::post().to(request_link))
+                .route("/magic-link/login", web::post().to(login)),
+        )
+        .await;
+        for (uri, body) in [
+            (
+                "/magic-link",
+                serde_json::json!({ "email": "ada@example.com" }),
+            ),
+            ("/magic-link/login", token()),
+        ] {
+            let req = test::TestRequest::post()
+                .uri(uri)
+                .set_json(body)
+                .to_request();
+            assert_eq!(
+                test::call_service(&app, req).await.status(),
+                StatusCode::FORBIDDEN,
+                "{}",
+                uri
+            );
+        }
+    }
+}
@@ -0,0 +1,381 @@
+use crate::auth::extractors::{
+    CurrentUser, MfaUser, consume_mfa_token, ensure_mfa_token_unused, mfa_token_user,
+};
+use crate::auth::jwt::{self, TokenType};
+use crate::auth::mfa;
+use crate::error::ApiError;
+use crate::handlers::auth::{TokenResponse, complete_login};
+use crate::state::AppState;
+use actix_web::{HttpRequest, web};
+use apistos::actix::NoContent;
+use apistos::{ApiComponent, api_operation};
+use entity::auth_users::Model as User;
+use entity::{
+    AuthUserRecoveryCodes, AuthUserTotp, RecoveryCodeEntityExt, TotpDeviceEntityExt,
+    TotpDeviceModelExt, WebauthnCredentialEntityExt, WebauthnCredentials,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, JsonSchema, ApiComponent)]
+pub struct MfaVerifyRequest {
+    /// Token returned by `/auth/login`
+    pub mfa_token: String,
+    /// Current code of the authenticator app
+    pub code: Option<String>,
+    /// Single-use recovery code, when the authenticator app is lost
+    pub recovery_code: Option<String>,
+}
+
+#[derive(Deserialize, JsonSchema, ApiComponent)]
+pub struct MfaCodeRequest {
+    /// Current code of the authenticator app
+    pub code: Option<String>,
+    /// Single-use recovery code
+    pub recovery_code: Option<String>,
+}
+
+#[derive(Deserialize, JsonSchema, ApiComponent)]
+pub struct TotpCodeRequest {
+    /// Current code of the authenticator app
+    pub code: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, ApiComponent)]
+pub struct MfaStatusResponse {
+    /// A TOTP device is confirmed or a passkey is registered
+    pub enabled: bool,
+    /// The account may not disable two-factor authentication
+    pub required: bool,
+    pub recovery_codes_remaining: u64,
+    pub passkeys: u64,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, ApiComponent)]
+pub struct TotpSetupResponse {
+    /// Base32 secret for manual entry
+    pub secret: String,
+    /// `otpauth://` URI to show as a QR code
+    pub otpauth_uri: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, ApiComponent)]
+pub struct RecoveryCodesResponse {
+    /// Shown only once; each code can be used once instead of a TOTP code
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, ApiComponent)]
+pub struct TotpConfirmResponse {
+    /// Shown only once; each code can be used once instead of a TOTP code
+    pub recovery_codes: Vec<String>,
+    /// Issued when enrollment finished a login started with an MFA token
+    pub tokens: Option<TokenResponse>,
+}
+
+/// Count a second factor attempt of the user
+pub(crate) fn throttle(app_state: &AppState, user: &User) -> Result<(), ApiError> {
+    app_state
+        .mfa_attempts
+        .check(&user.id.to_string())
+        .map_err(|retry_after| ApiError::too_many_requests("Too many attempts", retry_after))
+}
+
+/// Check a TOTP or recovery code of a user with two-factor authentication
+pub(crate) async fn require_code(
+    app_state: &AppState,
+    user: &User,
+    code: Option<&str>,
+    recovery_code: Option<&str>,
+) -> Result<(), ApiError> {
+    throttle(app_state, user)?;
+    if mfa::verify(&app_state.db, &app_state.config, user, code, recovery_code).await? {
+        Ok(())
+    } else {
+        Err(ApiError::Unauthorized("Invalid code".to_string()))
+    }
+}
+
+#[api_operation(
+    summary = "Verify second factor",
+    description = "Finish a login with the MFA token from `/auth/login` and a TOTP code or a recovery code. Each MFA token finishes one login. Attempts are rate limited per user",
+    tag = "mfa"
+)]
+pub async fn verify(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    body: web::Json<MfaVerifyRequest>,
+) -> Result<web::Json<TokenResponse>, ApiError> {
+    let claims = jwt::decode_token(
+        &app_state.config.auth,
+        &body.mfa_token,
+        TokenType::MfaPending,
+    )
+    .map_err(|_| ApiError::Unauthorized("Invalid or expired MFA token".to_string()))?;
+    let user = mfa_token_user(&app_state, &claims).await?;
+    ensure_mfa_token_unused(&app_state, &claims.jti).await?;
+
+    require_code(
+        &app_state,
+        &user,
+        body.code.as_deref(),
+        body.recovery_code.as_deref(),
+    )
+    .await?;
+    consume_mfa_token(&app_state, &claims.jti).await?;
+
+    Ok(web::Json(complete_login(&req, &app_state, &user).await?))
+}
+
+#[api_operation(
+    summary = "Two-factor status",
+    description = "Whether the calling user has two-factor authentication, how many recovery codes are left and how many passkeys are registered",
+    tag = "mfa"
+)]
+pub async fn status(
+    app_state: web::Data<AppState>,
+    current_user: CurrentUser,
+) -> Result<web::Json<MfaStatusResponse>, ApiError> {
+    let enabled = matches!(
+        mfa::status(&app_state.db, &app_state.config, &current_user).await?,
+        mfa::MfaStatus::Required
+    );
+    let recovery_codes_remaining =
+        AuthUserRecoveryCodes::count_remaining(&app_state.db, current_user.id).await?;
+    let passkeys = WebauthnCredentials::count_for_user(&app_state.db, current_user.id).await?;
+
+    Ok(web::Json(MfaStatusResponse {
+        enabled,
+        required: mfa::is_mandatory(&app_state.config, &current_user),
+        recovery_codes_remaining,
+        passkeys,
+    }))
+}
+
+#[api_operation(
+    summary = "Set up TOTP",
+    description = "Generate a new TOTP secret for an authenticator app. Enrollment finishes at `/auth/mfa/totp/confirm`. Accepts an access token, or the MFA token of a login that requires enrollment",
+    tag = "mfa"
+)]
+pub async fn setup_totp(
+    app_state: web::Data<AppState>,
+    mfa_user: MfaUser,
+) -> Result<web::Json<TotpSetupResponse>, ApiError> {
+    let totp = mfa::begin_enrollment(&app_state.db, &app_state.config, &mfa_user).await?;
+
+    Ok(web::Json(TotpSetupResponse {
+        secret: totp.secret_base32(),
+        otpauth_uri: totp.provisioning_uri(&app_state.config.mfa.issuer, &mfa_user.email),
+    }))
+}
+
+#[api_operation(
+    summary = "Confirm TOTP",
+    description = "Turn on two-factor authentication with a code from the newly set up app, returning recovery codes. When called with an MFA token the login is completed and tokens are returned as well",
+    tag = "mfa"
+)]
+pub async fn confirm_totp(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    mfa_user: MfaUser,
+    body: web::Json<TotpCodeRequest>,
+) -> Result<web::Json<TotpConfirmResponse>, ApiError> {
+    throttle(&app_state, &mfa_user)?;
+    if let Some(jti) = mfa_user.pending_token() {
+        ensure_mfa_token_unused(&app_state, jti).await?;
+    }
+    let recovery_codes =
+        mfa::confirm_enrollment(&app_state.db, &app_state.config, &mfa_user, &body.code).await?;
+
+    let tokens = if let Some(jti) = mfa_user.pending_token() {
+        consume_mfa_token(&app_state, jti).await?;
+        Some(complete_login(&req, &app_state, &mfa_user).await?)
+    } else {
+        None
+    };
+
+    Ok(web::Json(TotpConfirmResponse {
+        recovery_codes,
+        tokens,
+    }))
+}
+
+#[api_operation(
+    summary = "Disable TOTP",
+    description = "Turn off two-factor authentication after checking a TOTP or recovery code. Refused for staff when `mfa.required_for_staff` is set",
+    tag = "mfa"
+)]
+pub async fn disable_totp(
+    app_state: web::Data<AppState>,
+    current_user: CurrentUser,
+    body: web::Json<MfaCodeRequest>,
+) -> Result<NoContent, ApiError> {
+    if mfa::is_mandatory(&app_state.config, &current_user) {
+        return Err(ApiError::Forbidden(
+            "Two-factor authentication is mandatory for this account".to_string(),
+        ));
+    }
+
+    // An unfinished enrollment can be dropped without a code
+    let device = AuthUserTotp::find_for_user(&app_state.db, current_user.id).await?;
+    if device.is_some_and(|device| device.is_confirmed()) {
+        require_code(
+            &app_state,
+            &current_user,
+            body.code.as_deref(),
+            body.recovery_code.as_deref(),
+        )
+        .await?;
+    }
+    mfa::disable(&app_state.db, &current_user).await?;
+    Ok(NoContent)
+}
+
+#[api_operation(
+    summary = "Regenerate recovery codes",
+    description = "Replace every recovery code after checking a TOTP code. Earlier codes stop working",
+    tag = "mfa"
+)]
+pub async fn regenerate_recovery_codes(
+    app_state: web::Data<AppState>,
+    current_user: CurrentUser,
+    body: web::Json<TotpCodeRequest>,
+) -> Result<web::Json<RecoveryCodesResponse>, ApiError> {
+    require_code(&app_state, &current_user, Some(&body.code), None).await?;
+    let recovery_codes =
+        mfa::regenerate_recovery_codes(&app_state.db, &app_state.config, &current_user).await?;
+
+    Ok(web::Json(RecoveryCodesResponse { recovery_codes }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::app_state;
+    use actix_web::http::{StatusCode, header};
+    use actix_web::{App, test};
+    use entity::auth_used_tokens::Model as UsedToken;
+    use entity::auth_user_totp::Model as TotpDevice;
+    use sea_orm::{DbBackend, MockDatabase, MockExecResult, Value};
+    use std::collections::BTreeMap;
+
+    /// Status of `POST uri` with an MFA token of `user`, who has `passkeys`
+    /// and no TOTP device. Enrollment is answered
+    /// with a new device
+    async fn with_mfa_token(user: User, passkeys: i64, uri: &str) -> StatusCode {
+        let db = MockDatabase::new(DbBackend::Postgres)
+            .append_query_results([vec![user.clone()]])
+            .append_query_results([Vec::<TotpDevice>::new()])
+            .append_query_results([vec![BTreeMap::from([(
+                "num_items",
+                Value::BigInt(Some(passkeys)),
+            )])]])
+            .append_query_results([Vec::<TotpDevice>::new()])
+            .append_exec_results([MockExecResult {
+                last_insert_id: 1,
+                rows_affected: 1,
+            }])
+            .append_query_results([vec![TotpDevice {
+                id: 1,
+                user_id: user.id,
+                secret_encrypted: String::new(),
+                confirmed_at: None,
+                last_used_step: None,
+                created_at: chrono::Utc::now().naive_utc(),
+            }]])
+            .into_connection();
+        let (state, _) = app_state(db);
+        let (token, _) = jwt::encode_mfa_token(&state.config.auth, user.id, 300).unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(state))
+                .route("/mfa/totp/setup", web::post().to(setup_totp))
+                .route("/mfa/totp/confirm", web::post().to(confirm_totp)),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri(uri)
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+            .set_json(serde_json::json!({ "code": "123456" }))
+            .to_request();
+        test::call_service(&app, req).await.status()
+    }
+
+    #[actix_web::test]
+    async fn test_mfa_token_of_passkey_user_cannot_enroll() {
+        // Enrolling TOTP would skip the passkey the login still needs
+        for uri in ["/mfa/totp/setup", "/mfa/totp/confirm"] {
+            assert_eq!(
+                with_mfa_token(User::fixture(), 1, uri).await,
+                StatusCode::UNAUTHORIZED,
+                "{}",
+                uri
+            );
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_mfa_token_allows_required_enrollment() {
+        let staff = User {
+            is_staff: true,
+            ..User::fixture()
+        };
+        assert_eq!(
+            with_mfa_token(staff, 0, "/mfa/totp/setup").await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            with_mfa_token(User::fixture(), 0, "/mfa/totp/setup").await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_used_mfa_token_is_refused() {
+        let user = User::fixture();
+        let db = MockDatabase::new(DbBackend::Postgres)
+            .append_query_results([vec![user.clone()]])
+            // The token finished a login before
+            .append_query_results([vec![UsedToken {
+                jti: String::new(),
+                expires_at: chrono::Utc::now().naive_utc(),
+            }]])
+            .into_connection();
+        let (state, _) = app_state(db);
+        let (token, _) = jwt::encode_mfa_token(&state.config.auth, user.id, 300).unwrap();
+        let state = web::Data::new(state);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .route("/mfa/verify", web::post().to(verify)),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/mfa/verify")
+            .set_json(serde_json::json!({
+                "mfa_token": token,
+                "recovery_code": "abcd-efgh",
+            }))
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::UNAUTHORIZED
+        );
+
+        drop(app);
+        let db = web::Data::into_inner(state).db.clone();
+        let log = std::sync::Arc::try_unwrap(db)
+            .ok()
+            .unwrap()
+            .into_transaction_log();
+        let log = format!("{:?}", log);
+        assert!(
+            !log.contains("auth_user_recovery_codes"),
+            "The recovery code stays usable: {}",
+            log
+        );
+        assert!(!log.contains("auth_user_sessions"), "No login is recorded");
+    }
+}
@@ -1 +1,16 @@
-pub mod health;
\ No newline at end of file
+pub mod auth;
+pub mod health;
+pub mod lockouts;
+pub mod magic_link;
+pub mod mfa;
+pub mod oauth;
+pub mod oidc;
+pub mod passkeys;
+pub mod password_reset;
+pub mod personal_access_tokens;
+pub mod profile;
+pub mod registration;
+pub mod session;
+pub mod sso;
+pub mod user_sessions;
+pub mod users;
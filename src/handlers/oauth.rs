@@ -0,0 +1,646 @@
+use crate::auth::extractors::{CurrentUser, resolve_current_user};
+use crate::error::ApiError;
+use crate::oauth::{
+    self, AuthorizationRequest, AuthorizeError, AuthorizeParams, IssuedTokens, OAuthError,
+};
+use crate::state::AppState;
+use actix_web::body::BoxBody;
+use actix_web::http::header;
+use actix_web::{HttpRequest, HttpResponse, Responder, web};
+use apistos::reference_or::ReferenceOr;
+use apistos::{ApiComponent, Schema, api_operation};
+use entity::{GrantType, OAuthTokenModelExt, OauthClients};
+use schemars::JsonSchema;
+use sea_orm::EntityTrait;
+use security::token::hash_token;
+use serde::{Deserialize, Serialize};
+
+/// JSON response that must not be cached, as it carries tokens (RFC 6749
+/// section 5.1)
+pub struct NoStore<T>(pub T);
+
+impl<T: Serialize> Responder for NoStore<T> {
+    type Body = BoxBody;
+
+    fn respond_to(self, _req: &HttpRequest) -> HttpResponse {
+        HttpResponse::Ok()
+            .insert_header((header::CACHE_CONTROL, "no-store"))
+            .insert_header((header::PRAGMA, "no-cache"))
+            .json(self.0)
+    }
+}
+
+impl<T: ApiComponent> ApiComponent for NoStore<T> {
+    fn required() -> bool {
+        T::required()
+    }
+
+    fn child_schemas() -> Vec<(String, ReferenceOr<Schema>)> {
+        T::child_schemas()
+    }
+
+    fn raw_schema() -> Option<ReferenceOr<Schema>> {
+        T::raw_schema()
+    }
+
+    fn schema() -> Option<(String, ReferenceOr<Schema>)> {
+        T::schema()
+    }
+}
+
+pub(crate) fn redirect(location: &str) -> HttpResponse {
+    HttpResponse::Found()
+        .insert_header((header::LOCATION, location))
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .finish()
+}
+
+#[api_operation(
+    summary = "Start an OAuth2 authorization",
+    description = "Authorization endpoint of the authorization code grant (RFC 6749). Logged-out users are redirected to the login page with this URL in `next`; users who have not approved the client for these scopes yet are redirected to the consent page with the same query string. Otherwise the user is redirected to `redirect_uri` with a `code` and the `state`.\n\nPublic clients must send a PKCE `code_challenge` with `code_challenge_method=S256`. Errors are reported to `redirect_uri`, except for an unknown client or redirect URI",
+    tag = "oauth"
+)]
+pub async fn authorize(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    query: web::Query<AuthorizeParams>,
+) -> Result<HttpResponse, ApiError> {
+    let request = match query.check(&app_state.db).await {
+        Ok(request) => request,
+        Err(AuthorizeError::BadClient(err)) => return Err(err),
+        Err(AuthorizeError::Redirect(location)) => return Ok(redirect(&location)),
+    };
+
+    let user = match resolve_current_user(&req).await {
+        Ok(current) => current.user,
+        Err(ApiError::Unauthorized(_)) => {
+            let next = req.full_url().to_string();
+            return Ok(redirect(&oauth::with_query(
+                &app_state.config.oauth.login_url,
+                &[("next", &next)],
+            )));
+        }
+        Err(err) => return Err(err),
+    };
+
+    if request.needs_consent(&app_state.db, user.id).await? {
+        let consent_url = &app_state.config.oauth.consent_url;
+        let separator = if consent_url.contains('?') { '&' } else { '?' };
+        return Ok(redirect(&format!(
+            "{}{}{}",
+            consent_url,
+            separator,
+            req.query_string()
+        )));
+    }
+
+    let location = request
+        .issue_code(&app_state.db, &app_state.config.oauth, user.id)
+        .await?;
+    Ok(redirect(&location))
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, ApiComponent)]
+pub struct ScopeResponse {
+    pub scope: String,
+    pub description: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, ApiComponent)]
+pub struct ConsentInfoResponse {
+    pub client_id: String,
+    pub client_name: String,
+    /// What the client asks to do on the user's behalf
+    pub scopes: Vec<ScopeResponse>,
+}
+
+/// Check a request forwarded by the consent page, which got it from
+/// `/oauth/authorize`
+async fn check_forwarded(
+    app_state: &AppState,
+    params: &AuthorizeParams,
+) -> Result<Result<AuthorizationRequest, String>, ApiError> {
+    match params.check(&app_state.db).await {
+        Ok(request) => Ok(Ok(request)),
+        Err(AuthorizeError::BadClient(err)) => Err(err),
+        Err(AuthorizeError::Redirect(location)) => Ok(Err(location)),
+    }
+}
+
+#[api_operation(
+    summary = "Describe an OAuth2 authorization request",
+    description = "For the consent page: the client asking for access and the scopes it asks for. Takes the query string `/oauth/authorize` redirected to the consent page with",
+    tag = "oauth"
+)]
+pub async fn consent_info(
+    app_state: web::Data<AppState>,
+    _current_user: CurrentUser,
+    query: web::Query<AuthorizeParams>,
+) -> Result<web::Json<ConsentInfoResponse>, ApiError> {
+    let request = check_forwarded(&app_state, &query)
+        .await?
+        .map_err(|_| ApiError::BadRequest("Invalid authorization request".to_string()))?;
+
+    let scopes = request
+        .scopes
+        .into_iter()
+        .map(|scope| ScopeResponse {
+            description: oauth::describe_scope(&scope)
+                .unwrap_or_default()
+                .to_string(),
+            scope,
+        })
+        .collect();
+
+    Ok(web::Json(ConsentInfoResponse {
+        client_id: request.client.client_id,
+        client_name: request.client.name,
+        scopes,
+    }))
+}
+
+#[derive(Deserialize, JsonSchema, ApiComponent)]
+pub struct ConsentRequest {
+    #[serde(flatten)]
+    pub params: AuthorizeParams,
+    /// Whether the user approved the client
+    pub approve: bool,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, ApiComponent)]
+pub struct ConsentResponse {
+    /// Where to send the browser next
+    pub redirect_to: String,
+}
+
+#[api_operation(
+    summary = "Approve or deny an OAuth2 client",
+    description = "Record the user's decision on the consent page. An approval is remembered for the client and these scopes, so the user is not asked again. Returns the client's redirect URI with a `code`, or with `error=access_denied` when denied",
+    tag = "oauth"
+)]
+pub async fn consent(
+    app_state: web::Data<AppState>,
+    current_user: CurrentUser,
+    body: web::Json<ConsentRequest>,
+) -> Result<web::Json<ConsentResponse>, ApiError> {
+    let request = match check_forwarded(&app_state, &body.params).await? {
+        Ok(request) => request,
+        Err(redirect_to) => return Ok(web::Json(ConsentResponse { redirect_to })),
+    };
+
+    let redirect_to = if body.approve {
+        request
+            .grant_consent(&app_state.db, current_user.id)
+            .await?;
+        request
+            .issue_code(&app_state.db, &app_state.config.oauth, current_user.id)
+            .await?
+    } else {
+        request.denied_redirect()
+    };
+
+    Ok(web::Json(ConsentResponse { redirect_to }))
+}
+
+#[derive(Deserialize, JsonSchema, ApiComponent)]
+pub struct TokenRequest {
+    /// `authorization_code`, `refresh_token` or `client_credentials`
+    pub grant_type: String,
+    /// For `authorization_code`
+    pub code: Option<String>,
+    /// For `authorization_code`, the redirect URI the code was sent to
+    pub redirect_uri: Option<String>,
+    /// For `authorization_code` with PKCE
+    pub code_verifier: Option<String>,
+    /// For `refresh_token`
+    pub refresh_token: Option<String>,
+    /// Space-separated scopes to narrow the grant to
+    pub scope: Option<String>,
+    /// Unless sent with HTTP Basic authentication
+    pub client_id: Option<String>,
+    /// Unless sent with HTTP Basic authentication; public clients have none
+    pub client_secret: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, ApiComponent)]
+pub struct TokenResponse {
+    /// Send as `Authorization: Bearer <token>`
+    pub access_token: String,
+    /// Always `Bearer`
+    pub token_type: String,
+    /// Seconds until the access token expires
+    pub expires_in: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    /// OpenID Connect ID token, when the `openid` scope was granted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id_token: Option<String>,
+    /// Space-separated scopes of the access token
+    pub scope: String,
+}
+
+impl From<IssuedTokens> for TokenResponse {
+    fn from(tokens: IssuedTokens) -> Self {
+        Self {
+            access_token: tokens.access_token,
+            token_type: "Bearer".to_string(),
+            expires_in: tokens.expires_in,
+            refresh_token: tokens.refresh_token,
+            id_token: tokens.id_token,
+            scope: tokens.scopes.join(" "),
+        }
+    }
+}
+
+fn required<'a>(value: &'a Option<String>, name: &str) -> Result<&'a str, OAuthError> {
+    value
+        .as_deref()
+        .ok_or_else(|| OAuthError::InvalidRequest(format!("{} is required", name)))
+}
+
+#[api_operation(
+    summary = "Issue OAuth2 tokens",
+    description = "Token endpoint (RFC 6749) for the `authorization_code`, `refresh_token` and `client_credentials` grants. Confidential clients authenticate with HTTP Basic or with `client_id` and `client_secret`; public clients only send `client_id`. Refresh tokens are rotated on every use, and reusing an old one revokes the grant",
+    tag = "oauth"
+)]
+pub async fn token(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    form: web::Form<TokenRequest>,
+) -> Result<NoStore<TokenResponse>, OAuthError> {
+    let client = oauth::authenticate_client(
+        &app_state.db,
+        &req,
+        form.client_id.as_deref(),
+        form.client_secret.as_deref(),
+    )
+    .await?;
+
+    let grant_type: GrantType = form.grant_type.parse().map_err(|_| {
+        OAuthError::UnsupportedGrantType(format!("Unsupported grant type: {}", form.grant_type))
+    })?;
+    let tokens = match grant_type {
+        GrantType::AuthorizationCode => {
+            oauth::exchange_code(
+                &app_state.db,
+                &app_state.config,
+                &client,
+                required(&form.code, "code")?,
+                form.redirect_uri.as_deref(),
+                form.code_verifier.as_deref(),
+            )
+            .await?
+        }
+        GrantType::RefreshToken => {
+            oauth::refresh_tokens(
+                &app_state.db,
+                &app_state.config,
+                &client,
+                required(&form.refresh_token, "refresh_token")?,
+                form.scope.as_deref(),
+            )
+            .await?
+        }
+        GrantType::ClientCredentials => {
+            oauth::client_credentials(
+                &app_state.db,
+                &app_state.config,
+                &client,
+                form.scope.as_deref(),
+            )
+            .await?
+        }
+    };
+
+    Ok(NoStore(tokens.into()))
+}
+
+#[derive(Deserialize, JsonSchema, ApiComponent)]
+pub struct TokenLookupRequest {
+    /// Access or refresh token
+    pub token: String,
+    /// `access_token` or `refresh_token`; tokens are found either way
+    pub token_type_hint: Option<String>,
+    /// Unless sent with HTTP Basic authentication
+    pub client_id: Option<String>,
+    /// Unless sent with HTTP Basic authentication
+    pub client_secret: Option<String>,
+}
+
+#[api_operation(
+    summary = "Revoke an OAuth2 token",
+    description = "Revocation endpoint (RFC 7009). Revoking either token of a pair revokes both. Succeeds for unknown tokens and for tokens of other clients too, without revoking them",
+    tag = "oauth"
+)]
+pub async fn revoke(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    form: web::Form<TokenLookupRequest>,
+) -> Result<HttpResponse, OAuthError> {
+    let client = oauth::authenticate_client(
+        &app_state.db,
+        &req,
+        form.client_id.as_deref(),
+        form.client_secret.as_deref(),
+    )
+    .await?;
+
+    if let Some(stored) = oauth::find_client_token(&app_state.db, &client, &form.token).await? {
+        stored.revoke(&app_state.db).await?;
+    }
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, ApiComponent)]
+pub struct IntrospectionResponse {
+    pub active: bool,
+    /// Space-separated scopes of the token
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    /// Client the token was issued to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    /// User who granted the token; absent for client credentials tokens
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    /// `Bearer` for access tokens, `refresh_token` for refresh tokens
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_type: Option<String>,
+    /// Expiry as a Unix timestamp
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exp: Option<i64>,
+    /// Issue time as a Unix timestamp
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iat: Option<i64>,
+    /// Id of the user who granted the token
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
+}
+
+impl IntrospectionResponse {
+    fn inactive() -> Self {
+        Self {
+            active: false,
+            scope: None,
+            client_id: None,
+            username: None,
+            token_type: None,
+            exp: None,
+            iat: None,
+            sub: None,
+        }
+    }
+}
+
+#[api_operation(
+    summary = "Introspect an OAuth2 token",
+    description = "Introspection endpoint (RFC 7662) for resource servers, which authenticate as confidential clients. Unknown, expired and revoked tokens, and tokens of inactive users, are reported as `{\"active\": false}`",
+    tag = "oauth"
+)]
+pub async fn introspect(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    form: web::Form<TokenLookupRequest>,
+) -> Result<NoStore<IntrospectionResponse>, OAuthError> {
+    let client = oauth::authenticate_client(
+        &app_state.db,
+        &req,
+        form.client_id.as_deref(),
+        form.client_secret.as_deref(),
+    )
+    .await?;
+    if client.client_secret_hash.is_none() {
+        return Err(OAuthError::UnauthorizedClient(
+            "Only confidential clients may introspect tokens".to_string(),
+        ));
+    }
+
+    let Some(stored) = oauth::find_token(&app_state.db, &form.token).await? else {
+        return Ok(NoStore(IntrospectionResponse::inactive()));
+    };
+    let is_access = stored.access_token_hash == hash_token(&form.token);
+    let (active, expires_at) = if is_access {
+        (stored.is_access_active(), Some(stored.access_expires_at))
+    } else {
+        (stored.is_refresh_active(), stored.refresh_expires_at)
+    };
+    if !active {
+        return Ok(NoStore(IntrospectionResponse::inactive()));
+    }
+
+    let user = match stored.user_id {
+        Some(user_id) => match entity::AuthUsers::find_by_id(user_id)
+            .one(app_state.db.as_ref())
+            .await?
+        {
+            Some(user) if user.is_active => Some(user),
+            _ => return Ok(NoStore(IntrospectionResponse::inactive())),
+        },
+        None => None,
+    };
+    let owner = OauthClients::find_by_id(stored.client_id)
+        .one(app_state.db.as_ref())
+        .await?;
+
+    Ok(NoStore(IntrospectionResponse {
+        active: true,
+        scope: Some(stored.scopes.clone()),
+        client_id: owner.map(|owner| owner.client_id),
+        sub: user.as_ref().map(|user| user.id.to_string()),
+        username: user.map(|user| user.username),
+        token_type: Some(if is_access { "Bearer" } else { "refresh_token" }.to_string()),
+        exp: expires_at.map(|expires_at| expires_at.and_utc().timestamp()),
+        iat: Some(stored.created_at.and_utc().timestamp()),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::app_state;
+    use actix_web::http::StatusCode;
+    use actix_web::{App, test};
+    use entity::auth_users::Model as User;
+    use entity::oauth_clients::Model as Client;
+    use entity::oauth_tokens::Model as OAuthToken;
+    use sea_orm::{DatabaseConnection, DbBackend, MockDatabase, MockExecResult};
+    use std::sync::Arc;
+
+    fn client(id: i32, secret: Option<&str>) -> Client {
+        Client {
+            id,
+            client_id: format!("client-{}", id),
+            client_secret_hash: secret.map(hash_token),
+            name: "API".to_string(),
+            redirect_uris: "https://app.example.com/cb".to_string(),
+            grant_types: "authorization_code refresh_token".to_string(),
+            scopes: "profile:read".to_string(),
+            skip_consent: false,
+            created_at: chrono::Utc::now().naive_utc(),
+        }
+    }
+
+    fn token() -> OAuthToken {
+        let now = chrono::Utc::now().naive_utc();
+        OAuthToken {
+            id: 1,
+            client_id: 1,
+            user_id: Some(42),
+            access_token_hash: hash_token("access"),
+            refresh_token_hash: Some(hash_token("refresh")),
+            scopes: "profile:read".to_string(),
+            access_expires_at: now + chrono::Duration::minutes(5),
+            refresh_expires_at: Some(now + chrono::Duration::days(1)),
+            revoked_at: None,
+            created_at: now,
+        }
+    }
+
+    /// Post `token` to `uri` as client 1, whose secret is `secret`
+    async fn lookup(
+        db: MockDatabase,
+        uri: &str,
+        token: &str,
+    ) -> (StatusCode, Vec<u8>, Arc<DatabaseConnection>) {
+        let (state, _) = app_state(db.into_connection());
+        let db = state.db.clone();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(state))
+                .route("/oauth/revoke", web::post().to(revoke))
+                .route("/oauth/introspect", web::post().to(introspect)),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri(uri)
+            .set_form([
+                ("token", token),
+                ("client_id", "client-1"),
+                ("client_secret", "secret"),
+            ])
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        let status = res.status();
+        (status, test::read_body(res).await.to_vec(), db)
+    }
+
+    fn with_client() -> MockDatabase {
+        MockDatabase::new(DbBackend::Postgres)
+            .append_query_results([vec![client(1, Some("secret"))]])
+    }
+
+    #[actix_web::test]
+    async fn test_revoke_leaves_tokens_of_other_clients() {
+        let foreign = OAuthToken {
+            client_id: 2,
+            ..token()
+        };
+        let (status, _, db) = lookup(
+            with_client().append_query_results([vec![foreign]]),
+            "/oauth/revoke",
+            "access",
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let log = format!("{:?}", Arc::try_unwrap(db).unwrap().into_transaction_log());
+        assert!(!log.contains("UPDATE"), "{}", log);
+    }
+
+    #[actix_web::test]
+    async fn test_revoke_inactive_tokens() {
+        let revoked = OAuthToken {
+            revoked_at: Some(chrono::Utc::now().naive_utc()),
+            ..token()
+        };
+        let (status, _, _) = lookup(
+            with_client()
+                .append_query_results([vec![revoked]])
+                .append_exec_results([MockExecResult {
+                    last_insert_id: 0,
+                    rows_affected: 0,
+                }]),
+            "/oauth/revoke",
+            "refresh",
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, _, _) = lookup(
+            with_client()
+                .append_query_results([Vec::<OAuthToken>::new()])
+                .append_query_results([Vec::<OAuthToken>::new()]),
+            "/oauth/revoke",
+            "unknown",
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn test_introspect_inactive_tokens() {
+        let now = chrono::Utc::now().naive_utc();
+        let revoked = OAuthToken {
+            revoked_at: Some(now),
+            ..token()
+        };
+        let expired = OAuthToken {
+            access_expires_at: now - chrono::Duration::seconds(1),
+            ..token()
+        };
+        for stored in [revoked, expired] {
+            let (status, body, _) = lookup(
+                with_client().append_query_results([vec![stored]]),
+                "/oauth/introspect",
+                "access",
+            )
+            .await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(body, br#"{"active":false}"#);
+        }
+
+        let inactive_user = User {
+            is_active: false,
+            ..User::fixture()
+        };
+        let (_, body, _) = lookup(
+            with_client()
+                .append_query_results([vec![token()]])
+                .append_query_results([vec![inactive_user]]),
+            "/oauth/introspect",
+            "access",
+        )
+        .await;
+        assert_eq!(body, br#"{"active":false}"#);
+
+        let (_, body, _) = lookup(
+            with_client()
+                .append_query_results([Vec::<OAuthToken>::new()])
+                .append_query_results([Vec::<OAuthToken>::new()]),
+            "/oauth/introspect",
+            "unknown",
+        )
+        .await;
+        assert_eq!(body, br#"{"active":false}"#);
+    }
+
+    #[actix_web::test]
+    async fn test_public_clients_may_not_introspect() {
+        let db =
+            MockDatabase::new(DbBackend::Postgres).append_query_results([vec![client(1, None)]]);
+        let (state, _) = app_state(db.into_connection());
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(state))
+                .route("/oauth/introspect", web::post().to(introspect)),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/oauth/introspect")
+            .set_form([("token", "access"), ("client_id", "client-1")])
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,231 @@
+use crate::auth::extractors::Credential;
+use crate::auth::scopes::{self, RequireScope};
+use crate::error::ApiError;
+use crate::handlers::oauth::NoStore;
+use crate::oidc::keys::{self, Jwk};
+use crate::oidc::{self, OpenId, UserClaims};
+use crate::state::AppState;
+use actix_web::web;
+use apistos::{ApiComponent, api_operation};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, JsonSchema, ApiComponent)]
+pub struct DiscoveryResponse {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub userinfo_endpoint: String,
+    pub jwks_uri: String,
+    pub revocation_endpoint: String,
+    pub introspection_endpoint: String,
+    pub scopes_supported: Vec<String>,
+    pub response_types_supported: Vec<String>,
+    pub grant_types_supported: Vec<String>,
+    pub subject_types_supported: Vec<String>,
+    pub id_token_signing_alg_values_supported: Vec<String>,
+    pub token_endpoint_auth_methods_supported: Vec<String>,
+    pub code_challenge_methods_supported: Vec<String>,
+    pub claims_supported: Vec<String>,
+}
+
+fn strings(values: &[&str]) -> Vec<String> {
+    values.iter().map(|value| value.to_string()).collect()
+}
+
+#[api_operation(
+    summary = "OpenID Connect discovery",
+    description = "Provider metadata (OpenID Connect Discovery 1.0): the endpoints, scopes and algorithms relying parties configure themselves from",
+    tag = "oidc"
+)]
+pub async fn discovery(
+    app_state: web::Data<AppState>,
+) -> Result<web::Json<DiscoveryResponse>, ApiError> {
+    let issuer = app_state.config.oidc.issuer.trim_end_matches('/');
+    let endpoint = |path: &str| format!("{}{}", issuer, path);
+
+    Ok(web::Json(DiscoveryResponse {
+        issuer: issuer.to_string(),
+        authorization_endpoint: endpoint("/oauth/authorize"),
+        token_endpoint: endpoint("/oauth/token"),
+        userinfo_endpoint: endpoint("/oauth/userinfo"),
+        jwks_uri: endpoint("/jwks.json"),
+        revocation_endpoint: endpoint("/oauth/revoke"),
+        introspection_endpoint: endpoint("/oauth/introspect"),
+        scopes_supported: oidc::SCOPES
+            .iter()
+            .chain(scopes::SCOPES)
+            .map(|(name, _)| name.to_string())
+            .collect(),
+        response_types_supported: strings(&["code"]),
+        grant_types_supported: strings(&[
+            "authorization_code",
+            "refresh_token",
+            "client_credentials",
+        ]),
+        subject_types_supported: strings(&["public"]),
+        id_token_signing_alg_values_supported: strings(&[keys::ALGORITHM]),
+        token_endpoint_auth_methods_supported: strings(&[
+            "client_secret_basic",
+            "client_secret_post",
+            "none",
+        ]),
+        code_challenge_methods_supported: strings(&["S256"]),
+        claims_supported: strings(&[
+            "sub",
+            "iss",
+            "aud",
+            "exp",
+            "iat",
+            "auth_time",
+            "nonce",
+            "name",
+            "given_name",
+            "family_name",
+            "preferred_username",
+            "updated_at",
+            "email",
+            "email_verified",
+        ]),
+    }))
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, ApiComponent)]
+pub struct JwksResponse {
+    pub keys: Vec<Jwk>,
+}
+
+#[api_operation(
+    summary = "Signing keys",
+    description = "JSON Web Key Set (RFC 7517) to verify ID tokens with: the current signing key, and rotated keys for `oidc.key_retention` seconds after their rotation",
+    tag = "oidc"
+)]
+pub async fn jwks(app_state: web::Data<AppState>) -> Result<web::Json<JwksResponse>, ApiError> {
+    Ok(web::Json(JwksResponse {
+        keys: keys::published(&app_state.db, &app_state.config).await?,
+    }))
+}
+
+#[api_operation(
+    summary = "Get the user's claims",
+    description = "UserInfo endpoint of OpenID Connect. Takes an OAuth access token granted the `openid` scope, and returns the claims its `profile` and `email` scopes allow",
+    tag = "oidc"
+)]
+pub async fn userinfo(current_user: RequireScope<OpenId>) -> Result<NoStore<UserClaims>, ApiError> {
+    let Credential::OAuthToken { scopes, .. } = &current_user.credential else {
+        return Err(ApiError::Forbidden(
+            "An OAuth access token is required".to_string(),
+        ));
+    };
+
+    Ok(NoStore(UserClaims::new(&current_user.user, scopes)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::oauth::ACCESS_TOKEN_PREFIX;
+    use crate::test_support::{access_token, app_state, device_session};
+    use actix_web::http::{StatusCode, header};
+    use actix_web::{App, test};
+    use entity::auth_users::Model as User;
+    use entity::oauth_tokens::Model as OAuthToken;
+    use sea_orm::{DbBackend, MockDatabase};
+    use security::token::hash_token;
+
+    fn oauth_token(scopes: &str) -> OAuthToken {
+        let now = chrono::Utc::now().naive_utc();
+        OAuthToken {
+            id: 1,
+            client_id: 1,
+            user_id: Some(42),
+            access_token_hash: hash_token(&format!("{}access", ACCESS_TOKEN_PREFIX)),
+            refresh_token_hash: None,
+            scopes: scopes.to_string(),
+            access_expires_at: now + chrono::Duration::minutes(5),
+            refresh_expires_at: None,
+            revoked_at: None,
+            created_at: now,
+        }
+    }
+
+    /// Status and body of `/oauth/userinfo` for `token`
+    async fn userinfo_with(db: MockDatabase, token: Option<String>) -> (StatusCode, String) {
+        let (state, _) = app_state(db.into_connection());
+        let token = token.unwrap_or_else(|| format!("{}access", ACCESS_TOKEN_PREFIX));
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(state))
+                .route("/oauth/userinfo", web::get().to(userinfo)),
+        )
+        .await;
+        let req = test::TestRequest::get()
+            .uri("/oauth/userinfo")
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        let status = response.status();
+        let body = String::from_utf8(test::read_body(response).await.to_vec()).unwrap();
+        (status, body)
+    }
+
+    #[actix_web::test]
+    async fn test_userinfo_follows_the_granted_scopes() {
+        let (status, body) = userinfo_with(
+            MockDatabase::new(DbBackend::Postgres)
+                .append_query_results([vec![oauth_token("openid email")]])
+                .append_query_results([vec![User::fixture()]]),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body,
+            r#"{"sub":"42","email":"ada@example.com","email_verified":true}"#
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_userinfo_needs_the_openid_scope() {
+        let (status, _) = userinfo_with(
+            MockDatabase::new(DbBackend::Postgres)
+                .append_query_results([vec![oauth_token("profile email")]])
+                .append_query_results([vec![User::fixture()]]),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[actix_web::test]
+    async fn test_userinfo_refuses_revoked_tokens() {
+        let revoked = OAuthToken {
+            revoked_at: Some(chrono::Utc::now().naive_utc()),
+            ..oauth_token("openid")
+        };
+        let (status, _) = userinfo_with(
+            MockDatabase::new(DbBackend::Postgres).append_query_results([vec![revoked]]),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn test_userinfo_needs_an_oauth_token() {
+        // Login tokens carry every scope, but are not granted to a client
+        let user = User::fixture();
+        let (state, _) = app_state(MockDatabase::new(DbBackend::Postgres).into_connection());
+        let token = access_token(&state.config, &user, 1);
+
+        let (status, _) = userinfo_with(
+            MockDatabase::new(DbBackend::Postgres)
+                .append_query_results([vec![user.clone()]])
+                .append_query_results([vec![device_session(&user, 1)]]),
+            Some(token),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+}
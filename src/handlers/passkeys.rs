@@ -0,0 +1,639 @@
+use crate::auth::extractors::{CurrentUser, consume_mfa_token, mfa_token_user};
+use crate::auth::jwt::{self, TokenType};
+use crate::auth::mfa;
+use crate::auth::passkeys::{self, Assertion, Ceremony};
+use crate::auth::session::MODEL_BACKEND;
+use crate::config::Settings;
+use crate::error::ApiError;
+use crate::handlers::auth::{TokenResponse, complete_login};
+use crate::handlers::mfa::throttle;
+use crate::handlers::session::start_session;
+use crate::session::Session;
+use crate::state::AppState;
+use actix_web::{HttpRequest, web};
+use apistos::actix::{CreatedJson, NoContent};
+use apistos::{ApiComponent, api_operation};
+use chrono::NaiveDateTime;
+use entity::auth_users::Model as User;
+use entity::webauthn_credentials::Model as Passkey;
+use entity::{
+    AuthUserTotp, TotpDeviceEntityExt, TotpDeviceModelExt, WebauthnCredentialEntityExt,
+    WebauthnCredentialModelExt, WebauthnCredentials,
+};
+use schemars::JsonSchema;
+use security::webauthn::{ES256, RS256};
+use serde::{Deserialize, Serialize};
+
+const MAX_NAME_LEN: usize = 100;
+const DEFAULT_NAME: &str = "Passkey";
+
+/// `PublicKeyCredentialCreationOptionsJSON`, for
+/// `PublicKeyCredential.parseCreationOptionsFromJSON()`
+#[derive(Serialize, Deserialize, JsonSchema, ApiComponent)]
+#[serde(rename_all = "camelCase")]
+pub struct CreationOptions {
+    pub rp: RelyingPartyEntity,
+    pub user: UserEntity,
+    pub challenge: String,
+    pub pub_key_cred_params: Vec<CredentialParameters>,
+    /// Milliseconds the browser prompt stays open
+    pub timeout: i64,
+    /// Passkeys the user already has, so the authenticator does not make a
+    /// second one
+    pub exclude_credentials: Vec<CredentialDescriptor>,
+    pub authenticator_selection: AuthenticatorSelection,
+    pub attestation: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, ApiComponent)]
+pub struct RelyingPartyEntity {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, ApiComponent)]
+#[serde(rename_all = "camelCase")]
+pub struct UserEntity {
+    /// Opaque user handle, base64url
+    pub id: String,
+    pub name: String,
+    pub display_name: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, ApiComponent)]
+pub struct CredentialParameters {
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// COSE algorithm
+    pub alg: i64,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, ApiComponent)]
+pub struct CredentialDescriptor {
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// Credential id, base64url
+    pub id: String,
+    pub transports: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, ApiComponent)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthenticatorSelection {
+    pub resident_key: String,
+    pub require_resident_key: bool,
+    pub user_verification: String,
+}
+
+/// `PublicKeyCredentialRequestOptionsJSON`, for
+/// `PublicKeyCredential.parseRequestOptionsFromJSON()`
+#[derive(Serialize, Deserialize, JsonSchema, ApiComponent)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestOptions {
+    pub challenge: String,
+    /// Milliseconds the browser prompt stays open
+    pub timeout: i64,
+    pub rp_id: String,
+    /// Empty for a login, so the browser offers every passkey of the site
+    pub allow_credentials: Vec<CredentialDescriptor>,
+    pub user_verification: String,
+}
+
+/// `response` of a `RegistrationResponseJSON`
+#[derive(Deserialize, JsonSchema, ApiComponent)]
+#[serde(rename_all = "camelCase")]
+pub struct AttestationResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    pub attestation_object: String,
+    /// From `getTransports()`
+    #[serde(default)]
+    pub transports: Vec<String>,
+}
+
+/// `RegistrationResponseJSON`, the result of `navigator.credentials.create()`
+#[derive(Deserialize, JsonSchema, ApiComponent)]
+pub struct RegistrationCredential {
+    pub id: String,
+    pub response: AttestationResponse,
+}
+
+#[derive(Deserialize, JsonSchema, ApiComponent)]
+pub struct PasskeyRegisterRequest {
+    /// Shown in the list of passkeys, e.g. the device it lives on
+    pub name: Option<String>,
+    pub credential: RegistrationCredential,
+}
+
+/// `response` of an `AuthenticationResponseJSON`
+#[derive(Deserialize, JsonSchema, ApiComponent)]
+#[serde(rename_all = "camelCase")]
+pub struct AssertionResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    pub authenticator_data: String,
+    pub signature: String,
+    pub user_handle: Option<String>,
+}
+
+/// `AuthenticationResponseJSON`, the result of `navigator.credentials.get()`
+#[derive(Deserialize, JsonSchema, ApiComponent)]
+pub struct AuthenticationCredential {
+    pub id: String,
+    pub response: AssertionResponse,
+}
+
+impl AuthenticationCredential {
+    fn assertion(&self) -> Assertion<'_> {
+        Assertion {
+            credential_id: &self.id,
+            client_data_json: &self.response.client_data_json,
+            authenticator_data: &self.response.authenticator_data,
+            signature: &self.response.signature,
+            user_handle: self.response.user_handle.as_deref(),
+        }
+    }
+}
+
+#[derive(Deserialize, JsonSchema, ApiComponent)]
+pub struct PasskeyLoginRequest {
+    pub credential: AuthenticationCredential,
+}
+
+#[derive(Deserialize, JsonSchema, ApiComponent)]
+pub struct MfaPasskeyOptionsRequest {
+    /// Token returned by `/auth/login`
+    pub mfa_token: String,
+}
+
+#[derive(Deserialize, JsonSchema, ApiComponent)]
+pub struct MfaPasskeyVerifyRequest {
+    /// Token returned by `/auth/login`
+    pub mfa_token: String,
+    pub credential: AuthenticationCredential,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, ApiComponent)]
+pub struct PasskeyResponse {
+    pub id: i32,
+    pub name: String,
+    /// How the browser reaches the authenticator, e.g. `internal` or `hybrid`
+    pub transports: Vec<String>,
+    /// Model of the authenticator, all zeros when it does not say
+    pub aaguid: String,
+    /// The passkey may be synced to the user's other devices
+    pub backup_eligible: bool,
+    pub backed_up: bool,
+    pub last_used_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+impl From<Passkey> for PasskeyResponse {
+    fn from(passkey: Passkey) -> Self {
+        Self {
+            transports: passkey.transport_list(),
+            id: passkey.id,
+            name: passkey.name,
+            aaguid: passkey.aaguid,
+            backup_eligible: passkey.backup_eligible,
+            backed_up: passkey.backed_up,
+            last_used_at: passkey.last_used_at,
+            created_at: passkey.created_at,
+        }
+    }
+}
+
+fn descriptors(passkeys: &[Passkey]) -> Vec<CredentialDescriptor> {
+    passkeys
+        .iter()
+        .map(|passkey| CredentialDescriptor {
+            kind: "public-key".to_string(),
+            id: passkey.credential_id.clone(),
+            transports: passkey.transport_list(),
+        })
+        .collect()
+}
+
+fn request_options(
+    config: &Settings,
+    challenge: String,
+    allow_credentials: Vec<CredentialDescriptor>,
+    user_verification: &str,
+) -> RequestOptions {
+    RequestOptions {
+        challenge,
+        timeout: config.webauthn.timeout * 1000,
+        rp_id: config.webauthn.rp_id.clone(),
+        allow_credentials,
+        user_verification: user_verification.to_string(),
+    }
+}
+
+/// Check a passkey login, refusing unverified accounts as a password login
+/// would
+async fn passkey_login(
+    app_state: &AppState,
+    session: &Session,
+    credential: &AuthenticationCredential,
+) -> Result<User, ApiError> {
+    let user = passkeys::authenticate(
+        &app_state.db,
+        &app_state.config,
+        session,
+        Ceremony::Login,
+        None,
+        credential.assertion(),
+    )
+    .await?;
+    if !user.is_verified && !app_state.config.registration.allow_unverified_login {
+        return Err(ApiError::Forbidden(
+            "Email address is not verified".to_string(),
+        ));
+    }
+    Ok(user)
+}
+
+/// User an MFA token was issued to, and the token's `jti`
+async fn mfa_pending_user(
+    app_state: &AppState,
+    mfa_token: &str,
+) -> Result<(User, String), ApiError> {
+    let claims = jwt::decode_token(&app_state.config.auth, mfa_token, TokenType::MfaPending)
+        .map_err(|_| ApiError::Unauthorized("Invalid or expired MFA token".to_string()))?;
+    let user = mfa_token_user(app_state, &claims).await?;
+    Ok((user, claims.jti))
+}
+
+#[api_operation(
+    summary = "Start adding a passkey",
+    description = "Options for `navigator.credentials.create()`. The challenge is kept in the session until `/me/passkeys` is called with the result",
+    tag = "passkeys"
+)]
+pub async fn registration_options(
+    app_state: web::Data<AppState>,
+    session: Session,
+    current_user: CurrentUser,
+) -> Result<web::Json<CreationOptions>, ApiError> {
+    let config = &app_state.config;
+    let existing = passkeys::list(&app_state.db, &current_user).await?;
+    let challenge = passkeys::begin(&session, Ceremony::Registration, Some(current_user.id))?;
+
+    Ok(web::Json(CreationOptions {
+        rp: RelyingPartyEntity {
+            id: config.webauthn.rp_id.clone(),
+            name: config.webauthn.rp_name.clone(),
+        },
+        user: UserEntity {
+            id: passkeys::user_handle(config, &current_user),
+            name: current_user.username.clone(),
+            display_name: current_user.email.clone(),
+        },
+        challenge,
+        pub_key_cred_params: [ES256, RS256]
+            .into_iter()
+            .map(|alg| CredentialParameters {
+                kind: "public-key".to_string(),
+                alg,
+            })
+            .collect(),
+        timeout: config.webauthn.timeout * 1000,
+        exclude_credentials: descriptors(&existing),
+        authenticator_selection: AuthenticatorSelection {
+            resident_key: "required".to_string(),
+            require_resident_key: true,
+            user_verification: "preferred".to_string(),
+        },
+        attestation: "none".to_string(),
+    }))
+}
+
+#[api_operation(
+    summary = "Add a passkey",
+    description = "Store the passkey created with the options of `/me/passkeys/options`. From then on it logs in without a password, and the account has two-factor authentication: password logins must be finished with a passkey or a TOTP code",
+    tag = "passkeys"
+)]
+pub async fn register(
+    app_state: web::Data<AppState>,
+    session: Session,
+    current_user: CurrentUser,
+    body: web::Json<PasskeyRegisterRequest>,
+) -> Result<CreatedJson<PasskeyResponse>, ApiError> {
+    let name = body
+        .name
+        .as_deref()
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .unwrap_or(DEFAULT_NAME);
+    if name.chars().count() > MAX_NAME_LEN {
+        return Err(ApiError::BadRequest(format!(
+            "Name must be at most {} characters",
+            MAX_NAME_LEN
+        )));
+    }
+
+    let response = &body.credential.response;
+    let passkey = passkeys::register(
+        &app_state.db,
+        &app_state.config,
+        &session,
+        &current_user,
+        name,
+        &response.client_data_json,
+        &response.attestation_object,
+        &response.transports,
+    )
+    .await?;
+    Ok(CreatedJson(passkey.into()))
+}
+
+#[api_operation(
+    summary = "List passkeys",
+    description = "Passkeys of the calling user, oldest first",
+    tag = "passkeys"
+)]
+pub async fn list(
+    app_state: web::Data<AppState>,
+    current_user: CurrentUser,
+) -> Result<web::Json<Vec<PasskeyResponse>>, ApiError> {
+    let passkeys = passkeys::list(&app_state.db, &current_user).await?;
+    Ok(web::Json(passkeys.into_iter().map(Into::into).collect()))
+}
+
+#[api_operation(
+    summary = "Remove a passkey",
+    description = "Delete one of the calling user's passkeys. Refused when it is the last second factor of an account that must have one",
+    tag = "passkeys"
+)]
+pub async fn remove(
+    app_state: web::Data<AppState>,
+    current_user: CurrentUser,
+    path: web::Path<i32>,
+) -> Result<NoContent, ApiError> {
+    let id = path.into_inner();
+
+    if mfa::is_mandatory(&app_state.config, &current_user) {
+        let has_totp = AuthUserTotp::find_for_user(&app_state.db, current_user.id)
+            .await?
+            .is_some_and(|device| device.is_confirmed());
+        let count = WebauthnCredentials::count_for_user(&app_state.db, current_user.id).await?;
+        if !has_totp && count <= 1 {
+            return Err(ApiError::Forbidden(
+                "Two-factor authentication is mandatory for this account".to_string(),
+            ));
+        }
+    }
+
+    if WebauthnCredentials::remove(&app_state.db, current_user.id, id).await? {
+        Ok(NoContent)
+    } else {
+        Err(ApiError::NotFound("Passkey not found".to_string()))
+    }
+}
+
+#[api_operation(
+    summary = "Start a passkey login",
+    description = "Options for `navigator.credentials.get()`, offering every passkey of the site. The challenge is kept in the session until `/auth/passkey/login` or `/auth/passkey/session` is called with the result",
+    tag = "auth"
+)]
+pub async fn login_options(
+    app_state: web::Data<AppState>,
+    session: Session,
+) -> Result<web::Json<RequestOptions>, ApiError> {
+    let challenge = passkeys::begin(&session, Ceremony::Login, None)?;
+    Ok(web::Json(request_options(
+        &app_state.config,
+        challenge,
+        Vec::new(),
+        "required",
+    )))
+}
+
+#[api_operation(
+    summary = "Log in with a passkey",
+    description = "Exchange the result of `navigator.credentials.get()` for tokens. The passkey must have verified the user with a PIN or biometric, so no second factor is asked for",
+    tag = "auth"
+)]
+pub async fn login(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    session: Session,
+    body: web::Json<PasskeyLoginRequest>,
+) -> Result<web::Json<TokenResponse>, ApiError> {
+    let user = passkey_login(&app_state, &session, &body.credential).await?;
+    Ok(web::Json(complete_login(&req, &app_state, &user).await?))
+}
+
+#[api_operation(
+    summary = "Log in to a session with a passkey",
+    description = "Like `/auth/passkey/login`, but log the browser into a cookie session as `/auth/session/login` does",
+    tag = "auth"
+)]
+pub async fn session_login(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    session: Session,
+    body: web::Json<PasskeyLoginRequest>,
+) -> Result<NoContent, ApiError> {
+    let user = passkey_login(&app_state, &session, &body.credential).await?;
+    start_session(&req, &app_state, &session, user, MODEL_BACKEND).await?;
+    Ok(NoContent)
+}
+
+#[api_operation(
+    summary = "Start a passkey second factor",
+    description = "Options for `navigator.credentials.get()` offering the passkeys of the user the MFA token from `/auth/login` belongs to",
+    tag = "mfa"
+)]
+pub async fn mfa_options(
+    app_state: web::Data<AppState>,
+    session: Session,
+    body: web::Json<MfaPasskeyOptionsRequest>,
+) -> Result<web::Json<RequestOptions>, ApiError> {
+    let (user, _) = mfa_pending_user(&app_state, &body.mfa_token).await?;
+    let passkeys = passkeys::list(&app_state.db, &user).await?;
+    if passkeys.is_empty() {
+        return Err(ApiError::BadRequest("No passkey is registered".to_string()));
+    }
+
+    let challenge = passkeys::begin(&session, Ceremony::SecondFactor, Some(user.id))?;
+    Ok(web::Json(request_options(
+        &app_state.config,
+        challenge,
+        descriptors(&passkeys),
+        "preferred",
+    )))
+}
+
+#[api_operation(
+    summary = "Verify second factor with a passkey",
+    description = "Finish a login with the MFA token from `/auth/login` and the result of `navigator.credentials.get()` for the options of `/auth/mfa/passkey/options`. Attempts are rate limited per user",
+    tag = "mfa"
+)]
+pub async fn mfa_verify(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    session: Session,
+    body: web::Json<MfaPasskeyVerifyRequest>,
+) -> Result<web::Json<TokenResponse>, ApiError> {
+    let (user, jti) = mfa_pending_user(&app_state, &body.mfa_token).await?;
+    throttle(&app_state, &user)?;
+
+    let user = passkeys::authenticate(
+        &app_state.db,
+        &app_state.config,
+        &session,
+        Ceremony::SecondFactor,
+        Some(&user),
+        body.credential.assertion(),
+    )
+    .await?;
+    consume_mfa_token(&app_state, &jti).await?;
+    Ok(web::Json(complete_login(&req, &app_state, &user).await?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{anonymous_session, app_state};
+    use actix_web::dev::Service;
+    use actix_web::http::StatusCode;
+    use actix_web::{App, HttpMessage, test};
+    use sea_orm::{DbBackend, MockDatabase, Transaction};
+
+    fn credential() -> serde_json::Value {
+        serde_json::json!({
+            "id": "Y3JlZGVudGlhbA",
+            "response": {
+                "clientDataJSON": "e30",
+                "authenticatorData": "",
+                "signature": "",
+                "userHandle": null,
+            },
+        })
+    }
+
+    /// Status of `POST uri` with `body` in `session`, and the statements
+    /// that ran
+    async fn post(
+        db: MockDatabase,
+        session: &Session,
+        uri: &str,
+        body: serde_json::Value,
+    ) -> (StatusCode, Vec<Transaction>) {
+        let (state, _) = app_state(db.into_connection());
+        let state = web::Data::new(state);
+        let session = session.clone();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .wrap_fn(move |req, srv| {
+                    req.extensions_mut().insert(session.clone());
+                    srv.call(req)
+                })
+                .route("/passkey/login", web::post().to(login))
+                .route("/mfa/passkey/verify", web::post().to(mfa_verify)),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri(uri)
+            .set_json(body)
+            .to_request();
+        let status = test::call_service(&app, req).await.status();
+        drop(app);
+
+        let db = web::Data::into_inner(state).db.clone();
+        let log = std::sync::Arc::try_unwrap(db)
+            .ok()
+            .unwrap()
+            .into_transaction_log();
+        (status, log)
+    }
+
+    async fn login_with(session: &Session) -> (StatusCode, Vec<Transaction>) {
+        post(
+            MockDatabase::new(DbBackend::Postgres),
+            session,
+            "/passkey/login",
+            serde_json::json!({ "credential": credential() }),
+        )
+        .await
+    }
+
+    #[actix_web::test]
+    async fn test_challenge_of_another_ceremony_is_refused() {
+        let session = anonymous_session();
+        passkeys::begin(&session, Ceremony::Registration, Some(42)).unwrap();
+
+        let (status, log) = login_with(&session).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(log.is_empty(), "{:?}", log);
+        // Taken out all the same, so it cannot be tried again
+        assert!(
+            session
+                .get::<serde_json::Value>(passkeys::CHALLENGE_SESSION_KEY)
+                .is_none()
+        );
+
+        let (status, _) = login_with(&anonymous_session()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_challenge_of_another_user_is_refused() {
+        let user = User::fixture();
+        let session = anonymous_session();
+        passkeys::begin(&session, Ceremony::SecondFactor, Some(7)).unwrap();
+
+        let (state, _) = app_state(MockDatabase::new(DbBackend::Postgres).into_connection());
+        let (mfa_token, _) = jwt::encode_mfa_token(&state.config.auth, user.id, 300).unwrap();
+        let (status, log) = post(
+            MockDatabase::new(DbBackend::Postgres).append_query_results([vec![user]]),
+            &session,
+            "/mfa/passkey/verify",
+            serde_json::json!({ "mfa_token": mfa_token, "credential": credential() }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(log.len(), 1, "Only the user is loaded: {:?}", log);
+    }
+
+    #[actix_web::test]
+    async fn test_expired_challenge_is_refused() {
+        let (state, _) = app_state(MockDatabase::new(DbBackend::Postgres).into_connection());
+        let session = anonymous_session();
+        session
+            .insert(
+                passkeys::CHALLENGE_SESSION_KEY,
+                serde_json::json!({
+                    "ceremony": "Login",
+                    "challenge": "challenge",
+                    "user_id": null,
+                    "started_at": chrono::Utc::now().timestamp()
+                        - state.config.webauthn.timeout
+                        - 1,
+                }),
+            )
+            .unwrap();
+
+        let (status, log) = login_with(&session).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(log.is_empty(), "{:?}", log);
+    }
+
+    #[actix_web::test]
+    async fn test_unknown_passkey_is_refused() {
+        let session = anonymous_session();
+        passkeys::begin(&session, Ceremony::Login, None).unwrap();
+
+        let (status, log) = post(
+            MockDatabase::new(DbBackend::Postgres).append_query_results([Vec::<Passkey>::new()]),
+            &session,
+            "/passkey/login",
+            serde_json::json!({ "credential": credential() }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(log.len(), 1, "{:?}", log);
+    }
+}
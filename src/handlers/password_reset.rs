@@ -0,0 +1,245 @@
+use crate::auth::password_reset;
+use crate::auth::throttle::client_ip;
+use crate::error::ApiError;
+use crate::handlers::registration::MessageResponse;
+use crate::state::AppState;
+use actix_web::{HttpRequest, web};
+use apistos::actix::{AcceptedJson, NoContent};
+use apistos::{ApiComponent, api_operation};
+use entity::{
+    AuthUserEntityExt, AuthUserModelExt, AuthUserSessions, AuthUsers, LoginAttempt,
+    UserSessionEntityExt,
+};
+use log::error;
+use schemars::JsonSchema;
+use sea_orm::EntityTrait;
+use security::reset_token::decode_uid;
+use serde::Deserialize;
+
+#[derive(Deserialize, JsonSchema, ApiComponent)]
+pub struct ForgotPasswordRequest {
+    pub email: String,
+}
+
+#[derive(Deserialize, JsonSchema, ApiComponent)]
+pub struct ResetPasswordRequest {
+    /// `uid` from the reset link
+    pub uid: String,
+    /// `token` from the reset link
+    pub token: String,
+    pub new_password: String,
+}
+
+#[api_operation(
+    summary = "Forgot password",
+    description = "Email a password reset link. The response is the same whether or not the address belongs to an account. Requests are limited per address and per client IP like failed logins",
+    tag = "auth"
+)]
+pub async fn forgot_password(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    body: web::Json<ForgotPasswordRequest>,
+) -> Result<AcceptedJson<MessageResponse>, ApiError> {
+    let email = body.email.trim();
+    let user = AuthUsers::find_by_email(&app_state.db, email).await?;
+
+    // Unknown addresses are counted alike, so a refusal reveals nothing
+    let ip = client_ip(&req, &app_state.config.lockout.trusted_proxies);
+    LoginAttempt::new(user.as_ref(), email)
+        .from_ip(ip.as_deref())
+        .counted_as("password_reset")
+        .record::<ApiError>(&app_state.db)
+        .await?;
+
+    if let Some(user) = user.filter(|user| user.is_active) {
+        // Send in the background so the response time does not reveal
+        // whether an email went out
+        let app_state = app_state.clone();
+        let locale = app_state.mail.request_locale(&req);
+        actix_web::rt::spawn(async move {
+            if let Err(e) = password_reset::send_reset_email(
+                &app_state.mail,
+                &app_state.config,
+                &user,
+                locale.as_deref(),
+            )
+            .await
+            {
+                error!(
+                    "Failed to send password reset email to user {}: {}",
+                    user.id, e
+                );
+            }
+        });
+    }
+
+    Ok(AcceptedJson(MessageResponse {
+        detail: "If the address belongs to an account, a password reset email has been sent"
+            .to_string(),
+    }))
+}
+
+#[api_operation(
+    summary = "Reset password",
+    description = "Set a new password using the uid and token from a reset link. Every session and refresh token of the user is ended",
+    tag = "auth"
+)]
+pub async fn reset_password(
+    app_state: web::Data<AppState>,
+    body: web::Json<ResetPasswordRequest>,
+) -> Result<NoContent, ApiError> {
+    let invalid = || ApiError::BadRequest("Invalid or expired reset link".to_string());
+
+    let user_id = decode_uid(&body.uid).ok_or_else(invalid)?;
+    let user = AuthUsers::find_by_id(user_id)
+        .one(app_state.db.as_ref())
+        .await?
+        .filter(|user| user.is_active)
+        .ok_or_else(invalid)?;
+
+    if !password_reset::check_token(&app_state.config, &user, &body.token) {
+        return Err(invalid());
+    }
+
+    // The new hash invalidates the token, so the link works only once
+    let user = user.set_password(&app_state.db, &body.new_password).await?;
+    // Refresh tokens belong to a session and go with it
+    AuthUserSessions::end_all(&app_state.db, user.id, None).await?;
+
+    Ok(NoContent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::app_state;
+    use actix_web::http::StatusCode;
+    use actix_web::{App, test};
+    use entity::auth_login_failures::Model as LoginFailure;
+    use entity::auth_users::Model as User;
+    use sea_orm::{DbBackend, MockDatabase, MockExecResult, Transaction};
+    use security::reset_token::encode_uid;
+
+    const NEW_PASSWORD: &str = "correct horse battery staple";
+
+    /// Status of `POST /password/reset` with `body`, and the statements
+    /// that ran
+    async fn reset(db: MockDatabase, body: serde_json::Value) -> (StatusCode, Vec<Transaction>) {
+        let (state, _) = app_state(db.into_connection());
+        let state = web::Data::new(state);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .route("/password/reset", web::post().to(reset_password)),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/password/reset")
+            .set_json(body)
+            .to_request();
+        let status = test::call_service(&app, req).await.status();
+        drop(app);
+
+        let db = web::Data::into_inner(state).db.clone();
+        let log = std::sync::Arc::try_unwrap(db)
+            .ok()
+            .unwrap()
+            .into_transaction_log();
+        (status, log)
+    }
+
+    #[actix_web::test]
+    async fn test_reset_ends_every_session() {
+        let user = User::fixture();
+        let (state, _) = app_state(MockDatabase::new(DbBackend::Postgres).into_connection());
+        let token = password_reset::make_token(&state.config, &user);
+
+        let (status, log) = reset(
+            MockDatabase::new(DbBackend::Postgres)
+                .append_query_results([vec![user.clone()]])
+                .append_query_results([vec![user.clone()]])
+                .append_exec_results([MockExecResult {
+                    last_insert_id: 0,
+                    rows_affected: 3,
+                }]),
+            serde_json::json!({
+                "uid": encode_uid(user.id),
+                "token": token,
+                "new_password": NEW_PASSWORD,
+            }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        assert_eq!(log.len(), 3, "{:?}", log);
+        let end_all = format!("{:?}", log[2]);
+        assert!(
+            end_all.contains(r#"DELETE FROM \"auth_user_sessions\" WHERE \"auth_user_sessions\".\"user_id\" = $1""#),
+            "No session is kept: {}",
+            end_all
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_invalid_reset_link_is_refused() {
+        let user = User::fixture();
+
+        let (status, log) = reset(
+            MockDatabase::new(DbBackend::Postgres).append_query_results([vec![user.clone()]]),
+            serde_json::json!({
+                "uid": encode_uid(user.id),
+                "token": "1a2b3c-0123456789abcdef",
+                "new_password": NEW_PASSWORD,
+            }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(log.len(), 1, "The password is kept: {:?}", log);
+
+        let (status, log) = reset(
+            MockDatabase::new(DbBackend::Postgres),
+            serde_json::json!({
+                "uid": "!",
+                "token": "1a2b3c-0123456789abcdef",
+                "new_password": NEW_PASSWORD,
+            }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(log.is_empty(), "{:?}", log);
+    }
+
+    #[actix_web::test]
+    async fn test_locked_out_address_gets_no_email() {
+        let now = chrono::Utc::now().naive_utc();
+        let db = MockDatabase::new(DbBackend::Postgres)
+            .append_query_results([vec![User::fixture()]])
+            .append_query_results([vec![LoginFailure {
+                id: 1,
+                scope: "user".to_string(),
+                key: "password_reset:42".to_string(),
+                failures: 5,
+                last_failure_at: now,
+                locked_until: Some(now + chrono::Duration::minutes(5)),
+            }]])
+            .into_connection();
+        let (state, outbox) = app_state(db);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(state))
+                .route("/password/forgot", web::post().to(forgot_password)),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/password/forgot")
+            .set_json(serde_json::json!({ "email": "ada@example.com" }))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        actix_web::rt::task::yield_now().await;
+        assert!(outbox.outbox().is_empty());
+    }
+}
@@ -0,0 +1,166 @@
+use crate::auth::extractors::CurrentUser;
+use crate::auth::personal_access_tokens;
+use crate::auth::scopes;
+use crate::error::ApiError;
+use crate::state::AppState;
+use actix_web::web;
+use apistos::actix::{CreatedJson, NoContent};
+use apistos::{ApiComponent, api_operation};
+use chrono::NaiveDateTime;
+use entity::auth_personal_access_tokens::Model as PersonalAccessToken;
+use entity::{AuthPersonalAccessTokens, PersonalAccessTokenEntityExt, PersonalAccessTokenModelExt};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+const MAX_NAME_LEN: usize = 100;
+
+#[derive(Deserialize, JsonSchema, ApiComponent)]
+pub struct CreateTokenRequest {
+    /// What the token is for, e.g. the CI job using it
+    pub name: String,
+    /// `profile:read`, `profile:write`, `users:read` or `users:write`
+    pub scopes: Vec<String>,
+    /// Days until the token expires. Required when
+    /// `personal_access_tokens.max_lifetime_days` is set, otherwise the token
+    /// never expires when omitted
+    pub expires_in_days: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, ApiComponent)]
+pub struct TokenInfoResponse {
+    pub id: i32,
+    pub name: String,
+    /// First characters of the token, to tell tokens apart
+    pub token_prefix: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<NaiveDateTime>,
+    pub last_used_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+impl From<PersonalAccessToken> for TokenInfoResponse {
+    fn from(token: PersonalAccessToken) -> Self {
+        Self {
+            scopes: token.scope_list(),
+            id: token.id,
+            name: token.name,
+            token_prefix: token.token_prefix,
+            expires_at: token.expires_at,
+            last_used_at: token.last_used_at,
+            created_at: token.created_at,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, ApiComponent)]
+pub struct CreatedTokenResponse {
+    /// Shown only once; send it as `Authorization: Bearer <token>`
+    pub token: String,
+    #[serde(flatten)]
+    pub info: TokenInfoResponse,
+}
+
+fn validate(app_state: &AppState, body: &CreateTokenRequest) -> Result<(), ApiError> {
+    let name = body.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LEN {
+        return Err(ApiError::BadRequest(format!(
+            "Name must be between 1 and {} characters",
+            MAX_NAME_LEN
+        )));
+    }
+
+    if body.scopes.is_empty() {
+        return Err(ApiError::BadRequest(
+            "At least one scope is required".to_string(),
+        ));
+    }
+    if let Some(unknown) = body.scopes.iter().find(|scope| !scopes::is_known(scope)) {
+        return Err(ApiError::BadRequest(format!("Unknown scope: {}", unknown)));
+    }
+
+    let max_lifetime_days = app_state.config.personal_access_tokens.max_lifetime_days;
+    match (body.expires_in_days, max_lifetime_days) {
+        (Some(0), _) => Err(ApiError::BadRequest(
+            "expires_in_days must be at least 1".to_string(),
+        )),
+        (None, Some(_)) => Err(ApiError::BadRequest(
+            "expires_in_days is required".to_string(),
+        )),
+        (Some(days), Some(max)) if days > max => Err(ApiError::BadRequest(format!(
+            "Tokens may live at most {} days",
+            max
+        ))),
+        _ => Ok(()),
+    }
+}
+
+#[api_operation(
+    summary = "Create a personal access token",
+    description = "Issue a long-lived token for scripts and integrations. The token is returned once and only its hash is stored. It is accepted only by routes that ask for one of its scopes",
+    tag = "tokens"
+)]
+pub async fn create(
+    app_state: web::Data<AppState>,
+    current_user: CurrentUser,
+    body: web::Json<CreateTokenRequest>,
+) -> Result<CreatedJson<CreatedTokenResponse>, ApiError> {
+    validate(&app_state, &body)?;
+
+    let existing = AuthPersonalAccessTokens::list_for_user(&app_state.db, current_user.id).await?;
+    if existing.len() >= app_state.config.personal_access_tokens.max_per_user {
+        return Err(ApiError::BadRequest(
+            "Too many personal access tokens, revoke one first".to_string(),
+        ));
+    }
+
+    let body = body.into_inner();
+    let mut token_scopes = body.scopes;
+    token_scopes.sort();
+    token_scopes.dedup();
+    let expires_at = body
+        .expires_in_days
+        .map(|days| chrono::Utc::now().naive_utc() + chrono::Duration::days(days.into()));
+
+    let (token, stored) = personal_access_tokens::issue(
+        &app_state.db,
+        &current_user,
+        body.name.trim().to_string(),
+        token_scopes,
+        expires_at,
+    )
+    .await?;
+
+    Ok(CreatedJson(CreatedTokenResponse {
+        token,
+        info: stored.into(),
+    }))
+}
+
+#[api_operation(
+    summary = "List personal access tokens",
+    description = "Tokens of the calling user, newest first, including expired ones",
+    tag = "tokens"
+)]
+pub async fn list(
+    app_state: web::Data<AppState>,
+    current_user: CurrentUser,
+) -> Result<web::Json<Vec<TokenInfoResponse>>, ApiError> {
+    let tokens = AuthPersonalAccessTokens::list_for_user(&app_state.db, current_user.id).await?;
+    Ok(web::Json(tokens.into_iter().map(Into::into).collect()))
+}
+
+#[api_operation(
+    summary = "Revoke a personal access token",
+    description = "Delete a token of the calling user; it stops working immediately",
+    tag = "tokens"
+)]
+pub async fn revoke(
+    app_state: web::Data<AppState>,
+    current_user: CurrentUser,
+    path: web::Path<i32>,
+) -> Result<NoContent, ApiError> {
+    if !AuthPersonalAccessTokens::revoke(&app_state.db, current_user.id, path.into_inner()).await? {
+        return Err(ApiError::NotFound("Token not found".to_string()));
+    }
+    Ok(NoContent)
+}
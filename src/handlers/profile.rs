@@ -0,0 +1,336 @@
+use crate::auth::email_change;
+use crate::auth::extractors::CurrentUser;
+use crate::auth::scopes::{ProfileRead, ProfileWrite, RequireScope};
+use crate::auth::session::update_session_auth_hash;
+use crate::error::ApiError;
+use crate::handlers::oauth::redirect;
+use crate::handlers::registration::{MessageResponse, VerifyEmailRequest, VerifyEmailResponse};
+use crate::handlers::users::name_change;
+use crate::session::Session;
+use crate::state::AppState;
+use actix_web::{HttpRequest, HttpResponse, web};
+use apistos::actix::{AcceptedJson, NoContent};
+use apistos::{ApiComponent, api_operation};
+use entity::{
+    AuthRefreshTokens, AuthUserEntityExt, AuthUserModelExt, AuthUserSessions, AuthUsers,
+    LoginAttempt, RefreshTokenEntityExt, UpdateUserData, UserSelf, UserSessionEntityExt,
+};
+use log::error;
+use schemars::JsonSchema;
+use sea_orm::EntityTrait;
+use serde::Deserialize;
+use std::time::Duration;
+
+#[derive(Deserialize, JsonSchema, ApiComponent)]
+pub struct UpdateProfileRequest {
+    /// An empty string clears the name
+    pub first_name: Option<String>,
+    /// An empty string clears the name
+    pub last_name: Option<String>,
+}
+
+#[derive(Deserialize, JsonSchema, ApiComponent)]
+pub struct ChangePasswordRequest {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+#[derive(Deserialize, JsonSchema, ApiComponent)]
+pub struct ChangeEmailRequest {
+    pub new_email: String,
+}
+
+#[api_operation(
+    summary = "Get my profile",
+    description = "The calling user's account. Personal access tokens need the `profile:read` scope",
+    tag = "profile"
+)]
+pub async fn retrieve(
+    current_user: RequireScope<ProfileRead>,
+) -> Result<web::Json<UserSelf>, ApiError> {
+    Ok(web::Json(UserSelf::from(&current_user.user.user)))
+}
+
+#[api_operation(
+    summary = "Update my profile",
+    description = "Change the calling user's first and last name. Personal access tokens need the `profile:write` scope",
+    tag = "profile"
+)]
+pub async fn update(
+    app_state: web::Data<AppState>,
+    current_user: RequireScope<ProfileWrite>,
+    body: web::Json<UpdateProfileRequest>,
+) -> Result<web::Json<UserSelf>, ApiError> {
+    let body = body.into_inner();
+    let user = current_user
+        .user
+        .update_details(
+            &app_state.db,
+            UpdateUserData {
+                first_name: name_change(body.first_name),
+                last_name: name_change(body.last_name),
+                ..Default::default()
+            },
+        )
+        .await?;
+    Ok(web::Json(user.into()))
+}
+
+#[api_operation(
+    summary = "Change my password",
+    description = "Set a new password after checking the current one. Every other session and its refresh tokens are ended; the one making the request stays logged in. Wrong current passwords count towards the login lockout",
+    tag = "profile"
+)]
+pub async fn change_password(
+    app_state: web::Data<AppState>,
+    current_user: CurrentUser,
+    session: Session,
+    body: web::Json<ChangePasswordRequest>,
+) -> Result<NoContent, ApiError> {
+    let checked = LoginAttempt::for_user(current_user.id)
+        .check(&app_state.db, async {
+            let matches = current_user.check_password(&body.current_password).await?;
+            Ok::<_, ApiError>(matches.then_some(()))
+        })
+        .await?;
+    if checked.is_none() {
+        return Err(ApiError::BadRequest(
+            "Your current password is incorrect".to_string(),
+        ));
+    }
+
+    let user = current_user
+        .set_password(&app_state.db, &body.new_password)
+        .await?;
+
+    // Refresh tokens belong to a session and go with it
+    let keep = current_user.session_id();
+    AuthUserSessions::end_all(&app_state.db, user.id, keep).await?;
+    if keep.is_none() {
+        AuthRefreshTokens::revoke_all_for_user(&app_state.db, user.id).await?;
+    }
+    // The new hash would log the calling session out as well
+    update_session_auth_hash(&session, &user, &app_state.config.auth.secret_key)?;
+
+    Ok(NoContent)
+}
+
+#[api_operation(
+    summary = "Change my email address",
+    description = "Email a confirmation link to the new address and a notice to the current one. The address changes once the link is opened. The response is the same whether or not the new address belongs to another account, whose owner is told instead",
+    tag = "profile"
+)]
+pub async fn change_email(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    current_user: CurrentUser,
+    body: web::Json<ChangeEmailRequest>,
+) -> Result<AcceptedJson<MessageResponse>, ApiError> {
+    let new_email = body.new_email.trim().to_string();
+    if !new_email.contains('@') {
+        return Err(ApiError::BadRequest(
+            "Enter a valid email address".to_string(),
+        ));
+    }
+    if new_email == current_user.email {
+        return Err(ApiError::BadRequest(
+            "This is already your email address".to_string(),
+        ));
+    }
+
+    app_state
+        .email_change_requests
+        .check(&current_user.id.to_string())
+        .map_err(|retry_after| {
+            ApiError::too_many_requests("Too many email changes requested", retry_after)
+        })?;
+
+    let owner = AuthUsers::find_by_email(&app_state.db, &new_email).await?;
+
+    // Send in the background so the response time does not reveal
+    // whether the address belongs to another account
+    let app_state = app_state.clone();
+    let locale = app_state.mail.request_locale(&req);
+    let user = current_user.user;
+    actix_web::rt::spawn(async move {
+        if let Some(owner) = owner {
+            if let Err(e) =
+                email_change::send_taken_email(&app_state.mail, &owner, locale.as_deref()).await
+            {
+                error!(
+                    "Failed to send email change attempt to user {}: {}",
+                    owner.id, e
+                );
+            }
+            return;
+        }
+
+        if let Err(e) = email_change::send_confirmation_email(
+            &app_state.mail,
+            &app_state.config,
+            &user,
+            &new_email,
+            locale.as_deref(),
+        )
+        .await
+        {
+            error!(
+                "Failed to send email change confirmation to user {}: {}",
+                user.id, e
+            );
+            return;
+        }
+        if let Err(e) =
+            email_change::send_notice_email(&app_state.mail, &user, &new_email, locale.as_deref())
+                .await
+        {
+            error!(
+                "Failed to send email change notice to user {}: {}",
+                user.id, e
+            );
+        }
+    });
+
+    Ok(AcceptedJson(MessageResponse {
+        detail: "A confirmation link has been sent to the new address".to_string(),
+    }))
+}
+
+async fn confirm(app_state: &AppState, token: &str) -> Result<VerifyEmailResponse, ApiError> {
+    let invalid = || ApiError::BadRequest("Invalid or expired email change link".to_string());
+    let max_age = Duration::from_secs(app_state.config.email_change.timeout.max(0) as u64);
+
+    let (user_id, email, new_email) =
+        email_change::check_token(&app_state.config.auth.secret_key, token, max_age)
+            .map_err(|_| invalid())?;
+
+    // Once the address changed, links for the previous one stop working
+    let user = AuthUsers::find_by_id(user_id)
+        .one(app_state.db.as_ref())
+        .await?
+        .filter(|user| user.is_active && user.email == email)
+        .ok_or_else(invalid)?;
+
+    let user = user
+        .update_details(
+            &app_state.db,
+            UpdateUserData {
+                email: Some(new_email),
+                ..Default::default()
+            },
+        )
+        .await?;
+    // Opening the link proved control of the new address
+    let user = if user.is_verified {
+        user
+    } else {
+        user.verify_email(&app_state.db).await?
+    };
+
+    Ok(VerifyEmailResponse {
+        email: user.email,
+        is_verified: user.is_verified,
+    })
+}
+
+#[api_operation(
+    summary = "Open email change link",
+    description = "Redirect to the page at `email_change.confirm_url`, which confirms the change with `POST /auth/email/confirm`. Opening the link changes nothing, so link scanners and prefetching cannot",
+    tag = "profile"
+)]
+pub async fn confirm_email_link(
+    app_state: web::Data<AppState>,
+    query: web::Query<VerifyEmailRequest>,
+) -> HttpResponse {
+    redirect(&email_change::confirmation_link(
+        &app_state.config,
+        &query.token,
+    ))
+}
+
+#[api_operation(
+    summary = "Confirm email change",
+    description = "Change the email address using the token from the confirmation link",
+    tag = "profile"
+)]
+pub async fn confirm_email(
+    app_state: web::Data<AppState>,
+    body: web::Json<VerifyEmailRequest>,
+) -> Result<web::Json<VerifyEmailResponse>, ApiError> {
+    Ok(web::Json(confirm(&app_state, &body.token).await?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{access_token, app_state, device_session};
+    use actix_web::http::{StatusCode, header};
+    use actix_web::{App, test};
+    use entity::auth_users::Model as User;
+    use sea_orm::{DbBackend, MockDatabase};
+
+    #[actix_web::test]
+    async fn test_taken_address_is_not_revealed() {
+        let user = User::fixture();
+        let owner = User {
+            id: 7,
+            email: "grace@example.com".to_string(),
+            username: "grace".to_string(),
+            ..User::fixture()
+        };
+        let db = MockDatabase::new(DbBackend::Postgres)
+            .append_query_results([vec![user.clone()]])
+            .append_query_results([vec![device_session(&user, 1)]])
+            .append_query_results([vec![owner]])
+            .into_connection();
+        let (state, outbox) = app_state(db);
+        let token = access_token(&state.config, &user, 1);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(state))
+                .route("/me/email", web::post().to(change_email)),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/me/email")
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+            .set_json(serde_json::json!({ "new_email": "grace@example.com" }))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::ACCEPTED);
+
+        // Sent in the background
+        while outbox.outbox().is_empty() {
+            actix_web::rt::time::sleep(Duration::from_millis(10)).await;
+        }
+        let sent = outbox.outbox();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].to, "grace@example.com");
+        assert!(!sent[0].text.contains("token"), "{}", sent[0].text);
+    }
+
+    #[actix_web::test]
+    async fn test_opening_the_link_changes_nothing() {
+        // Any query would fail, having no result to answer with
+        let db = MockDatabase::new(DbBackend::Postgres).into_connection();
+        let (state, _) = app_state(db);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(state))
+                .route("/email/confirm", web::get().to(confirm_email_link)),
+        )
+        .await;
+        let req = test::TestRequest::get()
+            .uri("/email/confirm?token=abc%3Adef")
+            .to_request();
+        let res = test::call_service(&app, req).await;
+
+        assert_eq!(res.status(), StatusCode::FOUND);
+        assert_eq!(
+            res.headers().get(header::LOCATION).unwrap(),
+            "http://127.0.0.1:8080/confirm-email?token=abc%3Adef"
+        );
+    }
+}
@@ -0,0 +1,333 @@
+use crate::auth::verification;
+use crate::error::ApiError;
+use crate::state::AppState;
+use actix_web::{HttpRequest, web};
+use apistos::actix::{AcceptedJson, CreatedJson};
+use apistos::{ApiComponent, api_operation};
+use entity::auth_users_ext::CreateUserData;
+use entity::{AuthUserEntityExt, AuthUserModelExt, AuthUsers, UserSelf};
+use log::error;
+use schemars::JsonSchema;
+use sea_orm::EntityTrait;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[derive(Deserialize, JsonSchema, ApiComponent)]
+pub struct RegisterRequest {
+    pub email: String,
+    pub username: String,
+    pub password: String,
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+}
+
+#[derive(Deserialize, JsonSchema, ApiComponent)]
+pub struct VerifyEmailRequest {
+    /// Token from the verification link
+    pub token: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, ApiComponent)]
+pub struct VerifyEmailResponse {
+    pub email: String,
+    pub is_verified: bool,
+}
+
+#[derive(Deserialize, JsonSchema, ApiComponent)]
+pub struct ResendVerificationRequest {
+    pub email: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, ApiComponent)]
+pub struct MessageResponse {
+    pub detail: String,
+}
+
+#[api_operation(
+    summary = "Register",
+    description = "Create an unverified account and email a link to verify the address",
+    tag = "auth"
+)]
+pub async fn register(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    body: web::Json<RegisterRequest>,
+) -> Result<CreatedJson<UserSelf>, ApiError> {
+    if !app_state.config.registration.enabled {
+        return Err(ApiError::Forbidden("Registration is disabled".to_string()));
+    }
+
+    let body = body.into_inner();
+    let email = body.email.trim().to_string();
+    let username = body.username.trim().to_string();
+    if !email.contains('@') {
+        return Err(ApiError::BadRequest(
+            "Enter a valid email address".to_string(),
+        ));
+    }
+    if username.is_empty() || body.password.is_empty() {
+        return Err(ApiError::BadRequest(
+            "Username and password are required".to_string(),
+        ));
+    }
+
+    let user = AuthUsers::create_user(
+        &app_state.db,
+        CreateUserData {
+            email,
+            username,
+            password: body.password,
+            first_name: body.first_name,
+            last_name: body.last_name,
+            is_verified: false,
+            ..Default::default()
+        },
+    )
+    .await?;
+
+    // The account exists either way; a failed email can be resent
+    if let Err(e) = verification::send_verification_email(
+        &app_state.mail,
+        &app_state.config,
+        &user,
+        app_state.mail.request_locale(&req).as_deref(),
+    )
+    .await
+    {
+        error!(
+            "Failed to send verification email to user {}: {}",
+            user.id, e
+        );
+    }
+
+    Ok(CreatedJson(user.into()))
+}
+
+async fn verify(app_state: &AppState, token: &str) -> Result<VerifyEmailResponse, ApiError> {
+    let invalid = || ApiError::BadRequest("Invalid or expired verification link".to_string());
+    let max_age = Duration::from_secs(app_state.config.registration.verification_ttl.max(0) as u64);
+
+    let (user_id, email) =
+        verification::check_token(&app_state.config.auth.secret_key, token, max_age)
+            .map_err(|_| invalid())?;
+
+    let user = AuthUsers::find_by_id(user_id)
+        .one(app_state.db.as_ref())
+        .await?
+        .filter(|user| user.email == email)
+        .ok_or_else(invalid)?;
+
+    let user = if user.is_verified {
+        user
+    } else {
+        user.verify_email(&app_state.db).await?
+    };
+
+    Ok(VerifyEmailResponse {
+        email: user.email,
+        is_verified: user.is_verified,
+    })
+}
+
+#[api_operation(
+    summary = "Verify email from link",
+    description = "Mark the email address as verified using the token from the verification link",
+    tag = "auth"
+)]
+pub async fn verify_email_link(
+    app_state: web::Data<AppState>,
+    query: web::Query<VerifyEmailRequest>,
+) -> Result<web::Json<VerifyEmailResponse>, ApiError> {
+    Ok(web::Json(verify(&app_state, &query.token).await?))
+}
+
+#[api_operation(
+    summary = "Verify email",
+    description = "Mark the email address as verified using the token from the verification link",
+    tag = "auth"
+)]
+pub async fn verify_email(
+    app_state: web::Data<AppState>,
+    body: web::Json<VerifyEmailRequest>,
+) -> Result<web::Json<VerifyEmailResponse>, ApiError> {
+    Ok(web::Json(verify(&app_state, &body.token).await?))
+}
+
+#[api_operation(
+    summary = "Resend verification email",
+    description = "Email a new verification link. The response is the same whether or not the address belongs to an unverified account",
+    tag = "auth"
+)]
+pub async fn resend_verification(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    body: web::Json<ResendVerificationRequest>,
+) -> Result<AcceptedJson<MessageResponse>, ApiError> {
+    let email = body.email.trim();
+
+    app_state
+        .verification_resends
+        .check(&email.to_lowercase())
+        .map_err(|retry_after| {
+            ApiError::too_many_requests("Too many verification emails requested", retry_after)
+        })?;
+
+    let user = AuthUsers::find_by_email(&app_state.db, email).await?;
+    if let Some(user) = user.filter(|user| user.is_active && !user.is_verified) {
+        // Send in the background so the response time does not reveal
+        // whether an email went out
+        let app_state = app_state.clone();
+        let locale = app_state.mail.request_locale(&req);
+        actix_web::rt::spawn(async move {
+            if let Err(e) = verification::send_verification_email(
+                &app_state.mail,
+                &app_state.config,
+                &user,
+                locale.as_deref(),
+            )
+            .await
+            {
+                error!(
+                    "Failed to send verification email to user {}: {}",
+                    user.id, e
+                );
+            }
+        });
+    }
+
+    Ok(AcceptedJson(MessageResponse {
+        detail:
+            "If the address belongs to an unverified account, a verification email has been sent"
+                .to_string(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Settings;
+    use crate::test_support::{app_state, app_state_with};
+    use actix_web::http::StatusCode;
+    use actix_web::{App, test};
+    use entity::auth_users::Model as User;
+    use sea_orm::{DatabaseConnection, DbBackend, MockDatabase, Transaction};
+
+    /// Status of `POST uri` with `body` on `state`, and the statements that
+    /// ran
+    async fn post(
+        state: AppState,
+        uri: &str,
+        body: serde_json::Value,
+    ) -> (StatusCode, Vec<Transaction>) {
+        let state = web::Data::new(state);
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .route("/register", web::post().to(register))
+                .route("/verify-email", web::post().to(verify_email))
+                .route("/verify-email/resend", web::post().to(resend_verification)),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri(uri)
+            .set_json(body)
+            .to_request();
+        let status = test::call_service(&app, req).await.status();
+        drop(app);
+
+        let db = web::Data::into_inner(state).db.clone();
+        let log = std::sync::Arc::try_unwrap(db)
+            .ok()
+            .unwrap()
+            .into_transaction_log();
+        (status, log)
+    }
+
+    fn mock(users: Vec<Vec<User>>) -> DatabaseConnection {
+        MockDatabase::new(DbBackend::Postgres)
+            .append_query_results(users)
+            .into_connection()
+    }
+
+    fn registration() -> serde_json::Value {
+        serde_json::json!({
+            "email": "ada@example.com",
+            "username": "ada",
+            "password": "correct horse battery staple",
+        })
+    }
+
+    #[actix_web::test]
+    async fn test_disabled_registration_is_refused() {
+        let mut config = Settings::new().unwrap();
+        config.registration.enabled = false;
+        let (state, outbox) = app_state_with(mock(vec![]), config);
+
+        let (status, log) = post(state, "/register", registration()).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert!(log.is_empty(), "{:?}", log);
+        assert!(outbox.outbox().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_invalid_registration_is_refused() {
+        for body in [
+            serde_json::json!({ "email": "ada", "username": "ada", "password": "secret" }),
+            serde_json::json!({ "email": "ada@example.com", "username": " ", "password": "secret" }),
+        ] {
+            let (state, _) = app_state(mock(vec![]));
+            let (status, log) = post(state, "/register", body).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+            assert!(log.is_empty(), "{:?}", log);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_link_to_a_previous_address_is_refused() {
+        let user = User {
+            is_verified: false,
+            ..User::fixture()
+        };
+        let changed = User {
+            email: "ada@example.org".to_string(),
+            ..user.clone()
+        };
+        let (state, _) = app_state(mock(vec![vec![changed]]));
+        let token = verification::make_token(&state.config.auth.secret_key, &user);
+
+        let (status, log) = post(
+            state,
+            "/verify-email",
+            serde_json::json!({ "token": token }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(log.len(), 1, "The address stays unverified: {:?}", log);
+
+        let (state, _) = app_state(mock(vec![]));
+        let (status, log) = post(
+            state,
+            "/verify-email",
+            serde_json::json!({ "token": "42:ada@example.com:forged" }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(log.is_empty(), "{:?}", log);
+    }
+
+    #[actix_web::test]
+    async fn test_verified_account_gets_no_email() {
+        let (state, outbox) = app_state(mock(vec![vec![User::fixture()]]));
+
+        let (status, _) = post(
+            state,
+            "/verify-email/resend",
+            serde_json::json!({ "email": "ada@example.com" }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+
+        actix_web::rt::task::yield_now().await;
+        assert!(outbox.outbox().is_empty());
+    }
+}
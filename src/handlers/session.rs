@@ -0,0 +1,128 @@
+use crate::auth::backends::Authenticated;
+use crate::auth::devices;
+use crate::auth::mfa::{self, MfaStatus};
+use crate::auth::session;
+use crate::csrf::{self, CsrfToken};
+use crate::error::ApiError;
+use crate::handlers::auth::check_password;
+use crate::handlers::mfa::require_code;
+use crate::session::Session;
+use crate::state::AppState;
+use actix_web::{HttpRequest, web};
+use apistos::actix::NoContent;
+use apistos::{ApiComponent, api_operation};
+use entity::AuthUserModelExt;
+use entity::auth_users::Model as User;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, JsonSchema, ApiComponent)]
+pub struct SessionLoginRequest {
+    /// Username or email address
+    pub username: String,
+    pub password: String,
+    /// Current code of the authenticator app, for accounts with two-factor
+    /// authentication
+    pub code: Option<String>,
+    /// Single-use recovery code, when the authenticator app is lost
+    pub recovery_code: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, ApiComponent)]
+pub struct CsrfTokenResponse {
+    /// Send as the `X-CSRFToken` header of unsafe cookie-authenticated requests
+    pub csrf_token: String,
+}
+
+#[api_operation(
+    summary = "Get a CSRF token",
+    description = "Set the CSRF cookie and return a token for it. Browsers logging in with a session cookie must send the token in the `X-CSRFToken` header of every POST, PUT, PATCH and DELETE request, including the login",
+    tag = "auth"
+)]
+pub async fn csrf_token(token: CsrfToken) -> Result<web::Json<CsrfTokenResponse>, ApiError> {
+    Ok(web::Json(CsrfTokenResponse {
+        csrf_token: token.0,
+    }))
+}
+
+#[api_operation(
+    summary = "Log in with a session cookie",
+    description = "Authenticate like `/auth/login` and log the browser into a cookie session shared with Django. The session key and the CSRF token are replaced on login.\n\nAccounts with two-factor authentication must send `code` or `recovery_code` along with the password. Accounts that must still enroll are refused; they enroll through `/auth/login`. Accounts with passkeys may log in with `/auth/passkey/session` instead",
+    tag = "auth"
+)]
+pub async fn login(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    session: Session,
+    body: web::Json<SessionLoginRequest>,
+) -> Result<NoContent, ApiError> {
+    let Authenticated { user, backend } =
+        check_password(&req, &app_state, &body.username, &body.password).await?;
+    check_second_factor(
+        &app_state,
+        &user,
+        body.code.as_deref(),
+        body.recovery_code.as_deref(),
+    )
+    .await?;
+
+    start_session(&req, &app_state, &session, user, backend).await?;
+    Ok(NoContent)
+}
+
+/// Check the second factor sent along with the first, for accounts with
+/// two-factor authentication. Accounts that must still enroll are refused.
+pub(crate) async fn check_second_factor(
+    app_state: &AppState,
+    user: &User,
+    code: Option<&str>,
+    recovery_code: Option<&str>,
+) -> Result<(), ApiError> {
+    match mfa::status(&app_state.db, &app_state.config, user).await? {
+        MfaStatus::NotRequired => Ok(()),
+        MfaStatus::Required => {
+            if code.is_none() && recovery_code.is_none() {
+                return Err(ApiError::Unauthorized(
+                    "A two-factor code is required".to_string(),
+                ));
+            }
+            require_code(app_state, user, code, recovery_code).await
+        }
+        MfaStatus::EnrollmentRequired => Err(ApiError::Forbidden(
+            "Two-factor authentication must be set up first".to_string(),
+        )),
+    }
+}
+
+/// Log a fully authenticated user into the cookie session, recording the
+/// login and the device, and replace the CSRF token
+pub(crate) async fn start_session(
+    req: &HttpRequest,
+    app_state: &AppState,
+    session: &Session,
+    user: User,
+    backend: &str,
+) -> Result<(), ApiError> {
+    let user = user.update_last_login(&app_state.db).await?;
+    // Logging in again replaces the recorded login of the session
+    devices::end_cookie_session(app_state, session, user.id).await?;
+    session::login(session, &user, backend, &app_state.config.auth.secret_key)?;
+    devices::start_cookie_session(app_state, req, session, &user).await?;
+    csrf::rotate_token(req)
+}
+
+#[api_operation(
+    summary = "Log out of the session",
+    description = "Delete the cookie session and everything stored in it",
+    tag = "auth"
+)]
+pub async fn logout(
+    app_state: web::Data<AppState>,
+    session: Session,
+) -> Result<NoContent, ApiError> {
+    if let Some(user_id) = session::session_user_id(&session) {
+        devices::end_cookie_session(&app_state, &session, user_id).await?;
+    }
+    session::logout(&session);
+    Ok(NoContent)
+}
@@ -0,0 +1,339 @@
+use crate::auth::extractors::{Credential, CurrentUser};
+use crate::auth::session::MODEL_BACKEND;
+use crate::error::ApiError;
+use crate::handlers::oauth::redirect;
+use crate::handlers::session::start_session;
+use crate::session::Session;
+use crate::sso::{self, Callback, Outcome};
+use crate::state::AppState;
+use actix_web::{HttpRequest, HttpResponse, web};
+use apistos::actix::NoContent;
+use apistos::{ApiComponent, api_operation};
+use chrono::NaiveDateTime;
+use entity::{UserIdentities, UserIdentityEntityExt};
+use schemars::JsonSchema;
+use security::password::is_password_usable;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, JsonSchema, ApiComponent)]
+pub struct SsoProviderResponse {
+    pub name: String,
+    /// Label for the login button
+    pub display_name: String,
+    /// Send the browser here to log in with the provider
+    pub login_url: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, ApiComponent)]
+pub struct IdentityResponse {
+    pub id: i32,
+    /// Name of the provider
+    pub provider: String,
+    /// The user's id at the provider
+    pub subject: String,
+    /// Address the provider last reported
+    pub email: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub last_login_at: Option<NaiveDateTime>,
+}
+
+#[api_operation(
+    summary = "List login providers",
+    description = "External OpenID Connect providers users may log in with",
+    tag = "sso"
+)]
+pub async fn providers(
+    app_state: web::Data<AppState>,
+) -> Result<web::Json<Vec<SsoProviderResponse>>, ApiError> {
+    Ok(web::Json(
+        app_state
+            .config
+            .sso
+            .providers
+            .iter()
+            .map(|provider| SsoProviderResponse {
+                name: provider.name.clone(),
+                display_name: provider.display_name.clone(),
+                login_url: format!("/api/v1/auth/sso/{}/login", provider.name),
+            })
+            .collect(),
+    ))
+}
+
+#[api_operation(
+    summary = "Log in with a provider",
+    description = "Redirect the browser to the provider's login page. The provider sends it back to `/auth/sso/{provider}/callback`",
+    tag = "sso"
+)]
+pub async fn login(
+    app_state: web::Data<AppState>,
+    session: Session,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let provider = sso::find_provider(&app_state.config.sso, &path)?;
+    let location = sso::start(&app_state, &session, provider, None).await?;
+    Ok(redirect(&location))
+}
+
+#[api_operation(
+    summary = "Link a provider identity",
+    description = "Like `/auth/sso/{provider}/login`, but the identity the user logs in with at the provider is linked to the account logged into the cookie session",
+    tag = "sso"
+)]
+pub async fn link(
+    app_state: web::Data<AppState>,
+    session: Session,
+    current_user: CurrentUser,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    if !matches!(current_user.credential, Credential::Session { .. }) {
+        return Err(ApiError::Forbidden(
+            "Identities are linked from a cookie session".to_string(),
+        ));
+    }
+
+    let provider = sso::find_provider(&app_state.config.sso, &path)?;
+    let location = sso::start(&app_state, &session, provider, Some(current_user.id)).await?;
+    Ok(redirect(&location))
+}
+
+#[api_operation(
+    summary = "Finish a provider login",
+    description = "Where the provider sends the browser back to. The ID token is validated, and the user it identifies is logged into a cookie session before being redirected to `sso.login_redirect_url`.\n\nAn identity no account is linked to yet is linked by its verified email address or provisioned a new account, when the provider is configured to",
+    tag = "sso"
+)]
+pub async fn callback(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    session: Session,
+    path: web::Path<String>,
+    query: web::Query<Callback>,
+) -> Result<HttpResponse, ApiError> {
+    let provider = sso::find_provider(&app_state.config.sso, &path)?;
+
+    match sso::finish(&app_state, &session, provider, &query).await? {
+        Outcome::Linked => {}
+        Outcome::Login(user) => {
+            start_session(&req, &app_state, &session, user, MODEL_BACKEND).await?;
+        }
+    }
+
+    Ok(redirect(&app_state.config.sso.login_redirect_url))
+}
+
+#[api_operation(
+    summary = "List linked identities",
+    description = "External provider identities the calling user can log in with",
+    tag = "sso"
+)]
+pub async fn list_identities(
+    app_state: web::Data<AppState>,
+    current_user: CurrentUser,
+) -> Result<web::Json<Vec<IdentityResponse>>, ApiError> {
+    let identities = UserIdentities::list_for_user(&app_state.db, current_user.id).await?;
+
+    Ok(web::Json(
+        identities
+            .into_iter()
+            .map(|identity| IdentityResponse {
+                id: identity.id,
+                provider: identity.provider,
+                subject: identity.subject,
+                email: identity.email,
+                created_at: identity.created_at,
+                last_login_at: identity.last_login_at,
+            })
+            .collect(),
+    ))
+}
+
+#[api_operation(
+    summary = "Unlink an identity",
+    description = "Stop logging in with an external identity. The last one cannot be unlinked from an account without a password",
+    tag = "sso"
+)]
+pub async fn unlink_identity(
+    app_state: web::Data<AppState>,
+    current_user: CurrentUser,
+    path: web::Path<i32>,
+) -> Result<NoContent, ApiError> {
+    let id = path.into_inner();
+    let identities = UserIdentities::list_for_user(&app_state.db, current_user.id).await?;
+    if !identities.iter().any(|identity| identity.id == id) {
+        return Err(ApiError::NotFound("Identity not found".to_string()));
+    }
+    if identities.len() == 1 && !is_password_usable(&current_user.user.password) {
+        return Err(ApiError::BadRequest(
+            "Set a password before unlinking the last identity".to_string(),
+        ));
+    }
+
+    UserIdentities::unlink(&app_state.db, current_user.id, id).await?;
+    Ok(NoContent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Settings, SsoProviderSettings};
+    use crate::test_support::{access_token, anonymous_session, app_state_with, device_session};
+    use actix_web::dev::Service;
+    use actix_web::http::{StatusCode, header};
+    use actix_web::{App, HttpMessage, test};
+    use entity::auth_users::Model as User;
+    use sea_orm::{DbBackend, MockDatabase};
+
+    fn config() -> Settings {
+        let mut config = Settings::new().unwrap();
+        config.sso.providers = vec![SsoProviderSettings {
+            name: "corp".to_string(),
+            display_name: "Example Corp".to_string(),
+            issuer: "https://login.example.com".to_string(),
+            client_id: "r-web".to_string(),
+            client_secret: None,
+            redirect_uri: "http://localhost/api/v1/auth/sso/corp/callback".to_string(),
+            scopes: vec!["openid".to_string()],
+            auto_provision: false,
+            link_by_email: true,
+            trusted_for_mfa: false,
+            allowed_domains: Vec::new(),
+        }];
+        config
+    }
+
+    /// A session in which a login with `provider` started `age` seconds ago
+    fn pending(provider: &str, age: i64) -> Session {
+        let session = anonymous_session();
+        session
+            .insert(
+                sso::STATE_SESSION_KEY,
+                serde_json::json!({
+                    "provider": provider,
+                    "state": "state",
+                    "nonce": "nonce",
+                    "code_verifier": "verifier",
+                    "link_user_id": null,
+                    "started_at": chrono::Utc::now().timestamp() - age,
+                }),
+            )
+            .unwrap();
+        session
+    }
+
+    /// Status of the provider redirecting back to `uri` in `session`. The
+    /// provider is never reached, and the database never queried.
+    async fn callback_status(session: &Session, uri: &str) -> StatusCode {
+        let db = MockDatabase::new(DbBackend::Postgres).into_connection();
+        let (state, _) = app_state_with(db, config());
+        let session = session.clone();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(state))
+                .wrap_fn(move |req, srv| {
+                    req.extensions_mut().insert(session.clone());
+                    srv.call(req)
+                })
+                .route("/sso/{provider}/callback", web::get().to(callback)),
+        )
+        .await;
+        let req = test::TestRequest::get().uri(uri).to_request();
+        test::call_service(&app, req).await.status()
+    }
+
+    #[actix_web::test]
+    async fn test_callback_without_its_login_is_refused() {
+        let uri = "/sso/corp/callback?code=abc&state=state";
+        assert_eq!(
+            callback_status(&anonymous_session(), uri).await,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            callback_status(&pending("other", 0), uri).await,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            callback_status(
+                &pending("corp", 0),
+                "/sso/unknown/callback?code=abc&state=state"
+            )
+            .await,
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_callback_with_wrong_state_is_refused() {
+        let session = pending("corp", 0);
+        assert_eq!(
+            callback_status(&session, "/sso/corp/callback?code=abc&state=forged").await,
+            StatusCode::BAD_REQUEST
+        );
+        // The login is used up, so the right state cannot follow
+        assert!(
+            session
+                .get::<serde_json::Value>(sso::STATE_SESSION_KEY)
+                .is_none()
+        );
+        assert_eq!(
+            callback_status(&session, "/sso/corp/callback?code=abc&state=state").await,
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_expired_or_refused_login_is_refused() {
+        let ttl = config().sso.state_ttl;
+        assert_eq!(
+            callback_status(
+                &pending("corp", ttl + 1),
+                "/sso/corp/callback?code=abc&state=state"
+            )
+            .await,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            callback_status(
+                &pending("corp", 0),
+                "/sso/corp/callback?error=access_denied&state=state"
+            )
+            .await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            callback_status(&pending("corp", 0), "/sso/corp/callback?state=state").await,
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_identities_are_linked_from_a_cookie_session_only() {
+        let user = User::fixture();
+        let db = MockDatabase::new(DbBackend::Postgres)
+            .append_query_results([vec![user.clone()]])
+            .append_query_results([vec![device_session(&user, 1)]])
+            .into_connection();
+        let (state, _) = app_state_with(db, config());
+        let token = access_token(&state.config, &user, 1);
+        let session = anonymous_session();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(state))
+                .wrap_fn(move |req, srv| {
+                    req.extensions_mut().insert(session.clone());
+                    srv.call(req)
+                })
+                .route("/sso/{provider}/link", web::get().to(link)),
+        )
+        .await;
+        let req = test::TestRequest::get()
+            .uri("/sso/corp/link")
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::FORBIDDEN
+        );
+    }
+}
@@ -0,0 +1,92 @@
+use crate::auth::extractors::CurrentUser;
+use crate::error::ApiError;
+use crate::state::AppState;
+use actix_web::web;
+use apistos::actix::NoContent;
+use apistos::{ApiComponent, api_operation};
+use chrono::NaiveDateTime;
+use entity::{AuthUserSessions, UserSessionEntityExt};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, JsonSchema, ApiComponent)]
+pub struct SessionResponse {
+    pub id: i32,
+    /// `token` for logins through `/auth/login`, `cookie` for cookie sessions
+    pub kind: String,
+    /// Browser and system guessed from the user agent, e.g. `Firefox on Linux`
+    pub label: String,
+    pub user_agent: Option<String>,
+    /// Address the device was last seen from
+    pub ip_address: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub last_seen_at: NaiveDateTime,
+    /// The session making this request
+    pub current: bool,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, ApiComponent)]
+pub struct SessionsEndedResponse {
+    /// Sessions ended
+    pub ended: u64,
+}
+
+#[api_operation(
+    summary = "List sessions",
+    description = "Devices logged into the calling user's account, most recently seen first",
+    tag = "sessions"
+)]
+pub async fn list(
+    app_state: web::Data<AppState>,
+    current_user: CurrentUser,
+) -> Result<web::Json<Vec<SessionResponse>>, ApiError> {
+    let current = current_user.session_id();
+    let sessions = AuthUserSessions::list_for_user(&app_state.db, current_user.id).await?;
+
+    Ok(web::Json(
+        sessions
+            .into_iter()
+            .map(|session| SessionResponse {
+                current: Some(session.id) == current,
+                id: session.id,
+                kind: session.kind,
+                label: session.label,
+                user_agent: session.user_agent,
+                ip_address: session.ip_address,
+                created_at: session.created_at,
+                last_seen_at: session.last_seen_at,
+            })
+            .collect(),
+    ))
+}
+
+#[api_operation(
+    summary = "End a session",
+    description = "Log a device out. Its refresh token and cookie session stop working immediately, and so do its access tokens",
+    tag = "sessions"
+)]
+pub async fn revoke(
+    app_state: web::Data<AppState>,
+    current_user: CurrentUser,
+    path: web::Path<i32>,
+) -> Result<NoContent, ApiError> {
+    if !AuthUserSessions::end(&app_state.db, current_user.id, path.into_inner()).await? {
+        return Err(ApiError::NotFound("Session not found".to_string()));
+    }
+    Ok(NoContent)
+}
+
+#[api_operation(
+    summary = "Log out everywhere else",
+    description = "End every session of the calling user except the one making the request",
+    tag = "sessions"
+)]
+pub async fn revoke_others(
+    app_state: web::Data<AppState>,
+    current_user: CurrentUser,
+) -> Result<web::Json<SessionsEndedResponse>, ApiError> {
+    let ended =
+        AuthUserSessions::end_all(&app_state.db, current_user.id, current_user.session_id())
+            .await?;
+    Ok(web::Json(SessionsEndedResponse { ended }))
+}
@@ -0,0 +1,422 @@
+use crate::auth::extractors::CurrentUser;
+use crate::auth::permissions::{
+    AddUser, ChangeUser, DeleteUser, RequirePermission, Staff, ViewUser,
+};
+use crate::error::ApiError;
+use crate::state::AppState;
+use actix_web::web;
+use apistos::actix::{CreatedJson, NoContent};
+use apistos::{ApiComponent, api_operation};
+use chrono::NaiveDateTime;
+use entity::auth_users::Model as User;
+use entity::{
+    AuthRefreshTokens, AuthUserEntityExt, AuthUserModelExt, AuthUserSessions, AuthUsers,
+    CreateUserData, RefreshTokenEntityExt, UpdateUserData, UserAdmin, UserFilter,
+    UserSessionEntityExt, UserSort, UserSortField,
+};
+use schemars::JsonSchema;
+use sea_orm::{DatabaseConnection, EntityTrait};
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_PER_PAGE: u64 = 20;
+const MAX_PER_PAGE: u64 = 100;
+
+#[derive(Deserialize, JsonSchema, ApiComponent)]
+pub struct UserListQuery {
+    pub is_active: Option<bool>,
+    pub is_staff: Option<bool>,
+    pub is_verified: Option<bool>,
+    /// Joined at or after, e.g. `2026-01-01T00:00:00`
+    pub created_after: Option<NaiveDateTime>,
+    /// Joined before
+    pub created_before: Option<NaiveDateTime>,
+    /// Last logged in at or after
+    pub last_login_after: Option<NaiveDateTime>,
+    /// Last logged in before
+    pub last_login_before: Option<NaiveDateTime>,
+    /// Part of the email address or username, in any case
+    pub search: Option<String>,
+    /// `id`, `email`, `username`, `created_at` or `last_login`, with a
+    /// leading `-` for descending order. Defaults to `id`
+    pub sort: Option<String>,
+    /// Page number, from 1
+    pub page: Option<u64>,
+    /// Users per page, at most 100. Defaults to 20
+    pub per_page: Option<u64>,
+}
+
+#[derive(Deserialize, JsonSchema, ApiComponent)]
+pub struct CreateUserRequest {
+    pub email: String,
+    pub username: String,
+    /// Without a password the user logs in by other means, such as a login
+    /// link or single sign-on, until one is set
+    pub password: Option<String>,
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    #[serde(default = "default_true")]
+    pub is_active: bool,
+    #[serde(default)]
+    pub is_verified: bool,
+    #[serde(default)]
+    pub is_staff: bool,
+    /// Only superusers may create superusers
+    #[serde(default)]
+    pub is_superuser: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Deserialize, JsonSchema, ApiComponent)]
+pub struct UpdateUserRequest {
+    pub email: Option<String>,
+    pub username: Option<String>,
+    /// An empty string clears the name
+    pub first_name: Option<String>,
+    /// An empty string clears the name
+    pub last_name: Option<String>,
+    pub is_staff: Option<bool>,
+    /// Only superusers may grant or revoke superuser status
+    pub is_superuser: Option<bool>,
+}
+
+#[derive(Deserialize, JsonSchema, ApiComponent)]
+pub struct SetPasswordRequest {
+    pub password: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, ApiComponent)]
+pub struct UserListResponse {
+    /// Users matching the filters, on every page
+    pub count: u64,
+    pub page: u64,
+    pub per_page: u64,
+    pub results: Vec<UserAdmin>,
+}
+
+fn parse_sort(sort: Option<&str>) -> Result<UserSort, ApiError> {
+    let Some(sort) = sort.filter(|sort| !sort.is_empty()) else {
+        return Ok(UserSort::default());
+    };
+    let (descending, name) = match sort.strip_prefix('-') {
+        Some(name) => (true, name),
+        None => (false, sort),
+    };
+    let field = match name {
+        "id" => UserSortField::Id,
+        "email" => UserSortField::Email,
+        "username" => UserSortField::Username,
+        "created_at" => UserSortField::CreatedAt,
+        "last_login" => UserSortField::LastLogin,
+        _ => {
+            return Err(ApiError::BadRequest(format!("Cannot sort by {}", name)));
+        }
+    };
+    Ok(UserSort { field, descending })
+}
+
+/// Blank names are stored as no name
+pub(crate) fn name_change(name: Option<String>) -> Option<Option<String>> {
+    name.map(|name| Some(name.trim().to_string()).filter(|name| !name.is_empty()))
+}
+
+/// Only superusers may manage superusers, so that staff cannot take over
+/// their accounts
+fn require_manageable(caller: &CurrentUser, user: &User) -> Result<(), ApiError> {
+    if user.is_superuser && !caller.is_superuser {
+        return Err(ApiError::Forbidden(
+            "Only superusers may change superuser accounts".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+async fn find_user(db: &DatabaseConnection, id: i32) -> Result<User, ApiError> {
+    AuthUsers::find_by_id(id)
+        .one(db)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("User not found".to_string()))
+}
+
+/// Log the user out everywhere
+async fn end_sessions(db: &DatabaseConnection, user: &User) -> Result<(), ApiError> {
+    AuthUserSessions::end_all(db, user.id, None).await?;
+    AuthRefreshTokens::revoke_all_for_user(db, user.id).await?;
+    Ok(())
+}
+
+#[api_operation(
+    summary = "List users",
+    description = "Users matching the filters, one page at a time. `search` matches part of the email address or username. Date ranges include their start and exclude their end. Requires staff status and `auth.view_user`",
+    tag = "users"
+)]
+pub async fn list(
+    app_state: web::Data<AppState>,
+    _admin: RequirePermission<Staff<ViewUser>>,
+    query: web::Query<UserListQuery>,
+) -> Result<web::Json<UserListResponse>, ApiError> {
+    let query = query.into_inner();
+    let sort = parse_sort(query.sort.as_deref())?;
+    let page = query.page.unwrap_or(1).max(1);
+    let per_page = query
+        .per_page
+        .unwrap_or(DEFAULT_PER_PAGE)
+        .clamp(1, MAX_PER_PAGE);
+
+    let filter = UserFilter {
+        is_active: query.is_active,
+        is_staff: query.is_staff,
+        is_verified: query.is_verified,
+        created_after: query.created_after,
+        created_before: query.created_before,
+        last_login_after: query.last_login_after,
+        last_login_before: query.last_login_before,
+        search: query.search.map(|search| search.trim().to_string()),
+    };
+    let (users, count) =
+        AuthUsers::list_users(&app_state.db, &filter, sort, page, per_page).await?;
+
+    Ok(web::Json(UserListResponse {
+        count,
+        page,
+        per_page,
+        results: users.into_iter().map(Into::into).collect(),
+    }))
+}
+
+#[api_operation(
+    summary = "Create a user",
+    description = "Create an account, with a validated password or without one. Requires staff status and `auth.add_user`",
+    tag = "users"
+)]
+pub async fn create(
+    app_state: web::Data<AppState>,
+    admin: RequirePermission<Staff<AddUser>>,
+    body: web::Json<CreateUserRequest>,
+) -> Result<CreatedJson<UserAdmin>, ApiError> {
+    let body = body.into_inner();
+    if body.is_superuser && !admin.is_superuser {
+        return Err(ApiError::Forbidden(
+            "Only superusers may create superusers".to_string(),
+        ));
+    }
+
+    let email = body.email.trim().to_string();
+    let username = body.username.trim().to_string();
+    if !email.contains('@') {
+        return Err(ApiError::BadRequest(
+            "Enter a valid email address".to_string(),
+        ));
+    }
+    if username.is_empty() {
+        return Err(ApiError::BadRequest("Username is required".to_string()));
+    }
+
+    let data = CreateUserData {
+        email,
+        username,
+        password: body.password.clone().unwrap_or_default(),
+        first_name: name_change(body.first_name).flatten(),
+        last_name: name_change(body.last_name).flatten(),
+        is_active: body.is_active,
+        is_verified: body.is_verified,
+        is_staff: body.is_staff,
+        is_superuser: body.is_superuser,
+    };
+    let user = match body.password {
+        Some(_) => AuthUsers::create_user(&app_state.db, data).await?,
+        None => AuthUsers::create_user_without_password(&app_state.db, data).await?,
+    };
+    Ok(CreatedJson(user.into()))
+}
+
+#[api_operation(
+    summary = "Get a user",
+    description = "Requires staff status and `auth.view_user`",
+    tag = "users"
+)]
+pub async fn retrieve(
+    app_state: web::Data<AppState>,
+    _admin: RequirePermission<Staff<ViewUser>>,
+    path: web::Path<i32>,
+) -> Result<web::Json<UserAdmin>, ApiError> {
+    let user = find_user(&app_state.db, path.into_inner()).await?;
+    Ok(web::Json(user.into()))
+}
+
+#[api_operation(
+    summary = "Update a user",
+    description = "Change the fields that are sent. Email addresses and usernames must stay unique. Requires staff status and `auth.change_user`; superuser accounts and status are for superusers only",
+    tag = "users"
+)]
+pub async fn update(
+    app_state: web::Data<AppState>,
+    admin: RequirePermission<Staff<ChangeUser>>,
+    path: web::Path<i32>,
+    body: web::Json<UpdateUserRequest>,
+) -> Result<web::Json<UserAdmin>, ApiError> {
+    let user = find_user(&app_state.db, path.into_inner()).await?;
+    require_manageable(&admin, &user)?;
+    let body = body.into_inner();
+    if body.is_superuser.is_some() && !admin.is_superuser {
+        return Err(ApiError::Forbidden(
+            "Only superusers may change superuser status".to_string(),
+        ));
+    }
+
+    let email = body.email.map(|email| email.trim().to_string());
+    if email.as_ref().is_some_and(|email| !email.contains('@')) {
+        return Err(ApiError::BadRequest(
+            "Enter a valid email address".to_string(),
+        ));
+    }
+    let username = body.username.map(|username| username.trim().to_string());
+    if username
+        .as_ref()
+        .is_some_and(|username| username.is_empty())
+    {
+        return Err(ApiError::BadRequest("Username is required".to_string()));
+    }
+
+    let user = user
+        .update_details(
+            &app_state.db,
+            UpdateUserData {
+                email,
+                username,
+                first_name: name_change(body.first_name),
+                last_name: name_change(body.last_name),
+                is_staff: body.is_staff,
+                is_superuser: body.is_superuser,
+            },
+        )
+        .await?;
+    Ok(web::Json(user.into()))
+}
+
+#[api_operation(
+    summary = "Delete a user",
+    description = "Delete the account and everything that belongs to it. Callers cannot delete themselves. Requires staff status and `auth.delete_user`",
+    tag = "users"
+)]
+pub async fn delete(
+    app_state: web::Data<AppState>,
+    admin: RequirePermission<Staff<DeleteUser>>,
+    path: web::Path<i32>,
+) -> Result<NoContent, ApiError> {
+    let user = find_user(&app_state.db, path.into_inner()).await?;
+    require_manageable(&admin, &user)?;
+    if user.id == admin.id {
+        return Err(ApiError::BadRequest(
+            "You cannot delete your own account".to_string(),
+        ));
+    }
+
+    AuthUsers::delete_by_id(user.id)
+        .exec(&*app_state.db)
+        .await?;
+    Ok(NoContent)
+}
+
+#[api_operation(
+    summary = "Activate a user",
+    description = "Let the user log in again. Requires staff status and `auth.change_user`",
+    tag = "users"
+)]
+pub async fn activate(
+    app_state: web::Data<AppState>,
+    admin: RequirePermission<Staff<ChangeUser>>,
+    path: web::Path<i32>,
+) -> Result<web::Json<UserAdmin>, ApiError> {
+    let user = find_user(&app_state.db, path.into_inner()).await?;
+    require_manageable(&admin, &user)?;
+
+    let user = user.activate(&app_state.db).await?;
+    Ok(web::Json(user.into()))
+}
+
+#[api_operation(
+    summary = "Deactivate a user",
+    description = "Refuse the user's logins and log them out everywhere, keeping the account. Callers cannot deactivate themselves. Requires staff status and `auth.change_user`",
+    tag = "users"
+)]
+pub async fn deactivate(
+    app_state: web::Data<AppState>,
+    admin: RequirePermission<Staff<ChangeUser>>,
+    path: web::Path<i32>,
+) -> Result<web::Json<UserAdmin>, ApiError> {
+    let user = find_user(&app_state.db, path.into_inner()).await?;
+    require_manageable(&admin, &user)?;
+    if user.id == admin.id {
+        return Err(ApiError::BadRequest(
+            "You cannot deactivate your own account".to_string(),
+        ));
+    }
+
+    let user = user.deactivate(&app_state.db).await?;
+    end_sessions(&app_state.db, &user).await?;
+    Ok(web::Json(user.into()))
+}
+
+#[api_operation(
+    summary = "Verify a user's email",
+    description = "Mark the email address as verified without a verification link. Requires staff status and `auth.change_user`",
+    tag = "users"
+)]
+pub async fn verify(
+    app_state: web::Data<AppState>,
+    admin: RequirePermission<Staff<ChangeUser>>,
+    path: web::Path<i32>,
+) -> Result<web::Json<UserAdmin>, ApiError> {
+    let user = find_user(&app_state.db, path.into_inner()).await?;
+    require_manageable(&admin, &user)?;
+
+    let user = user.verify_email(&app_state.db).await?;
+    Ok(web::Json(user.into()))
+}
+
+#[api_operation(
+    summary = "Set a user's password",
+    description = "Replace the password after checking it against the password validators, and log the user out everywhere. Requires staff status and `auth.change_user`",
+    tag = "users"
+)]
+pub async fn set_password(
+    app_state: web::Data<AppState>,
+    admin: RequirePermission<Staff<ChangeUser>>,
+    path: web::Path<i32>,
+    body: web::Json<SetPasswordRequest>,
+) -> Result<NoContent, ApiError> {
+    let user = find_user(&app_state.db, path.into_inner()).await?;
+    require_manageable(&admin, &user)?;
+
+    let user = user.set_password(&app_state.db, &body.password).await?;
+    end_sessions(&app_state.db, &user).await?;
+    Ok(NoContent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sort() {
+        assert_eq!(parse_sort(None).unwrap(), UserSort::default());
+        assert_eq!(
+            parse_sort(Some("-created_at")).unwrap(),
+            UserSort {
+                field: UserSortField::CreatedAt,
+                descending: true,
+            }
+        );
+        assert_eq!(
+            parse_sort(Some("email")).unwrap(),
+            UserSort {
+                field: UserSortField::Email,
+                descending: false,
+            }
+        );
+        assert!(parse_sort(Some("password")).is_err());
+    }
+}
@@ -1,6 +1,15 @@
+pub mod auth;
 pub mod config;
+pub mod csrf;
 pub mod db;
 pub mod error;
 pub mod handlers;
+pub mod mail;
+pub mod oauth;
+pub mod oidc;
 pub mod routes;
+pub mod session;
+pub mod sso;
 pub mod state;
+#[cfg(test)]
+pub(crate) mod test_support;
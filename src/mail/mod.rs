@@ -0,0 +1,247 @@
+mod templates;
+mod transports;
+
+pub use templates::{MailTemplates, RenderedMail};
+pub use transports::{
+    ConsoleMailer, FileMailer, MemoryMailer, SmtpMailer, SmtpSecurity, to_message,
+};
+
+use crate::config::MailSettings;
+use actix_web::HttpRequest;
+use actix_web::http::header;
+use std::fmt;
+use std::sync::Arc;
+use tera::Context;
+
+/// An email ready to be delivered
+#[derive(Debug, Clone)]
+pub struct Email {
+    pub from: String,
+    pub to: String,
+    pub subject: String,
+    pub text: String,
+    pub html: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct MailError(pub String);
+
+impl fmt::Display for MailError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Failed to send email: {}", self.0)
+    }
+}
+
+impl std::error::Error for MailError {}
+
+/// A transport that delivers outgoing email
+#[async_trait::async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, email: Email) -> Result<(), MailError>;
+}
+
+/// Renders templated emails and hands them to the configured transport
+pub struct Mail {
+    transport: Arc<dyn Mailer>,
+    templates: MailTemplates,
+    from: String,
+    site_name: String,
+}
+
+impl Mail {
+    pub fn new(
+        transport: Arc<dyn Mailer>,
+        templates: MailTemplates,
+        from: impl Into<String>,
+        site_name: impl Into<String>,
+    ) -> Self {
+        Self {
+            transport,
+            templates,
+            from: from.into(),
+            site_name: site_name.into(),
+        }
+    }
+
+    pub fn from_settings(settings: &MailSettings) -> Result<Self, MailError> {
+        let transport: Arc<dyn Mailer> = match settings.transport.as_str() {
+            "console" => Arc::new(ConsoleMailer),
+            "file" => Arc::new(FileMailer::new(&settings.file_path)),
+            "smtp" => {
+                let smtp = &settings.smtp;
+                let security = match smtp.security.as_str() {
+                    "tls" => SmtpSecurity::Tls,
+                    "starttls" => SmtpSecurity::StartTls,
+                    "none" => SmtpSecurity::None,
+                    other => {
+                        return Err(MailError(format!("Unknown SMTP security: {}", other)));
+                    }
+                };
+                let credentials = smtp
+                    .username
+                    .clone()
+                    .map(|username| (username, smtp.password.clone().unwrap_or_default()));
+                Arc::new(SmtpMailer::new(
+                    &smtp.host,
+                    smtp.port,
+                    security,
+                    credentials,
+                )?)
+            }
+            other => return Err(MailError(format!("Unknown mail transport: {}", other))),
+        };
+
+        let templates =
+            MailTemplates::new(settings.template_dir.as_deref(), &settings.default_locale)?;
+        Ok(Self::new(
+            transport,
+            templates,
+            &settings.from_email,
+            &settings.site_name,
+        ))
+    }
+
+    /// Send an email as is
+    pub async fn send(&self, email: Email) -> Result<(), MailError> {
+        self.transport.send(email).await
+    }
+
+    /// Render template `name` in `locale` and send it to `to`.
+    /// `site_name` is always available to templates.
+    pub async fn send_template(
+        &self,
+        to: &str,
+        name: &str,
+        locale: Option<&str>,
+        mut context: Context,
+    ) -> Result<(), MailError> {
+        context.insert("site_name", &self.site_name);
+        let rendered = self.templates.render(name, locale, &context)?;
+
+        self.send(Email {
+            from: self.from.clone(),
+            to: to.to_string(),
+            subject: rendered.subject,
+            text: rendered.text,
+            html: rendered.html,
+        })
+        .await
+    }
+
+    /// Best supported locale from the request's `Accept-Language` header
+    pub fn request_locale(&self, req: &HttpRequest) -> Option<String> {
+        let accept_language = req.headers().get(header::ACCEPT_LANGUAGE)?.to_str().ok()?;
+        preferred_locale(accept_language, self.templates.locales())
+    }
+}
+
+/// Pick the highest weighted language of an `Accept-Language` value that is
+/// supported, matching `id-ID` to `id` when only the primary tag is known
+pub fn preferred_locale<'a>(
+    accept_language: &str,
+    supported: impl Iterator<Item = &'a str>,
+) -> Option<String> {
+    let supported: Vec<&str> = supported.collect();
+
+    let mut wanted: Vec<(&str, f32)> = accept_language
+        .split(',')
+        .filter_map(|entry| {
+            let mut parts = entry.trim().split(';');
+            let tag = parts.next()?.trim();
+            let quality = parts
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.parse().ok())?;
+            (!tag.is_empty() && quality > 0.0).then_some((tag, quality))
+        })
+        .collect();
+    // Stable, so equal weights keep the client's order
+    wanted.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    wanted.iter().find_map(|(tag, _)| {
+        let primary = tag.split('-').next().unwrap_or(tag);
+        supported
+            .iter()
+            .find(|locale| locale.eq_ignore_ascii_case(tag))
+            .or_else(|| {
+                supported
+                    .iter()
+                    .find(|locale| locale.eq_ignore_ascii_case(primary))
+            })
+            .map(|locale| locale.to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mail(transport: Arc<dyn Mailer>) -> Mail {
+        let templates = MailTemplates::new(None, "en").unwrap();
+        Mail::new(transport, templates, "no-reply@example.com", "R-Web")
+    }
+
+    fn context() -> Context {
+        let mut context = Context::new();
+        context.insert("username", "ada");
+        context.insert("link", "https://example.com/verify?token=a&b");
+        context.insert("expires_hours", &72);
+        context
+    }
+
+    #[tokio::test]
+    async fn test_renders_into_memory_outbox() {
+        let outbox = MemoryMailer::new();
+        let mail = mail(Arc::new(outbox.clone()));
+
+        mail.send_template("ada@example.com", "verify_email", None, context())
+            .await
+            .unwrap();
+        mail.send_template("ada@example.com", "verify_email", Some("id"), context())
+            .await
+            .unwrap();
+
+        let sent = outbox.outbox();
+        assert_eq!(sent.len(), 2);
+        assert_eq!(sent[0].to, "ada@example.com");
+        assert_eq!(sent[0].subject, "Verify your email address for R-Web");
+        assert!(
+            sent[0]
+                .text
+                .contains("https://example.com/verify?token=a&b")
+        );
+        // HTML bodies are escaped, plain text is not
+        let html = sent[0].html.as_deref().unwrap();
+        assert!(html.contains("https:&#x2F;&#x2F;example.com&#x2F;verify?token=a&amp;b"));
+        assert_eq!(sent[1].subject, "Verifikasi alamat email Anda untuk R-Web");
+    }
+
+    #[tokio::test]
+    async fn test_file_transport_writes_eml() {
+        let dir = std::env::temp_dir().join(format!("r-web-mail-{}", uuid::Uuid::new_v4()));
+        let mail = mail(Arc::new(FileMailer::new(&dir)));
+
+        mail.send_template("ada@example.com", "password_reset", None, context())
+            .await
+            .unwrap();
+
+        let files: Vec<_> = std::fs::read_dir(&dir).unwrap().collect();
+        assert_eq!(files.len(), 1);
+        let eml = std::fs::read_to_string(files[0].as_ref().unwrap().path()).unwrap();
+        assert!(eml.contains("To: ada@example.com"));
+        assert!(eml.contains("Subject: Reset your R-Web password"));
+        assert!(eml.contains("multipart/alternative"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_preferred_locale() {
+        let supported = ["en", "id"];
+        let pick = |header: &str| preferred_locale(header, supported.iter().copied());
+
+        assert_eq!(pick("id-ID,id;q=0.9,en;q=0.8").as_deref(), Some("id"));
+        assert_eq!(pick("fr;q=0.9, en;q=0.5").as_deref(), Some("en"));
+        assert_eq!(pick("en;q=0.2, id;q=0.8").as_deref(), Some("id"));
+        assert_eq!(pick("fr, de").as_deref(), None);
+        assert_eq!(pick("").as_deref(), None);
+    }
+}
@@ -0,0 +1,173 @@
+use super::MailError;
+use std::collections::HashMap;
+use tera::{Context, Tera};
+
+/// Templates bundled into the binary; a `mail.template_dir` can override any of them
+const BUILTIN_TEMPLATES: &[(&str, &str)] = &[
+    ("base.html", include_str!("../../templates/mail/base.html")),
+    (
+        "verify_email.txt",
+        include_str!("../../templates/mail/verify_email.txt"),
+    ),
+    (
+        "verify_email.html",
+        include_str!("../../templates/mail/verify_email.html"),
+    ),
+    (
+        "password_reset.txt",
+        include_str!("../../templates/mail/password_reset.txt"),
+    ),
+    (
+        "password_reset.html",
+        include_str!("../../templates/mail/password_reset.html"),
+    ),
+    (
+        "magic_link.txt",
+        include_str!("../../templates/mail/magic_link.txt"),
+    ),
+    (
+        "magic_link.html",
+        include_str!("../../templates/mail/magic_link.html"),
+    ),
+    (
+        "email_change.txt",
+        include_str!("../../templates/mail/email_change.txt"),
+    ),
+    (
+        "email_change.html",
+        include_str!("../../templates/mail/email_change.html"),
+    ),
+    (
+        "email_change_notice.txt",
+        include_str!("../../templates/mail/email_change_notice.txt"),
+    ),
+    (
+        "email_change_notice.html",
+        include_str!("../../templates/mail/email_change_notice.html"),
+    ),
+    (
+        "email_change_taken.txt",
+        include_str!("../../templates/mail/email_change_taken.txt"),
+    ),
+    (
+        "email_change_taken.html",
+        include_str!("../../templates/mail/email_change_taken.html"),
+    ),
+];
+const BUILTIN_SUBJECTS: &str = include_str!("../../templates/mail/subjects.toml");
+
+/// Subject and bodies of a rendered template
+#[derive(Debug, Clone)]
+pub struct RenderedMail {
+    pub subject: String,
+    pub text: String,
+    pub html: Option<String>,
+}
+
+/// Email templates: `<name>.txt` and an optional `<name>.html` rendered
+/// with Tera, plus a subject per locale from `subjects.toml`
+pub struct MailTemplates {
+    tera: Tera,
+    /// locale -> template name -> subject template
+    subjects: HashMap<String, HashMap<String, String>>,
+    default_locale: String,
+}
+
+impl MailTemplates {
+    /// Load the bundled templates, overridden by files in `template_dir` when given
+    pub fn new(template_dir: Option<&str>, default_locale: &str) -> Result<Self, MailError> {
+        let mut tera = match template_dir {
+            Some(dir) => Tera::parse(&format!("{}/**/*", dir.trim_end_matches('/')))
+                .map_err(|e| MailError(format!("Failed to load mail templates: {}", e)))?,
+            None => Tera::default(),
+        };
+
+        // Files from `template_dir` win over the bundled templates
+        let mut builtin = Tera::default();
+        builtin
+            .add_raw_templates(BUILTIN_TEMPLATES.to_vec())
+            .map_err(|e| MailError(format!("Invalid bundled mail template: {}", e)))?;
+        tera.extend(&builtin)
+            .map_err(|e| MailError(format!("Failed to load mail templates: {}", e)))?;
+        tera.build_inheritance_chains()
+            .map_err(|e| MailError(format!("Failed to load mail templates: {}", e)))?;
+
+        let mut subjects = parse_subjects(BUILTIN_SUBJECTS)?;
+        if let Some(dir) = template_dir {
+            let path = std::path::Path::new(dir).join("subjects.toml");
+            if path.exists() {
+                let custom = std::fs::read_to_string(&path)
+                    .map_err(|e| MailError(format!("Failed to read {}: {}", path.display(), e)))?;
+                for (locale, names) in parse_subjects(&custom)? {
+                    subjects.entry(locale).or_default().extend(names);
+                }
+            }
+        }
+
+        Ok(Self {
+            tera,
+            subjects,
+            default_locale: default_locale.to_string(),
+        })
+    }
+
+    /// Locales that have at least one subject
+    pub fn locales(&self) -> impl Iterator<Item = &str> {
+        self.subjects.keys().map(String::as_str)
+    }
+
+    /// Render template `name` in `locale`, falling back to the default locale
+    /// for a missing subject. `subject` and `locale` are added to the context.
+    pub fn render(
+        &self,
+        name: &str,
+        locale: Option<&str>,
+        context: &Context,
+    ) -> Result<RenderedMail, MailError> {
+        let locale = locale
+            .filter(|locale| self.subjects.contains_key(*locale))
+            .unwrap_or(&self.default_locale);
+        let subject = self
+            .subjects
+            .get(locale)
+            .and_then(|names| names.get(name))
+            .or_else(|| {
+                self.subjects
+                    .get(&self.default_locale)
+                    .and_then(|names| names.get(name))
+            })
+            .ok_or_else(|| MailError(format!("No subject for mail template {}", name)))?;
+
+        let mut context = context.clone();
+        context.insert("locale", locale);
+        let subject = Tera::one_off(subject, &context, false)
+            .map_err(|e| MailError(format!("Failed to render subject of {}: {}", name, e)))?;
+        context.insert("subject", &subject);
+
+        let text = self
+            .tera
+            .render(&format!("{}.txt", name), &context)
+            .map_err(|e| MailError(format!("Failed to render {}.txt: {}", name, e)))?;
+
+        let html_name = format!("{}.html", name);
+        let html = if self.tera.get_template_names().any(|n| n == html_name) {
+            Some(
+                self.tera
+                    .render(&html_name, &context)
+                    .map_err(|e| MailError(format!("Failed to render {}: {}", html_name, e)))?,
+            )
+        } else {
+            None
+        };
+
+        Ok(RenderedMail {
+            subject: subject.trim().to_string(),
+            text,
+            html,
+        })
+    }
+}
+
+fn parse_subjects(source: &str) -> Result<HashMap<String, HashMap<String, String>>, MailError> {
+    toml::from_str(source).map_err(|e| MailError(format!("Invalid mail subjects: {}", e)))
+}
@@ -0,0 +1,176 @@
+use super::{Email, MailError, Mailer};
+use lettre::message::{Mailbox, MultiPart, SinglePart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// Build a MIME message, multipart when the email has an HTML body
+pub fn to_message(email: &Email) -> Result<Message, MailError> {
+    let from: Mailbox = email
+        .from
+        .parse()
+        .map_err(|e| MailError(format!("Invalid sender {}: {}", email.from, e)))?;
+    let to: Mailbox = email
+        .to
+        .parse()
+        .map_err(|e| MailError(format!("Invalid recipient {}: {}", email.to, e)))?;
+
+    let builder = Message::builder()
+        .from(from)
+        .to(to)
+        .subject(email.subject.clone());
+    let message = match &email.html {
+        Some(html) => builder.multipart(MultiPart::alternative_plain_html(
+            email.text.clone(),
+            html.clone(),
+        )),
+        None => builder.singlepart(SinglePart::plain(email.text.clone())),
+    };
+
+    message.map_err(|e| MailError(e.to_string()))
+}
+
+/// Prints emails to stdout instead of sending them, for development
+pub struct ConsoleMailer;
+
+#[async_trait::async_trait]
+impl Mailer for ConsoleMailer {
+    async fn send(&self, email: Email) -> Result<(), MailError> {
+        println!(
+            "From: {}\nTo: {}\nSubject: {}\n\n{}\n{}",
+            email.from,
+            email.to,
+            email.subject,
+            email.text,
+            "-".repeat(79)
+        );
+        Ok(())
+    }
+}
+
+/// Writes every email as an `.eml` file into a directory
+pub struct FileMailer {
+    dir: PathBuf,
+}
+
+impl FileMailer {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+}
+
+#[async_trait::async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, email: Email) -> Result<(), MailError> {
+        let message = to_message(&email)?;
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .map_err(|e| MailError(format!("Failed to create {}: {}", self.dir.display(), e)))?;
+
+        let name = format!(
+            "{}-{}.eml",
+            chrono::Utc::now().format("%Y%m%d-%H%M%S"),
+            uuid::Uuid::new_v4()
+        );
+        let path = self.dir.join(name);
+        tokio::fs::write(&path, message.formatted())
+            .await
+            .map_err(|e| MailError(format!("Failed to write {}: {}", path.display(), e)))
+    }
+}
+
+/// How to secure the connection to the SMTP server
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmtpSecurity {
+    /// Implicit TLS, usually port 465
+    Tls,
+    /// Upgrade with STARTTLS, usually port 587
+    StartTls,
+    /// Plain text, only for local relays such as Mailpit
+    None,
+}
+
+/// Sends email through an SMTP relay
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpMailer {
+    pub fn new(
+        host: &str,
+        port: u16,
+        security: SmtpSecurity,
+        credentials: Option<(String, String)>,
+    ) -> Result<Self, MailError> {
+        let builder = match security {
+            SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host),
+            SmtpSecurity::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host),
+            SmtpSecurity::None => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(
+                host,
+            )),
+        }
+        .map_err(|e| MailError(format!("Invalid SMTP relay {}: {}", host, e)))?;
+
+        let mut builder = builder.port(port);
+        if let Some((username, password)) = credentials {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+
+        Ok(Self {
+            transport: builder.build(),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, email: Email) -> Result<(), MailError> {
+        let message = to_message(&email)?;
+        self.transport
+            .send(message)
+            .await
+            .map(|_| ())
+            .map_err(|e| MailError(e.to_string()))
+    }
+}
+
+/// Keeps sent emails in memory so tests can assert on them
+#[derive(Clone, Default)]
+pub struct MemoryMailer {
+    outbox: Arc<Mutex<Vec<Email>>>,
+}
+
+impl MemoryMailer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Emails sent so far, oldest first
+    pub fn outbox(&self) -> Vec<Email> {
+        self.outbox
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    pub fn clear(&self) {
+        self.outbox
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+}
+
+#[async_trait::async_trait]
+impl Mailer for MemoryMailer {
+    async fn send(&self, email: Email) -> Result<(), MailError> {
+        // Reject what a real transport would reject
+        to_message(&email)?;
+        self.outbox
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(email);
+        Ok(())
+    }
+}
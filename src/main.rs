@@ -7,7 +7,7 @@
 fn main() {
     eprintln!("This is the library crate. To run the server, use:");
     eprintln!("  cargo run --bin server");
-    eprintln!("");
+    eprintln!();
     eprintln!("To create a superuser, use:");
     eprintln!("  cargo run --bin create_superuser");
     std::process::exit(1);
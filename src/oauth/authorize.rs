@@ -0,0 +1,312 @@
+use super::{OAuthError, requested_scopes, with_query};
+use crate::config::OAuthSettings;
+use crate::error::ApiError;
+use apistos::ApiComponent;
+use entity::oauth_clients::Model as Client;
+use entity::{
+    AuthorizationCodeEntityExt, ConsentEntityExt, GrantType, NewAuthorizationCode,
+    OAuthClientEntityExt, OAuthClientModelExt, OauthAuthorizationCodes, OauthClients,
+    OauthConsents, has_scopes,
+};
+use schemars::JsonSchema;
+use sea_orm::DatabaseConnection;
+use security::token::{generate_token, hash_token};
+use serde::{Deserialize, Serialize};
+
+/// Query parameters of `/oauth/authorize`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ApiComponent)]
+pub struct AuthorizeParams {
+    /// Must be `code`
+    pub response_type: String,
+    pub client_id: String,
+    /// One of the client's registered URIs; may be left out when it has
+    /// only one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redirect_uri: Option<String>,
+    /// Space-separated scopes; all of the client's when left out
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    /// Returned unchanged to the client
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state: Option<String>,
+    /// PKCE challenge, required from public clients
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code_challenge: Option<String>,
+    /// Must be `S256` when a challenge is sent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code_challenge_method: Option<String>,
+    /// Repeated in the ID token, when the `openid` scope is requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+}
+
+/// Why an authorization request was refused
+#[derive(Debug)]
+pub enum AuthorizeError {
+    /// The client or redirect URI is unknown, so the user must not be
+    /// sent back to it
+    BadClient(ApiError),
+    /// Reported to the client at this redirect URI
+    Redirect(String),
+}
+
+impl From<sea_orm::DbErr> for AuthorizeError {
+    fn from(err: sea_orm::DbErr) -> Self {
+        AuthorizeError::BadClient(err.into())
+    }
+}
+
+impl From<entity::AuthError> for AuthorizeError {
+    fn from(err: entity::AuthError) -> Self {
+        AuthorizeError::BadClient(err.into())
+    }
+}
+
+/// A checked authorization request
+#[derive(Debug)]
+pub struct AuthorizationRequest {
+    pub client: Client,
+    pub redirect_uri: String,
+    pub scopes: Vec<String>,
+    pub state: Option<String>,
+    pub code_challenge: Option<String>,
+    pub nonce: Option<String>,
+}
+
+impl AuthorizeParams {
+    /// Check the request against the client's registration
+    pub async fn check(
+        &self,
+        db: &DatabaseConnection,
+    ) -> Result<AuthorizationRequest, AuthorizeError> {
+        let client = OauthClients::find_by_client_id(db, &self.client_id)
+            .await?
+            .ok_or_else(|| {
+                AuthorizeError::BadClient(ApiError::BadRequest("Unknown client".to_string()))
+            })?;
+
+        let redirect_uri = match &self.redirect_uri {
+            Some(uri) if client.allows_redirect_uri(uri) => uri.clone(),
+            Some(_) => {
+                return Err(AuthorizeError::BadClient(ApiError::BadRequest(
+                    "redirect_uri is not registered for the client".to_string(),
+                )));
+            }
+            None => match client.redirect_uri_list().as_slice() {
+                [only] => only.clone(),
+                _ => {
+                    return Err(AuthorizeError::BadClient(ApiError::BadRequest(
+                        "redirect_uri is required".to_string(),
+                    )));
+                }
+            },
+        };
+
+        let mut request = AuthorizationRequest {
+            client,
+            redirect_uri,
+            scopes: Vec::new(),
+            state: self.state.clone(),
+            code_challenge: None,
+            nonce: self.nonce.clone(),
+        };
+        match self.check_grant(&request.client) {
+            Ok((scopes, code_challenge)) => {
+                request.scopes = scopes;
+                request.code_challenge = code_challenge;
+                Ok(request)
+            }
+            Err(err) => Err(AuthorizeError::Redirect(request.error_redirect(&err))),
+        }
+    }
+
+    fn check_grant(&self, client: &Client) -> Result<(Vec<String>, Option<String>), OAuthError> {
+        if self.response_type != "code" {
+            return Err(OAuthError::UnsupportedResponseType(
+                "Only the code response type is supported".to_string(),
+            ));
+        }
+        if !client.allows_grant(GrantType::AuthorizationCode) {
+            return Err(OAuthError::UnauthorizedClient(
+                "The client may not use the authorization code grant".to_string(),
+            ));
+        }
+
+        let scopes = requested_scopes(client, self.scope.as_deref())?;
+
+        let code_challenge = match (&self.code_challenge, self.code_challenge_method.as_deref()) {
+            (Some(challenge), Some("S256")) if is_valid_challenge(challenge) => {
+                Some(challenge.clone())
+            }
+            (Some(_), Some("S256")) => {
+                return Err(OAuthError::InvalidRequest(
+                    "code_challenge is malformed".to_string(),
+                ));
+            }
+            (Some(_), _) => {
+                return Err(OAuthError::InvalidRequest(
+                    "code_challenge_method must be S256".to_string(),
+                ));
+            }
+            (None, _) if !client.is_confidential() => {
+                return Err(OAuthError::InvalidRequest(
+                    "Public clients must use PKCE".to_string(),
+                ));
+            }
+            (None, _) => None,
+        };
+
+        Ok((scopes, code_challenge))
+    }
+}
+
+/// An `S256` challenge is an unpadded base64url SHA-256 digest
+fn is_valid_challenge(challenge: &str) -> bool {
+    challenge.len() == 43
+        && challenge
+            .bytes()
+            .all(|c| c.is_ascii_alphanumeric() || c == b'-' || c == b'_')
+}
+
+impl AuthorizationRequest {
+    /// The redirect URI with `params` and the client's `state`
+    fn redirect_with(&self, params: &[(&str, &str)]) -> String {
+        let mut params = params.to_vec();
+        if let Some(state) = &self.state {
+            params.push(("state", state));
+        }
+        with_query(&self.redirect_uri, &params)
+    }
+
+    pub fn error_redirect(&self, err: &OAuthError) -> String {
+        self.redirect_with(&[
+            ("error", err.code()),
+            ("error_description", err.description()),
+        ])
+    }
+
+    /// Whether the user must still approve the client for these scopes
+    pub async fn needs_consent(
+        &self,
+        db: &DatabaseConnection,
+        user_id: i32,
+    ) -> Result<bool, ApiError> {
+        if self.client.skip_consent {
+            return Ok(false);
+        }
+        let consent = OauthConsents::find_for(db, user_id, self.client.id).await?;
+        Ok(!consent.is_some_and(|consent| has_scopes(&consent.scopes, &self.scopes)))
+    }
+
+    /// Remember that the user approved the client for these scopes
+    pub async fn grant_consent(
+        &self,
+        db: &DatabaseConnection,
+        user_id: i32,
+    ) -> Result<(), ApiError> {
+        Ok(OauthConsents::grant(db, user_id, self.client.id, &self.scopes).await?)
+    }
+
+    /// Issue an authorization code for the user, returning where to send
+    /// them with it
+    pub async fn issue_code(
+        &self,
+        db: &DatabaseConnection,
+        config: &OAuthSettings,
+        user_id: i32,
+    ) -> Result<String, ApiError> {
+        let code = generate_token();
+        OauthAuthorizationCodes::issue(
+            db,
+            NewAuthorizationCode {
+                client_id: self.client.id,
+                user_id,
+                code_hash: hash_token(&code),
+                redirect_uri: self.redirect_uri.clone(),
+                scopes: self.scopes.clone(),
+                code_challenge: self.code_challenge.clone(),
+                nonce: self.nonce.clone(),
+                expires_at: chrono::Utc::now().naive_utc()
+                    + chrono::Duration::seconds(config.code_ttl),
+            },
+        )
+        .await?;
+
+        Ok(self.redirect_with(&[("code", &code)]))
+    }
+
+    /// Where to send a user who refused to approve the client
+    pub fn denied_redirect(&self) -> String {
+        self.error_redirect(&OAuthError::AccessDenied(
+            "The user denied the request".to_string(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client(secret: Option<&str>) -> Client {
+        Client {
+            id: 1,
+            client_id: "spa".to_string(),
+            client_secret_hash: secret.map(hash_token),
+            name: "SPA".to_string(),
+            redirect_uris: "https://app.example.com/cb".to_string(),
+            grant_types: "authorization_code refresh_token".to_string(),
+            scopes: "profile:read profile:write".to_string(),
+            skip_consent: false,
+            created_at: chrono::Utc::now().naive_utc(),
+        }
+    }
+
+    fn params() -> AuthorizeParams {
+        AuthorizeParams {
+            response_type: "code".to_string(),
+            client_id: "spa".to_string(),
+            redirect_uri: None,
+            scope: Some("profile:read".to_string()),
+            state: Some("xyz".to_string()),
+            code_challenge: Some("E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM".to_string()),
+            code_challenge_method: Some("S256".to_string()),
+            nonce: None,
+        }
+    }
+
+    #[test]
+    fn test_check_grant() {
+        let (scopes, challenge) = params().check_grant(&client(None)).unwrap();
+        assert_eq!(scopes, vec!["profile:read".to_string()]);
+        assert!(challenge.is_some());
+
+        let without_pkce = AuthorizeParams {
+            code_challenge: None,
+            code_challenge_method: None,
+            ..params()
+        };
+        assert!(matches!(
+            without_pkce.check_grant(&client(None)),
+            Err(OAuthError::InvalidRequest(_))
+        ));
+        assert!(without_pkce.check_grant(&client(Some("secret"))).is_ok());
+
+        let plain = AuthorizeParams {
+            code_challenge_method: Some("plain".to_string()),
+            ..params()
+        };
+        assert!(matches!(
+            plain.check_grant(&client(None)),
+            Err(OAuthError::InvalidRequest(_))
+        ));
+
+        let foreign_scope = AuthorizeParams {
+            scope: Some("users:write".to_string()),
+            ..params()
+        };
+        assert!(matches!(
+            foreign_scope.check_grant(&client(None)),
+            Err(OAuthError::InvalidScope(_))
+        ));
+    }
+}
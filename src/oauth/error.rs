@@ -0,0 +1,114 @@
+use crate::error::ApiError;
+use actix_web::{HttpResponse, error::ResponseError, http::header};
+use apistos::ApiErrorComponent;
+use entity::AuthError;
+use log::error;
+use serde_json::json;
+use std::fmt;
+
+/// Errors of the OAuth2 endpoints, answered as RFC 6749 error objects:
+/// `{"error": "invalid_grant", "error_description": "..."}`
+// `openapi_error` repeats `status(...)` by design
+#[allow(clippy::duplicated_attributes)]
+#[derive(Debug, ApiErrorComponent)]
+#[openapi_error(status(code = 400), status(code = 401), status(code = 500))]
+pub enum OAuthError {
+    InvalidRequest(String),
+    /// Client authentication failed
+    InvalidClient(String),
+    /// The code or refresh token is invalid, expired, revoked or belongs
+    /// to another client
+    InvalidGrant(String),
+    UnauthorizedClient(String),
+    UnsupportedGrantType(String),
+    UnsupportedResponseType(String),
+    InvalidScope(String),
+    AccessDenied(String),
+    ServerError(String),
+}
+
+impl OAuthError {
+    /// The `error` code of RFC 6749
+    pub fn code(&self) -> &'static str {
+        match self {
+            OAuthError::InvalidRequest(_) => "invalid_request",
+            OAuthError::InvalidClient(_) => "invalid_client",
+            OAuthError::InvalidGrant(_) => "invalid_grant",
+            OAuthError::UnauthorizedClient(_) => "unauthorized_client",
+            OAuthError::UnsupportedGrantType(_) => "unsupported_grant_type",
+            OAuthError::UnsupportedResponseType(_) => "unsupported_response_type",
+            OAuthError::InvalidScope(_) => "invalid_scope",
+            OAuthError::AccessDenied(_) => "access_denied",
+            OAuthError::ServerError(_) => "server_error",
+        }
+    }
+
+    /// The `error_description`; internal errors are not described
+    pub fn description(&self) -> &str {
+        match self {
+            OAuthError::InvalidRequest(msg)
+            | OAuthError::InvalidClient(msg)
+            | OAuthError::InvalidGrant(msg)
+            | OAuthError::UnauthorizedClient(msg)
+            | OAuthError::UnsupportedGrantType(msg)
+            | OAuthError::UnsupportedResponseType(msg)
+            | OAuthError::InvalidScope(msg)
+            | OAuthError::AccessDenied(msg) => msg,
+            OAuthError::ServerError(_) => "Internal server error",
+        }
+    }
+}
+
+impl fmt::Display for OAuthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OAuthError::ServerError(msg) => write!(f, "server_error: {}", msg),
+            _ => write!(f, "{}: {}", self.code(), self.description()),
+        }
+    }
+}
+
+impl ResponseError for OAuthError {
+    fn error_response(&self) -> HttpResponse {
+        let body = json!({
+            "error": self.code(),
+            "error_description": self.description(),
+        });
+        match self {
+            OAuthError::InvalidClient(_) => HttpResponse::Unauthorized()
+                .insert_header((header::WWW_AUTHENTICATE, "Basic"))
+                .insert_header((header::CACHE_CONTROL, "no-store"))
+                .json(body),
+            OAuthError::ServerError(msg) => {
+                // Log the actual error internally
+                error!("OAuth server error: {}", msg);
+                HttpResponse::InternalServerError().json(body)
+            }
+            _ => HttpResponse::BadRequest()
+                .insert_header((header::CACHE_CONTROL, "no-store"))
+                .json(body),
+        }
+    }
+}
+
+impl From<sea_orm::DbErr> for OAuthError {
+    fn from(err: sea_orm::DbErr) -> Self {
+        OAuthError::ServerError(err.to_string())
+    }
+}
+
+impl From<AuthError> for OAuthError {
+    fn from(err: AuthError) -> Self {
+        OAuthError::from(ApiError::from(err))
+    }
+}
+
+impl From<ApiError> for OAuthError {
+    fn from(err: ApiError) -> Self {
+        match err {
+            ApiError::BadRequest(msg) => OAuthError::InvalidRequest(msg),
+            ApiError::Unauthorized(msg) | ApiError::Forbidden(msg) => OAuthError::InvalidGrant(msg),
+            other => OAuthError::ServerError(other.to_string()),
+        }
+    }
+}
@@ -0,0 +1,248 @@
+//! OAuth2 authorization server for our own SPAs and partner apps.
+//!
+//! Supports the authorization code grant with PKCE (RFC 6749, RFC 7636),
+//! refresh tokens and client credentials, along with token revocation
+//! (RFC 7009) and introspection (RFC 7662). Users log in through the cookie
+//! session, so `/oauth/authorize` knows who is asking, and approve clients
+//! on the front end's consent page. Tokens are opaque and only their hashes
+//! are stored.
+
+mod authorize;
+mod error;
+mod tokens;
+
+pub use authorize::{AuthorizationRequest, AuthorizeError, AuthorizeParams};
+pub use error::OAuthError;
+pub use tokens::{
+    IssuedTokens, authenticate, client_credentials, exchange_code, find_client_token, find_token,
+    refresh_tokens,
+};
+
+use crate::auth::scopes;
+use crate::oidc;
+use actix_web::HttpRequest;
+use actix_web::http::header;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use entity::oauth_clients::Model as Client;
+use entity::{OAuthClientEntityExt, OAuthClientModelExt, OauthClients};
+use sea_orm::DatabaseConnection;
+use security::signing::constant_time_compare;
+use security::token::hash_token;
+
+/// Marks OAuth access tokens, so they are told apart from JWTs and
+/// personal access tokens
+pub const ACCESS_TOKEN_PREFIX: &str = "rsw_oat_";
+
+/// Marks OAuth refresh tokens
+pub const REFRESH_TOKEN_PREFIX: &str = "rsw_ort_";
+
+pub fn is_access_token(token: &str) -> bool {
+    token.starts_with(ACCESS_TOKEN_PREFIX)
+}
+
+/// Whether clients may be registered for `scope`: the scopes of personal
+/// access tokens and those of OpenID Connect
+pub fn is_known_scope(scope: &str) -> bool {
+    scopes::is_known(scope) || oidc::is_known(scope)
+}
+
+/// What a scope lets a client do, for the consent page
+pub fn describe_scope(scope: &str) -> Option<&'static str> {
+    scopes::SCOPES
+        .iter()
+        .chain(oidc::SCOPES)
+        .find(|(name, _)| *name == scope)
+        .map(|(_, description)| *description)
+}
+
+/// The scopes granted for a `scope` parameter: the requested ones, which
+/// the client must be registered for, or all of the client's when omitted
+fn requested_scopes(client: &Client, scope: Option<&str>) -> Result<Vec<String>, OAuthError> {
+    let registered = client.scope_list();
+    let mut requested: Vec<String> = match scope.map(str::trim).filter(|s| !s.is_empty()) {
+        Some(scope) => scope.split_whitespace().map(str::to_string).collect(),
+        None => registered.clone(),
+    };
+    if let Some(unknown) = requested.iter().find(|scope| !registered.contains(scope)) {
+        return Err(OAuthError::InvalidScope(format!(
+            "The client may not request the {} scope",
+            unknown
+        )));
+    }
+    requested.sort();
+    requested.dedup();
+    Ok(requested)
+}
+
+/// `uri` with `params` added to its query string
+pub(crate) fn with_query(uri: &str, params: &[(&str, &str)]) -> String {
+    let query = serde_urlencoded::to_string(params).expect("params are plain strings");
+    let separator = if uri.contains('?') { '&' } else { '?' };
+    format!("{}{}{}", uri, separator, query)
+}
+
+/// Undo the form encoding of credentials in a Basic header (RFC 6749
+/// section 2.3.1)
+fn form_decode(value: &str) -> Option<String> {
+    serde_urlencoded::from_str::<Vec<(String, String)>>(&format!("v={}", value))
+        .ok()?
+        .pop()
+        .map(|(_, value)| value)
+}
+
+/// Client id and secret of an `Authorization: Basic` header
+fn basic_credentials(req: &HttpRequest) -> Option<(String, String)> {
+    let value = req.headers().get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, encoded) = value.split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("basic") {
+        return None;
+    }
+    let decoded = String::from_utf8(STANDARD.decode(encoded.trim()).ok()?).ok()?;
+    let (client_id, client_secret) = decoded.split_once(':')?;
+    Some((form_decode(client_id)?, form_decode(client_secret)?))
+}
+
+/// Authenticate the client calling the token, revocation or introspection
+/// endpoint, with HTTP Basic or with `client_id` and `client_secret` in the
+/// form. Public clients only send their `client_id`.
+pub async fn authenticate_client(
+    db: &DatabaseConnection,
+    req: &HttpRequest,
+    client_id: Option<&str>,
+    client_secret: Option<&str>,
+) -> Result<Client, OAuthError> {
+    let invalid = || OAuthError::InvalidClient("Client authentication failed".to_string());
+
+    let (client_id, client_secret) = match basic_credentials(req) {
+        Some((basic_id, basic_secret)) => {
+            if client_id.is_some_and(|client_id| client_id != basic_id) || client_secret.is_some() {
+                return Err(OAuthError::InvalidRequest(
+                    "Use a single client authentication method".to_string(),
+                ));
+            }
+            (basic_id, Some(basic_secret))
+        }
+        None => (
+            client_id.ok_or_else(invalid)?.to_string(),
+            client_secret.map(str::to_string),
+        ),
+    };
+
+    let client = OauthClients::find_by_client_id(db, &client_id)
+        .await?
+        .ok_or_else(invalid)?;
+
+    let authenticated = match (&client.client_secret_hash, client_secret) {
+        (Some(stored), Some(secret)) => constant_time_compare(&hash_token(&secret), stored),
+        (None, None) => true,
+        _ => false,
+    };
+    if !authenticated {
+        return Err(invalid());
+    }
+    Ok(client)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+    use sea_orm::{DbBackend, MockDatabase};
+
+    fn client(secret: Option<&str>) -> Client {
+        Client {
+            id: 1,
+            client_id: "spa".to_string(),
+            client_secret_hash: secret.map(hash_token),
+            name: "SPA".to_string(),
+            redirect_uris: "https://app.example.com/cb".to_string(),
+            grant_types: "authorization_code refresh_token".to_string(),
+            scopes: "profile:read".to_string(),
+            skip_consent: false,
+            created_at: chrono::Utc::now().naive_utc(),
+        }
+    }
+
+    async fn authenticate(
+        stored: Client,
+        client_id: Option<&str>,
+        client_secret: Option<&str>,
+    ) -> Result<Client, OAuthError> {
+        let db = MockDatabase::new(DbBackend::Postgres)
+            .append_query_results([vec![stored]])
+            .into_connection();
+        let req = TestRequest::default().to_http_request();
+        authenticate_client(&db, &req, client_id, client_secret).await
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_confidential_client() {
+        assert!(
+            authenticate(client(Some("secret")), Some("spa"), Some("secret"))
+                .await
+                .is_ok()
+        );
+        for secret in [Some("wrong"), None] {
+            assert!(matches!(
+                authenticate(client(Some("secret")), Some("spa"), secret).await,
+                Err(OAuthError::InvalidClient(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_public_client() {
+        assert!(authenticate(client(None), Some("spa"), None).await.is_ok());
+        // A public client has no secret to send
+        assert!(matches!(
+            authenticate(client(None), Some("spa"), Some("secret")).await,
+            Err(OAuthError::InvalidClient(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_unknown_client() {
+        let db = MockDatabase::new(DbBackend::Postgres)
+            .append_query_results([Vec::<Client>::new()])
+            .into_connection();
+        let req = TestRequest::default().to_http_request();
+        assert!(matches!(
+            authenticate_client(&db, &req, Some("spa"), None).await,
+            Err(OAuthError::InvalidClient(_))
+        ));
+    }
+
+    #[test]
+    fn test_with_query() {
+        assert_eq!(
+            with_query(
+                "https://app.example.com/cb",
+                &[("code", "abc"), ("state", "x y")]
+            ),
+            "https://app.example.com/cb?code=abc&state=x+y"
+        );
+        assert_eq!(
+            with_query("https://app.example.com/cb?tab=1", &[("code", "abc")]),
+            "https://app.example.com/cb?tab=1&code=abc"
+        );
+    }
+
+    #[test]
+    fn test_basic_credentials() {
+        // "my%20app:s3cr%3At" form-encodes "my app" and "s3cr:t"
+        let encoded = STANDARD.encode("my%20app:s3cr%3At");
+        let req = TestRequest::default()
+            .insert_header((header::AUTHORIZATION, format!("Basic {}", encoded)))
+            .to_http_request();
+        assert_eq!(
+            basic_credentials(&req),
+            Some(("my app".to_string(), "s3cr:t".to_string()))
+        );
+
+        let req = TestRequest::default()
+            .insert_header((header::AUTHORIZATION, "Bearer abc"))
+            .to_http_request();
+        assert_eq!(basic_credentials(&req), None);
+    }
+}
@@ -0,0 +1,591 @@
+use super::{ACCESS_TOKEN_PREFIX, OAuthError, REFRESH_TOKEN_PREFIX, requested_scopes};
+use crate::auth::scopes::Scope;
+use crate::config::Settings;
+use crate::error::ApiError;
+use crate::oidc;
+use entity::auth_users::{Entity as AuthUsers, Model as User};
+use entity::oauth_clients::Model as Client;
+use entity::oauth_tokens::Model as OAuthToken;
+use entity::{
+    AuthorizationCodeEntityExt, GrantType, NewOAuthToken, OAuthClientModelExt, OAuthTokenEntityExt,
+    OAuthTokenModelExt, OauthAuthorizationCodes, OauthTokens, has_scopes,
+};
+use sea_orm::{DatabaseConnection, EntityTrait};
+use security::signing::constant_time_compare;
+use security::token::{generate_token, hash_token, pkce_challenge};
+
+/// Tokens handed to a client, in clear text
+#[derive(Debug)]
+pub struct IssuedTokens {
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    /// OpenID Connect ID token, when the `openid` scope was granted
+    pub id_token: Option<String>,
+    pub expires_in: i64,
+    pub scopes: Vec<String>,
+}
+
+/// Issue an access token, with a refresh token when a user granted it and
+/// the client may refresh, and an ID token when they granted `openid`
+async fn issue_tokens(
+    db: &DatabaseConnection,
+    config: &Settings,
+    client: &Client,
+    user: Option<&User>,
+    scopes: Vec<String>,
+    nonce: Option<String>,
+) -> Result<IssuedTokens, OAuthError> {
+    let now = chrono::Utc::now().naive_utc();
+    let access_token = format!("{}{}", ACCESS_TOKEN_PREFIX, generate_token());
+    let refresh_token = (user.is_some() && client.allows_grant(GrantType::RefreshToken))
+        .then(|| format!("{}{}", REFRESH_TOKEN_PREFIX, generate_token()));
+
+    OauthTokens::issue(
+        db,
+        NewOAuthToken {
+            client_id: client.id,
+            user_id: user.map(|user| user.id),
+            access_token_hash: hash_token(&access_token),
+            refresh_token_hash: refresh_token.as_deref().map(hash_token),
+            scopes: scopes.clone(),
+            access_expires_at: now + chrono::Duration::seconds(config.oauth.access_token_ttl),
+            refresh_expires_at: refresh_token
+                .as_ref()
+                .map(|_| now + chrono::Duration::seconds(config.oauth.refresh_token_ttl)),
+        },
+    )
+    .await?;
+
+    let id_token = match user {
+        Some(user) if scopes.iter().any(|scope| scope == oidc::OpenId::NAME) => {
+            Some(oidc::issue_id_token(db, config, &client.client_id, user, &scopes, nonce).await?)
+        }
+        _ => None,
+    };
+
+    Ok(IssuedTokens {
+        access_token,
+        refresh_token,
+        id_token,
+        expires_in: config.oauth.access_token_ttl,
+        scopes,
+    })
+}
+
+/// A PKCE verifier is 43 to 128 unreserved characters (RFC 7636 section 4.1)
+fn is_valid_verifier(verifier: &str) -> bool {
+    (43..=128).contains(&verifier.len())
+        && verifier
+            .bytes()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, b'-' | b'.' | b'_' | b'~'))
+}
+
+async fn active_user(db: &DatabaseConnection, user_id: i32) -> Result<User, OAuthError> {
+    AuthUsers::find_by_id(user_id)
+        .one(db)
+        .await?
+        .filter(|user| user.is_active)
+        .ok_or_else(|| OAuthError::InvalidGrant("The user is no longer active".to_string()))
+}
+
+/// The `authorization_code` grant
+pub async fn exchange_code(
+    db: &DatabaseConnection,
+    config: &Settings,
+    client: &Client,
+    code: &str,
+    redirect_uri: Option<&str>,
+    code_verifier: Option<&str>,
+) -> Result<IssuedTokens, OAuthError> {
+    let invalid = || OAuthError::InvalidGrant("Invalid authorization code".to_string());
+
+    if !client.allows_grant(GrantType::AuthorizationCode) {
+        return Err(OAuthError::UnauthorizedClient(
+            "The client may not use the authorization code grant".to_string(),
+        ));
+    }
+
+    let stored = OauthAuthorizationCodes::find_by_hash(db, &hash_token(code))
+        .await?
+        .filter(|stored| stored.client_id == client.id)
+        .ok_or_else(invalid)?;
+
+    if stored.used_at.is_some() {
+        // A code used twice has likely leaked: revoke what it was exchanged
+        // for (RFC 6749 section 4.1.2)
+        OauthTokens::revoke_all_for_grant(db, stored.user_id, client.id).await?;
+        return Err(invalid());
+    }
+    if stored.expires_at <= chrono::Utc::now().naive_utc() {
+        return Err(OAuthError::InvalidGrant(
+            "Authorization code has expired".to_string(),
+        ));
+    }
+    // The code was always issued for a redirect URI, which must be sent
+    // back (RFC 6749 section 4.1.3)
+    match redirect_uri {
+        None => {
+            return Err(OAuthError::InvalidRequest(
+                "redirect_uri is required".to_string(),
+            ));
+        }
+        Some(uri) if uri != stored.redirect_uri => {
+            return Err(OAuthError::InvalidGrant(
+                "redirect_uri does not match the authorization request".to_string(),
+            ));
+        }
+        Some(_) => {}
+    }
+
+    match (&stored.code_challenge, code_verifier) {
+        (Some(challenge), Some(verifier)) => {
+            if !is_valid_verifier(verifier)
+                || !constant_time_compare(&pkce_challenge(verifier), challenge)
+            {
+                return Err(OAuthError::InvalidGrant(
+                    "code_verifier does not match the code challenge".to_string(),
+                ));
+            }
+        }
+        (Some(_), None) => {
+            return Err(OAuthError::InvalidRequest(
+                "code_verifier is required".to_string(),
+            ));
+        }
+        (None, Some(_)) => {
+            return Err(OAuthError::InvalidGrant(
+                "No code challenge was sent for this code".to_string(),
+            ));
+        }
+        (None, None) => {}
+    }
+
+    if !OauthAuthorizationCodes::consume(db, stored.id).await? {
+        return Err(invalid());
+    }
+    let user = active_user(db, stored.user_id).await?;
+
+    issue_tokens(
+        db,
+        config,
+        client,
+        Some(&user),
+        entity::split_scopes(&stored.scopes),
+        stored.nonce,
+    )
+    .await
+}
+
+/// The `refresh_token` grant: the refresh token is rotated, and may only
+/// narrow the scopes it was granted
+pub async fn refresh_tokens(
+    db: &DatabaseConnection,
+    config: &Settings,
+    client: &Client,
+    refresh_token: &str,
+    scope: Option<&str>,
+) -> Result<IssuedTokens, OAuthError> {
+    let invalid = || OAuthError::InvalidGrant("Invalid refresh token".to_string());
+
+    if !client.allows_grant(GrantType::RefreshToken) {
+        return Err(OAuthError::UnauthorizedClient(
+            "The client may not use the refresh token grant".to_string(),
+        ));
+    }
+
+    let stored = OauthTokens::find_by_refresh_hash(db, &hash_token(refresh_token))
+        .await?
+        .filter(|stored| stored.client_id == client.id)
+        .ok_or_else(invalid)?;
+    let user_id = stored.user_id.ok_or_else(invalid)?;
+
+    if stored.revoked_at.is_some() {
+        // A rotated refresh token came back: revoke the whole grant
+        OauthTokens::revoke_all_for_grant(db, user_id, client.id).await?;
+        return Err(invalid());
+    }
+    if !stored.is_refresh_active() {
+        return Err(OAuthError::InvalidGrant(
+            "Refresh token has expired".to_string(),
+        ));
+    }
+
+    let scopes = match scope.map(str::trim).filter(|s| !s.is_empty()) {
+        Some(scope) => {
+            let requested = requested_scopes(client, Some(scope))?;
+            if !has_scopes(&stored.scopes, &requested) {
+                return Err(OAuthError::InvalidScope(
+                    "The requested scopes exceed those originally granted".to_string(),
+                ));
+            }
+            requested
+        }
+        None => stored.scope_list(),
+    };
+
+    if !stored.revoke(db).await? {
+        return Err(invalid());
+    }
+    let user = active_user(db, user_id).await?;
+
+    issue_tokens(db, config, client, Some(&user), scopes, None).await
+}
+
+/// The `client_credentials` grant, acting for the client itself
+pub async fn client_credentials(
+    db: &DatabaseConnection,
+    config: &Settings,
+    client: &Client,
+    scope: Option<&str>,
+) -> Result<IssuedTokens, OAuthError> {
+    if !client.is_confidential() || !client.allows_grant(GrantType::ClientCredentials) {
+        return Err(OAuthError::UnauthorizedClient(
+            "The client may not use the client credentials grant".to_string(),
+        ));
+    }
+
+    let scopes = requested_scopes(client, scope)?;
+    issue_tokens(db, config, client, None, scopes, None).await
+}
+
+/// The access or refresh token `token`, whichever it is
+pub async fn find_token(
+    db: &DatabaseConnection,
+    token: &str,
+) -> Result<Option<OAuthToken>, OAuthError> {
+    let token_hash = hash_token(token);
+    match OauthTokens::find_by_access_hash(db, &token_hash).await? {
+        Some(stored) => Ok(Some(stored)),
+        None => Ok(OauthTokens::find_by_refresh_hash(db, &token_hash).await?),
+    }
+}
+
+/// An access or refresh token issued to `client`, for revocation
+pub async fn find_client_token(
+    db: &DatabaseConnection,
+    client: &Client,
+    token: &str,
+) -> Result<Option<OAuthToken>, OAuthError> {
+    Ok(find_token(db, token)
+        .await?
+        .filter(|stored| stored.client_id == client.id))
+}
+
+/// Resolve an OAuth access token to the active user who granted it
+pub async fn authenticate(
+    db: &DatabaseConnection,
+    token: &str,
+) -> Result<(User, OAuthToken), ApiError> {
+    let invalid = || ApiError::Unauthorized("Invalid or expired token".to_string());
+
+    let stored = OauthTokens::find_by_access_hash(db, &hash_token(token))
+        .await?
+        .filter(|stored| stored.is_access_active())
+        .ok_or_else(invalid)?;
+    // Client credentials tokens act for no user
+    let user_id = stored.user_id.ok_or_else(invalid)?;
+    let user = AuthUsers::find_by_id(user_id)
+        .one(db)
+        .await?
+        .ok_or_else(invalid)?;
+
+    if !user.is_active {
+        return Err(ApiError::Unauthorized("Account is inactive".to_string()));
+    }
+
+    Ok((user, stored))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use entity::oauth_authorization_codes::Model as AuthorizationCode;
+    use sea_orm::{DbBackend, MockDatabase, MockExecResult, Transaction};
+
+    const REDIRECT_URI: &str = "https://app.example.com/cb";
+    // RFC 7636 appendix B
+    const VERIFIER: &str = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+    const CHALLENGE: &str = "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM";
+
+    fn client(secret: Option<&str>, grant_types: &str) -> Client {
+        Client {
+            id: 1,
+            client_id: "spa".to_string(),
+            client_secret_hash: secret.map(hash_token),
+            name: "SPA".to_string(),
+            redirect_uris: REDIRECT_URI.to_string(),
+            grant_types: grant_types.to_string(),
+            scopes: "profile:read profile:write".to_string(),
+            skip_consent: false,
+            created_at: chrono::Utc::now().naive_utc(),
+        }
+    }
+
+    fn spa() -> Client {
+        client(None, "authorization_code refresh_token")
+    }
+
+    fn code() -> AuthorizationCode {
+        let now = chrono::Utc::now().naive_utc();
+        AuthorizationCode {
+            id: 1,
+            code_hash: hash_token("code"),
+            client_id: 1,
+            user_id: 42,
+            redirect_uri: REDIRECT_URI.to_string(),
+            scopes: "profile:read".to_string(),
+            code_challenge: Some(CHALLENGE.to_string()),
+            expires_at: now + chrono::Duration::minutes(1),
+            used_at: None,
+            created_at: now,
+            nonce: None,
+        }
+    }
+
+    fn token(scopes: &str) -> OAuthToken {
+        let now = chrono::Utc::now().naive_utc();
+        OAuthToken {
+            id: 1,
+            client_id: 1,
+            user_id: Some(42),
+            access_token_hash: hash_token("access"),
+            refresh_token_hash: Some(hash_token("refresh")),
+            scopes: scopes.to_string(),
+            access_expires_at: now + chrono::Duration::minutes(5),
+            refresh_expires_at: Some(now + chrono::Duration::days(1)),
+            revoked_at: None,
+            created_at: now,
+        }
+    }
+
+    fn exec(rows_affected: u64) -> MockExecResult {
+        MockExecResult {
+            last_insert_id: 0,
+            rows_affected,
+        }
+    }
+
+    /// Statements run by exchanging `code` with `redirect_uri` and
+    /// `verifier`, which must fail with an error `expected` accepts
+    async fn refused_exchange(
+        db: MockDatabase,
+        redirect_uri: Option<&str>,
+        verifier: Option<&str>,
+        expected: fn(&OAuthError) -> bool,
+    ) -> Vec<Transaction> {
+        let db = db.into_connection();
+        let config = Settings::new().unwrap();
+
+        let result = exchange_code(&db, &config, &spa(), "code", redirect_uri, verifier).await;
+        let err = result.expect_err("the exchange is refused");
+        assert!(expected(&err), "{:?}", err);
+        db.into_transaction_log()
+    }
+
+    fn with_code(code: AuthorizationCode) -> MockDatabase {
+        MockDatabase::new(DbBackend::Postgres).append_query_results([vec![code]])
+    }
+
+    #[tokio::test]
+    async fn test_exchange_code() {
+        let db = with_code(code())
+            .append_exec_results([exec(1)])
+            .append_query_results([vec![User::fixture()]])
+            .append_query_results([vec![token("profile:read")]])
+            .into_connection();
+        let config = Settings::new().unwrap();
+
+        let tokens = exchange_code(
+            &db,
+            &config,
+            &spa(),
+            "code",
+            Some(REDIRECT_URI),
+            Some(VERIFIER),
+        )
+        .await
+        .unwrap();
+        assert!(tokens.access_token.starts_with(ACCESS_TOKEN_PREFIX));
+        assert!(tokens.refresh_token.is_some());
+        assert_eq!(tokens.scopes, vec!["profile:read".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_exchange_code_checks_pkce() {
+        let log = refused_exchange(
+            with_code(code()),
+            Some(REDIRECT_URI),
+            Some(&"a".repeat(43)),
+            |err| matches!(err, OAuthError::InvalidGrant(_)),
+        )
+        .await;
+        assert_eq!(log.len(), 1, "The code is not consumed: {:?}", log);
+
+        refused_exchange(with_code(code()), Some(REDIRECT_URI), None, |err| {
+            matches!(err, OAuthError::InvalidRequest(_))
+        })
+        .await;
+
+        let without_challenge = AuthorizationCode {
+            code_challenge: None,
+            ..code()
+        };
+        refused_exchange(
+            with_code(without_challenge),
+            Some(REDIRECT_URI),
+            Some(VERIFIER),
+            |err| matches!(err, OAuthError::InvalidGrant(_)),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_reused_code_revokes_the_grant() {
+        let used = AuthorizationCode {
+            used_at: Some(chrono::Utc::now().naive_utc()),
+            ..code()
+        };
+        let log = refused_exchange(
+            with_code(used).append_exec_results([exec(2)]),
+            Some(REDIRECT_URI),
+            Some(VERIFIER),
+            |err| matches!(err, OAuthError::InvalidGrant(_)),
+        )
+        .await;
+
+        assert_eq!(log.len(), 2, "{:?}", log);
+        let revoke = format!("{:?}", log[1]);
+        assert!(revoke.contains(r#"UPDATE \"oauth_tokens\""#), "{}", revoke);
+    }
+
+    #[tokio::test]
+    async fn test_expired_code_is_refused() {
+        let expired = AuthorizationCode {
+            expires_at: chrono::Utc::now().naive_utc() - chrono::Duration::seconds(1),
+            ..code()
+        };
+        let log = refused_exchange(
+            with_code(expired),
+            Some(REDIRECT_URI),
+            Some(VERIFIER),
+            |err| matches!(err, OAuthError::InvalidGrant(_)),
+        )
+        .await;
+        assert_eq!(log.len(), 1, "{:?}", log);
+    }
+
+    #[tokio::test]
+    async fn test_exchange_code_checks_the_redirect_uri() {
+        refused_exchange(
+            with_code(code()),
+            Some("https://evil.example.com/cb"),
+            Some(VERIFIER),
+            |err| matches!(err, OAuthError::InvalidGrant(_)),
+        )
+        .await;
+
+        refused_exchange(with_code(code()), None, Some(VERIFIER), |err| {
+            matches!(err, OAuthError::InvalidRequest(_))
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_refresh_rotates_the_token() {
+        let db = MockDatabase::new(DbBackend::Postgres)
+            .append_query_results([vec![token("profile:read profile:write")]])
+            .append_exec_results([exec(1)])
+            .append_query_results([vec![User::fixture()]])
+            .append_query_results([vec![token("profile:read")]])
+            .into_connection();
+        let config = Settings::new().unwrap();
+
+        let tokens = refresh_tokens(&db, &config, &spa(), "refresh", Some("profile:read"))
+            .await
+            .unwrap();
+        assert_eq!(tokens.scopes, vec!["profile:read".to_string()]);
+        let refresh_token = tokens.refresh_token.expect("a new refresh token");
+        assert!(refresh_token.starts_with(REFRESH_TOKEN_PREFIX));
+        assert_ne!(refresh_token, "refresh");
+
+        let log = format!("{:?}", db.into_transaction_log());
+        assert!(log.contains(r#"UPDATE \"oauth_tokens\""#), "{}", log);
+        assert!(log.contains(r#"INSERT INTO \"oauth_tokens\""#), "{}", log);
+    }
+
+    #[tokio::test]
+    async fn test_reused_refresh_token_revokes_the_grant() {
+        let rotated = OAuthToken {
+            revoked_at: Some(chrono::Utc::now().naive_utc()),
+            ..token("profile:read")
+        };
+        let db = MockDatabase::new(DbBackend::Postgres)
+            .append_query_results([vec![rotated]])
+            .append_exec_results([exec(1)])
+            .into_connection();
+        let config = Settings::new().unwrap();
+
+        let result = refresh_tokens(&db, &config, &spa(), "refresh", None).await;
+        assert!(matches!(result, Err(OAuthError::InvalidGrant(_))));
+
+        let log = db.into_transaction_log();
+        assert_eq!(log.len(), 2, "{:?}", log);
+        let revoke = format!("{:?}", log[1]);
+        assert!(revoke.contains(r#"\"user_id\" = $2"#), "{}", revoke);
+        assert!(revoke.contains(r#"\"revoked_at\" IS NULL"#), "{}", revoke);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_may_only_narrow_the_scopes() {
+        let db = MockDatabase::new(DbBackend::Postgres)
+            .append_query_results([vec![token("profile:read")]])
+            .into_connection();
+        let config = Settings::new().unwrap();
+
+        let result = refresh_tokens(&db, &config, &spa(), "refresh", Some("profile:write")).await;
+        assert!(matches!(result, Err(OAuthError::InvalidScope(_))));
+        assert_eq!(db.into_transaction_log().len(), 1, "Nothing is revoked");
+    }
+
+    #[tokio::test]
+    async fn test_client_credentials() {
+        let service = client(Some("secret"), "client_credentials");
+        let db = MockDatabase::new(DbBackend::Postgres)
+            .append_query_results([vec![OAuthToken {
+                user_id: None,
+                refresh_token_hash: None,
+                refresh_expires_at: None,
+                ..token("profile:read")
+            }]])
+            .into_connection();
+        let config = Settings::new().unwrap();
+
+        let tokens = client_credentials(&db, &config, &service, Some("profile:read"))
+            .await
+            .unwrap();
+        assert!(tokens.refresh_token.is_none());
+        assert!(tokens.id_token.is_none());
+        assert_eq!(tokens.scopes, vec!["profile:read".to_string()]);
+
+        // Public clients, and clients not registered for the grant
+        let db = MockDatabase::new(DbBackend::Postgres).into_connection();
+        for client in [
+            client(None, "client_credentials"),
+            client(Some("secret"), "authorization_code"),
+        ] {
+            let result = client_credentials(&db, &config, &client, None).await;
+            assert!(matches!(result, Err(OAuthError::UnauthorizedClient(_))));
+        }
+    }
+
+    #[test]
+    fn test_is_valid_verifier() {
+        assert!(is_valid_verifier(
+            "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"
+        ));
+        assert!(is_valid_verifier(&"a.~_-".repeat(20)));
+        assert!(!is_valid_verifier("too-short"));
+        assert!(!is_valid_verifier(&"a".repeat(129)));
+        assert!(!is_valid_verifier(&format!("{}+", "a".repeat(43))));
+    }
+}
@@ -0,0 +1,121 @@
+use crate::config::Settings;
+use crate::error::ApiError;
+use apistos::ApiComponent;
+use entity::oidc_signing_keys::Model as SigningKey;
+use entity::{NewSigningKey, OidcSigningKeys, SigningKeyEntityExt};
+use jsonwebtoken::EncodingKey;
+use schemars::JsonSchema;
+use sea_orm::DatabaseConnection;
+use security::encryption::SecretBox;
+use security::rsa_key::RsaKeyPair;
+use security::token::get_random_string;
+use serde::{Deserialize, Serialize};
+
+const SALT: &str = "r-web.oidc.signing-key";
+
+/// Every key is an RSA key used with RS256, which all relying parties
+/// support
+pub const ALGORITHM: &str = "RS256";
+
+const KID_CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+const KID_LEN: usize = 20;
+
+fn secret_box(config: &Settings) -> SecretBox {
+    let key = config
+        .oidc
+        .encryption_key
+        .as_deref()
+        .unwrap_or(&config.auth.secret_key);
+    SecretBox::from_secret(key, SALT)
+}
+
+/// Public half of a signing key, as published at `/jwks.json` (RFC 7517)
+#[derive(Debug, Serialize, Deserialize, JsonSchema, ApiComponent)]
+pub struct Jwk {
+    /// Always `RSA`
+    pub kty: String,
+    /// Always `sig`
+    #[serde(rename = "use")]
+    pub use_: String,
+    pub alg: String,
+    /// Matches the `kid` header of the tokens the key signed
+    pub kid: String,
+    /// Modulus, base64url-encoded
+    pub n: String,
+    /// Exponent, base64url-encoded
+    pub e: String,
+}
+
+impl From<SigningKey> for Jwk {
+    fn from(key: SigningKey) -> Self {
+        Self {
+            kty: "RSA".to_string(),
+            use_: "sig".to_string(),
+            alg: key.algorithm,
+            kid: key.kid,
+            n: key.modulus,
+            e: key.exponent,
+        }
+    }
+}
+
+/// Generate a key pair and make it the signing key. The previous key is
+/// retired but stays published for `oidc.key_retention` seconds.
+pub async fn rotate(db: &DatabaseConnection, config: &Settings) -> Result<SigningKey, ApiError> {
+    // Generating an RSA key takes a while, keep it off the async workers
+    let key_pair = tokio::task::spawn_blocking(RsaKeyPair::generate)
+        .await
+        .map_err(|e| ApiError::InternalServerError(e.to_string()))?;
+
+    Ok(OidcSigningKeys::rotate(
+        db,
+        NewSigningKey {
+            kid: get_random_string(KID_LEN, KID_CHARS),
+            algorithm: ALGORITHM.to_string(),
+            private_key_encrypted: secret_box(config).encrypt(key_pair.to_pem().as_bytes()),
+            modulus: key_pair.modulus(),
+            exponent: key_pair.exponent(),
+        },
+    )
+    .await?)
+}
+
+/// The current signing key with its `kid`, generating the first one on
+/// first use
+pub async fn signing_key(
+    db: &DatabaseConnection,
+    config: &Settings,
+) -> Result<(String, EncodingKey), ApiError> {
+    let key = match OidcSigningKeys::find_active(db).await? {
+        Some(key) => key,
+        None => rotate(db, config).await?,
+    };
+
+    let pem = secret_box(config)
+        .decrypt(&key.private_key_encrypted)
+        .map_err(|e| ApiError::InternalServerError(format!("Signing key {}: {}", key.kid, e)))?;
+    let encoding_key = EncodingKey::from_rsa_pem(&pem)
+        .map_err(|e| ApiError::InternalServerError(format!("Signing key {}: {}", key.kid, e)))?;
+
+    Ok((key.kid, encoding_key))
+}
+
+/// Keys relying parties may see on tokens: the signing key and those
+/// retired less than `oidc.key_retention` seconds ago
+pub async fn published(db: &DatabaseConnection, config: &Settings) -> Result<Vec<Jwk>, ApiError> {
+    if OidcSigningKeys::find_active(db).await?.is_none() {
+        rotate(db, config).await?;
+    }
+
+    let since =
+        chrono::Utc::now().naive_utc() - chrono::Duration::seconds(config.oidc.key_retention);
+    let keys = OidcSigningKeys::list_published(db, since).await?;
+    Ok(keys.into_iter().map(Jwk::from).collect())
+}
+
+/// Delete keys no longer published, returning how many were
+pub async fn prune(db: &DatabaseConnection, config: &Settings) -> Result<u64, ApiError> {
+    let before =
+        chrono::Utc::now().naive_utc() - chrono::Duration::seconds(config.oidc.key_retention);
+    Ok(OidcSigningKeys::delete_retired_before(db, before).await?)
+}
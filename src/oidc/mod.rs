@@ -0,0 +1,153 @@
+//! OpenID Connect provider on top of the OAuth2 authorization server.
+//!
+//! Clients that ask for the `openid` scope get an ID token next to their
+//! access token, signed with RS256 by a key published at `/jwks.json` and
+//! identified by its `kid`. The claims come from `auth_users`, filtered by
+//! the `profile` and `email` scopes; `/oauth/userinfo` returns the same
+//! claims for an access token.
+
+pub mod keys;
+
+use crate::config::Settings;
+use crate::error::ApiError;
+use apistos::ApiComponent;
+use entity::auth_users::Model as User;
+use jsonwebtoken::{Algorithm, Header};
+use schemars::JsonSchema;
+use sea_orm::DatabaseConnection;
+use serde::{Deserialize, Serialize};
+
+crate::scope!(OpenId, "openid");
+
+/// Scopes of OpenID Connect, which clients may be registered for next to
+/// those of personal access tokens
+pub const SCOPES: &[(&str, &str)] = &[
+    ("openid", "Confirm who you are"),
+    ("profile", "See your name and username"),
+    ("email", "See your email address"),
+];
+
+pub fn is_known(scope: &str) -> bool {
+    SCOPES.iter().any(|(name, _)| *name == scope)
+}
+
+/// Claims about a user, as far as the granted scopes allow
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, ApiComponent)]
+pub struct UserClaims {
+    /// User id
+    pub sub: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub given_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub family_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preferred_username: Option<String>,
+    /// Last profile change as a Unix timestamp
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email_verified: Option<bool>,
+}
+
+impl UserClaims {
+    pub fn new(user: &User, scopes: &[String]) -> Self {
+        let granted = |scope: &str| scopes.iter().any(|granted| granted == scope);
+        let profile = granted("profile");
+        let email = granted("email");
+
+        Self {
+            sub: user.id.to_string(),
+            name: profile.then(|| user.full_name()),
+            given_name: user.first_name.clone().filter(|_| profile),
+            family_name: user.last_name.clone().filter(|_| profile),
+            preferred_username: profile.then(|| user.username.clone()),
+            updated_at: profile.then(|| user.updated_at.and_utc().timestamp()),
+            email: email.then(|| user.email.clone()),
+            email_verified: email.then_some(user.is_verified),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IdTokenClaims {
+    pub iss: String,
+    /// `client_id` of the client the token is for
+    pub aud: String,
+    pub iat: i64,
+    pub exp: i64,
+    /// When the user last logged in
+    pub auth_time: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+    #[serde(flatten)]
+    pub user: UserClaims,
+}
+
+/// Sign an ID token for `user`, issued to the client `client_id`
+pub async fn issue_id_token(
+    db: &DatabaseConnection,
+    config: &Settings,
+    client_id: &str,
+    user: &User,
+    scopes: &[String],
+    nonce: Option<String>,
+) -> Result<String, ApiError> {
+    let (kid, key) = keys::signing_key(db, config).await?;
+
+    let now = chrono::Utc::now().timestamp();
+    let claims = IdTokenClaims {
+        iss: config.oidc.issuer.clone(),
+        aud: client_id.to_string(),
+        iat: now,
+        exp: now + config.oidc.id_token_ttl,
+        auth_time: user.last_login.and_utc().timestamp(),
+        nonce,
+        user: UserClaims::new(user, scopes),
+    };
+
+    let mut header = Header::new(Algorithm::RS256);
+    header.kid = Some(kid);
+    jsonwebtoken::encode(&header, &claims, &key)
+        .map_err(|e| ApiError::InternalServerError(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user() -> User {
+        User {
+            id: 7,
+            first_name: Some("Ada".to_string()),
+            last_name: Some("Lovelace".to_string()),
+            ..User::fixture()
+        }
+    }
+
+    #[test]
+    fn test_user_claims_follow_scopes() {
+        let claims = UserClaims::new(&user(), &["openid".to_string()]);
+        assert_eq!(
+            serde_json::to_value(&claims).unwrap(),
+            serde_json::json!({"sub": "7"})
+        );
+
+        let claims = UserClaims::new(
+            &user(),
+            &[
+                "openid".to_string(),
+                "profile".to_string(),
+                "email".to_string(),
+            ],
+        );
+        assert_eq!(claims.name.as_deref(), Some("Ada Lovelace"));
+        assert_eq!(claims.given_name.as_deref(), Some("Ada"));
+        assert_eq!(claims.preferred_username.as_deref(), Some("ada"));
+        assert_eq!(claims.email.as_deref(), Some("ada@example.com"));
+        assert_eq!(claims.email_verified, Some(true));
+    }
+}
@@ -1,11 +1,41 @@
+pub mod oauth;
 pub mod v1;
 
 use apistos::web::ServiceConfig;
 
+/// Unsafe routes that skip CSRF checks. None of them acts on the login of
+/// the session: they take their credentials in the request body or, for
+/// OAuth clients, in HTTP Basic authentication. The magic link and passkey
+/// routes only use the session to bind links to a browser or to keep a
+/// challenge.
+pub const CSRF_EXEMPT: &[&str] = &[
+    "/api/v1/auth/login",
+    "/api/v1/auth/refresh",
+    "/api/v1/auth/register",
+    "/api/v1/auth/verify-email",
+    "/api/v1/auth/verify-email/resend",
+    "/api/v1/auth/password/forgot",
+    "/api/v1/auth/password/reset",
+    "/api/v1/auth/email/confirm",
+    "/api/v1/auth/magic-link",
+    "/api/v1/auth/magic-link/login",
+    "/api/v1/auth/passkey/options",
+    "/api/v1/auth/passkey/login",
+    "/api/v1/auth/mfa/verify",
+    "/api/v1/auth/mfa/passkey/options",
+    "/api/v1/auth/mfa/passkey/verify",
+    "/oauth/token",
+    "/oauth/revoke",
+    "/oauth/introspect",
+];
+
 pub fn configure(cfg: &mut ServiceConfig) {
     // Configure v1 routes
     v1::configure(cfg);
 
+    // OAuth2 and OpenID Connect, outside of the versioned API
+    oauth::configure(cfg);
+
     // Future: Configure v2 routes
     // v2::configure(cfg);
 }
@@ -0,0 +1,24 @@
+use crate::handlers;
+use apistos::web::{ServiceConfig, get, post, scope};
+
+pub fn configure(cfg: &mut ServiceConfig) {
+    cfg.service(
+        scope("/oauth")
+            .route("/authorize", get().to(handlers::oauth::authorize))
+            .route(
+                "/authorize/consent",
+                get().to(handlers::oauth::consent_info),
+            )
+            .route("/authorize/consent", post().to(handlers::oauth::consent))
+            .route("/token", post().to(handlers::oauth::token))
+            .route("/revoke", post().to(handlers::oauth::revoke))
+            .route("/introspect", post().to(handlers::oauth::introspect))
+            .route("/userinfo", get().to(handlers::oidc::userinfo))
+            .route("/userinfo", post().to(handlers::oidc::userinfo)),
+    )
+    .route(
+        "/.well-known/openid-configuration",
+        get().to(handlers::oidc::discovery),
+    )
+    .route("/jwks.json", get().to(handlers::oidc::jwks));
+}
@@ -1,6 +1,144 @@
 use crate::handlers;
-use apistos::web::{ServiceConfig, get, scope};
+use apistos::web::{ServiceConfig, delete, get, patch, post, scope};
 
 pub fn configure(cfg: &mut ServiceConfig) {
-    cfg.service(scope("/api/v1").route("/health", get().to(handlers::health::health_check)));
+    cfg.service(
+        scope("/api/v1")
+            .route("/health", get().to(handlers::health::health_check))
+            .service(
+                scope("/auth")
+                    .route("/login", post().to(handlers::auth::login))
+                    .route("/refresh", post().to(handlers::auth::refresh))
+                    .route("/logout", post().to(handlers::auth::logout))
+                    .route("/csrf", get().to(handlers::session::csrf_token))
+                    .route("/session/login", post().to(handlers::session::login))
+                    .route("/session/logout", post().to(handlers::session::logout))
+                    .route("/register", post().to(handlers::registration::register))
+                    .route(
+                        "/verify-email",
+                        get().to(handlers::registration::verify_email_link),
+                    )
+                    .route(
+                        "/verify-email",
+                        post().to(handlers::registration::verify_email),
+                    )
+                    .route(
+                        "/verify-email/resend",
+                        post().to(handlers::registration::resend_verification),
+                    )
+                    .route(
+                        "/password/forgot",
+                        post().to(handlers::password_reset::forgot_password),
+                    )
+                    .route(
+                        "/password/reset",
+                        post().to(handlers::password_reset::reset_password),
+                    )
+                    .route(
+                        "/email/confirm",
+                        get().to(handlers::profile::confirm_email_link),
+                    )
+                    .route(
+                        "/email/confirm",
+                        post().to(handlers::profile::confirm_email),
+                    )
+                    .route("/magic-link", post().to(handlers::magic_link::request_link))
+                    .route("/magic-link/login", post().to(handlers::magic_link::login))
+                    .route(
+                        "/magic-link/session",
+                        post().to(handlers::magic_link::session_login),
+                    )
+                    .route(
+                        "/passkey/options",
+                        post().to(handlers::passkeys::login_options),
+                    )
+                    .route("/passkey/login", post().to(handlers::passkeys::login))
+                    .route(
+                        "/passkey/session",
+                        post().to(handlers::passkeys::session_login),
+                    )
+                    .route("/mfa", get().to(handlers::mfa::status))
+                    .route("/mfa/verify", post().to(handlers::mfa::verify))
+                    .route("/mfa/totp/setup", post().to(handlers::mfa::setup_totp))
+                    .route("/mfa/totp/confirm", post().to(handlers::mfa::confirm_totp))
+                    .route("/mfa/totp/disable", post().to(handlers::mfa::disable_totp))
+                    .route(
+                        "/mfa/recovery-codes",
+                        post().to(handlers::mfa::regenerate_recovery_codes),
+                    )
+                    .route(
+                        "/mfa/passkey/options",
+                        post().to(handlers::passkeys::mfa_options),
+                    )
+                    .route(
+                        "/mfa/passkey/verify",
+                        post().to(handlers::passkeys::mfa_verify),
+                    )
+                    .route("/sso/providers", get().to(handlers::sso::providers))
+                    .route("/sso/{provider}/login", get().to(handlers::sso::login))
+                    .route("/sso/{provider}/link", get().to(handlers::sso::link))
+                    .route(
+                        "/sso/{provider}/callback",
+                        get().to(handlers::sso::callback),
+                    ),
+            )
+            .service(
+                scope("/me")
+                    .route("", get().to(handlers::profile::retrieve))
+                    .route("", patch().to(handlers::profile::update))
+                    .route("/password", post().to(handlers::profile::change_password))
+                    .route("/email", post().to(handlers::profile::change_email))
+                    .route("/tokens", get().to(handlers::personal_access_tokens::list))
+                    .route(
+                        "/tokens",
+                        post().to(handlers::personal_access_tokens::create),
+                    )
+                    .route(
+                        "/tokens/{id}",
+                        delete().to(handlers::personal_access_tokens::revoke),
+                    )
+                    .route("/sessions", get().to(handlers::user_sessions::list))
+                    .route(
+                        "/sessions",
+                        delete().to(handlers::user_sessions::revoke_others),
+                    )
+                    .route(
+                        "/sessions/{id}",
+                        delete().to(handlers::user_sessions::revoke),
+                    )
+                    .route("/passkeys", get().to(handlers::passkeys::list))
+                    .route("/passkeys", post().to(handlers::passkeys::register))
+                    .route(
+                        "/passkeys/options",
+                        post().to(handlers::passkeys::registration_options),
+                    )
+                    .route("/passkeys/{id}", delete().to(handlers::passkeys::remove))
+                    .route("/identities", get().to(handlers::sso::list_identities))
+                    .route(
+                        "/identities/{id}",
+                        delete().to(handlers::sso::unlink_identity),
+                    ),
+            )
+            .service(
+                scope("/users")
+                    .route("", get().to(handlers::users::list))
+                    .route("", post().to(handlers::users::create))
+                    .route("/{id}", get().to(handlers::users::retrieve))
+                    .route("/{id}", patch().to(handlers::users::update))
+                    .route("/{id}", delete().to(handlers::users::delete))
+                    .route("/{id}/activate", post().to(handlers::users::activate))
+                    .route("/{id}/deactivate", post().to(handlers::users::deactivate))
+                    .route("/{id}/verify", post().to(handlers::users::verify))
+                    .route(
+                        "/{id}/set-password",
+                        post().to(handlers::users::set_password),
+                    ),
+            )
+            .service(
+                scope("/admin")
+                    .route("/lockouts", get().to(handlers::lockouts::list))
+                    .route("/lockouts", delete().to(handlers::lockouts::clear_all))
+                    .route("/lockouts/{id}", delete().to(handlers::lockouts::clear)),
+            ),
+    );
 }
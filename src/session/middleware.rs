@@ -0,0 +1,224 @@
+use super::{KEY_CHARS, SESSION_SALT, Session, SessionState, generate_session_key};
+use crate::config::{SameSite, SessionSettings, Settings};
+use crate::error::ApiError;
+use actix_web::body::MessageBody;
+use actix_web::cookie::{Cookie, SameSite as CookieSameSite, time};
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform, forward_ready};
+use actix_web::{Error, HttpMessage};
+use entity::{DjangoSession, SessionEntityExt};
+use sea_orm::DatabaseConnection;
+use serde_json::{Map, Value};
+use std::future::{Future, Ready, ready};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// Loads the [`Session`] named by the session cookie before each request
+/// and saves it, setting or removing the cookie, after the response.
+///
+/// Works like Django's `SessionMiddleware` with the database backend.
+#[derive(Clone)]
+pub struct SessionMiddleware {
+    inner: Rc<Inner>,
+}
+
+struct Inner {
+    db: Arc<DatabaseConnection>,
+    config: Arc<Settings>,
+}
+
+impl SessionMiddleware {
+    pub fn new(db: Arc<DatabaseConnection>, config: Arc<Settings>) -> Self {
+        Self {
+            inner: Rc::new(Inner { db, config }),
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for SessionMiddleware
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = SessionService<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(SessionService {
+            service: Rc::new(service),
+            inner: self.inner.clone(),
+        }))
+    }
+}
+
+pub struct SessionService<S> {
+    service: Rc<S>,
+    inner: Rc<Inner>,
+}
+
+impl<S, B> Service<ServiceRequest> for SessionService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let inner = self.inner.clone();
+
+        Box::pin(async move {
+            let cookie_key = req
+                .cookie(&inner.config.session.cookie_name)
+                .map(|cookie| cookie.value().to_string());
+
+            let session = Session::from_state(inner.load(cookie_key.as_deref()).await?);
+            req.extensions_mut().insert(session.clone());
+
+            let mut res = service.call(req).await?;
+            inner
+                .persist(&session, cookie_key.is_some(), &mut res)
+                .await?;
+            Ok(res)
+        })
+    }
+}
+
+/// Whether a cookie value can be a key written by Django or by us
+fn is_valid_key(key: &str) -> bool {
+    (8..=40).contains(&key.len()) && key.bytes().all(|c| KEY_CHARS.contains(&c))
+}
+
+impl Inner {
+    fn secret(&self) -> &str {
+        &self.config.auth.secret_key
+    }
+
+    fn settings(&self) -> &SessionSettings {
+        &self.config.session
+    }
+
+    async fn load(&self, cookie_key: Option<&str>) -> Result<SessionState, ApiError> {
+        let Some(key) = cookie_key.filter(|key| is_valid_key(key)) else {
+            return Ok(SessionState::new(None, Map::new()));
+        };
+
+        let Some(stored) = DjangoSession::find_active(&self.db, key).await? else {
+            return Ok(SessionState::new(None, Map::new()));
+        };
+
+        // Data that fails to verify is dropped, as Django does
+        let data = security::signing::loads::<Map<String, Value>>(
+            &stored.session_data,
+            self.secret(),
+            SESSION_SALT,
+            None,
+        )
+        .unwrap_or_default();
+
+        Ok(SessionState::new(Some(stored.session_key), data))
+    }
+
+    async fn persist<B>(
+        &self,
+        session: &Session,
+        had_cookie: bool,
+        res: &mut ServiceResponse<B>,
+    ) -> Result<(), ApiError> {
+        let (key, stale_key, data, changed) = {
+            let mut state = session.state_mut();
+            (
+                state.key.clone(),
+                state.stale_key.take(),
+                std::mem::take(&mut state.data),
+                state.changed,
+            )
+        };
+
+        if let Some(stale_key) = stale_key {
+            DjangoSession::delete_key(&self.db, &stale_key).await?;
+        }
+
+        if data.is_empty() {
+            if changed && let Some(key) = &key {
+                DjangoSession::delete_key(&self.db, key).await?;
+            }
+            if had_cookie {
+                res.response_mut()
+                    .add_removal_cookie(&self.cookie(String::new()))
+                    .map_err(|e| ApiError::InternalServerError(e.to_string()))?;
+            }
+            return Ok(());
+        }
+
+        if !changed && !self.settings().save_every_request {
+            return Ok(());
+        }
+
+        let session_data = security::signing::dumps(&data, self.secret(), SESSION_SALT, true)
+            .map_err(|e| ApiError::InternalServerError(e.to_string()))?;
+        let expire_date =
+            chrono::Utc::now().naive_utc() + chrono::Duration::seconds(self.settings().cookie_age);
+
+        let key = match key {
+            Some(key) => {
+                DjangoSession::save(&self.db, &key, session_data, expire_date).await?;
+                key
+            }
+            None => loop {
+                let key = generate_session_key();
+                if DjangoSession::create(&self.db, &key, session_data.clone(), expire_date).await? {
+                    break key;
+                }
+            },
+        };
+
+        let mut cookie = self.cookie(key);
+        if !self.settings().expire_at_browser_close {
+            cookie.set_max_age(time::Duration::seconds(self.settings().cookie_age));
+        }
+        res.response_mut()
+            .add_cookie(&cookie)
+            .map_err(|e| ApiError::InternalServerError(e.to_string()))?;
+        Ok(())
+    }
+
+    fn cookie(&self, value: String) -> Cookie<'static> {
+        let settings = self.settings();
+        let mut cookie = Cookie::build(settings.cookie_name.clone(), value)
+            .path(settings.cookie_path.clone())
+            .secure(settings.cookie_secure)
+            .http_only(settings.cookie_httponly)
+            .same_site(match settings.cookie_samesite {
+                SameSite::Lax => CookieSameSite::Lax,
+                SameSite::Strict => CookieSameSite::Strict,
+                SameSite::None => CookieSameSite::None,
+            })
+            .finish();
+        if let Some(domain) = &settings.cookie_domain {
+            cookie.set_domain(domain.clone());
+        }
+        cookie
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_valid_key() {
+        assert!(is_valid_key(&generate_session_key()));
+        assert!(is_valid_key("abcd1234"));
+        assert!(!is_valid_key("short"));
+        assert!(!is_valid_key("ABCD1234"));
+        assert!(!is_valid_key(&"a".repeat(41)));
+    }
+}
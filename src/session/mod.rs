@@ -0,0 +1,203 @@
+//! Server-side cookie sessions stored in Django's `django_session` table.
+//!
+//! Session data is written the way Django's database backend writes it
+//! (`signing.dumps` with the JSON serializer and compression), so a Django
+//! service sharing the database and `SECRET_KEY` can read and write the same
+//! sessions.
+
+mod middleware;
+
+pub use middleware::SessionMiddleware;
+
+use crate::error::ApiError;
+use actix_web::dev::Payload;
+use actix_web::{FromRequest, HttpMessage, HttpRequest};
+use apistos::ApiComponent;
+use apistos::Schema;
+use apistos::reference_or::ReferenceOr;
+use security::token::get_random_string;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use std::cell::RefCell;
+use std::future::{Ready, ready};
+use std::rc::Rc;
+
+/// Salt of Django's `django.contrib.sessions.backends.db.SessionStore`
+pub const SESSION_SALT: &str = "django.contrib.sessions.SessionStore";
+
+const KEY_CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789";
+const KEY_LENGTH: usize = 32;
+
+/// A new random session key, like Django's `_get_new_session_key`
+pub fn generate_session_key() -> String {
+    get_random_string(KEY_LENGTH, KEY_CHARS)
+}
+
+#[derive(Debug)]
+pub(crate) struct SessionState {
+    /// Key of the stored session; `None` until the session is first saved
+    pub(crate) key: Option<String>,
+    /// Key to delete on save after the key was cycled
+    pub(crate) stale_key: Option<String>,
+    pub(crate) data: Map<String, Value>,
+    pub(crate) changed: bool,
+}
+
+impl SessionState {
+    pub(crate) fn new(key: Option<String>, data: Map<String, Value>) -> Self {
+        Self {
+            key,
+            stale_key: None,
+            data,
+            changed: false,
+        }
+    }
+
+    /// Move the key aside so the session is saved under a new one
+    fn retire_key(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.stale_key = Some(key);
+        }
+    }
+}
+
+/// The session of the current request, loaded by [`SessionMiddleware`].
+///
+/// Changes are saved when the response is sent. A session that was never
+/// written to is not stored and sets no cookie.
+#[derive(Clone)]
+pub struct Session(Rc<RefCell<SessionState>>);
+
+impl Session {
+    pub(crate) fn from_state(state: SessionState) -> Self {
+        Session(Rc::new(RefCell::new(state)))
+    }
+
+    #[cfg(test)]
+    pub(crate) fn state(&self) -> std::cell::Ref<'_, SessionState> {
+        self.0.borrow()
+    }
+
+    pub(crate) fn state_mut(&self) -> std::cell::RefMut<'_, SessionState> {
+        self.0.borrow_mut()
+    }
+
+    /// Key of the stored session, if it was saved before
+    pub fn session_key(&self) -> Option<String> {
+        self.0.borrow().key.clone()
+    }
+
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let state = self.0.borrow();
+        serde_json::from_value(state.data.get(key)?.clone()).ok()
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.0.borrow().data.contains_key(key)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.borrow().data.is_empty()
+    }
+
+    pub fn insert<T: Serialize>(&self, key: &str, value: T) -> Result<(), serde_json::Error> {
+        let value = serde_json::to_value(value)?;
+        let mut state = self.0.borrow_mut();
+        state.data.insert(key.to_string(), value);
+        state.changed = true;
+        Ok(())
+    }
+
+    pub fn remove(&self, key: &str) -> Option<Value> {
+        let mut state = self.0.borrow_mut();
+        let removed = state.data.remove(key);
+        if removed.is_some() {
+            state.changed = true;
+        }
+        removed
+    }
+
+    /// Remove every value but keep the session key
+    pub fn clear(&self) {
+        let mut state = self.0.borrow_mut();
+        state.data.clear();
+        state.changed = true;
+    }
+
+    /// Remove every value and delete the stored session, like logging out.
+    /// Values written afterwards go to a new session.
+    pub fn flush(&self) {
+        let mut state = self.0.borrow_mut();
+        state.data.clear();
+        state.retire_key();
+        state.changed = true;
+    }
+
+    /// Keep the values under a new key, so a key known before login cannot
+    /// be used to hijack the session afterwards
+    pub fn cycle_key(&self) {
+        let mut state = self.0.borrow_mut();
+        state.retire_key();
+        state.changed = true;
+    }
+}
+
+impl FromRequest for Session {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(req.extensions().get::<Session>().cloned().ok_or_else(|| {
+            ApiError::InternalServerError("SessionMiddleware is not installed".to_string())
+        }))
+    }
+}
+
+impl ApiComponent for Session {
+    fn child_schemas() -> Vec<(String, ReferenceOr<Schema>)> {
+        vec![]
+    }
+
+    fn schema() -> Option<(String, ReferenceOr<Schema>)> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cycle_key_keeps_data_and_retires_old_key() {
+        let mut data = Map::new();
+        data.insert("cart".to_string(), Value::from(3));
+        let session = Session::from_state(SessionState::new(Some("old".to_string()), data));
+
+        session.cycle_key();
+        assert_eq!(session.session_key(), None);
+        assert_eq!(session.get::<i32>("cart"), Some(3));
+        assert_eq!(session.state().stale_key.as_deref(), Some("old"));
+        assert!(session.state().changed);
+    }
+
+    #[test]
+    fn test_flush_retires_key() {
+        let mut data = Map::new();
+        data.insert("_auth_user_id".to_string(), Value::from("42"));
+        let session = Session::from_state(SessionState::new(Some("old".to_string()), data));
+
+        session.flush();
+        assert!(session.is_empty());
+        assert_eq!(session.session_key(), None);
+        assert_eq!(session.state().stale_key.as_deref(), Some("old"));
+    }
+
+    #[test]
+    fn test_generated_keys_fit_django_session() {
+        let key = generate_session_key();
+        assert_eq!(key.len(), KEY_LENGTH);
+        assert!(key.bytes().all(|c| KEY_CHARS.contains(&c)));
+        assert_ne!(key, generate_session_key());
+    }
+}
@@ -0,0 +1,527 @@
+//! Logging in with external OpenID Connect providers, such as a corporate
+//! IdP.
+//!
+//! `/auth/sso/{provider}/login` keeps a random state, nonce and PKCE
+//! verifier in the cookie session and sends the browser to the provider.
+//! The callback checks the state, exchanges the code, and validates the ID
+//! token against the provider's JWKS before logging the linked user into
+//! the session. Accounts with two-factor authentication are refused unless
+//! the provider is `trusted_for_mfa`, as the callback cannot ask for a code.
+//!
+//! An identity nobody is linked to yet is linked to the account with the
+//! same email address when `link_by_email` is set and both the provider and
+//! the account verified it, or gets a new account when `auto_provision` is
+//! set. Logged-in users link identities
+//! themselves through `/auth/sso/{provider}/link`.
+
+pub mod provider;
+
+pub use provider::{IdentityClaims, ProviderMetadata, SsoClient};
+
+use crate::auth::mfa::{self, MfaStatus};
+use crate::config::{SsoProviderSettings, SsoSettings};
+use crate::error::ApiError;
+use crate::oauth::with_query;
+use crate::session::Session;
+use crate::state::AppState;
+use apistos::ApiComponent;
+use entity::auth_users::Model as User;
+use entity::{
+    AuthUserEntityExt, AuthUsers, CreateUserData, NewUserIdentity, UserIdentities,
+    UserIdentityEntityExt, UserIdentityModelExt,
+};
+use schemars::JsonSchema;
+use sea_orm::EntityTrait;
+use security::signing::constant_time_compare;
+use security::token::{generate_token, get_random_string, pkce_challenge};
+use serde::{Deserialize, Serialize};
+
+/// Session data key of the login in progress
+pub const STATE_SESSION_KEY: &str = "_sso_state";
+
+/// Django's `auth_user.username` limit
+const USERNAME_MAX_LENGTH: usize = 150;
+
+const SUFFIX_CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789";
+const SUFFIX_LEN: usize = 6;
+
+/// A login at a provider, from the redirect until the callback
+#[derive(Debug, Serialize, Deserialize)]
+struct PendingLogin {
+    provider: String,
+    state: String,
+    nonce: String,
+    code_verifier: String,
+    /// User linking the identity, rather than logging in with it
+    link_user_id: Option<i32>,
+    /// Unix timestamp of the redirect
+    started_at: i64,
+}
+
+/// Query parameters the provider redirects back with
+#[derive(Debug, Deserialize, JsonSchema, ApiComponent)]
+pub struct Callback {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    /// Set instead of `code` when the login failed or was refused
+    pub error: Option<String>,
+    pub error_description: Option<String>,
+}
+
+/// Where a finished login leaves the browser
+pub enum Outcome {
+    /// Log this user into the session
+    Login(User),
+    /// The identity was linked to the user logged into the session
+    Linked,
+}
+
+pub fn find_provider<'a>(
+    config: &'a SsoSettings,
+    name: &str,
+) -> Result<&'a SsoProviderSettings, ApiError> {
+    config
+        .provider(name)
+        .ok_or_else(|| ApiError::NotFound("Unknown provider".to_string()))
+}
+
+/// Remember a new login in the session, returning the provider URL to send
+/// the browser to
+pub async fn start(
+    app_state: &AppState,
+    session: &Session,
+    provider: &SsoProviderSettings,
+    link_user_id: Option<i32>,
+) -> Result<String, ApiError> {
+    let metadata = app_state.sso.metadata(provider).await?;
+
+    let pending = PendingLogin {
+        provider: provider.name.clone(),
+        state: generate_token(),
+        nonce: generate_token(),
+        code_verifier: generate_token(),
+        link_user_id,
+        started_at: chrono::Utc::now().timestamp(),
+    };
+    session
+        .insert(STATE_SESSION_KEY, &pending)
+        .map_err(|e| ApiError::InternalServerError(e.to_string()))?;
+
+    let scope = provider.scopes.join(" ");
+    let code_challenge = pkce_challenge(&pending.code_verifier);
+    Ok(with_query(
+        &metadata.authorization_endpoint,
+        &[
+            ("response_type", "code"),
+            ("client_id", &provider.client_id),
+            ("redirect_uri", &provider.redirect_uri),
+            ("scope", &scope),
+            ("state", &pending.state),
+            ("nonce", &pending.nonce),
+            ("code_challenge", &code_challenge),
+            ("code_challenge_method", "S256"),
+        ],
+    ))
+}
+
+/// Check the callback against the login in the session and resolve the
+/// identity it returns. The login is taken out of the session, so a
+/// callback is only accepted once.
+pub async fn finish(
+    app_state: &AppState,
+    session: &Session,
+    provider: &SsoProviderSettings,
+    callback: &Callback,
+) -> Result<Outcome, ApiError> {
+    let pending = session
+        .remove(STATE_SESSION_KEY)
+        .and_then(|value| serde_json::from_value::<PendingLogin>(value).ok())
+        .filter(|pending| pending.provider == provider.name)
+        .ok_or_else(|| {
+            ApiError::BadRequest("No login with this provider is pending".to_string())
+        })?;
+
+    let state_matches = callback
+        .state
+        .as_deref()
+        .is_some_and(|state| constant_time_compare(state, &pending.state));
+    if !state_matches {
+        return Err(ApiError::BadRequest("State mismatch".to_string()));
+    }
+    if chrono::Utc::now().timestamp() - pending.started_at > app_state.config.sso.state_ttl {
+        return Err(ApiError::BadRequest(
+            "The login took too long, please try again".to_string(),
+        ));
+    }
+    if let Some(error) = &callback.error {
+        return Err(ApiError::Unauthorized(format!(
+            "Provider {} refused the login: {}",
+            provider.name,
+            callback.error_description.as_deref().unwrap_or(error)
+        )));
+    }
+    let code = callback
+        .code
+        .as_deref()
+        .ok_or_else(|| ApiError::BadRequest("code is required".to_string()))?;
+
+    let id_token = app_state
+        .sso
+        .exchange_code(provider, code, &pending.code_verifier)
+        .await?;
+    let claims = app_state
+        .sso
+        .validate_id_token(provider, &id_token, &pending.nonce)
+        .await?;
+
+    match pending.link_user_id {
+        Some(user_id) => {
+            // The browser must still be logged in as the user who started
+            if crate::auth::session::session_user_id(session) != Some(user_id) {
+                return Err(ApiError::Forbidden(
+                    "The session changed during the login".to_string(),
+                ));
+            }
+            link(app_state, provider, &claims, user_id).await?;
+            Ok(Outcome::Linked)
+        }
+        None => {
+            let user = resolve_user(app_state, provider, &claims).await?;
+            check_second_factor(app_state, provider, &user).await?;
+            Ok(Outcome::Login(user))
+        }
+    }
+}
+
+async fn link(
+    app_state: &AppState,
+    provider: &SsoProviderSettings,
+    claims: &IdentityClaims,
+    user_id: i32,
+) -> Result<(), ApiError> {
+    let db = &app_state.db;
+    match UserIdentities::find_by_subject(db, &provider.name, &claims.sub).await? {
+        Some(identity) if identity.user_id == user_id => {
+            identity.touch(db, claims.email.clone()).await?;
+        }
+        Some(_) => {
+            return Err(ApiError::Forbidden(
+                "This identity is linked to another account".to_string(),
+            ));
+        }
+        None => {
+            UserIdentities::link(db, new_identity(provider, claims, user_id)).await?;
+        }
+    }
+    Ok(())
+}
+
+fn new_identity(
+    provider: &SsoProviderSettings,
+    claims: &IdentityClaims,
+    user_id: i32,
+) -> NewUserIdentity {
+    NewUserIdentity {
+        user_id,
+        provider: provider.name.clone(),
+        subject: claims.sub.clone(),
+        email: claims.email.clone(),
+    }
+}
+
+/// The active user the identity belongs to, linking or provisioning one
+/// as the provider's settings allow
+async fn resolve_user(
+    app_state: &AppState,
+    provider: &SsoProviderSettings,
+    claims: &IdentityClaims,
+) -> Result<User, ApiError> {
+    let db = &app_state.db;
+
+    let user = match UserIdentities::find_by_subject(db, &provider.name, &claims.sub).await? {
+        Some(identity) => {
+            let identity = identity.touch(db, claims.email.clone()).await?;
+            AuthUsers::find_by_id(identity.user_id)
+                .one(db.as_ref())
+                .await?
+                .ok_or_else(|| ApiError::Unauthorized("Account not found".to_string()))?
+        }
+        None => {
+            let email = claims
+                .verified_email()
+                .filter(|email| provider.allows_email(email));
+            let existing = match email {
+                Some(email) if provider.link_by_email => {
+                    AuthUsers::find_by_email(db, email).await?
+                }
+                _ => None,
+            };
+
+            let user = match (existing, email) {
+                // Otherwise whoever registered the address first, here or
+                // at the provider, would get into the other's account
+                (Some(user), _) if !user.is_verified => {
+                    return Err(ApiError::Forbidden(
+                        "Verify your email address, then log in and link this identity".to_string(),
+                    ));
+                }
+                (Some(user), _) => user,
+                (None, Some(email)) if provider.auto_provision => {
+                    provision(app_state, claims, email).await?
+                }
+                _ => {
+                    return Err(ApiError::Forbidden(
+                        "No account is linked to this identity".to_string(),
+                    ));
+                }
+            };
+            UserIdentities::link(db, new_identity(provider, claims, user.id)).await?;
+            user
+        }
+    };
+
+    if !user.is_active {
+        return Err(ApiError::Forbidden("Account is inactive".to_string()));
+    }
+    Ok(user)
+}
+
+/// Refuse users whose second factor the provider's login cannot replace.
+/// The callback is a browser redirect and has no way to ask for a code.
+async fn check_second_factor(
+    app_state: &AppState,
+    provider: &SsoProviderSettings,
+    user: &User,
+) -> Result<(), ApiError> {
+    if provider.trusted_for_mfa {
+        return Ok(());
+    }
+    match mfa::status(&app_state.db, &app_state.config, user).await? {
+        MfaStatus::NotRequired => Ok(()),
+        MfaStatus::Required => Err(ApiError::Forbidden(format!(
+            "Log in with your password and second factor; {} cannot replace it",
+            provider.display_name
+        ))),
+        MfaStatus::EnrollmentRequired => Err(ApiError::Forbidden(
+            "Two-factor authentication must be set up first".to_string(),
+        )),
+    }
+}
+
+/// Create an account for a new identity. It has no password; the user
+/// may set one through a password reset.
+async fn provision(
+    app_state: &AppState,
+    claims: &IdentityClaims,
+    email: &str,
+) -> Result<User, ApiError> {
+    let db = &app_state.db;
+    let base = username_base(claims, email);
+    let mut username = base.clone();
+    while AuthUsers::username_exists(db, &username).await? {
+        username = with_suffix(&base, &get_random_string(SUFFIX_LEN, SUFFIX_CHARS));
+    }
+
+    Ok(AuthUsers::create_user_without_password(
+        db,
+        CreateUserData {
+            email: email.to_string(),
+            username,
+            first_name: claims.given_name.clone(),
+            last_name: claims.family_name.clone(),
+            // Only addresses the provider verified are provisioned
+            is_verified: true,
+            ..Default::default()
+        },
+    )
+    .await?)
+}
+
+/// Username for a provisioned account: the provider's preferred username
+/// or else the email's local part, limited to the characters Django
+/// allows (letters, digits and `@.+-_`)
+fn username_base(claims: &IdentityClaims, email: &str) -> String {
+    let candidate = claims
+        .preferred_username
+        .as_deref()
+        .unwrap_or_else(|| email.split('@').next().unwrap_or_default());
+    let username: String = candidate
+        .chars()
+        .filter(|c| c.is_alphanumeric() || "@.+-_".contains(*c))
+        .take(USERNAME_MAX_LENGTH)
+        .collect();
+
+    if username.is_empty() {
+        "user".to_string()
+    } else {
+        username
+    }
+}
+
+fn with_suffix(base: &str, suffix: &str) -> String {
+    let keep = USERNAME_MAX_LENGTH - suffix.len() - 1;
+    format!("{}-{}", base.chars().take(keep).collect::<String>(), suffix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::app_state;
+    use entity::auth_user_totp::Model as TotpDevice;
+    use entity::user_identities::Model as UserIdentity;
+    use sea_orm::{DbBackend, MockDatabase, Value};
+    use std::collections::BTreeMap;
+
+    fn provider(trusted_for_mfa: bool) -> SsoProviderSettings {
+        SsoProviderSettings {
+            name: "corp".to_string(),
+            display_name: "Example Corp".to_string(),
+            issuer: "https://login.example.com".to_string(),
+            client_id: "r-web".to_string(),
+            client_secret: None,
+            redirect_uri: "http://localhost/api/v1/auth/sso/corp/callback".to_string(),
+            scopes: vec!["openid".to_string()],
+            auto_provision: false,
+            link_by_email: true,
+            trusted_for_mfa,
+            allowed_domains: Vec::new(),
+        }
+    }
+
+    fn local_user(is_verified: bool) -> User {
+        User {
+            email: "ada.lovelace@example.com".to_string(),
+            is_verified,
+            ..User::fixture()
+        }
+    }
+
+    /// Outcome of resolving an identity nobody is linked to, the account
+    /// with its address answering `find_by_email`, and the queries that ran
+    async fn link_by_email(
+        email_verified: Option<bool>,
+        existing: Vec<User>,
+    ) -> (Result<User, ApiError>, usize) {
+        let now = chrono::Utc::now().naive_utc();
+        let db = MockDatabase::new(DbBackend::Postgres)
+            .append_query_results([Vec::<UserIdentity>::new()])
+            .append_query_results([existing])
+            .append_query_results([vec![UserIdentity {
+                id: 1,
+                user_id: 42,
+                provider: "corp".to_string(),
+                subject: "248289761001".to_string(),
+                email: Some("ada.lovelace@example.com".to_string()),
+                created_at: now,
+                last_login_at: Some(now),
+            }]])
+            .into_connection();
+        let (state, _) = app_state(db);
+        let claims = IdentityClaims {
+            email_verified,
+            ..claims(None)
+        };
+
+        let result = resolve_user(&state, &provider(false), &claims).await;
+        let queries = std::sync::Arc::try_unwrap(state.db)
+            .ok()
+            .unwrap()
+            .into_transaction_log()
+            .len();
+        (result, queries)
+    }
+
+    #[tokio::test]
+    async fn test_link_by_email_needs_both_sides_verified() {
+        // The provider does not vouch for the address
+        for email_verified in [None, Some(false)] {
+            let (result, queries) = link_by_email(email_verified, vec![local_user(true)]).await;
+            assert!(matches!(result, Err(ApiError::Forbidden(_))));
+            assert_eq!(queries, 1);
+        }
+
+        // The local account never proved it owns the address
+        let (result, queries) = link_by_email(Some(true), vec![local_user(false)]).await;
+        assert!(matches!(result, Err(ApiError::Forbidden(_))));
+        assert_eq!(queries, 2);
+
+        let (result, queries) = link_by_email(Some(true), vec![local_user(true)]).await;
+        assert_eq!(result.unwrap().id, 42);
+        assert_eq!(queries, 3);
+    }
+
+    /// Whether `user`, with a confirmed TOTP device or `passkeys`, may log
+    /// in with a provider
+    async fn sso_login_allowed(user: User, totp: bool, passkeys: i64, trusted: bool) -> bool {
+        let now = chrono::Utc::now().naive_utc();
+        let devices: Vec<TotpDevice> = if totp {
+            vec![TotpDevice {
+                id: 1,
+                user_id: user.id,
+                secret_encrypted: String::new(),
+                confirmed_at: Some(now),
+                last_used_step: None,
+                created_at: now,
+            }]
+        } else {
+            Vec::new()
+        };
+        let db = MockDatabase::new(DbBackend::Postgres)
+            .append_query_results([devices])
+            .append_query_results([vec![BTreeMap::from([(
+                "num_items",
+                Value::BigInt(Some(passkeys)),
+            )])]])
+            .into_connection();
+        let (state, _) = app_state(db);
+
+        check_second_factor(&state, &provider(trusted), &user)
+            .await
+            .is_ok()
+    }
+
+    #[tokio::test]
+    async fn test_second_factor_unless_provider_is_trusted() {
+        let staff = User {
+            is_staff: true,
+            ..User::fixture()
+        };
+
+        assert!(sso_login_allowed(User::fixture(), false, 0, false).await);
+        assert!(!sso_login_allowed(User::fixture(), true, 0, false).await);
+        assert!(!sso_login_allowed(User::fixture(), false, 1, false).await);
+        assert!(!sso_login_allowed(staff.clone(), false, 0, false).await);
+
+        assert!(sso_login_allowed(User::fixture(), true, 0, true).await);
+        assert!(sso_login_allowed(staff, false, 0, true).await);
+    }
+
+    fn claims(preferred_username: Option<&str>) -> IdentityClaims {
+        IdentityClaims {
+            sub: "248289761001".to_string(),
+            nonce: None,
+            email: Some("ada.lovelace@example.com".to_string()),
+            email_verified: Some(true),
+            preferred_username: preferred_username.map(str::to_string),
+            given_name: None,
+            family_name: None,
+        }
+    }
+
+    #[test]
+    fn test_username_base() {
+        let email = "ada.lovelace@example.com";
+        assert_eq!(username_base(&claims(None), email), "ada.lovelace");
+        assert_eq!(username_base(&claims(Some("ada")), email), "ada");
+        assert_eq!(
+            username_base(&claims(Some("Ada Lovelace (HR)")), email),
+            "AdaLovelaceHR"
+        );
+        assert_eq!(username_base(&claims(Some("()")), email), "user");
+
+        let long = "a".repeat(200);
+        assert_eq!(username_base(&claims(Some(&long)), email).len(), 150);
+        assert_eq!(with_suffix(&long, "x1y2z3").len(), 150);
+        assert!(with_suffix("ada", "x1y2z3").ends_with("ada-x1y2z3"));
+    }
+}
@@ -0,0 +1,433 @@
+use crate::config::SsoProviderSettings;
+use crate::error::ApiError;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Algorithms accepted on ID tokens. Symmetric ones are left out: they
+/// would be keyed with the client secret, not the published keys.
+const ALGORITHMS: &[Algorithm] = &[
+    Algorithm::RS256,
+    Algorithm::RS384,
+    Algorithm::RS512,
+    Algorithm::PS256,
+    Algorithm::PS384,
+    Algorithm::PS512,
+    Algorithm::ES256,
+    Algorithm::ES384,
+];
+
+/// What this service uses of a provider's discovery document
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+/// Claims of a provider's ID token
+#[derive(Debug, Clone, Deserialize)]
+pub struct IdentityClaims {
+    /// The user's id at the provider
+    pub sub: String,
+    #[serde(default)]
+    pub nonce: Option<String>,
+    #[serde(default)]
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: Option<bool>,
+    #[serde(default)]
+    pub preferred_username: Option<String>,
+    #[serde(default)]
+    pub given_name: Option<String>,
+    #[serde(default)]
+    pub family_name: Option<String>,
+}
+
+impl IdentityClaims {
+    /// The email address, if the provider vouches for it
+    pub fn verified_email(&self) -> Option<&str> {
+        self.email
+            .as_deref()
+            .filter(|_| self.email_verified == Some(true))
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: Option<String>,
+}
+
+struct Cached {
+    metadata: ProviderMetadata,
+    keys: JwkSet,
+    fetched_at: Instant,
+}
+
+/// HTTP client for the configured providers. Discovery documents and
+/// signing keys are cached for `sso.metadata_ttl` seconds, and the keys
+/// are fetched again when a token names one that is not known yet.
+pub struct SsoClient {
+    http: reqwest::Client,
+    metadata_ttl: Duration,
+    cache: Mutex<HashMap<String, Arc<Cached>>>,
+}
+
+fn unavailable(provider: &SsoProviderSettings, err: impl std::fmt::Display) -> ApiError {
+    ApiError::ServiceUnavailable(format!("Provider {} failed: {}", provider.name, err))
+}
+
+impl SsoClient {
+    pub fn new(metadata_ttl: Duration) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .expect("HTTP client settings are valid");
+
+        Self {
+            http,
+            metadata_ttl,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    async fn get_json<T: serde::de::DeserializeOwned>(
+        &self,
+        provider: &SsoProviderSettings,
+        url: &str,
+    ) -> Result<T, ApiError> {
+        self.http
+            .get(url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| unavailable(provider, e))?
+            .json()
+            .await
+            .map_err(|e| unavailable(provider, e))
+    }
+
+    async fn fetch(&self, provider: &SsoProviderSettings) -> Result<Arc<Cached>, ApiError> {
+        let url = format!(
+            "{}/.well-known/openid-configuration",
+            provider.issuer.trim_end_matches('/')
+        );
+        let metadata: ProviderMetadata = self.get_json(provider, &url).await?;
+        if metadata.issuer != provider.issuer {
+            return Err(unavailable(
+                provider,
+                format!("discovery names issuer {}", metadata.issuer),
+            ));
+        }
+        let keys = self.get_json(provider, &metadata.jwks_uri).await?;
+
+        let cached = Arc::new(Cached {
+            metadata,
+            keys,
+            fetched_at: Instant::now(),
+        });
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(provider.name.clone(), cached.clone());
+        Ok(cached)
+    }
+
+    async fn cached(&self, provider: &SsoProviderSettings) -> Result<Arc<Cached>, ApiError> {
+        let cached = self.cache.lock().unwrap().get(&provider.name).cloned();
+        match cached {
+            Some(cached) if cached.fetched_at.elapsed() < self.metadata_ttl => Ok(cached),
+            _ => self.fetch(provider).await,
+        }
+    }
+
+    pub async fn metadata(
+        &self,
+        provider: &SsoProviderSettings,
+    ) -> Result<ProviderMetadata, ApiError> {
+        Ok(self.cached(provider).await?.metadata.clone())
+    }
+
+    /// Exchange an authorization code for the provider's ID token
+    pub async fn exchange_code(
+        &self,
+        provider: &SsoProviderSettings,
+        code: &str,
+        code_verifier: &str,
+    ) -> Result<String, ApiError> {
+        let metadata = self.metadata(provider).await?;
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &provider.redirect_uri),
+            ("code_verifier", code_verifier),
+        ];
+        let mut request = self.http.post(&metadata.token_endpoint);
+        match &provider.client_secret {
+            Some(secret) => request = request.basic_auth(&provider.client_id, Some(secret)),
+            None => form.push(("client_id", &provider.client_id)),
+        }
+
+        let response = request
+            .form(&form)
+            .send()
+            .await
+            .map_err(|e| unavailable(provider, e))?;
+        if !response.status().is_success() {
+            // The code was refused, most likely used or expired
+            return Err(ApiError::Unauthorized(format!(
+                "Provider {} refused the authorization code",
+                provider.name
+            )));
+        }
+        let tokens: TokenResponse = response
+            .json()
+            .await
+            .map_err(|e| unavailable(provider, e))?;
+
+        tokens
+            .id_token
+            .ok_or_else(|| unavailable(provider, "no ID token was returned"))
+    }
+
+    /// Check the signature, issuer, audience, expiry and nonce of an ID
+    /// token
+    pub async fn validate_id_token(
+        &self,
+        provider: &SsoProviderSettings,
+        id_token: &str,
+        nonce: &str,
+    ) -> Result<IdentityClaims, ApiError> {
+        let invalid =
+            |reason: &str| ApiError::Unauthorized(format!("Invalid ID token: {}", reason));
+
+        let header = jsonwebtoken::decode_header(id_token).map_err(|_| invalid("malformed"))?;
+        if !ALGORITHMS.contains(&header.alg) {
+            return Err(invalid("unsupported algorithm"));
+        }
+        let kid = header.kid.ok_or_else(|| invalid("no key id"))?;
+
+        let mut cached = self.cached(provider).await?;
+        if cached.keys.find(&kid).is_none() {
+            // The provider may have rotated its keys since they were cached
+            cached = self.fetch(provider).await?;
+        }
+        let jwk = cached
+            .keys
+            .find(&kid)
+            .ok_or_else(|| invalid("unknown key"))?;
+        let key = DecodingKey::from_jwk(jwk).map_err(|_| invalid("unusable key"))?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&cached.metadata.issuer]);
+        validation.set_audience(&[&provider.client_id]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+
+        let claims = jsonwebtoken::decode::<IdentityClaims>(id_token, &key, &validation)
+            .map_err(|e| invalid(&e.to_string()))?
+            .claims;
+
+        if claims.nonce.as_deref() != Some(nonce) {
+            return Err(invalid("nonce mismatch"));
+        }
+        Ok(claims)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::oidc::keys::Jwk;
+    use actix_web::{App, HttpResponse, HttpServer, web};
+    use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+    use jsonwebtoken::{EncodingKey, Header};
+    use security::rsa_key::RsaKeyPair;
+    use serde_json::json;
+
+    const CLIENT_ID: &str = "r-web";
+    const CODE: &str = "good-code";
+    const NONCE: &str = "n-0S6_WzA2Mj";
+
+    struct MockIdp {
+        issuer: String,
+        key: EncodingKey,
+        jwk: serde_json::Value,
+    }
+
+    impl MockIdp {
+        fn sign(&self, kid: &str, claims: serde_json::Value) -> String {
+            let mut header = Header::new(Algorithm::RS256);
+            header.kid = Some(kid.to_string());
+            jsonwebtoken::encode(&header, &claims, &self.key).unwrap()
+        }
+
+        fn claims(&self, aud: &str) -> serde_json::Value {
+            let now = chrono::Utc::now().timestamp();
+            json!({
+                "iss": self.issuer,
+                "aud": aud,
+                "sub": "248289761001",
+                "iat": now,
+                "exp": now + 300,
+                "nonce": NONCE,
+                "email": "ada@example.com",
+                "email_verified": true,
+            })
+        }
+    }
+
+    async fn discovery(idp: web::Data<MockIdp>) -> HttpResponse {
+        HttpResponse::Ok().json(json!({
+            "issuer": idp.issuer,
+            "authorization_endpoint": format!("{}/authorize", idp.issuer),
+            "token_endpoint": format!("{}/token", idp.issuer),
+            "jwks_uri": format!("{}/jwks", idp.issuer),
+        }))
+    }
+
+    async fn jwks(idp: web::Data<MockIdp>) -> HttpResponse {
+        HttpResponse::Ok().json(json!({ "keys": [idp.jwk] }))
+    }
+
+    async fn token(
+        idp: web::Data<MockIdp>,
+        form: web::Form<HashMap<String, String>>,
+    ) -> HttpResponse {
+        let valid = form.get("grant_type").map(String::as_str) == Some("authorization_code")
+            && form.get("code").map(String::as_str) == Some(CODE)
+            && form.contains_key("code_verifier")
+            && form.get("client_id").map(String::as_str) == Some(CLIENT_ID);
+        if !valid {
+            return HttpResponse::BadRequest().json(json!({ "error": "invalid_grant" }));
+        }
+        HttpResponse::Ok().json(json!({
+            "access_token": "at",
+            "token_type": "Bearer",
+            "id_token": idp.sign("k1", idp.claims(CLIENT_ID)),
+        }))
+    }
+
+    /// Serve an identity provider on a free local port
+    async fn start_idp() -> (web::Data<MockIdp>, SsoProviderSettings) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let issuer = format!("http://{}", listener.local_addr().unwrap());
+
+        let key_pair = RsaKeyPair::generate();
+        let jwk = Jwk {
+            kty: "RSA".to_string(),
+            use_: "sig".to_string(),
+            alg: "RS256".to_string(),
+            kid: "k1".to_string(),
+            n: key_pair.modulus(),
+            e: key_pair.exponent(),
+        };
+        let idp = web::Data::new(MockIdp {
+            issuer: issuer.clone(),
+            key: EncodingKey::from_rsa_pem(key_pair.to_pem().as_bytes()).unwrap(),
+            jwk: serde_json::to_value(jwk).unwrap(),
+        });
+
+        let data = idp.clone();
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(data.clone())
+                .route(
+                    "/.well-known/openid-configuration",
+                    web::get().to(discovery),
+                )
+                .route("/jwks", web::get().to(jwks))
+                .route("/token", web::post().to(token))
+        })
+        .workers(1)
+        .listen(listener)
+        .unwrap()
+        .run();
+        actix_web::rt::spawn(server);
+
+        let provider = SsoProviderSettings {
+            name: "mock".to_string(),
+            display_name: "Mock IdP".to_string(),
+            issuer,
+            client_id: CLIENT_ID.to_string(),
+            client_secret: None,
+            redirect_uri: "http://localhost/api/v1/auth/sso/mock/callback".to_string(),
+            scopes: vec!["openid".to_string(), "email".to_string()],
+            auto_provision: false,
+            link_by_email: false,
+            trusted_for_mfa: false,
+            allowed_domains: Vec::new(),
+        };
+        (idp, provider)
+    }
+
+    #[actix_web::test]
+    async fn test_login_against_mock_idp() {
+        let (idp, provider) = start_idp().await;
+        let client = SsoClient::new(Duration::from_secs(60));
+
+        let metadata = client.metadata(&provider).await.unwrap();
+        assert_eq!(metadata.token_endpoint, format!("{}/token", idp.issuer));
+
+        let id_token = client
+            .exchange_code(&provider, CODE, "verifier")
+            .await
+            .unwrap();
+        let claims = client
+            .validate_id_token(&provider, &id_token, NONCE)
+            .await
+            .unwrap();
+        assert_eq!(claims.sub, "248289761001");
+        assert_eq!(claims.verified_email(), Some("ada@example.com"));
+
+        assert!(matches!(
+            client
+                .exchange_code(&provider, "used-code", "verifier")
+                .await,
+            Err(ApiError::Unauthorized(_))
+        ));
+        assert!(matches!(
+            client
+                .validate_id_token(&provider, &id_token, "other-nonce")
+                .await,
+            Err(ApiError::Unauthorized(_))
+        ));
+
+        let other_audience = idp.sign("k1", idp.claims("another-client"));
+        assert!(matches!(
+            client
+                .validate_id_token(&provider, &other_audience, NONCE)
+                .await,
+            Err(ApiError::Unauthorized(_))
+        ));
+
+        let unknown_key = idp.sign("k2", idp.claims(CLIENT_ID));
+        assert!(matches!(
+            client
+                .validate_id_token(&provider, &unknown_key, NONCE)
+                .await,
+            Err(ApiError::Unauthorized(_))
+        ));
+
+        let mut expired = idp.claims(CLIENT_ID);
+        expired["exp"] = json!(chrono::Utc::now().timestamp() - 3_600);
+        let expired = idp.sign("k1", expired);
+        assert!(matches!(
+            client.validate_id_token(&provider, &expired, NONCE).await,
+            Err(ApiError::Unauthorized(_))
+        ));
+
+        let unsigned = format!(
+            "{}.{}.",
+            URL_SAFE_NO_PAD.encode(r#"{"alg":"none","kid":"k1"}"#),
+            URL_SAFE_NO_PAD.encode(idp.claims(CLIENT_ID).to_string())
+        );
+        assert!(matches!(
+            client.validate_id_token(&provider, &unsigned, NONCE).await,
+            Err(ApiError::Unauthorized(_))
+        ));
+    }
+}
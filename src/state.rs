@@ -1,18 +1,70 @@
+use crate::auth::backends::BackendChain;
+use crate::auth::throttle::RateLimiter;
 use crate::config::Settings;
+use crate::mail::Mail;
+use crate::sso::SsoClient;
 use sea_orm::DatabaseConnection;
 use std::sync::Arc;
+use std::time::Duration;
 
 #[derive(Clone)]
 pub struct AppState {
     pub db: Arc<DatabaseConnection>,
     pub config: Arc<Settings>,
+    pub mail: Arc<Mail>,
+    /// Throttles verification emails per address
+    pub verification_resends: Arc<RateLimiter>,
+    /// Throttles login link emails per address
+    pub magic_link_requests: Arc<RateLimiter>,
+    /// Throttles email change confirmations per user
+    pub email_change_requests: Arc<RateLimiter>,
+    /// Throttles second factor attempts per user
+    pub mfa_attempts: Arc<RateLimiter>,
+    /// Talks to external OpenID Connect providers
+    pub sso: Arc<SsoClient>,
+    /// `auth.backends`, asked in order on login
+    pub auth_backends: Arc<BackendChain>,
 }
 
 impl AppState {
     pub fn new(db: DatabaseConnection, config: Settings) -> Self {
+        let verification_resends = RateLimiter::new(
+            config.registration.resend_limit,
+            Duration::from_secs(config.registration.resend_window),
+        );
+
+        let magic_link_requests = RateLimiter::new(
+            config.magic_link.request_limit,
+            Duration::from_secs(config.magic_link.request_window),
+        );
+
+        let email_change_requests = RateLimiter::new(
+            config.email_change.request_limit,
+            Duration::from_secs(config.email_change.request_window),
+        );
+
+        let mfa_attempts = RateLimiter::new(
+            config.mfa.max_attempts,
+            Duration::from_secs(config.mfa.attempt_window),
+        );
+
+        let sso = SsoClient::new(Duration::from_secs(config.sso.metadata_ttl));
+
+        let mail = Mail::from_settings(&config.mail).expect("Invalid mail configuration");
+
+        let auth_backends =
+            BackendChain::from_settings(&config).expect("Invalid authentication backends");
+
         Self {
             db: Arc::new(db),
             config: Arc::new(config),
+            mail: Arc::new(mail),
+            verification_resends: Arc::new(verification_resends),
+            magic_link_requests: Arc::new(magic_link_requests),
+            email_change_requests: Arc::new(email_change_requests),
+            mfa_attempts: Arc::new(mfa_attempts),
+            sso: Arc::new(sso),
+            auth_backends: Arc::new(auth_backends),
         }
     }
 }
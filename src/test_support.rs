@@ -0,0 +1,62 @@
+//! Helpers for handler tests, which run against a `sea_orm::MockDatabase`
+//! and answer each query with the next result appended to it.
+
+use crate::auth::jwt;
+use crate::config::Settings;
+use crate::mail::{Mail, MailTemplates, MemoryMailer};
+use crate::session::{Session, SessionState};
+use crate::state::AppState;
+use entity::auth_user_sessions::Model as UserSession;
+use entity::auth_users::Model as User;
+use sea_orm::DatabaseConnection;
+use serde_json::Map;
+use std::sync::Arc;
+
+/// Application state on `db` with the default settings, sending mail to
+/// the returned outbox
+pub fn app_state(db: DatabaseConnection) -> (AppState, MemoryMailer) {
+    app_state_with(db, Settings::new().unwrap())
+}
+
+/// Like [`app_state`], with `config` instead of the default settings
+pub fn app_state_with(db: DatabaseConnection, config: Settings) -> (AppState, MemoryMailer) {
+    let mut state = AppState::new(db, config);
+    let outbox = MemoryMailer::new();
+    let templates = MailTemplates::new(None, "en").unwrap();
+    state.mail = Arc::new(Mail::new(
+        Arc::new(outbox.clone()),
+        templates,
+        "R-Web <no-reply@localhost>",
+        "R-Web",
+    ));
+    (state, outbox)
+}
+
+/// A new, empty cookie session. Handlers taking a [`Session`] find it in
+/// the request extensions, where `SessionMiddleware` would have put it.
+pub fn anonymous_session() -> Session {
+    Session::from_state(SessionState::new(None, Map::new()))
+}
+
+/// The recorded login `id` of `user`, seen just now
+pub fn device_session(user: &User, id: i32) -> UserSession {
+    let now = chrono::Utc::now().naive_utc();
+    UserSession {
+        id,
+        user_id: user.id,
+        kind: "token".to_string(),
+        label: "curl".to_string(),
+        user_agent: None,
+        ip_address: None,
+        created_at: now,
+        last_seen_at: now,
+    }
+}
+
+/// Access token of `user` in the recorded login `session_id`. Resolving it
+/// queries the user, then the session.
+pub fn access_token(config: &Settings, user: &User, session_id: i32) -> String {
+    jwt::encode_access_token(&config.auth, user.id, session_id)
+        .unwrap()
+        .0
+}